@@ -0,0 +1,106 @@
+// Developed by PowerShield, as an alternative to Diesel
+
+// Benchmarks for Diesel Emulator
+// Compares peak memory and time of eager `load()`, the lazy `load_iter()` cursor
+// and `load_shared()`, which returns rows shared with the table
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Borrow;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::Instant;
+
+#[allow(dead_code)]
+mod emulator {
+    /// Fill a table directly, skipping the per-insert logging
+    pub fn seed(conn: &Connection, table: &str, count: usize) {
+        let mut tables = conn.tables.lock().unwrap();
+        let rows = tables.entry(table.to_string()).or_default();
+        for i in 0..count {
+            let mut row = Row::new();
+            row.set("id", Value::Integer(i as i32));
+            row.set("name", Value::Text(format!("user-{:08}", i)));
+            row.set("email", Value::Text(format!("user-{:08}@example.com", i)));
+            row.set("age", Value::Integer((i % 90) as i32));
+            rows.push(Arc::new(row));
+        }
+    }
+
+    include!("diesel_emulator.rs");
+}
+
+use emulator::*;
+
+/// Tracks live and peak heap usage
+struct CountingAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), AtomicOrdering::SeqCst) + layout.size();
+            PEAK.fetch_max(current, AtomicOrdering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), AtomicOrdering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run `f`, returning its result, the peak heap growth in bytes and the elapsed time
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, f64) {
+    let baseline = CURRENT.load(AtomicOrdering::SeqCst);
+    PEAK.store(baseline, AtomicOrdering::SeqCst);
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    let peak = PEAK.load(AtomicOrdering::SeqCst) - baseline;
+    (result, peak, elapsed)
+}
+
+fn sum_ages<R: Borrow<Row>>(rows: impl IntoIterator<Item = R>) -> i64 {
+    rows.into_iter()
+        .map(|row| match row.borrow().get("age") {
+            Some(Value::Integer(age)) => *age as i64,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn main() {
+    println!("Diesel Emulator benchmarks");
+    println!("==========================\n");
+
+    for count in [10_000, 50_000] {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed(&conn, "users", count);
+        let query = SelectQuery::new("users").filter(Column::new("age").ge(18));
+
+        let eager = measure(|| sum_ages(query.load(&conn).unwrap()));
+        let lazy = measure(|| sum_ages(query.load_iter(&conn).unwrap().map(Result::unwrap)));
+        let shared = measure(|| sum_ages(query.load_shared(&conn).unwrap()));
+        assert_eq!(eager.0, lazy.0);
+        assert_eq!(eager.0, shared.0);
+
+        println!("{} rows:", count);
+        for (name, (_, peak, ms)) in [
+            ("load()", eager),
+            ("load_iter()", lazy),
+            ("load_shared()", shared),
+        ] {
+            println!(
+                "  {:<14} peak heap {:>12} bytes  {:>8.2} ms",
+                name, peak, ms
+            );
+        }
+        println!();
+    }
+}
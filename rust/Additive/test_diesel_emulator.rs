@@ -4,6 +4,7 @@
 // This file contains comprehensive tests for the Diesel ORM emulator
 
 #[cfg(test)]
+#[allow(dead_code, clippy::module_inception, clippy::approx_constant)]
 mod tests {
     // Import from the main diesel_emulator module
     include!("diesel_emulator.rs");
@@ -37,7 +38,7 @@ mod tests {
         let conn = Connection::establish_sqlite(":memory:").unwrap();
         let result = conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY)");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 1);
+        assert_eq!(result.unwrap(), 0);
     }
 
     #[test]
@@ -162,6 +163,11 @@ mod tests {
     #[test]
     fn test_update_execution() {
         let conn = Connection::establish_sqlite(":memory:").unwrap();
+        InsertQuery::new("users")
+            .value("age", Value::Integer(30))
+            .execute(&conn)
+            .unwrap();
+
         let result = UpdateQuery::new("users")
             .set("status", Value::Text("active".to_string()))
             .filter("age > 18")
@@ -386,6 +392,2998 @@ mod tests {
         assert!(sql.contains("LIMIT 25"));
         assert!(sql.contains("OFFSET 0"));
     }
+
+    fn seed_users_and_orders(conn: &Connection) {
+        for (id, name) in [(1, "Alice"), (2, "Bob"), (3, "Carol")] {
+            InsertQuery::new("users")
+                .value("id", Value::Integer(id))
+                .value("name", Value::Text(name.to_string()))
+                .execute(conn)
+                .unwrap();
+        }
+        for (id, user_id, total) in [(10, 1, 50), (11, 1, 250), (12, 3, 120)] {
+            InsertQuery::new("orders")
+                .value("id", Value::Integer(id))
+                .value("user_id", Value::Integer(user_id))
+                .value("total", Value::Integer(total))
+                .execute(conn)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_typed_filter_expression() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let query = SelectQuery::new("orders").filter(Column::new("total").gt(100));
+        assert_eq!(query.to_sql(), "SELECT * FROM orders WHERE total > 100");
+        assert_eq!(query.load(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_string_filters_exclude_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let rows = SelectQuery::new("users")
+            .filter("id = 1")
+            .load(&conn)
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_string("name").unwrap(), "Alice");
+        let big = SelectQuery::new("orders")
+            .filter("total > 100 AND user_id <> 1")
+            .load(&conn)
+            .unwrap();
+        assert_eq!(big.len(), 1);
+        assert_eq!(big[0].get("id"), Some(&Value::Integer(12)));
+
+        assert_eq!(
+            DeleteQuery::new("users")
+                .filter("id = 2")
+                .execute(&conn)
+                .unwrap(),
+            1
+        );
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 2);
+
+        let err = SelectQuery::new("users")
+            .filter("id = = 1")
+            .load(&conn)
+            .unwrap_err();
+        assert!(matches!(err, DieselError::SyntaxError(_)));
+        let err = DeleteQuery::new("users")
+            .filter("id ==")
+            .execute(&conn)
+            .unwrap_err();
+        assert!(matches!(err, DieselError::SyntaxError(_)));
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_string_filters_with_like_and_between() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let names = |filter: &str| -> Vec<String> {
+            SelectQuery::new("users")
+                .filter(filter)
+                .load(&conn)
+                .unwrap()
+                .iter()
+                .map(|row| row.get_string("name").unwrap())
+                .collect()
+        };
+
+        assert_eq!(names("name LIKE 'A%'"), ["Alice"]);
+        assert_eq!(names("name LIKE '%o%'"), ["Bob", "Carol"]);
+        assert_eq!(names("name NOT LIKE '_o%'"), ["Alice", "Carol"]);
+        assert_eq!(names("name like 'c_r%l' OR name LIKE 'Bob'"), ["Bob"]);
+        assert_eq!(names("id BETWEEN 2 AND 3 AND name LIKE '%l'"), ["Carol"]);
+        assert_eq!(names("id NOT BETWEEN 2 AND 3"), ["Alice"]);
+        assert_eq!(
+            SelectQuery::new("users")
+                .filter(Column::new("name").like("%li%"))
+                .to_sql(),
+            "SELECT * FROM users WHERE name LIKE '%li%'"
+        );
+
+        // SQL the emulator can't evaluate keeps matching every row
+        assert_eq!(names("id % 2 = 1").len(), 3);
+        assert_eq!(names("length(name) > 3").len(), 3);
+        assert_eq!(
+            DeleteQuery::new("users")
+                .filter("name LIKE '%o%'")
+                .execute(&conn)
+                .unwrap(),
+            2
+        );
+        assert_eq!(names("name LIKE '%'"), ["Alice"]);
+    }
+
+    #[test]
+    fn test_eq_any_subquery() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let big_spenders = SelectQuery::new("orders")
+            .select(vec!["user_id"])
+            .filter(Column::new("total").gt(100));
+        let query = SelectQuery::new("users")
+            .filter(Column::new("id").eq_any(big_spenders))
+            .order_by("id", "ASC");
+
+        assert_eq!(
+            query.to_sql(),
+            "SELECT * FROM users WHERE id IN (SELECT user_id FROM orders WHERE total > 100) ORDER BY id ASC"
+        );
+        let rows = query.load(&conn).unwrap();
+        let names: Vec<_> = rows
+            .iter()
+            .map(|r| r.get("name").unwrap().to_string())
+            .collect();
+        assert_eq!(names, vec!["Alice", "Carol"]);
+    }
+
+    #[test]
+    fn test_eq_any_values() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let query = SelectQuery::new("users")
+            .filter(Column::new("name").eq_any(vec![Value::from("Bob"), Value::from("Carol")]));
+        assert!(query.to_sql().contains("name IN ('Bob', 'Carol')"));
+        assert_eq!(query.load(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_correlated_exists_subquery() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let has_orders = SelectQuery::new("orders")
+            .filter(Column::new("orders.user_id").eq_column(&Column::new("users.id")));
+        let query = SelectQuery::new("users").filter(!exists(has_orders));
+
+        assert!(query
+            .to_sql()
+            .contains("WHERE NOT (EXISTS (SELECT * FROM orders WHERE orders.user_id = users.id))"));
+        let rows = query.load(&conn).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("name"), Some(&Value::Text("Bob".to_string())));
+    }
+
+    #[test]
+    fn test_derived_table() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let large = SelectQuery::new("orders").filter(Column::new("total").ge(100));
+        let query = SelectQuery::from_subquery(large, "large_orders")
+            .select(vec!["id"])
+            .order_by("id", "DESC")
+            .limit(1);
+
+        assert_eq!(
+            query.to_sql(),
+            "SELECT id FROM (SELECT * FROM orders WHERE total >= 100) AS large_orders ORDER BY id DESC LIMIT 1"
+        );
+        let rows = query.load(&conn).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("id"), Some(&Value::Integer(12)));
+        assert!(rows[0].get("total").is_none());
+    }
+
+    #[test]
+    fn test_delete_with_subquery_filter() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let customers = SelectQuery::new("orders").select(vec!["user_id"]);
+        let deleted = DeleteQuery::new("users")
+            .filter(!Column::new("id").eq_any(customers))
+            .execute(&conn)
+            .unwrap();
+
+        assert_eq!(deleted, 1);
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_distinct_sql_and_dedup() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let query = SelectQuery::new("orders")
+            .select(vec!["user_id"])
+            .distinct();
+        assert_eq!(query.to_sql(), "SELECT DISTINCT user_id FROM orders");
+        assert_eq!(query.load(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_distinct_applies_before_limit() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let rows = SelectQuery::new("orders")
+            .select(vec!["user_id"])
+            .distinct()
+            .order_by("user_id", "ASC")
+            .limit(2)
+            .load(&conn)
+            .unwrap();
+        let ids: Vec<_> = rows.iter().map(|r| r.get("user_id").cloned()).collect();
+        assert_eq!(ids, vec![Some(Value::Integer(1)), Some(Value::Integer(3))]);
+    }
+
+    #[test]
+    fn test_distinct_on_keeps_first_row_per_value() {
+        let conn = Connection::establish_postgres("postgres://localhost/test").unwrap();
+        seed_users_and_orders(&conn);
+
+        let query = SelectQuery::new("orders")
+            .distinct_on("user_id")
+            .order_by("total", "DESC");
+        assert_eq!(
+            query.to_sql(),
+            "SELECT DISTINCT ON (user_id) * FROM orders ORDER BY total DESC"
+        );
+
+        let rows = query.load(&conn).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].get("total"), Some(&Value::Integer(250)));
+        assert_eq!(rows[1].get("total"), Some(&Value::Integer(120)));
+    }
+
+    #[test]
+    fn test_distinct_on_requires_postgres() {
+        let conn = Connection::establish_mysql("mysql://localhost/test").unwrap();
+        let result = SelectQuery::new("orders")
+            .distinct_on("user_id")
+            .load(&conn);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_soft_delete_marks_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let users = Table::new("users").with_soft_delete("deleted_at");
+
+        let query = users.delete().filter(Column::new("name").eq("Bob"));
+        assert_eq!(
+            query.to_sql(),
+            "UPDATE users SET deleted_at = CURRENT_TIMESTAMP WHERE name = 'Bob' AND deleted_at IS NULL"
+        );
+        assert_eq!(query.execute(&conn).unwrap(), 1);
+        // Deleting again is a no-op because the row is already trashed
+        assert_eq!(query.execute(&conn).unwrap(), 0);
+
+        assert_eq!(users.count(&conn).unwrap(), 2);
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_soft_delete_select_filters_trashed() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let users = Table::new("users").with_soft_delete("deleted_at");
+        users
+            .delete()
+            .filter(Column::new("id").eq(1))
+            .execute(&conn)
+            .unwrap();
+
+        let query = users.select();
+        assert_eq!(
+            query.to_sql(),
+            "SELECT * FROM users WHERE deleted_at IS NULL"
+        );
+        assert_eq!(query.load(&conn).unwrap().len(), 2);
+
+        let trashed = users.select().with_trashed().load(&conn).unwrap();
+        assert_eq!(trashed.len(), 3);
+        let alice = trashed
+            .iter()
+            .find(|r| r.get("id") == Some(&Value::Integer(1)))
+            .unwrap();
+        assert!(matches!(alice.get("deleted_at"), Some(Value::Timestamp(_))));
+    }
+
+    #[test]
+    fn test_force_delete_removes_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let users = Table::new("users").with_soft_delete("deleted_at");
+
+        let query = users.force_delete().filter(Column::new("id").eq(2));
+        assert_eq!(query.to_sql(), "DELETE FROM users WHERE id = 2");
+        assert_eq!(query.execute(&conn).unwrap(), 1);
+        assert_eq!(users.select().with_trashed().load(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_update_applies_to_matching_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let updated = UpdateQuery::new("orders")
+            .set("total", Value::Integer(0))
+            .filter(Column::new("user_id").eq(1))
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(updated, 2);
+
+        let zeroed = SelectQuery::new("orders")
+            .filter(Column::new("total").eq(0))
+            .load(&conn)
+            .unwrap();
+        assert_eq!(zeroed.len(), 2);
+    }
+
+    #[test]
+    fn test_in_subquery_requires_one_column() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        for subquery in [
+            SelectQuery::new("orders"),
+            SelectQuery::new("orders").select(vec!["user_id", "total"]),
+        ] {
+            let query = SelectQuery::new("users").filter(Column::new("id").eq_any(subquery));
+            assert!(matches!(
+                query.load(&conn),
+                Err(DieselError::SyntaxError(message))
+                    if message.starts_with("subquery must select exactly one column")
+            ));
+        }
+        let deleted = DeleteQuery::new("users")
+            .filter(Column::new("id").eq_any(SelectQuery::new("orders")))
+            .execute(&conn);
+        assert_eq!(
+            deleted.unwrap_err().to_string(),
+            "subquery must select exactly one column: SELECT * FROM orders"
+        );
+        assert_eq!(SelectQuery::new("users").count(&conn).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_update_with_string_filter_changes_only_matches() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let updated = UpdateQuery::new("users")
+            .set("name", Value::Text("Alicia".to_string()))
+            .filter("id = 1")
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(updated, 1);
+        assert_eq!(user_name(&conn, 1), "Alicia");
+        assert_eq!(user_name(&conn, 2), "Bob");
+        assert_eq!(user_name(&conn, 3), "Carol");
+
+        let err = UpdateQuery::new("users")
+            .set("name", Value::Text("Nobody".to_string()))
+            .filter("id = = 2")
+            .execute(&conn)
+            .unwrap_err();
+        assert!(matches!(err, DieselError::SyntaxError(_)));
+        assert_eq!(user_name(&conn, 2), "Bob");
+    }
+
+    #[test]
+    fn test_optimistic_lock_bumps_version() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        InsertQuery::new("accounts")
+            .value("id", Value::Integer(1))
+            .value("balance", Value::Integer(100))
+            .value("version", Value::Integer(1))
+            .execute(&conn)
+            .unwrap();
+
+        let query = UpdateQuery::new("accounts")
+            .set("balance", Value::Integer(80))
+            .filter(Column::new("id").eq(1))
+            .optimistic_lock("version", 1);
+        assert!(query
+            .to_sql()
+            .ends_with("version = version + 1 WHERE id = 1 AND version = 1"));
+        assert_eq!(query.execute(&conn).unwrap(), 1);
+
+        let row = SelectQuery::new("accounts").first(&conn).unwrap().unwrap();
+        assert_eq!(row.get("version"), Some(&Value::Integer(2)));
+        assert_eq!(row.get("balance"), Some(&Value::Integer(80)));
+    }
+
+    #[test]
+    fn test_optimistic_lock_detects_concurrent_writer() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        InsertQuery::new("accounts")
+            .value("id", Value::Integer(1))
+            .value("version", Value::Integer(1))
+            .execute(&conn)
+            .unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let conn = conn.clone();
+                std::thread::spawn(move || {
+                    UpdateQuery::new("accounts")
+                        .set("owner", Value::Integer(n))
+                        .filter(Column::new("id").eq(1))
+                        .optimistic_lock("version", 1)
+                        .execute(&conn)
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        for err in results.iter().filter_map(|r| r.as_ref().err()) {
+            assert!(matches!(err, DieselError::StaleObject { .. }));
+        }
+        let row = SelectQuery::new("accounts").first(&conn).unwrap().unwrap();
+        assert_eq!(row.get("version"), Some(&Value::Integer(2)));
+    }
+
+    #[test]
+    fn test_fault_injection_on_nth_statement() {
+        let policy = FailurePolicy::new()
+            .fail_on(2, Failure::Deadlock)
+            .fail_on(3, Failure::Serialization);
+        let conn = Connection::establish_postgres("postgres://localhost/test")
+            .unwrap()
+            .with_failures(policy);
+        let insert = InsertQuery::new("jobs").value("id", Value::Integer(1));
+
+        assert!(insert.execute(&conn).is_ok());
+        assert_eq!(insert.execute(&conn).unwrap_err(), DieselError::Deadlock);
+        assert_eq!(
+            insert.execute(&conn).unwrap_err(),
+            DieselError::SerializationError
+        );
+        assert!(insert.execute(&conn).is_ok());
+
+        assert_eq!(conn.statement_count(), 4);
+        assert_eq!(Table::new("jobs").count(&conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_fault_injection_supports_retry_loops() {
+        let conn = Connection::establish_sqlite(":memory:")
+            .unwrap()
+            .with_failures(FailurePolicy::new().fail_every(2, Failure::ConnectionDropped));
+        let query = SelectQuery::new("users");
+
+        assert!(query.load(&conn).is_ok());
+        let mut attempts = 0;
+        let result = loop {
+            attempts += 1;
+            match query.load(&conn) {
+                Err(DieselError::ConnectionError(_)) if attempts < 3 => continue,
+                other => break other,
+            }
+        };
+        assert!(result.is_ok());
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn test_fault_injection_slow_query() {
+        let conn = Connection::establish_sqlite(":memory:")
+            .unwrap()
+            .with_failures(
+                FailurePolicy::new().fail_on(1, Failure::SlowQuery(Duration::from_millis(30))),
+            );
+
+        let start = std::time::Instant::now();
+        assert!(conn.execute("SELECT 1").is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_fault_injection_shared_by_clones() {
+        let conn = Connection::establish_sqlite(":memory:")
+            .unwrap()
+            .with_failures(FailurePolicy::new().fail_on(2, Failure::Deadlock));
+        let clone = conn.clone();
+
+        assert!(conn.execute("SELECT 1").is_ok());
+        assert!(clone.execute("SELECT 1").is_err());
+    }
+
+    #[test]
+    fn test_load_iter_matches_load() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let queries = vec![
+            SelectQuery::new("orders"),
+            SelectQuery::new("orders").filter(Column::new("total").gt(60)),
+            SelectQuery::new("orders")
+                .order_by("total", "DESC")
+                .offset(1)
+                .limit(1),
+            SelectQuery::new("orders")
+                .select(vec!["user_id"])
+                .distinct()
+                .limit(5),
+            SelectQuery::from_subquery(SelectQuery::new("orders"), "o").limit(2),
+        ];
+        for query in queries {
+            let eager = query.load(&conn).unwrap();
+            let lazy: Vec<Row> = query
+                .load_iter(&conn)
+                .unwrap()
+                .collect::<QueryResult<_>>()
+                .unwrap();
+            assert_eq!(eager, lazy, "{}", query.to_sql());
+        }
+    }
+
+    #[test]
+    fn test_load_iter_is_lazy() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let mut cursor = SelectQuery::new("users").load_iter(&conn).unwrap();
+        assert_eq!(
+            cursor.next().unwrap().unwrap().get("id"),
+            Some(&Value::Integer(1))
+        );
+
+        // The table is not locked between rows, and later rows are read live
+        InsertQuery::new("users")
+            .value("id", Value::Integer(4))
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(cursor.count(), 3);
+    }
+
+    #[test]
+    fn test_sorted_load_iter_reads_rows_as_opened() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let mut cursor = SelectQuery::new("users")
+            .order_by("id", "DESC")
+            .load_iter(&conn)
+            .unwrap();
+        assert_eq!(user_id(cursor.next().unwrap().unwrap()), 3);
+
+        // Deleting a row neither shifts the cursor onto a row it already read
+        // nor hides one it matched when opened
+        conn.execute("DELETE FROM users WHERE id = 2").unwrap();
+        let rest: Vec<i32> = cursor.map(|row| user_id(row.unwrap())).collect();
+        assert_eq!(rest, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_load_iter_timeout_covers_reading_rows() {
+        let conn = seeded_events(100);
+        let mut cursor = SelectQuery::new("events")
+            .timeout(Duration::from_millis(50))
+            .load_iter(&conn)
+            .unwrap();
+        assert!(cursor.next().unwrap().is_ok());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(
+            cursor.next(),
+            Some(Err(DieselError::Timeout(Duration::from_millis(50))))
+        );
+        assert_eq!(cursor.next(), None);
+    }
+
+    fn user_id(row: Row) -> i32 {
+        match row.get("id") {
+            Some(Value::Integer(id)) => *id,
+            other => panic!("unexpected id {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_shared_reuses_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let first = SelectQuery::new("users").load_shared(&conn).unwrap();
+        let second = SelectQuery::new("users")
+            .filter(Column::new("id").eq(2))
+            .load_shared(&conn)
+            .unwrap();
+        assert!(Arc::ptr_eq(&first[1], &second[0]));
+    }
+
+    #[test]
+    fn test_update_copies_only_affected_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let before = SelectQuery::new("users").load_shared(&conn).unwrap();
+
+        UpdateQuery::new("users")
+            .set("name", Value::Text("Robert".to_string()))
+            .filter(Column::new("id").eq(2))
+            .execute(&conn)
+            .unwrap();
+        let after = SelectQuery::new("users").load_shared(&conn).unwrap();
+
+        // The earlier result still sees the old value
+        assert_eq!(before[1].get("name"), Some(&Value::Text("Bob".to_string())));
+        assert_eq!(
+            after[1].get("name"),
+            Some(&Value::Text("Robert".to_string()))
+        );
+        assert!(!Arc::ptr_eq(&before[1], &after[1]));
+        assert!(Arc::ptr_eq(&before[0], &after[0]));
+        assert!(Arc::ptr_eq(&before[2], &after[2]));
+    }
+
+    #[test]
+    fn test_select_count_respects_filters() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let query = SelectQuery::new("orders").filter(Column::new("user_id").eq(1));
+        assert_eq!(
+            query.to_count_sql(),
+            "SELECT COUNT(*) FROM orders WHERE user_id = 1"
+        );
+        assert_eq!(query.count(&conn).unwrap(), 2);
+        assert_eq!(Table::new("orders").count(&conn).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_select_count_wraps_limited_queries() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let query = SelectQuery::new("orders")
+            .select(vec!["user_id"])
+            .distinct();
+        assert_eq!(
+            query.to_count_sql(),
+            "SELECT COUNT(*) FROM (SELECT DISTINCT user_id FROM orders) AS counted"
+        );
+        assert_eq!(query.count(&conn).unwrap(), 2);
+        assert_eq!(SelectQuery::new("orders").limit(1).count(&conn).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_select_exists() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let query = SelectQuery::new("orders").filter(Column::new("total").gt(200));
+        assert_eq!(
+            query.to_exists_sql(),
+            "SELECT EXISTS(SELECT * FROM orders WHERE total > 200)"
+        );
+        assert!(query.exists(&conn).unwrap());
+        assert!(!SelectQuery::new("orders")
+            .filter(Column::new("total").gt(1000))
+            .exists(&conn)
+            .unwrap());
+        assert!(!SelectQuery::new("missing").exists(&conn).unwrap());
+    }
+
+    #[test]
+    fn test_row_hooks_receive_old_and_new_values() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = Arc::clone(&log);
+        conn.on_insert("users", move |change| {
+            let name = change
+                .new
+                .as_ref()
+                .unwrap()
+                .get("name")
+                .unwrap()
+                .to_string();
+            sink.lock().unwrap().push(format!("insert {}", name));
+        });
+        let sink = Arc::clone(&log);
+        conn.on_update("users", move |change| {
+            let old = change
+                .old
+                .as_ref()
+                .unwrap()
+                .get("name")
+                .unwrap()
+                .to_string();
+            let new = change
+                .new
+                .as_ref()
+                .unwrap()
+                .get("name")
+                .unwrap()
+                .to_string();
+            sink.lock()
+                .unwrap()
+                .push(format!("update {} -> {}", old, new));
+        });
+        let sink = Arc::clone(&log);
+        conn.on_delete("users", move |change| {
+            assert!(change.new.is_none());
+            let name = change
+                .old
+                .as_ref()
+                .unwrap()
+                .get("name")
+                .unwrap()
+                .to_string();
+            sink.lock().unwrap().push(format!("delete {}", name));
+        });
+
+        let users = Table::new("users");
+        users
+            .insert()
+            .value("name", Value::from("Ann"))
+            .execute(&conn)
+            .unwrap();
+        users
+            .update()
+            .set("name", Value::from("Anne"))
+            .execute(&conn)
+            .unwrap();
+        users.delete().execute(&conn).unwrap();
+        InsertQuery::new("posts")
+            .value("name", Value::from("ignored"))
+            .execute(&conn)
+            .unwrap();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["insert Ann", "update Ann -> Anne", "delete Anne"]
+        );
+    }
+
+    #[test]
+    fn test_row_hook_can_write_audit_log() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        let audit_conn = conn.clone();
+        conn.on_delete("users", move |change| {
+            let id = change.old.as_ref().unwrap().get("id").cloned().unwrap();
+            InsertQuery::new("audit_log")
+                .value("deleted_user", id)
+                .execute(&audit_conn)
+                .unwrap();
+        });
+        seed_users_and_orders(&conn);
+
+        DeleteQuery::new("users")
+            .filter(Column::new("id").ge(2))
+            .execute(&conn)
+            .unwrap();
+        let audit = SelectQuery::new("audit_log")
+            .order_by("deleted_user", "ASC")
+            .load(&conn)
+            .unwrap();
+        let ids: Vec<_> = audit
+            .iter()
+            .map(|r| r.get("deleted_user").cloned())
+            .collect();
+        assert_eq!(ids, vec![Some(Value::Integer(2)), Some(Value::Integer(3))]);
+    }
+
+    #[test]
+    fn test_soft_delete_fires_update_hook() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let fired = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&fired);
+        conn.on_update("users", move |change| {
+            assert!(change.old.as_ref().unwrap().get("deleted_at").is_none());
+            assert!(change.new.as_ref().unwrap().get("deleted_at").is_some());
+            counter.fetch_add(1, AtomicOrdering::SeqCst);
+        });
+
+        Table::new("users")
+            .with_soft_delete("deleted_at")
+            .delete()
+            .filter(Column::new("id").eq(1))
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(fired.load(AtomicOrdering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_execute_applies_dml_with_affected_counts() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INTEGER)",
+        )
+        .unwrap();
+
+        let inserted = conn
+            .execute("INSERT INTO users (id, name, age) VALUES (1, 'Ann', 30), (2, 'O''Brien', 41), (3, 'Cy', NULL)")
+            .unwrap();
+        assert_eq!(inserted, 3);
+        let obrien = SelectQuery::new("users")
+            .filter(Column::new("id").eq(2))
+            .first(&conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            obrien.get("name"),
+            Some(&Value::Text("O'Brien".to_string()))
+        );
+
+        let updated = conn
+            .execute(
+                "UPDATE users SET age = 31 WHERE name = 'Ann' OR (age IS NULL AND id IN (3, 4))",
+            )
+            .unwrap();
+        assert_eq!(updated, 2);
+        assert_eq!(
+            SelectQuery::new("users")
+                .filter(Column::new("age").eq(31))
+                .count(&conn)
+                .unwrap(),
+            2
+        );
+
+        assert_eq!(
+            conn.execute("DELETE FROM users WHERE age >= 40;").unwrap(),
+            1
+        );
+        assert_eq!(
+            conn.execute("DELETE FROM users WHERE NOT id <> 1").unwrap(),
+            1
+        );
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_migration_run_changes_store() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        Migration::new()
+            .create_table(
+                "posts",
+                vec![("id", "INTEGER PRIMARY KEY"), ("title", "VARCHAR(255)")],
+            )
+            .run(&conn)
+            .unwrap();
+        conn.execute("INSERT INTO posts (id, title) VALUES (1, 'Hello')")
+            .unwrap();
+
+        Migration::new()
+            .add_column("posts", "views", "INTEGER")
+            .remove_column("posts", "title")
+            .run(&conn)
+            .unwrap();
+        let post = SelectQuery::new("posts").first(&conn).unwrap().unwrap();
+        assert_eq!(post.get("views"), Some(&Value::Null));
+        assert_eq!(post.get("title"), None);
+
+        Migration::new().drop_table("posts").run(&conn).unwrap();
+        assert!(conn.execute("DROP TABLE posts").is_err());
+        assert_eq!(conn.execute("DROP TABLE IF EXISTS posts").unwrap(), 0);
+        assert!(conn
+            .execute("ALTER TABLE posts ADD COLUMN x INTEGER")
+            .is_err());
+    }
+
+    #[test]
+    fn test_execute_rejects_malformed_sql() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        conn.execute("CREATE TABLE t (id INTEGER)").unwrap();
+        assert!(conn.execute("CREATE TABLE t (id INTEGER)").is_err());
+        assert_eq!(
+            conn.execute("CREATE TABLE IF NOT EXISTS t (id INTEGER)")
+                .unwrap(),
+            0
+        );
+
+        let err = conn
+            .execute("INSERT INTO t (id) VALUES (1, 2)")
+            .unwrap_err();
+        assert!(err.to_string().contains("1 columns but 2 values"));
+        let err = conn.execute("DELETE FROM t WHERE id = = 1").unwrap_err();
+        assert_eq!(
+            err,
+            DieselError::SyntaxError("syntax error at or near \"=\"".to_string())
+        );
+        assert!(conn.execute("UPDATE t SET name = 'unterminated").is_err());
+
+        // Statements outside the supported subset are accepted as no-ops
+        assert_eq!(conn.execute("CREATE INDEX idx_t_id ON t(id)").unwrap(), 0);
+        assert_eq!(conn.execute("VACUUM").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_execute_fires_row_hooks() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        let fired = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&fired);
+        conn.on_insert("events", move |_| {
+            counter.fetch_add(1, AtomicOrdering::SeqCst);
+        });
+        conn.execute("INSERT INTO events (kind) VALUES ('a'), ('b')")
+            .unwrap();
+        assert_eq!(fired.load(AtomicOrdering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_row_typed_accessors() {
+        let mut row = Row::new();
+        row.set("id", Value::BigInt(7));
+        row.set("age", Value::Integer(30));
+        row.set("name", Value::from("Ann"));
+        row.set("score", Value::Float(9.5));
+        row.set("active", Value::Boolean(true));
+        row.set("nickname", Value::Null);
+
+        assert_eq!(row.get_as::<i32>("age"), Ok(30));
+        assert_eq!(row.get_i64("age"), Ok(30));
+        assert_eq!(row.get_i32("id"), Ok(7));
+        assert_eq!(row.get_f64("age"), Ok(30.0));
+        assert_eq!(row.get_f64("score"), Ok(9.5));
+        assert_eq!(row.get_string("name"), Ok("Ann".to_string()));
+        assert_eq!(row.get_bool("active"), Ok(true));
+        assert_eq!(row.get_as::<Option<String>>("nickname"), Ok(None));
+        assert_eq!(
+            row.get_as::<Option<String>>("name"),
+            Ok(Some("Ann".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_row_typed_accessor_errors() {
+        let mut row = Row::new();
+        row.set("name", Value::from("Ann"));
+        row.set("nickname", Value::Null);
+        row.set("big", Value::BigInt(i64::MAX));
+
+        assert_eq!(
+            row.get_i32("name"),
+            Err(DieselError::DeserializationError(
+                "column name has type Text, expected Integer".to_string()
+            ))
+        );
+        assert_eq!(
+            row.get_string("nickname"),
+            Err(DieselError::DeserializationError(
+                "column nickname is NULL, expected Text".to_string()
+            ))
+        );
+        assert_eq!(
+            row.get_bool("missing"),
+            Err(DieselError::NotFound("column missing".to_string()))
+        );
+        assert!(row.get_i32("big").is_err());
+        assert!(row.get_as::<Option<bool>>("name").is_err());
+    }
+
+    #[test]
+    fn test_row_columns_and_into_map() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let row = SelectQuery::new("users")
+            .filter(Column::new("id").eq(2))
+            .first(&conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.columns(), vec!["id", "name"]);
+
+        let map = row.into_map();
+        assert_eq!(map.get("name"), Some(&Value::from("Bob")));
+        assert_eq!(map.len(), 2);
+    }
+
+    fn create_accounts(conn: &Connection) {
+        Migration::new()
+            .create_table(
+                "accounts",
+                vec![
+                    ("id", "INTEGER PRIMARY KEY"),
+                    ("email", "VARCHAR(255) NOT NULL"),
+                    ("role", "TEXT DEFAULT 'member'"),
+                    ("credits", "INTEGER NOT NULL DEFAULT (0)"),
+                    ("note", "TEXT"),
+                ],
+            )
+            .run(conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_insert_fills_column_defaults() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        create_accounts(&conn);
+        InsertQuery::new("accounts")
+            .value("id", Value::Integer(1))
+            .value("email", Value::from("a@example.com"))
+            .execute(&conn)
+            .unwrap();
+
+        let account = SelectQuery::new("accounts").first(&conn).unwrap().unwrap();
+        assert_eq!(account.get("role"), Some(&Value::from("member")));
+        assert_eq!(account.get("credits"), Some(&Value::Integer(0)));
+        assert_eq!(account.get("note"), Some(&Value::Null));
+    }
+
+    #[test]
+    fn test_not_null_columns_are_enforced() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        create_accounts(&conn);
+
+        let err = InsertQuery::new("accounts")
+            .value("id", Value::Integer(1))
+            .execute(&conn)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            DieselError::ConstraintViolation {
+                kind: ConstraintKind::NotNull,
+                table: "accounts".to_string(),
+                column: Some("email".to_string()),
+            }
+        );
+        let err = conn
+            .execute("INSERT INTO accounts (id, email, credits) VALUES (2, 'b@example.com', NULL)")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "NOT NULL constraint failed: accounts.credits"
+        );
+        assert_eq!(Table::new("accounts").count(&conn).unwrap(), 0);
+
+        conn.execute("INSERT INTO accounts (id, email) VALUES (3, 'c@example.com')")
+            .unwrap();
+        let err = UpdateQuery::new("accounts")
+            .set("email", Value::Null)
+            .execute(&conn)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "NOT NULL constraint failed: accounts.email"
+        );
+        // Nullable columns accept an explicit NULL even when they have a default
+        UpdateQuery::new("accounts")
+            .set("role", Value::Null)
+            .execute(&conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_add_column_applies_default_to_existing_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        create_accounts(&conn);
+        conn.execute("INSERT INTO accounts (id, email) VALUES (1, 'a@example.com')")
+            .unwrap();
+
+        Migration::new()
+            .add_column("accounts", "active", "BOOLEAN NOT NULL DEFAULT TRUE")
+            .run(&conn)
+            .unwrap();
+        let account = SelectQuery::new("accounts").first(&conn).unwrap().unwrap();
+        assert_eq!(account.get("active"), Some(&Value::Boolean(true)));
+
+        let err = conn
+            .execute("ALTER TABLE accounts ADD COLUMN region TEXT NOT NULL")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "NOT NULL constraint failed: accounts.region"
+        );
+    }
+
+    #[test]
+    fn test_current_timestamp_default() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        conn.execute("CREATE TABLE audit (id INTEGER, at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, seen TIMESTAMP DEFAULT now())")
+            .unwrap();
+        conn.execute("INSERT INTO audit (id) VALUES (1)").unwrap();
+        let row = SelectQuery::new("audit").first(&conn).unwrap().unwrap();
+        assert!(matches!(row.get("at"), Some(Value::Timestamp(micros)) if *micros > 0));
+        assert!(matches!(row.get("seen"), Some(Value::Timestamp(_))));
+    }
+
+    fn seeded_postgres() -> Connection {
+        let conn = Connection::establish_postgres("postgres://localhost/app").unwrap();
+        seed_users_and_orders(&conn);
+        conn
+    }
+
+    fn user_name(conn: &Connection, id: i32) -> String {
+        SelectQuery::new("users")
+            .filter(Column::new("id").eq(id))
+            .first(conn)
+            .unwrap()
+            .unwrap()
+            .get_string("name")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_for_update_sql() {
+        let query = SelectQuery::new("jobs").filter(Column::new("id").eq(1));
+        assert_eq!(
+            query.clone().for_update().to_sql(),
+            "SELECT * FROM jobs WHERE id = 1 FOR UPDATE"
+        );
+        assert_eq!(
+            query.clone().limit(1).no_wait().to_sql(),
+            "SELECT * FROM jobs WHERE id = 1 LIMIT 1 FOR UPDATE NOWAIT"
+        );
+        assert_eq!(
+            query.skip_locked().to_sql(),
+            "SELECT * FROM jobs WHERE id = 1 FOR UPDATE SKIP LOCKED"
+        );
+
+        let sqlite = Connection::establish_sqlite(":memory:").unwrap();
+        let err = SelectQuery::new("jobs")
+            .for_update()
+            .load(&sqlite)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "FOR UPDATE is not supported by sqlite");
+    }
+
+    #[test]
+    fn test_for_update_blocks_concurrent_writers_until_commit() {
+        let conn = seeded_postgres();
+        let tx = conn.begin_transaction().unwrap();
+        let locked = SelectQuery::new("users")
+            .filter(Column::new("id").eq(1))
+            .for_update()
+            .load(tx.connection())
+            .unwrap();
+        assert_eq!(locked.len(), 1);
+
+        thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                UpdateQuery::new("users")
+                    .set("name", Value::from("Writer"))
+                    .filter(Column::new("id").eq(1))
+                    .execute(&conn)
+            });
+            thread::sleep(Duration::from_millis(100));
+            assert!(!writer.is_finished());
+
+            // Rows the transaction did not lock stay writable
+            UpdateQuery::new("users")
+                .set("name", Value::from("Bobby"))
+                .filter(Column::new("id").eq(2))
+                .execute(&conn)
+                .unwrap();
+
+            UpdateQuery::new("users")
+                .set("name", Value::from("Holder"))
+                .filter(Column::new("id").eq(1))
+                .execute(tx.connection())
+                .unwrap();
+            assert_eq!(user_name(&conn, 1), "Holder");
+            tx.commit().unwrap();
+            assert_eq!(writer.join().unwrap(), Ok(1));
+        });
+        assert_eq!(user_name(&conn, 1), "Writer");
+        assert_eq!(user_name(&conn, 2), "Bobby");
+    }
+
+    #[test]
+    fn test_for_update_no_wait_and_skip_locked() {
+        let conn = seeded_postgres();
+        let first = conn.begin_transaction().unwrap();
+        let claimed = SelectQuery::new("users")
+            .order_by("id", "ASC")
+            .limit(1)
+            .skip_locked()
+            .load(first.connection())
+            .unwrap();
+        assert_eq!(claimed[0].get_i32("id"), Ok(1));
+
+        // A second worker claims the next unlocked row
+        let second = conn.begin_transaction().unwrap();
+        let claimed = SelectQuery::new("users")
+            .order_by("id", "ASC")
+            .limit(1)
+            .skip_locked()
+            .load(second.connection())
+            .unwrap();
+        assert_eq!(claimed[0].get_i32("id"), Ok(2));
+
+        let err = SelectQuery::new("users")
+            .filter(Column::new("id").le(2))
+            .no_wait()
+            .load(&conn)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            DieselError::LockNotAvailable(Some("users".to_string()))
+        );
+        assert_eq!(
+            err.to_string(),
+            "could not obtain lock on row in relation \"users\""
+        );
+
+        first.rollback().unwrap();
+        drop(second);
+        assert_eq!(
+            SelectQuery::new("users")
+                .no_wait()
+                .load(&conn)
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_conflicting_row_locks_detect_deadlock() {
+        let conn = seeded_postgres();
+        let by_id = |id: i32| {
+            SelectQuery::new("users")
+                .filter(Column::new("id").eq(id))
+                .for_update()
+        };
+        let first = conn.begin_transaction().unwrap();
+        let second = conn.begin_transaction().unwrap();
+        by_id(1).load(first.connection()).unwrap();
+        by_id(2).load(second.connection()).unwrap();
+
+        thread::scope(|scope| {
+            let waiter = scope.spawn(|| by_id(2).load(first.connection()).map(|rows| rows.len()));
+            thread::sleep(Duration::from_millis(100));
+
+            let err = by_id(1).load(second.connection()).unwrap_err();
+            assert_eq!(err, DieselError::Deadlock);
+            second.rollback().unwrap();
+            assert_eq!(waiter.join().unwrap(), Ok(1));
+        });
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        create_accounts(&conn);
+        seed_users_and_orders(&conn);
+        let seeded = conn.snapshot();
+        assert_eq!(seeded.database(), "main");
+        assert_eq!(seeded.row_count("users"), 3);
+
+        UpdateQuery::new("users")
+            .set("name", Value::from("Changed"))
+            .execute(&conn)
+            .unwrap();
+        DeleteQuery::new("orders").execute(&conn).unwrap();
+        conn.execute("DROP TABLE accounts").unwrap();
+        // Later writes do not leak into the snapshot
+        assert_eq!(seeded.row_count("orders"), 3);
+
+        conn.restore(&seeded);
+        assert_eq!(user_name(&conn, 1), "Alice");
+        assert_eq!(Table::new("orders").count(&conn).unwrap(), 3);
+        // Schemas are restored along with rows
+        let err = conn
+            .execute("INSERT INTO accounts (id) VALUES (1)")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "NOT NULL constraint failed: accounts.email"
+        );
+
+        // The same snapshot can reset the database again
+        InsertQuery::new("users")
+            .value("id", Value::Integer(4))
+            .execute(&conn)
+            .unwrap();
+        conn.clone().restore(&seeded);
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_restore_into_another_database() {
+        let mut conn = Connection::establish_postgres("postgres://localhost/template").unwrap();
+        seed_users_and_orders(&conn);
+        let template = conn.snapshot();
+
+        conn.use_database("test_1");
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 0);
+        conn.restore(&template);
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 3);
+
+        DeleteQuery::new("users").execute(&conn).unwrap();
+        conn.use_database("template");
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_returning_sql() {
+        let update = UpdateQuery::new("users")
+            .set("name", Value::from("Ann"))
+            .filter(Column::new("id").eq(1))
+            .returning(vec!["id", "name"]);
+        assert_eq!(
+            update.to_sql(),
+            "UPDATE users SET name = Ann WHERE id = 1 RETURNING id, name"
+        );
+        let delete = DeleteQuery::new("users")
+            .soft("deleted_at")
+            .returning(vec!["id"]);
+        assert_eq!(
+            delete.to_sql(),
+            "UPDATE users SET deleted_at = CURRENT_TIMESTAMP WHERE deleted_at IS NULL RETURNING id"
+        );
+    }
+
+    #[test]
+    fn test_update_returning_rows() {
+        let conn = seeded_postgres();
+        let rows = UpdateQuery::new("orders")
+            .set("total", Value::Integer(0))
+            .filter(Column::new("user_id").eq(1))
+            .returning(vec!["id", "total"])
+            .get_results(&conn)
+            .unwrap();
+        let ids: Vec<_> = rows.iter().map(|r| r.get_i32("id").unwrap()).collect();
+        assert_eq!(ids, vec![10, 11]);
+        assert!(rows.iter().all(|r| r.get_i32("total") == Ok(0)));
+        assert_eq!(rows[0].columns(), vec!["id", "total"]);
+
+        // Without `returning`, every column comes back
+        let rows = UpdateQuery::new("users")
+            .set("name", Value::from("Bea"))
+            .filter(Column::new("id").eq(2))
+            .get_results(&conn)
+            .unwrap();
+        assert_eq!(rows[0].columns(), vec!["id", "name"]);
+    }
+
+    #[test]
+    fn test_delete_returning_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let deleted = DeleteQuery::new("orders")
+            .filter(Column::new("total").gt(100))
+            .returning(vec!["id"])
+            .get_results(&conn)
+            .unwrap();
+        let ids: Vec<_> = deleted.iter().map(|r| r.get_i32("id").unwrap()).collect();
+        assert_eq!(ids, vec![11, 12]);
+        assert_eq!(Table::new("orders").count(&conn).unwrap(), 1);
+
+        let trashed = DeleteQuery::new("users")
+            .soft("deleted_at")
+            .filter(Column::new("id").eq(3))
+            .get_results(&conn)
+            .unwrap();
+        assert!(matches!(
+            trashed[0].get("deleted_at"),
+            Some(Value::Timestamp(_))
+        ));
+    }
+
+    #[test]
+    fn test_returning_is_not_supported_by_mysql() {
+        let conn = Connection::establish_mysql("mysql://localhost/app").unwrap();
+        seed_users_and_orders(&conn);
+        let err = DeleteQuery::new("users")
+            .returning(vec!["id"])
+            .get_results(&conn)
+            .unwrap_err();
+        assert!(matches!(err, DieselError::Unsupported(_)));
+        assert_eq!(Table::new("users").count(&conn).unwrap(), 3);
+    }
+
+    /// A tree-shaped format so query plans can be read back in tests
+    #[derive(Debug, Clone, PartialEq)]
+    enum Tree {
+        Bool(bool),
+        Int(i64),
+        Float(f64),
+        Str(String),
+        None,
+        Seq(Vec<Tree>),
+        Map(Vec<(Tree, Tree)>),
+    }
+
+    struct TreeSerializer;
+
+    struct TreeSeq(Vec<Tree>);
+
+    struct TreeMap(Vec<(Tree, Tree)>, Option<Tree>);
+
+    impl serde_emulator::Serializer for TreeSerializer {
+        type Ok = Tree;
+        type Error = String;
+        type SerializeSeq = TreeSeq;
+        type SerializeTuple = TreeSeq;
+        type SerializeMap = TreeMap;
+        type SerializeStruct = TreeMap;
+
+        fn serialize_bool(self, v: bool) -> Result<Tree, String> {
+            Ok(Tree::Bool(v))
+        }
+
+        fn serialize_i8(self, v: i8) -> Result<Tree, String> {
+            Ok(Tree::Int(v.into()))
+        }
+
+        fn serialize_i16(self, v: i16) -> Result<Tree, String> {
+            Ok(Tree::Int(v.into()))
+        }
+
+        fn serialize_i32(self, v: i32) -> Result<Tree, String> {
+            Ok(Tree::Int(v as i64))
+        }
+
+        fn serialize_i64(self, v: i64) -> Result<Tree, String> {
+            Ok(Tree::Int(v))
+        }
+
+        fn serialize_i128(self, v: i128) -> Result<Tree, String> {
+            v.try_into().map(Tree::Int).map_err(|e| format!("{}", e))
+        }
+
+        fn serialize_u8(self, v: u8) -> Result<Tree, String> {
+            Ok(Tree::Int(v.into()))
+        }
+
+        fn serialize_u16(self, v: u16) -> Result<Tree, String> {
+            Ok(Tree::Int(v.into()))
+        }
+
+        fn serialize_u32(self, v: u32) -> Result<Tree, String> {
+            Ok(Tree::Int(v.into()))
+        }
+
+        fn serialize_u64(self, v: u64) -> Result<Tree, String> {
+            v.try_into().map(Tree::Int).map_err(|e| format!("{}", e))
+        }
+
+        fn serialize_u128(self, v: u128) -> Result<Tree, String> {
+            v.try_into().map(Tree::Int).map_err(|e| format!("{}", e))
+        }
+
+        fn serialize_f32(self, v: f32) -> Result<Tree, String> {
+            Ok(Tree::Float(v.into()))
+        }
+
+        fn serialize_f64(self, v: f64) -> Result<Tree, String> {
+            Ok(Tree::Float(v))
+        }
+
+        fn serialize_char(self, v: char) -> Result<Tree, String> {
+            Ok(Tree::Str(v.to_string()))
+        }
+
+        fn serialize_str(self, v: &str) -> Result<Tree, String> {
+            Ok(Tree::Str(v.to_string()))
+        }
+
+        fn serialize_bytes(self, v: &[u8]) -> Result<Tree, String> {
+            Ok(Tree::Seq(v.iter().map(|&b| Tree::Int(b.into())).collect()))
+        }
+
+        fn serialize_unit(self) -> Result<Tree, String> {
+            Ok(Tree::None)
+        }
+
+        fn serialize_none(self) -> Result<Tree, String> {
+            Ok(Tree::None)
+        }
+
+        fn serialize_some<T: serde_emulator::Serialize>(self, value: &T) -> Result<Tree, String> {
+            value.serialize(self)
+        }
+
+        fn serialize_seq(self, _len: Option<usize>) -> Result<TreeSeq, String> {
+            Ok(TreeSeq(Vec::new()))
+        }
+
+        fn serialize_tuple(self, len: usize) -> Result<TreeSeq, String> {
+            Ok(TreeSeq(Vec::with_capacity(len)))
+        }
+
+        fn serialize_map(self, _len: Option<usize>) -> Result<TreeMap, String> {
+            Ok(TreeMap(Vec::new(), None))
+        }
+
+        fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<TreeMap, String> {
+            Ok(TreeMap(Vec::new(), None))
+        }
+    }
+
+    impl serde_emulator::SerializeSeq for TreeSeq {
+        type Ok = Tree;
+        type Error = String;
+
+        fn serialize_element<T: serde_emulator::Serialize>(
+            &mut self,
+            value: &T,
+        ) -> Result<(), String> {
+            self.0.push(value.serialize(TreeSerializer)?);
+            Ok(())
+        }
+
+        fn end(self) -> Result<Tree, String> {
+            Ok(Tree::Seq(self.0))
+        }
+    }
+
+    impl serde_emulator::SerializeTuple for TreeSeq {
+        type Ok = Tree;
+        type Error = String;
+
+        fn serialize_element<T: serde_emulator::Serialize>(
+            &mut self,
+            value: &T,
+        ) -> Result<(), String> {
+            serde_emulator::SerializeSeq::serialize_element(self, value)
+        }
+
+        fn end(self) -> Result<Tree, String> {
+            serde_emulator::SerializeSeq::end(self)
+        }
+    }
+
+    impl serde_emulator::SerializeMap for TreeMap {
+        type Ok = Tree;
+        type Error = String;
+
+        fn serialize_key<T: serde_emulator::Serialize>(&mut self, key: &T) -> Result<(), String> {
+            self.1 = Some(key.serialize(TreeSerializer)?);
+            Ok(())
+        }
+
+        fn serialize_value<T: serde_emulator::Serialize>(
+            &mut self,
+            value: &T,
+        ) -> Result<(), String> {
+            let key = self.1.take().ok_or("value without a key")?;
+            self.0.push((key, value.serialize(TreeSerializer)?));
+            Ok(())
+        }
+
+        fn end(self) -> Result<Tree, String> {
+            Ok(Tree::Map(self.0))
+        }
+    }
+
+    impl serde_emulator::SerializeStruct for TreeMap {
+        type Ok = Tree;
+        type Error = String;
+
+        fn serialize_field<T: serde_emulator::Serialize>(
+            &mut self,
+            key: &'static str,
+            value: &T,
+        ) -> Result<(), String> {
+            let key = Tree::Str(key.to_string());
+            self.0.push((key, value.serialize(TreeSerializer)?));
+            Ok(())
+        }
+
+        fn end(self) -> Result<Tree, String> {
+            Ok(Tree::Map(self.0))
+        }
+    }
+
+    struct TreeElements(std::vec::IntoIter<Tree>);
+
+    struct TreeEntries(std::vec::IntoIter<(Tree, Tree)>, Option<Tree>);
+
+    impl<'de> serde_emulator::Deserializer<'de> for Tree {
+        type Error = String;
+
+        fn deserialize_any<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            match self {
+                Tree::Bool(v) => visitor.visit_bool(v),
+                Tree::Int(v) => visitor.visit_i64(v),
+                Tree::Float(v) => visitor.visit_f64(v),
+                Tree::Str(v) => visitor.visit_string(v),
+                Tree::None => visitor.visit_none(),
+                Tree::Seq(items) => visitor.visit_seq(TreeElements(items.into_iter())),
+                Tree::Map(entries) => visitor.visit_map(TreeEntries(entries.into_iter(), None)),
+            }
+        }
+
+        fn deserialize_bool<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_i32<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_i64<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_f64<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_str<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_string<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_i8<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_i16<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_i128<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_u8<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_u16<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_u32<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_u64<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_u128<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_f32<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_char<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_bytes<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_unit<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_option<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            match self {
+                Tree::None => visitor.visit_none(),
+                other => visitor.visit_some(other),
+            }
+        }
+
+        fn deserialize_seq<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_tuple<V: serde_emulator::Visitor<'de>>(
+            self,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+
+        fn deserialize_map<V: serde_emulator::Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, String> {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    impl<'de> serde_emulator::SeqAccess<'de> for TreeElements {
+        type Error = String;
+
+        fn next_element_seed<T: serde_emulator::DeserializeSeed<'de>>(
+            &mut self,
+            seed: T,
+        ) -> Result<Option<T::Value>, String> {
+            self.0.next().map(|item| seed.deserialize(item)).transpose()
+        }
+    }
+
+    impl<'de> serde_emulator::MapAccess<'de> for TreeEntries {
+        type Error = String;
+
+        fn next_key_seed<K: serde_emulator::DeserializeSeed<'de>>(
+            &mut self,
+            seed: K,
+        ) -> Result<Option<K::Value>, String> {
+            let Some((key, value)) = self.0.next() else {
+                return Ok(None);
+            };
+            self.1 = Some(value);
+            seed.deserialize(key).map(Some)
+        }
+
+        fn next_value_seed<V: serde_emulator::DeserializeSeed<'de>>(
+            &mut self,
+            seed: V,
+        ) -> Result<V::Value, String> {
+            seed.deserialize(self.1.take().ok_or("value without a key")?)
+        }
+    }
+
+    fn round_trip<T>(query: &T) -> T
+    where
+        T: serde_emulator::Serialize + for<'de> serde_emulator::Deserialize<'de>,
+    {
+        T::deserialize(query.serialize(TreeSerializer).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_select_plan_to_json() {
+        let query = SelectQuery::new("users")
+            .select(vec!["id", "name"])
+            .filter(Column::new("age").gt(Value::Integer(30)))
+            .order_by("name", "ASC")
+            .limit(5);
+
+        let json = serde_emulator::to_json(&query).unwrap();
+        assert!(json
+            .starts_with(r#"{"table": "users", "from_subquery": null, "columns": ["id", "name"]"#));
+        assert!(json.contains(
+            r#""where_clause": {"Compare": [{"Column": "age"}, "Gt", {"Literal": {"Integer": 30}}]}"#
+        ));
+        assert!(json.contains(r#""order_by": ["name", "ASC"], "limit": 5, "offset": null"#));
+        assert!(json.ends_with(
+            r#""distinct": "None", "soft_delete": null, "row_lock": null, "timeout": null}"#
+        ));
+    }
+
+    #[test]
+    fn test_insert_plan_json_is_stable() {
+        let query = InsertQuery::new("users")
+            .value("name", Value::Text("Dana".to_string()))
+            .value("id", Value::Integer(4))
+            .value("active", Value::Boolean(true));
+
+        // Values are written in column order, not insertion order
+        let json = serde_emulator::to_json(&query).unwrap();
+        assert_eq!(
+            json,
+            r#"{"table": "users", "values": {"active": {"Boolean": true}, "id": {"Integer": 4}, "name": {"Text": "Dana"}}}"#
+        );
+    }
+
+    #[test]
+    fn test_query_plans_round_trip() {
+        let select = SelectQuery::new("orders")
+            .filter(
+                Column::new("total")
+                    .gt(Value::Integer(100))
+                    .or(Column::new("user_id").eq_any(vec![Value::Integer(1), Value::Null])),
+            )
+            .distinct()
+            .offset(2)
+            .for_update()
+            .skip_locked();
+        assert_eq!(round_trip(&select).to_sql(), select.to_sql());
+
+        let nested = SelectQuery::new("users")
+            .filter(Column::new("id").eq_any(SelectQuery::new("orders").select(vec!["user_id"])));
+        assert_eq!(round_trip(&nested).to_sql(), nested.to_sql());
+
+        let update = UpdateQuery::new("users")
+            .set("name", Value::Text("Eve".to_string()))
+            .filter(Column::new("id").eq(Value::BigInt(2)))
+            .returning(vec!["id", "name"]);
+        assert_eq!(round_trip(&update).to_sql(), update.to_sql());
+
+        let delete = DeleteQuery::new("users").filter(
+            Column::new("name")
+                .is_not_null()
+                .and(Column::new("name").like("E%")),
+        );
+        assert_eq!(round_trip(&delete).to_sql(), delete.to_sql());
+
+        let insert = InsertQuery::new("users").value("score", Value::Float(1.5));
+        assert_eq!(round_trip(&insert).to_sql(), insert.to_sql());
+    }
+
+    #[test]
+    fn test_query_plan_keeps_timeout() {
+        let query = SelectQuery::new("events").timeout(Duration::from_millis(250));
+        let json = serde_emulator::to_json(&query).unwrap();
+        assert!(json.ends_with(r#""timeout": 250000}"#));
+        assert_eq!(round_trip(&query).timeout, Some(Duration::from_millis(250)));
+
+        // Plans saved without a timeout read back without one
+        let query: SelectQuery = serde_emulator::from_json(r#"{"table": "events"}"#).unwrap();
+        assert_eq!(query.timeout, None);
+        assert_eq!(
+            round_trip(&SelectQuery::new("events").timeout(Duration::from_nanos(1))).timeout,
+            Some(Duration::from_micros(1))
+        );
+    }
+
+    #[test]
+    fn test_replayed_plan_runs_against_connection() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+
+        let captured =
+            SelectQuery::new("orders").filter(Column::new("user_id").eq(Value::Integer(1)));
+        let replayed = round_trip(&captured);
+        assert_eq!(replayed.load(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_view_reruns_query() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        Migration::new()
+            .create_view(
+                "big_orders",
+                SelectQuery::new("orders").filter(Column::new("total").gt(Value::Integer(100))),
+            )
+            .run(&conn)
+            .unwrap();
+
+        let big = SelectQuery::new("big_orders").order_by("id", "ASC");
+        let ids: Vec<_> = big
+            .load(&conn)
+            .unwrap()
+            .iter()
+            .map(|r| r.get_i32("id").unwrap())
+            .collect();
+        assert_eq!(ids, vec![11, 12]);
+
+        InsertQuery::new("orders")
+            .value("id", Value::Integer(13))
+            .value("user_id", Value::Integer(2))
+            .value("total", Value::Integer(500))
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(big.count(&conn).unwrap(), 3);
+        let bob = big
+            .clone()
+            .filter(Column::new("user_id").eq(Value::Integer(2)));
+        assert_eq!(bob.load(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_views_compose_with_subqueries() {
+        let conn = Connection::establish_postgres("postgres://localhost/shop").unwrap();
+        seed_users_and_orders(&conn);
+        Migration::new()
+            .create_view(
+                "big_orders",
+                SelectQuery::new("orders").filter(Column::new("total").gt(Value::Integer(100))),
+            )
+            .create_view(
+                "big_spenders",
+                SelectQuery::new("users").filter(
+                    Column::new("id")
+                        .eq_any(SelectQuery::new("big_orders").select(vec!["user_id"])),
+                ),
+            )
+            .run(&conn)
+            .unwrap();
+
+        let names: Vec<_> = SelectQuery::new("big_spenders")
+            .order_by("name", "ASC")
+            .load(&conn)
+            .unwrap()
+            .iter()
+            .map(|r| r.get_string("name").unwrap())
+            .collect();
+        assert_eq!(names, vec!["Alice", "Carol"]);
+
+        let locked = SelectQuery::new("big_spenders").for_update().load(&conn);
+        assert_eq!(
+            locked.unwrap_err().to_string(),
+            "FOR UPDATE is only allowed on a plain table scan"
+        );
+    }
+
+    #[test]
+    fn test_materialized_view_refresh() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        Migration::new()
+            .create_materialized_view(
+                "alice_orders",
+                SelectQuery::new("orders").filter(Column::new("user_id").eq(Value::Integer(1))),
+            )
+            .run(&conn)
+            .unwrap();
+
+        let view = SelectQuery::new("alice_orders");
+        assert_eq!(view.count(&conn).unwrap(), 2);
+
+        DeleteQuery::new("orders")
+            .filter(Column::new("id").eq(Value::Integer(10)))
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(view.count(&conn).unwrap(), 2, "stale until refreshed");
+
+        assert_eq!(conn.refresh_materialized_view("alice_orders").unwrap(), 1);
+        assert_eq!(view.count(&conn).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_view_errors_and_drop() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed_users_and_orders(&conn);
+        let everyone = || SelectQuery::new("users");
+        Migration::new()
+            .create_view("people", everyone())
+            .run(&conn)
+            .unwrap();
+
+        let duplicate = Migration::new()
+            .create_view("people", everyone())
+            .run(&conn);
+        assert_eq!(
+            duplicate.unwrap_err(),
+            DieselError::AlreadyExists("view people".to_string())
+        );
+        let shadowing = Migration::new()
+            .create_materialized_view("users", everyone())
+            .run(&conn);
+        assert_eq!(
+            shadowing.unwrap_err().to_string(),
+            "table users already exists"
+        );
+        assert_eq!(
+            conn.refresh_materialized_view("people").unwrap_err(),
+            DieselError::NotFound("materialized view people".to_string())
+        );
+
+        Migration::new().drop_view("people").run(&conn).unwrap();
+        assert!(SelectQuery::new("people").load(&conn).unwrap().is_empty());
+        assert_eq!(
+            conn.execute("DROP VIEW people").unwrap_err().to_string(),
+            "view people does not exist"
+        );
+        assert_eq!(conn.execute("DROP VIEW IF EXISTS people").unwrap(), 0);
+    }
+
+    fn timestamp(text: &str) -> Value {
+        Value::parse_timestamp(text).unwrap()
+    }
+
+    /// Sessions 1-3 created a day apart, on a connection whose clock is
+    /// fixed at noon on 2024-03-04
+    fn sessions_at_noon() -> (Connection, Clock) {
+        let clock = Clock::fixed("2024-03-04 12:00:00").unwrap();
+        let conn = Connection::establish_postgres("postgres://localhost/app")
+            .unwrap()
+            .with_clock(clock.clone());
+        for (id, created) in [
+            (1, "2024-03-01 09:30:00"),
+            (2, "2024-03-02 18:45:10"),
+            (3, "2024-03-03 23:59:59"),
+        ] {
+            InsertQuery::new("sessions")
+                .value("id", Value::Integer(id))
+                .value("created_at", timestamp(created))
+                .execute(&conn)
+                .unwrap();
+        }
+        (conn, clock)
+    }
+
+    #[test]
+    fn test_timestamp_and_interval_values() {
+        let ts = timestamp("2024-02-29 08:05:03.25");
+        assert_eq!(ts, Value::Timestamp(1_709_193_903_250_000));
+        assert_eq!(ts.to_string(), "2024-02-29 08:05:03.25");
+        assert_eq!(
+            timestamp("1969-12-31").to_sql_literal(),
+            "TIMESTAMP '1969-12-31 00:00:00'"
+        );
+        assert!(Value::parse_timestamp("2023-02-29").is_err());
+
+        let interval = Interval::parse("1 year 2 mons 3 days 04:05:06").unwrap();
+        assert_eq!(interval.to_string(), "1 year 2 mons 3 days 04:05:06");
+        assert_eq!(
+            Interval::parse("90 minutes").unwrap(),
+            Interval::minutes(90).unwrap()
+        );
+        assert_eq!(
+            Value::from(Interval::days(1)).to_sql_literal(),
+            "INTERVAL '1 day'"
+        );
+        assert_eq!(
+            Value::from(Interval::hours(36).unwrap()).compare(&Value::from(Interval::days(1))),
+            Some(Ordering::Greater)
+        );
+    }
+
+    #[test]
+    fn test_interval_overflow_is_an_error() {
+        let out_of_range = Err(DieselError::InvalidValue(
+            "interval out of range".to_string(),
+        ));
+        assert_eq!(Interval::years(i32::MAX / 2), out_of_range);
+        assert_eq!(Interval::weeks(i32::MIN), out_of_range);
+        assert_eq!(Interval::hours(i64::MAX / 60), out_of_range);
+        assert_eq!(Interval::seconds(i64::MIN / 1000), out_of_range);
+        assert_eq!(Interval::hours(2), Interval::minutes(120));
+
+        for text in [
+            "99999999999999 hours",
+            "9223372036854775807 seconds",
+            "9999999999999:00:00",
+            "2147483647 days 1 day",
+            "200000000 years",
+            "2562047788 hours 2562047788 hours",
+        ] {
+            assert_eq!(
+                Interval::parse(text),
+                Err(DieselError::InvalidValue(format!(
+                    "invalid interval \"{}\"",
+                    text
+                ))),
+                "{}",
+                text
+            );
+        }
+        assert!(Value::parse_timestamp("99999999999-01-01").is_err());
+
+        // Arithmetic that overflows evaluates to NULL, like other bad math
+        let huge = Value::from(Interval::days(i32::MAX));
+        let day = Value::from(Interval::days(1));
+        assert_eq!(huge.checked_add(&day, false), None);
+        assert_eq!(
+            Value::from(Interval::days(i32::MIN)).checked_add(&day, true),
+            None
+        );
+        assert_eq!(
+            huge.compare(&Value::from(Interval::months(i32::MAX))),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Value::from(Interval::months(i32::MIN)).to_string(),
+            "-178956970 years -8 mons"
+        );
+    }
+
+    #[test]
+    fn test_now_in_filters_follows_the_clock() {
+        let (conn, clock) = sessions_at_noon();
+        let recent = || {
+            SelectQuery::new("sessions")
+                .filter(Expr::from(Column::new("created_at")).gt(now().minus(Interval::days(2))))
+        };
+        assert_eq!(
+            recent().to_sql(),
+            "SELECT * FROM sessions WHERE created_at > NOW() - INTERVAL '2 days'"
+        );
+        assert_eq!(recent().count(&conn).unwrap(), 2);
+
+        clock.advance(Duration::from_secs(24 * 3600));
+        assert_eq!(recent().count(&conn).unwrap(), 1);
+        assert_eq!(conn.clock.now(), timestamp("2024-03-05 12:00:00"));
+    }
+
+    #[test]
+    fn test_time_functions_in_set_clauses() {
+        let (conn, _clock) = sessions_at_noon();
+        let updated = UpdateQuery::new("sessions")
+            .set_expr("seen_at", now())
+            .set_expr(
+                "expires_at",
+                Expr::from(Column::new("created_at")).plus(Interval::months(1)),
+            )
+            .set_expr("created_day", date_trunc("day", Column::new("created_at")))
+            .filter(Column::new("id").eq(1))
+            .get_results(&conn)
+            .unwrap();
+
+        let row = &updated[0];
+        assert_eq!(row.get("seen_at"), Some(&timestamp("2024-03-04 12:00:00")));
+        assert_eq!(
+            row.get("expires_at"),
+            Some(&timestamp("2024-04-01 09:30:00"))
+        );
+        assert_eq!(row.get("created_day"), Some(&timestamp("2024-03-01")));
+    }
+
+    #[test]
+    fn test_date_trunc_and_extract() {
+        let (conn, _clock) = sessions_at_noon();
+        // 2024-03-02 is a Saturday
+        let weekend = SelectQuery::new("sessions")
+            .filter(extract("dow", Column::new("created_at")).eq(Value::Integer(6)));
+        assert_eq!(weekend.load(&conn).unwrap()[0].get_i32("id").unwrap(), 2);
+
+        let ts = timestamp("2024-05-15 13:14:15.5");
+        let eval = |expr: Expr| expr.eval(&[], &Tables::new());
+        assert_eq!(eval(extract("second", ts.clone())), Value::Float(15.5));
+        assert_eq!(eval(extract("week", ts.clone())), Value::Float(20.0));
+        assert_eq!(eval(extract("doy", ts.clone())), Value::Float(136.0));
+        assert_eq!(
+            eval(date_trunc("week", ts.clone())),
+            timestamp("2024-05-13")
+        );
+        assert_eq!(
+            eval(date_trunc("quarter", ts.clone())),
+            timestamp("2024-04-01")
+        );
+        assert_eq!(
+            eval(date_trunc("hour", ts.clone())),
+            timestamp("2024-05-15 13:00:00")
+        );
+
+        let end_of_january = Expr::from(timestamp("2023-01-31 10:00:00"));
+        assert_eq!(
+            eval(end_of_january.plus(Interval::months(1))),
+            timestamp("2023-02-28 10:00:00")
+        );
+        let elapsed = Expr::from(ts).minus(timestamp("2024-05-14 12:00:00"));
+        assert_eq!(eval(extract("epoch", elapsed)), Value::Float(90_855.5));
+    }
+
+    #[test]
+    fn test_time_functions_in_raw_sql() {
+        let (conn, _clock) = sessions_at_noon();
+        let updated = conn
+            .execute("UPDATE sessions SET expires_at = NOW() + INTERVAL '1 hour' WHERE id = 3")
+            .unwrap();
+        assert_eq!(updated, 1);
+        let row = SelectQuery::new("sessions")
+            .filter(Column::new("id").eq(3))
+            .first(&conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            row.get("expires_at"),
+            Some(&timestamp("2024-03-04 13:00:00"))
+        );
+
+        let deleted = conn
+            .execute(
+                "DELETE FROM sessions WHERE created_at < CURRENT_TIMESTAMP - INTERVAL '2 days' \
+                 OR EXTRACT(HOUR FROM created_at) = 23",
+            )
+            .unwrap();
+        assert_eq!(deleted, 2);
+        assert_eq!(SelectQuery::new("sessions").count(&conn).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_clock_drives_defaults_and_soft_deletes() {
+        let clock = Clock::fixed("2024-01-01 00:00:00").unwrap();
+        let conn = Connection::establish_sqlite(":memory:")
+            .unwrap()
+            .with_clock(clock);
+        conn.execute("CREATE TABLE audit (id INTEGER, at TIMESTAMP DEFAULT CURRENT_TIMESTAMP)")
+            .unwrap();
+        conn.execute("INSERT INTO audit (id) VALUES (1)").unwrap();
+        DeleteQuery::new("audit")
+            .soft("deleted_at")
+            .execute(&conn)
+            .unwrap();
+
+        let row = SelectQuery::new("audit").first(&conn).unwrap().unwrap();
+        assert_eq!(row.get("at"), Some(&timestamp("2024-01-01 00:00:00")));
+        assert_eq!(
+            row.get("deleted_at"),
+            Some(&timestamp("2024-01-01 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_timestamp_defaults_compare_with_now() {
+        let clock = Clock::fixed("2024-01-01 00:00:00").unwrap();
+        let conn = Connection::establish_sqlite(":memory:")
+            .unwrap()
+            .with_clock(clock.clone());
+        conn.execute("CREATE TABLE audit (id INTEGER, at TIMESTAMP DEFAULT CURRENT_TIMESTAMP)")
+            .unwrap();
+        conn.execute("INSERT INTO audit (id) VALUES (1)").unwrap();
+        clock.advance(Duration::from_secs(3 * 86_400));
+        conn.execute("INSERT INTO audit (id) VALUES (2)").unwrap();
+
+        let recent = SelectQuery::new("audit")
+            .filter("at > NOW() - INTERVAL '2 days'")
+            .load(&conn)
+            .unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].get("id"), Some(&Value::Integer(2)));
+        let older = SelectQuery::new("audit")
+            .filter(Expr::from(Column::new("at")).lt(now().minus(Interval::days(2))))
+            .count(&conn)
+            .unwrap();
+        assert_eq!(older, 1);
+
+        DeleteQuery::new("audit")
+            .soft("deleted_at")
+            .filter("id = 1")
+            .execute(&conn)
+            .unwrap();
+        let just_deleted = SelectQuery::new("audit")
+            .filter("deleted_at >= NOW()")
+            .count(&conn)
+            .unwrap();
+        assert_eq!(just_deleted, 1);
+    }
+
+    #[test]
+    fn test_check_constraints_on_insert() {
+        let conn = Connection::establish_postgres("postgres://localhost/shop").unwrap();
+        conn.execute(
+            "CREATE TABLE products (\
+                id INTEGER PRIMARY KEY, \
+                price INTEGER CHECK (price >= 0), \
+                sale_price INTEGER, \
+                CHECK (sale_price <= price), \
+                CONSTRAINT sale_is_discount CHECK (sale_price < price OR sale_price IS NULL), \
+                CHECK (id > 0))",
+        )
+        .unwrap();
+        let insert = |id: i32, price: i32, sale_price: Value| {
+            InsertQuery::new("products")
+                .value("id", Value::Integer(id))
+                .value("price", Value::Integer(price))
+                .value("sale_price", sale_price)
+                .execute(&conn)
+        };
+
+        assert_eq!(insert(1, 100, Value::Integer(80)), Ok(1));
+        // A NULL result passes the check
+        assert_eq!(insert(2, 100, Value::Null), Ok(1));
+        assert_eq!(
+            insert(3, -1, Value::Null),
+            Err(check_violation("products", "products_price_check"))
+        );
+        assert_eq!(
+            insert(4, 100, Value::Integer(120)),
+            Err(check_violation("products", "products_check"))
+        );
+        assert_eq!(
+            insert(5, 100, Value::Integer(100)),
+            Err(check_violation("products", "sale_is_discount"))
+        );
+        assert_eq!(
+            insert(0, 100, Value::Null),
+            Err(check_violation("products", "products_check1"))
+        );
+        assert_eq!(SelectQuery::new("products").count(&conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_and_or_use_three_valued_logic() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE pairs (id INTEGER, a INTEGER, b INTEGER, CHECK (a > 0 AND b > 0))",
+        )
+        .unwrap();
+        // TRUE AND NULL is NULL, which passes; FALSE AND NULL is FALSE
+        assert_eq!(
+            conn.execute("INSERT INTO pairs (id, a, b) VALUES (1, 1, NULL), (2, NULL, 5)"),
+            Ok(2)
+        );
+        assert_eq!(
+            conn.execute("INSERT INTO pairs (id, a, b) VALUES (3, -1, NULL)"),
+            Err(check_violation("pairs", "pairs_check"))
+        );
+        assert_eq!(
+            conn.execute("INSERT INTO pairs (id, a, b) VALUES (3, NULL, -1)"),
+            Err(check_violation("pairs", "pairs_check"))
+        );
+
+        let ids = |filter: Expr| -> Vec<i32> {
+            SelectQuery::new("pairs")
+                .filter(filter)
+                .order_by("id", "ASC")
+                .load(&conn)
+                .unwrap()
+                .iter()
+                .map(|row| row.get_i32("id").unwrap())
+                .collect()
+        };
+        let both = Column::new("a").gt(0).and(Column::new("b").gt(0));
+        // NOT NULL is still NULL, so neither row matches either way
+        assert_eq!(ids(both.clone()), Vec::<i32>::new());
+        assert_eq!(ids(!both), Vec::<i32>::new());
+        // TRUE OR NULL is TRUE; FALSE OR NULL is NULL
+        assert_eq!(
+            ids(Column::new("a").gt(0).or(Column::new("b").gt(0))),
+            vec![1, 2]
+        );
+        let either = Column::new("a").lt(0).or(Column::new("b").lt(0));
+        assert_eq!(ids(either.clone()), Vec::<i32>::new());
+        assert_eq!(ids(!either), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_check_constraints_on_update_are_atomic() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE wallets (id INTEGER, balance INTEGER NOT NULL CHECK (balance >= 0))",
+        )
+        .unwrap();
+        conn.execute("INSERT INTO wallets (id, balance) VALUES (1, 500), (2, 50)")
+            .unwrap();
+
+        let err = conn
+            .execute("UPDATE wallets SET balance = balance - 100")
+            .unwrap_err();
+        assert_eq!(err, check_violation("wallets", "wallets_balance_check"));
+        let balances: Vec<i32> = SelectQuery::new("wallets")
+            .order_by("id", "ASC")
+            .load(&conn)
+            .unwrap()
+            .iter()
+            .map(|row| row.get_i32("balance").unwrap())
+            .collect();
+        assert_eq!(balances, vec![500, 50]);
+
+        let withdrawn = UpdateQuery::new("wallets")
+            .set_expr(
+                "balance",
+                Expr::from(Column::new("balance")).minus(Value::Integer(100)),
+            )
+            .filter(Column::new("id").eq(1))
+            .execute(&conn);
+        assert_eq!(withdrawn, Ok(1));
+        assert!(UpdateQuery::new("wallets")
+            .set("balance", Value::Integer(-1))
+            .execute(&conn)
+            .is_err());
+    }
+
+    #[test]
+    fn test_migration_adds_and_drops_checks() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        Migration::new()
+            .create_table("line_items", vec![("id", "INTEGER"), ("qty", "INTEGER")])
+            .run(&conn)
+            .unwrap();
+        conn.execute("INSERT INTO line_items (id, qty) VALUES (1, 0)")
+            .unwrap();
+
+        let positive = Migration::new().add_check(
+            "line_items",
+            "qty_positive",
+            Column::new("qty").gt(0).and(Column::new("qty").le(1000)),
+        );
+        assert_eq!(
+            positive.run(&conn),
+            Err(check_violation("line_items", "qty_positive"))
+        );
+
+        conn.execute("DELETE FROM line_items").unwrap();
+        positive.run(&conn).unwrap();
+        assert_eq!(
+            conn.execute("INSERT INTO line_items (id, qty) VALUES (2, 1001)"),
+            Err(check_violation("line_items", "qty_positive"))
+        );
+        assert!(matches!(
+            positive.run(&conn),
+            Err(DieselError::AlreadyExists(_))
+        ));
+
+        Migration::new()
+            .drop_check("line_items", "qty_positive")
+            .run(&conn)
+            .unwrap();
+        assert_eq!(
+            conn.execute("INSERT INTO line_items (id, qty) VALUES (2, 1001)"),
+            Ok(1)
+        );
+        assert!(conn
+            .execute("ALTER TABLE line_items DROP CONSTRAINT qty_positive")
+            .is_err());
+        conn.execute("ALTER TABLE line_items DROP CONSTRAINT IF EXISTS qty_positive")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_add_column_with_check_validates_existing_rows() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        create_accounts(&conn);
+        conn.execute("INSERT INTO accounts (id, email) VALUES (1, 'a@example.com')")
+            .unwrap();
+
+        assert_eq!(
+            conn.execute("ALTER TABLE accounts ADD COLUMN tier INTEGER DEFAULT 0 CHECK (tier > 0)"),
+            Err(check_violation("accounts", "accounts_tier_check"))
+        );
+        conn.execute("ALTER TABLE accounts ADD COLUMN tier INTEGER DEFAULT 1 CHECK (tier > 0)")
+            .unwrap();
+        assert!(conn
+            .execute("UPDATE accounts SET tier = 0 WHERE id = 1")
+            .is_err());
+        // Checks survive a snapshot and restore
+        let snapshot = conn.snapshot();
+        conn.restore(&snapshot);
+        assert!(conn
+            .execute("INSERT INTO accounts (id, email, tier) VALUES (2, 'b@example.com', 0)")
+            .is_err());
+    }
+
+    /// A connection with `n` rows in `events`, enough that a scan takes a
+    /// measurable time
+    fn seeded_events(n: i32) -> Connection {
+        let conn = Connection::establish_postgres("postgres://localhost/x").unwrap();
+        for id in 0..n {
+            InsertQuery::new("events")
+                .value("id", Value::Integer(id))
+                .value(
+                    "kind",
+                    Value::from(if id % 2 == 0 { "click" } else { "view" }),
+                )
+                .execute(&conn)
+                .unwrap();
+        }
+        conn
+    }
+
+    fn is_timeout(result: QueryResult<usize>) -> bool {
+        matches!(result, Err(DieselError::Timeout(_)))
+    }
+
+    #[test]
+    fn test_query_timeout_cancels_scans() {
+        let conn = seeded_events(1000);
+        let clicks = SelectQuery::new("events").filter(Column::new("kind").eq("click"));
+
+        let err = clicks
+            .clone()
+            .timeout(Duration::from_nanos(1))
+            .load(&conn)
+            .unwrap_err();
+        assert_eq!(err, DieselError::Timeout(Duration::from_nanos(1)));
+        assert_eq!(
+            err.to_string(),
+            "canceling statement due to statement timeout after 1ns"
+        );
+        assert!(is_timeout(
+            clicks.clone().timeout(Duration::from_nanos(1)).count(&conn)
+        ));
+        assert!(clicks
+            .clone()
+            .order_by("id", "DESC")
+            .timeout(Duration::from_nanos(1))
+            .load_iter(&conn)
+            .is_err());
+        // Subqueries and derived tables are cancelled with the outer query
+        let derived =
+            SelectQuery::from_subquery(clicks.clone(), "c").timeout(Duration::from_nanos(1));
+        assert!(derived.load(&conn).is_err());
+
+        assert_eq!(
+            clicks.clone().timeout(Duration::from_secs(60)).count(&conn),
+            Ok(500)
+        );
+        // The deadline only applies to the statement that set it
+        assert_eq!(clicks.count(&conn), Ok(500));
+    }
+
+    #[test]
+    fn test_connection_query_timeout_default() {
+        let conn = seeded_events(100).with_query_timeout(Duration::from_nanos(1));
+        let events = SelectQuery::new("events");
+
+        assert!(is_timeout(events.count(&conn)));
+        assert_eq!(
+            events.clone().timeout(Duration::from_secs(60)).count(&conn),
+            Ok(100)
+        );
+        // Writes are not subject to the query timeout
+        assert_eq!(conn.execute("DELETE FROM events WHERE id < 10"), Ok(10));
+    }
+
+    #[test]
+    fn test_query_timeout_cuts_off_slow_queries() {
+        let conn = seeded_events(10);
+        let next = conn.statement_count() + 1;
+        let conn = conn.with_failures(
+            FailurePolicy::new()
+                .fail_on(next, Failure::SlowQuery(Duration::from_secs(5)))
+                .fail_on(next + 1, Failure::SlowQuery(Duration::from_millis(5))),
+        );
+        let events = SelectQuery::new("events").timeout(Duration::from_millis(50));
+
+        let start = std::time::Instant::now();
+        assert!(is_timeout(events.count(&conn)));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50));
+        assert!(elapsed < Duration::from_secs(5));
+
+        assert_eq!(events.count(&conn), Ok(10));
+    }
+
+    #[test]
+    fn test_errors_are_structured() {
+        fn insert_user(conn: &Connection) -> Result<usize, Box<dyn std::error::Error>> {
+            Ok(conn.execute("INSERT INTO users (id, name) VALUES (1, NULL)")?)
+        }
+
+        let conn = Connection::establish_postgres("postgres://localhost/x").unwrap();
+        conn.execute("CREATE TABLE users (id INTEGER, name TEXT NOT NULL)")
+            .unwrap();
+        let err = insert_user(&conn).unwrap_err();
+        assert_eq!(err.to_string(), "NOT NULL constraint failed: users.name");
+        assert_eq!(
+            err.downcast_ref::<DieselError>(),
+            Some(&not_null_violation("users", "name"))
+        );
+
+        assert_eq!(
+            conn.execute("DROP TABLE missing"),
+            Err(DieselError::NotFound("table missing".to_string()))
+        );
+        assert_eq!(
+            conn.execute("CREATE TABLE users (id INTEGER)"),
+            Err(DieselError::AlreadyExists("table users".to_string()))
+        );
+        assert_eq!(
+            conn.execute("DELETE FROM users WHERE"),
+            Err(DieselError::SyntaxError(
+                "syntax error at end of input".to_string()
+            ))
+        );
+        assert!(matches!(
+            Interval::parse("soon"),
+            Err(DieselError::InvalidValue(_))
+        ));
+        assert_eq!(
+            Clock::fixed("yesterday").unwrap_err().to_string(),
+            "invalid timestamp \"yesterday\""
+        );
+
+        let mysql = Connection::establish_mysql("mysql://localhost/x").unwrap();
+        let err = SelectQuery::new("users")
+            .distinct_on("name")
+            .load(&mysql)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            DieselError::Unsupported("DISTINCT ON is not supported by mysql".to_string())
+        );
+    }
+
+    fn replicated(replicas: usize) -> (ReplicatedConnection, Vec<Connection>) {
+        let primary = Connection::establish_postgres("postgres://primary/app").unwrap();
+        seed_users_and_orders(&primary);
+        let replicas: Vec<Connection> = (0..replicas)
+            .map(|_| Connection::establish_postgres("postgres://replica/app").unwrap())
+            .collect();
+        (
+            ReplicatedConnection::new(primary, replicas.clone()),
+            replicas,
+        )
+    }
+
+    #[test]
+    fn test_replicas_serve_reads_round_robin() {
+        let (db, replicas) = replicated(2);
+        let users = SelectQuery::new("users");
+        // Replicas start as copies of the primary
+        for replica in &replicas {
+            assert_eq!(users.count(replica).unwrap(), 3);
+        }
+        let before: Vec<usize> = replicas.iter().map(|r| r.statement_count()).collect();
+
+        for _ in 0..4 {
+            assert_eq!(db.load(&users).unwrap().len(), 3);
+        }
+        for (replica, before) in replicas.iter().zip(before) {
+            assert_eq!(replica.statement_count() - before, 2);
+        }
+
+        InsertQuery::new("users")
+            .value("id", Value::Integer(4))
+            .value("name", Value::from("Dan"))
+            .execute(db.primary())
+            .unwrap();
+        assert_eq!(users.count(db.reader()).unwrap(), 4);
+        assert_eq!(users.count(db.reader()).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_execute_routes_by_statement() {
+        let (db, replicas) = replicated(1);
+        let primary_statements = db.primary().statement_count();
+
+        db.execute("CREATE TABLE audit (id INTEGER)").unwrap();
+        db.execute("INSERT INTO audit (id) VALUES (1), (2)")
+            .unwrap();
+        assert_eq!(db.primary().statement_count(), primary_statements + 2);
+        assert_eq!(replicas[0].statement_count(), 0);
+
+        db.execute("  select * from audit").unwrap();
+        assert_eq!(db.primary().statement_count(), primary_statements + 2);
+        assert_eq!(replicas[0].statement_count(), 1);
+        assert_eq!(SelectQuery::new("audit").count(&replicas[0]).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_replication_lag_gives_stale_reads() {
+        let clock = Clock::fixed("2024-06-01 12:00:00").unwrap();
+        let primary = Connection::establish_postgres("postgres://primary/app")
+            .unwrap()
+            .with_clock(clock.clone());
+        seed_users_and_orders(&primary);
+        let replica = Connection::establish_postgres("postgres://replica/app").unwrap();
+        let db = ReplicatedConnection::new(primary, vec![replica])
+            .with_replication_lag(Duration::from_secs(5));
+
+        UpdateQuery::new("users")
+            .set("name", Value::from("Robert"))
+            .filter(Column::new("id").eq(2))
+            .execute(db.primary())
+            .unwrap();
+        clock.advance(Duration::from_secs(2));
+        DeleteQuery::new("orders").execute(db.primary()).unwrap();
+
+        // Neither write has replicated yet; the primary sees both
+        assert_eq!(user_name(db.reader(), 2), "Bob");
+        assert_eq!(SelectQuery::new("orders").count(db.reader()).unwrap(), 3);
+        assert_eq!(SelectQuery::new("orders").count(db.primary()).unwrap(), 0);
+
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(user_name(db.reader(), 2), "Robert");
+        assert_eq!(SelectQuery::new("orders").count(db.reader()).unwrap(), 3);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(SelectQuery::new("orders").count(db.reader()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_replication_log_keeps_only_versions_within_lag() {
+        let clock = Clock::fixed("2024-06-01 12:00:00").unwrap();
+        let primary = Connection::establish_postgres("postgres://primary/app")
+            .unwrap()
+            .with_clock(clock.clone());
+        seed_users_and_orders(&primary);
+        let replica = Connection::establish_postgres("postgres://replica/app").unwrap();
+        let db = ReplicatedConnection::new(primary, vec![replica])
+            .with_replication_lag(Duration::from_secs(5));
+        let versions = || db.log.versions.lock().unwrap().len();
+
+        // Writes without reads don't pile up versions past the lag
+        for id in 4..104 {
+            InsertQuery::new("users")
+                .value("id", Value::Integer(id))
+                .value("name", Value::from("Dan"))
+                .execute(db.primary())
+                .unwrap();
+            clock.advance(Duration::from_secs(1));
+        }
+        assert!(versions() <= 7, "{} versions kept", versions());
+        assert_eq!(SelectQuery::new("users").count(db.reader()).unwrap(), 99);
+
+        // Without lag, only the newest version is kept
+        let (db, _) = replicated(1);
+        for id in 4..10 {
+            InsertQuery::new("users")
+                .value("id", Value::Integer(id))
+                .value("name", Value::from("Dan"))
+                .execute(db.primary())
+                .unwrap();
+        }
+        assert_eq!(db.log.versions.lock().unwrap().len(), 1);
+        assert_eq!(SelectQuery::new("users").count(db.reader()).unwrap(), 9);
+    }
+
+    #[test]
+    fn test_reads_use_primary_without_replicas() {
+        let (db, _) = replicated(0);
+        db.execute("DELETE FROM users WHERE id = 3").unwrap();
+        assert_eq!(db.load(&SelectQuery::new("users")).unwrap().len(), 2);
+        assert!(std::ptr::eq(db.reader(), db.primary()));
+    }
+
+    /// A `cache` table expiring rows at `expires_at`, on a connection whose
+    /// clock is fixed at noon on 2024-03-04. Entry 1 expires at 12:30, entry 2
+    /// has already expired and entry 3 never expires.
+    fn cache_at_noon() -> (Connection, Clock) {
+        let clock = Clock::fixed("2024-03-04 12:00:00").unwrap();
+        let conn = Connection::establish_postgres("postgres://localhost/app")
+            .unwrap()
+            .with_clock(clock.clone());
+        Migration::new()
+            .create_table(
+                "cache",
+                vec![("id", "INTEGER"), ("expires_at", "TIMESTAMP")],
+            )
+            .set_ttl("cache", "expires_at")
+            .run(&conn)
+            .unwrap();
+        for (id, expires) in [
+            (1, timestamp("2024-03-04 12:30:00")),
+            (2, timestamp("2024-03-04 11:00:00")),
+            (3, Value::Null),
+        ] {
+            InsertQuery::new("cache")
+                .value("id", Value::Integer(id))
+                .value("expires_at", expires)
+                .execute(&conn)
+                .unwrap();
+        }
+        (conn, clock)
+    }
+
+    fn cache_ids(conn: &Connection) -> Vec<i32> {
+        SelectQuery::new("cache")
+            .order_by("id", "ASC")
+            .load(conn)
+            .unwrap()
+            .iter()
+            .map(|row| match row.get("id") {
+                Some(Value::Integer(id)) => *id,
+                other => panic!("unexpected id {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ttl_hides_expired_rows() {
+        let (conn, clock) = cache_at_noon();
+        assert_eq!(cache_ids(&conn), vec![1, 3]);
+        assert_eq!(Table::new("cache").count(&conn).unwrap(), 2);
+
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(cache_ids(&conn), vec![3]);
+
+        Migration::new().remove_ttl("cache").run(&conn).unwrap();
+        assert_eq!(cache_ids(&conn), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_ttl_applies_to_subqueries() {
+        let (conn, _clock) = cache_at_noon();
+        for id in 1..=3 {
+            InsertQuery::new("hits")
+                .value("cache_id", Value::Integer(id))
+                .execute(&conn)
+                .unwrap();
+        }
+        let live = SelectQuery::new("cache").select(vec!["id"]);
+        let hits = SelectQuery::new("hits").filter(Column::new("cache_id").eq_any(live));
+        assert_eq!(hits.count(&conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_vacuum_purges_expired_rows() {
+        let (conn, clock) = cache_at_noon();
+        assert_eq!(conn.vacuum().unwrap(), 1);
+        assert_eq!(conn.vacuum().unwrap(), 0);
+
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(conn.execute("VACUUM FULL cache").unwrap(), 1);
+        Migration::new().remove_ttl("cache").run(&conn).unwrap();
+        assert_eq!(cache_ids(&conn), vec![3]);
+
+        assert_eq!(
+            conn.execute("VACUUM missing").unwrap_err(),
+            DieselError::NotFound("table missing".to_string())
+        );
+        assert_eq!(
+            conn.execute("ALTER TABLE missing SET (ttl_expiration_expression = 'at')")
+                .unwrap_err(),
+            DieselError::NotFound("table missing".to_string())
+        );
+    }
+}
+
+// The schema macro expands to paths in the `diesel_emulator` library crate,
+// so these tests use it directly rather than the copy included above.
+#[cfg(test)]
+mod table_dsl_tests {
+    use diesel_emulator::*;
+
+    table_dsl! {
+        users {
+            id -> Integer,
+            name -> Text,
+            age -> Integer,
+            active -> Boolean,
+        }
+    }
+
+    table_dsl! {
+        posts {
+            id -> Integer,
+            author_id -> Integer,
+            title -> Text,
+        }
+    }
+
+    fn seed(conn: &Connection) {
+        for (id, name, age) in [(1, "Alice", 34), (2, "Bob", 17), (3, "Carol", 52)] {
+            users::table()
+                .insert()
+                .value(users::id.name(), Value::Integer(id))
+                .value(users::name.name(), Value::Text(name.to_string()))
+                .value(users::age.name(), Value::Integer(age))
+                .value(users::active.name(), Value::Boolean(id != 3))
+                .execute(conn)
+                .unwrap();
+        }
+        posts::table()
+            .insert()
+            .value(posts::id.name(), Value::Integer(1))
+            .value(posts::author_id.name(), Value::Integer(3))
+            .value(posts::title.name(), Value::Text("Hello".to_string()))
+            .execute(conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_generated_module() {
+        assert_eq!(users::NAME, "users");
+        assert_eq!(users::all_columns, &["id", "name", "age", "active"]);
+        assert_eq!(
+            posts::column_defs(),
+            vec![
+                ("id", "INTEGER"),
+                ("author_id", "INTEGER"),
+                ("title", "TEXT")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_typed_column_filters() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed(&conn);
+
+        let query = users::table()
+            .select()
+            .filter(users::age.gt(18))
+            .filter(users::active.eq(true));
+        assert_eq!(
+            query.to_sql(),
+            "SELECT * FROM users WHERE users.age > 18 AND users.active = true"
+        );
+        let rows = query.load(&conn).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("name"), Some(&Value::Text("Alice".to_string())));
+    }
+
+    #[test]
+    fn test_typed_columns_in_subqueries() {
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        seed(&conn);
+
+        let authors = posts::table().select().select(vec!["author_id"]);
+        let rows = users::table()
+            .select()
+            .filter(users::id.eq_any(authors))
+            .load(&conn)
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("name"), Some(&Value::Text("Carol".to_string())));
+
+        let has_posts = posts::table()
+            .select()
+            .filter(posts::author_id.eq_column(&users::id));
+        let count = users::table()
+            .select()
+            .filter(!exists(has_posts))
+            .count(&conn)
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_database_name_from_url() {
+        let conn = Connection::establish_postgres(
+            "postgres://user:pw@localhost:5432/app_test?sslmode=disable",
+        )
+        .unwrap();
+        assert_eq!(conn.database(), "app_test");
+
+        let conn = Connection::establish_mysql("mysql://root@localhost").unwrap();
+        assert_eq!(conn.database(), "mysql");
+
+        let conn = Connection::establish_sqlite(":memory:").unwrap();
+        assert_eq!(conn.database(), "main");
+    }
+
+    #[test]
+    fn test_use_database_isolates_tables() {
+        let conn = Connection::establish_postgres("postgres://localhost/app").unwrap();
+        let mut other = conn.clone();
+        other.use_database("analytics");
+
+        InsertQuery::new("events")
+            .value("id", Value::Integer(1))
+            .execute(&other)
+            .unwrap();
+
+        assert_eq!(Table::new("events").count(&other).unwrap(), 1);
+        assert_eq!(Table::new("events").count(&conn).unwrap(), 0);
+        assert_eq!(conn.databases(), vec!["analytics", "app"]);
+    }
+
+    #[test]
+    fn test_use_database_shares_server_state() {
+        let conn = Connection::establish_postgres("postgres://localhost/app").unwrap();
+        let mut first = conn.clone();
+        first.use_database("reports");
+        InsertQuery::new("daily")
+            .value("day", Value::Integer(1))
+            .execute(&first)
+            .unwrap();
+
+        let mut second = conn.clone();
+        second.use_database("reports");
+        assert_eq!(Table::new("daily").count(&second).unwrap(), 1);
+
+        second.use_database("app");
+        assert_eq!(Table::new("daily").count(&second).unwrap(), 0);
+    }
 }
 
 fn main() {
@@ -1,15 +1,45 @@
 // Developed by PowerShield, as an alternative to Clap
 
-use std::collections::HashMap;
+use serde_emulator::JsonValue;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::ops::{Range, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive};
+use std::path::Path;
+use std::sync::Arc;
 
 // Command represents a CLI command
 pub struct Command {
     name: String,
     about: Option<String>,
+    long_about: Option<String>,
     version: Option<String>,
     author: Option<String>,
+    // Replacements for and additions to the generated help
+    override_usage: Option<String>,
+    before_help: Option<String>,
+    after_help: Option<String>,
     args: Vec<Arg>,
+    groups: Vec<ArgGroup>,
     subcommands: Vec<Command>,
+    // Other names the Command answers to as a subcommand; visible ones
+    // are listed in the parent's help
+    aliases: Vec<String>,
+    visible_aliases: Vec<String>,
+    disable_version_flag: bool,
+    propagate_version: bool,
+    allow_unknown_args: bool,
+    color: ColorChoice,
+    help_template: Option<String>,
+    prompt_missing: bool,
+    config_file_arg: Option<String>,
+    subcommand_required: bool,
+    arg_required_else_help: bool,
+    // The name with its parent commands', such as `git remote`, once
+    // parsing reaches a subcommand
+    bin_name: Option<String>,
 }
 
 impl Command {
@@ -17,10 +47,27 @@ impl Command {
         Command {
             name: name.to_string(),
             about: None,
+            long_about: None,
             version: None,
             author: None,
+            override_usage: None,
+            before_help: None,
+            after_help: None,
             args: Vec::new(),
+            groups: Vec::new(),
             subcommands: Vec::new(),
+            aliases: Vec::new(),
+            visible_aliases: Vec::new(),
+            disable_version_flag: false,
+            propagate_version: false,
+            allow_unknown_args: false,
+            color: ColorChoice::Auto,
+            help_template: None,
+            prompt_missing: false,
+            config_file_arg: None,
+            subcommand_required: false,
+            arg_required_else_help: false,
+            bin_name: None,
         }
     }
     
@@ -29,6 +76,12 @@ impl Command {
         self
     }
     
+    // A fuller description for --help and man pages; -h shows about
+    pub fn long_about(mut self, about: &str) -> Self {
+        self.long_about = Some(about.to_string());
+        self
+    }
+    
     pub fn version(mut self, version: &str) -> Self {
         self.version = Some(version.to_string());
         self
@@ -39,170 +92,1944 @@ impl Command {
         self
     }
     
+    // Use `usage` after `Usage:` in help and errors instead of the
+    // generated line
+    pub fn override_usage(mut self, usage: &str) -> Self {
+        self.override_usage = Some(usage.to_string());
+        self
+    }
+    
+    // Text for the top of the help, above the about text
+    pub fn before_help(mut self, help: &str) -> Self {
+        self.before_help = Some(help.to_string());
+        self
+    }
+    
+    // Text for the end of the help, after the options, such as examples
+    pub fn after_help(mut self, help: &str) -> Self {
+        self.after_help = Some(help.to_string());
+        self
+    }
+    
     pub fn arg(mut self, arg: Arg) -> Self {
         self.args.push(arg);
         self
     }
     
+    pub fn group(mut self, group: ArgGroup) -> Self {
+        self.groups.push(group);
+        self
+    }
+    
     pub fn subcommand(mut self, cmd: Command) -> Self {
         self.subcommands.push(cmd);
         self
     }
     
+    // Another name for the Command as a subcommand, left out of help
+    pub fn alias(mut self, name: &str) -> Self {
+        self.aliases.push(name.to_string());
+        self
+    }
+    
+    // Another name for the Command as a subcommand, shown in the parent's
+    // help as `[aliases: ...]`
+    pub fn visible_alias(mut self, name: &str) -> Self {
+        self.visible_aliases.push(name.to_string());
+        self
+    }
+    
+    // Turn off the built-in --version/-V flag for this Command
+    pub fn disable_version_flag(mut self, disable: bool) -> Self {
+        self.disable_version_flag = disable;
+        self
+    }
+    
+    // Give subcommands without a version of their own the Command's, so
+    // `app sub --version` works; it carries on to their subcommands
+    pub fn propagate_version(mut self, propagate: bool) -> Self {
+        self.propagate_version = propagate;
+        self
+    }
+    
+    // Skip flags the Command doesn't know, listing them in
+    // ArgMatches::unknown_args, and keep extra positional values instead
+    // of failing on them
+    pub fn allow_unknown_args(mut self, allow: bool) -> Self {
+        self.allow_unknown_args = allow;
+        self
+    }
+    
+    // When help and errors use ANSI colors; subcommands follow the Command
+    pub fn color(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self
+    }
+    
+    // Lay out help with a template instead of the default. `{name}`,
+    // `{bin}`, `{version}`, `{author}`, `{about}`, `{usage-heading}` and
+    // `{usage}` fill in the Command's details; `{all-args}` gives every
+    // section with its heading, and `{subcommands}`, `{positionals}` and
+    // `{options}` one section's rows without it. `{before-help}` and
+    // `{after-help}` place before_help and after_help.
+    pub fn help_template(mut self, template: &str) -> Self {
+        self.help_template = Some(template.to_string());
+        self
+    }
+    
+    // Ask on the terminal for missing required values instead of failing,
+    // for Args that take a value; subcommands follow the Command
+    pub fn prompt_missing(mut self, prompt: bool) -> Self {
+        self.prompt_missing = prompt;
+        self
+    }
+    
+    // Read values for Args not given on the command line from the TOML,
+    // JSON or YAML file that Arg `id` names, a map from Arg ids to values. The file
+    // ranks below environment variables and above default values; a
+    // default path is skipped if there's no file there.
+    pub fn config_file_arg(mut self, id: &str) -> Self {
+        self.config_file_arg = Some(id.to_string());
+        self
+    }
+    
+    // Fail with MissingSubcommand when none of the Command's subcommands
+    // is given
+    pub fn subcommand_required(mut self, required: bool) -> Self {
+        self.subcommand_required = required;
+        self
+    }
+    
+    // Print help to stderr and exit with an error when the Command is given
+    // no arguments at all, instead of parsing an empty command line
+    pub fn arg_required_else_help(mut self, required: bool) -> Self {
+        self.arg_required_else_help = required;
+        self
+    }
+    
+    // Parse the process's arguments, printing any error (or help, or the
+    // version) and exiting as Error::exit does
     pub fn get_matches(self) -> ArgMatches {
-        let args: Vec<String> = std::env::args().collect();
-        self.parse_args(&args[1..])
+        self.try_get_matches().unwrap_or_else(|e| e.exit())
+    }
+    
+    pub fn try_get_matches(self) -> Result<ArgMatches, Error> {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        self.try_get_matches_from_os(&args)
+    }
+    
+    // Parse `args` as try_get_matches_from does, keeping values that aren't
+    // valid UTF-8, such as some file names, for ArgMatches::get_raw
+    pub fn try_get_matches_from_os(self, args: &[OsString]) -> Result<ArgMatches, Error> {
+        let escaped: Vec<String> = args.iter().skip(1).map(|arg| escape_os(arg)).collect();
+        let mut matches = self.parse_args(&escaped, 0, &mut TerminalPrompter).map_err(Error::unescape_raw)?;
+        matches.unescape_raw();
+        Ok(matches)
+    }
+    
+    // Parse `args` as a command line, whose first item is the program name
+    pub fn try_get_matches_from(self, args: &[&str]) -> Result<ArgMatches, Error> {
+        let string_args: Vec<String> = args.iter().skip(1).map(|s| s.to_string()).collect();
+        self.parse_args(&string_args, 0, &mut TerminalPrompter)
+    }
+    
+    // Parse `args` as try_get_matches_from does, asking `prompter` rather
+    // than the terminal for missing values when prompt_missing is on
+    pub fn try_get_matches_from_with_prompter(self, args: &[&str], prompter: &mut dyn Prompter) -> Result<ArgMatches, Error> {
+        let string_args: Vec<String> = args.iter().skip(1).map(|s| s.to_string()).collect();
+        self.parse_args(&string_args, 0, prompter)
+    }
+    
+    // The help -h prints, as plain text whatever the color setting and
+    // terminal, for comparing against a snapshot in tests
+    pub fn render_help(&self) -> String {
+        self.plain().help_text(false)
+    }
+    
+    // The help --help prints, as plain text like render_help
+    pub fn render_long_help(&self) -> String {
+        self.plain().help_text(true)
+    }
+    
+    fn plain(&self) -> Command {
+        let mut cmd = self.clone();
+        cmd.color = ColorChoice::Never;
+        cmd
+    }
+    
+    // An error about the Command's arguments, shown with its usage line
+    // and a pointer to its help flag
+    fn error(&self, kind: ErrorKind, message: String, args: Vec<String>) -> Error {
+        let mut error = Error::new(kind, message).with_args(args).with_usage(self.usage());
+        error.color = self.use_color(true);
+        error.help_flag = ["--help", "-h"].into_iter().find(|flag| self.is_help_flag(flag));
+        error
+    }
+    
+    // A value for an Arg that takes no more: one attached to a Count flag,
+    // as in `-q=2`, or one past num_args' maximum
+    fn unexpected_value(&self, arg: &Arg, value: &str) -> Error {
+        let message = format!("unexpected value '{}' for '{}' found; no more were expected", value, arg.display());
+        self.error(ErrorKind::TooManyValues, message, vec![value.to_string(), arg.display()])
+    }
+    
+    // The values for an occurrence of a named Arg: the one attached to the
+    // flag, if any, then tokens after `args[*i]` up to num_args' maximum
+    // (one by default), counting each part a value_delimiter splits off.
+    // The first value may start with a hyphen, as in `--offset -5`, but
+    // isn't taken if it names one of the Command's flags; values after the
+    // first stop at any flag. Fewer than the Arg's minimum is a
+    // TooFewValues error.
+    // A SetTrue or SetFalse flag may be given `=true` or `=false` instead,
+    // which is its one value.
+    fn take_values(&self, arg: &Arg, attached: Option<String>, args: &[String], i: &mut usize) -> Result<Vec<String>, Error> {
+        if !arg.takes_values() {
+            let Some(value) = attached else {
+                return Ok(Vec::new());
+            };
+            if !matches!(arg.action, ArgAction::SetTrue | ArgAction::SetFalse) {
+                return Err(self.unexpected_value(arg, &value));
+            }
+            let possible = ["true".to_string(), "false".to_string()];
+            if !possible.contains(&value) {
+                return Err(self.invalid_value(arg, &value, &possible));
+            }
+            return Ok(vec![value]);
+        }
+        let mut values: Vec<String> = attached.iter().flat_map(|v| arg.split_value(v)).collect();
+        while values.len() < arg.max_values() && *i + 1 < args.len() {
+            let next = &args[*i + 1];
+            let flag_like = next.starts_with('-') && next != "-" && !arg.allow_hyphen_values;
+            let stop = if values.is_empty() {
+                flag_like && self.is_known_flag(next)
+            } else {
+                next == "--" || flag_like
+            };
+            if stop {
+                break;
+            }
+            values.extend(arg.split_value(next));
+            *i += 1;
+        }
+        if let Some(extra) = values.get(arg.max_values()).filter(|_| arg.num_args.is_some()) {
+            return Err(self.unexpected_value(arg, extra));
+        }
+        self.check_value_count(arg, values.len())?;
+        for value in &values {
+            self.check_value(arg, value)?;
+        }
+        Ok(values)
+    }
+    
+    // Check that an Arg has at least its minimum number of values
+    fn check_value_count(&self, arg: &Arg, count: usize) -> Result<(), Error> {
+        let min = arg.min_values();
+        if count >= min {
+            return Ok(());
+        }
+        let message = match count {
+            0 => format!("a value is required for '{}' but none was supplied", arg.display()),
+            1 => format!("{} values required by '{}'; only 1 was provided", min, arg.display()),
+            _ => format!("{} values required by '{}'; only {} were provided", min, arg.display(), count),
+        };
+        Err(self.error(ErrorKind::TooFewValues, message, vec![arg.display()]))
+    }
+    
+    // Check a value against the Arg's possible values, if it has any, and
+    // then its validator
+    fn check_value(&self, arg: &Arg, value: &str) -> Result<(), Error> {
+        if let Some(parser) = &arg.value_parser {
+            let possible = parser.possible_values();
+            if !possible.iter().any(|p| p == value) {
+                return Err(self.invalid_value(arg, value, possible));
+            }
+        }
+        match &arg.validator {
+            Some(validator) => validator(value).map_err(|reason| {
+                let message = format!("invalid value '{}' for '{}': {}", value, arg.display(), reason);
+                self.error(ErrorKind::ValueValidation, message, vec![value.to_string(), arg.display()])
+            }),
+            None => Ok(()),
+        }
+    }
+    
+    fn invalid_value(&self, arg: &Arg, value: &str, possible: &[String]) -> Error {
+        let message = format!(
+            "invalid value '{}' for '{}'\n  [possible values: {}]",
+            value,
+            arg.display(),
+            possible.join(", ")
+        );
+        self.error(ErrorKind::InvalidValue, message, vec![value.to_string(), arg.display()])
+    }
+    
+    // The Args given on the command line for `id`, which names an Arg or
+    // an ArgGroup; default values don't count
+    fn given_args(&self, matches: &ArgMatches, id: &str) -> Vec<&Arg> {
+        let ids = match self.groups.iter().find(|g| g.id == id) {
+            Some(group) => group.args.clone(),
+            None => vec![id.to_string()],
+        };
+        self.args.iter()
+            .filter(|a| ids.contains(&a.id) && matches.occurrences_of(&a.id) > 0)
+            .collect()
+    }
+    
+    // How a missing Arg or ArgGroup is listed: `--output <OUTPUT>`, or
+    // `<--json|--yaml>` for a group, which any of its Args satisfies
+    fn missing_display(&self, matches: &ArgMatches, id: &str) -> Option<String> {
+        match self.groups.iter().find(|g| g.id == id) {
+            Some(group) if !group.args.iter().any(|a| matches.is_present(a)) => Some(self.group_display(group)),
+            Some(_) => None,
+            None if matches.is_present(id) => None,
+            None => Some(self.args.iter().find(|a| a.id == id).map_or(id.to_string(), Arg::display)),
+        }
+    }
+    
+    fn group_display(&self, group: &ArgGroup) -> String {
+        let members: Vec<String> = self.args.iter()
+            .filter(|a| group.args.contains(&a.id))
+            .map(Arg::display)
+            .collect();
+        format!("<{}>", members.join("|"))
+    }
+    
+    // Two Args given together that can't be
+    fn conflict(&self, arg: &Arg, other: &Arg) -> Error {
+        let message = format!("the argument '{}' cannot be used with '{}'", arg.display(), other.display());
+        self.error(ErrorKind::ArgumentConflict, message, vec![arg.display(), other.display()])
+    }
+    
+    // The error for a token with no Arg or subcommand to take it
+    fn unknown_argument(&self, arg: &str) -> Error {
+        let expects_subcommand = !self.subcommands.is_empty() && !self.args.iter().any(Arg::is_positional);
+        if expects_subcommand && !arg.starts_with('-') {
+            return self.invalid_subcommand(arg);
+        }
+        let mut message = format!("unexpected argument '{}' found", arg);
+        if !arg.starts_with('-') && self.args.iter().any(|a| a.last) {
+            message.push_str(&format!("\n\n  tip: to pass '{}' as a value, use '-- {}'", arg, arg));
+        }
+        if let Some(name) = arg.strip_prefix("--") {
+            let longs: Vec<String> = self.help_args().into_iter()
+                .chain(&self.builtin_flags())
+                .filter_map(|a| a.long.clone())
+                .collect();
+            if let Some(similar) = did_you_mean(name, longs.iter().map(String::as_str)) {
+                message.push_str(&format!("\n\n  tip: did you mean '--{}'?", similar));
+            }
+        }
+        self.error(ErrorKind::UnknownArgument, message, vec![arg.to_string()])
+    }
+    
+    // The error for a subcommand name the Command doesn't have, suggesting
+    // a similar one
+    fn invalid_subcommand(&self, name: &str) -> Error {
+        let mut message = format!("unrecognized subcommand '{}'", name);
+        let mut names: Vec<&str> = self.subcommands.iter()
+            .flat_map(|c| std::iter::once(&c.name).chain(&c.aliases).chain(&c.visible_aliases))
+            .map(String::as_str)
+            .collect();
+        if self.has_help_subcommand() {
+            names.push("help");
+        }
+        if let Some(similar) = did_you_mean(name, names.into_iter()) {
+            message.push_str(&format!("\n\n  tip: did you mean '{}'?", similar));
+        }
+        self.error(ErrorKind::InvalidSubcommand, message, vec![name.to_string()])
+    }
+    
+    fn display_name(&self) -> &str {
+        self.bin_name.as_deref().unwrap_or(&self.name)
+    }
+    
+    fn usage(&self) -> String {
+        format!("Usage: {}", self.usage_line())
+    }
+    
+    // The usage line after `Usage:`, such as `copy [OPTIONS] --input <INPUT>
+    // <OUTPUT>`: required options, then positionals in order, then any
+    // subcommand, unless override_usage replaces it
+    fn usage_line(&self) -> String {
+        if let Some(usage) = &self.override_usage {
+            return usage.clone();
+        }
+        let mut usage = self.display_name().to_string();
+        let args = self.help_args();
+        if args.iter().any(|a| !a.is_positional() && !a.required) {
+            usage.push_str(" [OPTIONS]");
+        }
+        for arg in args.iter().filter(|a| !a.is_positional() && a.required) {
+            usage.push(' ');
+            usage.push_str(&arg.display());
+        }
+        for group in self.groups.iter().filter(|g| g.required) {
+            usage.push(' ');
+            usage.push_str(&self.group_display(group));
+        }
+        for arg in args.iter().filter(|a| a.is_positional()) {
+            usage.push(' ');
+            let dashes = if arg.last { "-- " } else { "" };
+            if arg.required {
+                usage.push_str(&format!("{}{}", dashes, arg.display()));
+            } else if arg.last {
+                usage.push_str(&format!("[-- {}]", arg.display()));
+            } else {
+                usage.push_str(&format!("[{}]", arg.placeholder()));
+            }
+        }
+        if self.subcommand_required {
+            usage.push_str(" <COMMAND>");
+        } else if !self.subcommands.is_empty() {
+            usage.push_str(" [COMMAND]");
+        }
+        usage
+    }
+    
+    // Whether `arg` names one of the Command's own flags, as `--name`,
+    // `--name=value`, or a short group starting with a known short
+    fn is_known_flag(&self, arg: &str) -> bool {
+        if let Some(flag) = arg.strip_prefix("--") {
+            let name = flag.split_once('=').map_or(flag, |(name, _)| name);
+            return self.args.iter().any(|a| a.long.as_deref() == Some(name));
+        }
+        match arg.strip_prefix('-').and_then(|group| group.chars().next()) {
+            Some(c) => self.args.iter().any(|a| a.short == Some(c)),
+            None => false,
+        }
+    }
+    
+    // The subcommand called `name`, or with `name` as an alias
+    fn find_subcommand(&self, name: &str) -> Option<&Command> {
+        self.subcommands.iter().find(|c| {
+            c.name == name || c.aliases.iter().chain(&c.visible_aliases).any(|a| a == name)
+        })
+    }
+    
+    // A copy of `subcmd` to parse with, named after its parents and with
+    // this Command's global Args, which it doesn't require, and metadata
+    fn enter_subcommand(&self, subcmd: &Command) -> Command {
+        let mut subcmd = subcmd.clone();
+        subcmd.bin_name = Some(format!("{} {}", self.display_name(), subcmd.name));
+        subcmd.color = self.color;
+        subcmd.prompt_missing |= self.prompt_missing;
+        // Metadata the subcommand leaves out: the author, the version with
+        // propagate_version, and the long description if the subcommand
+        // has no description at all
+        if subcmd.author.is_none() {
+            subcmd.author = self.author.clone();
+        }
+        if self.propagate_version {
+            subcmd.propagate_version = true;
+            if subcmd.version.is_none() {
+                subcmd.version = self.version.clone();
+            }
+        }
+        if subcmd.about.is_none() && subcmd.long_about.is_none() {
+            subcmd.long_about = self.long_about.clone();
+        }
+        for arg in self.args.iter().filter(|a| a.global) {
+            if !subcmd.args.iter().any(|a| a.id == arg.id) {
+                let mut arg = arg.clone();
+                arg.required = false;
+                subcmd.args.push(arg);
+            }
+        }
+        subcmd
+    }
+    
+    // A Command with subcommands gets a built-in `help` subcommand, unless
+    // it has one of its own
+    fn has_help_subcommand(&self) -> bool {
+        !self.subcommands.is_empty() && self.find_subcommand("help").is_none()
+    }
+    
+    // The Args shown in help, usage and man pages: those not hidden,
+    // ordered by display_order
+    fn help_args(&self) -> Vec<&Arg> {
+        let mut args: Vec<&Arg> = self.args.iter().filter(|a| !a.hide).collect();
+        args.sort_by_key(|a| a.display_order.unwrap_or(usize::MAX));
+        args
+    }
+    
+    // The built-in --help and --version flags as Args, for help and man
+    // pages, with whichever forms the Command's own Args leave free
+    fn builtin_flags(&self) -> Vec<Arg> {
+        let mut flags = Vec::new();
+        let builtins = [("help", 'h', "Print help"), ("version", 'V', "Print version")];
+        for (long, short, help) in builtins {
+            let (long_flag, short_flag) = (format!("--{}", long), format!("-{}", short));
+            let is_builtin = |flag: &str| {
+                if long == "help" { self.is_help_flag(flag) } else { self.is_version_flag(flag) }
+            };
+            let action = if long == "help" { ArgAction::Help } else { ArgAction::Version };
+            let mut flag = Arg::new(long).help(help).action(action);
+            if is_builtin(&short_flag) {
+                flag = flag.short(short);
+            }
+            if is_builtin(&long_flag) {
+                flag = flag.long(long);
+            }
+            if !flag.is_positional() {
+                flags.push(flag);
+            }
+        }
+        flags
+    }
+    
+    // The built-in --help/-h flag, where no Arg of the Command's own takes
+    // the name
+    fn is_help_flag(&self, arg: &str) -> bool {
+        match arg {
+            "--help" => !self.args.iter().any(|a| a.long.as_deref() == Some("help")),
+            "-h" => !self.args.iter().any(|a| a.short == Some('h')),
+            _ => false,
+        }
     }
     
-    pub fn try_get_matches_from(self, args: &[&str]) -> Result<ArgMatches, String> {
-        let string_args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
-        Ok(self.parse_args(&string_args))
+    // The help for `help a b`: the help of subcommand `b` of `a`, or the
+    // Command's own with no names
+    fn subcommand_help(&self, names: &[String]) -> Error {
+        let Some((name, rest)) = names.split_first() else {
+            return Error::new(ErrorKind::DisplayHelp, self.help_text(true));
+        };
+        match self.find_subcommand(name) {
+            Some(subcmd) => self.enter_subcommand(subcmd).subcommand_help(rest),
+            None => self.invalid_subcommand(name),
+        }
     }
     
-    fn parse_args(self, args: &[String]) -> ArgMatches {
+    // The help's sections, each a heading with rows of an Arg or
+    // subcommand and its help. Args with a help_heading come after the
+    // built-in sections, grouped by heading in the order first used.
+    fn help_sections(&self) -> Vec<(String, Vec<(String, String)>)> {
+        let mut sections = Vec::new();
+        
+        let mut commands: Vec<(String, String)> = self.subcommands.iter()
+            .map(|c| {
+                let mut about = c.about.clone().unwrap_or_default();
+                if !c.visible_aliases.is_empty() {
+                    about = join_help(&about, &format!("[aliases: {}]", c.visible_aliases.join(", ")));
+                }
+                (c.name.clone(), about)
+            })
+            .collect();
+        if self.has_help_subcommand() {
+            commands.push(("help".to_string(), "Print this message or the help of the given subcommand(s)".to_string()));
+        }
+        sections.push(("Commands".to_string(), commands));
+        sections.push(("Arguments".to_string(), Vec::new()));
+        sections.push(("Options".to_string(), Vec::new()));
+        
+        let builtin_flags = self.builtin_flags();
+        for arg in self.help_args().into_iter().chain(&builtin_flags) {
+            let heading = match &arg.help_heading {
+                Some(heading) => heading.as_str(),
+                None if arg.is_positional() => "Arguments",
+                None => "Options",
+            };
+            let row = (arg.help_spec(), arg.help_description());
+            match sections.iter_mut().find(|(h, _)| h.as_str() == heading) {
+                Some((_, rows)) => rows.push(row),
+                None => sections.push((heading.to_string(), vec![row])),
+            }
+        }
+        sections
+    }
+    
+    // The text shown for --help, or -h when not `long`, laid out by
+    // help_template: by default any before_help, the about text and usage
+    // line, then the subcommands, positionals and options, each with its
+    // help, in columns lined up across the sections, then any after_help
+    fn help_text(&self, long: bool) -> String {
+        let styles = Styles::new(self.use_color(false));
+        let sections = self.help_sections();
+        let width = sections.iter()
+            .flat_map(|(_, rows)| rows.iter().map(|(spec, _)| spec.len()))
+            .max()
+            .unwrap_or(0);
+        let render_rows = |rows: &[(String, String)]| -> String {
+            let lines: Vec<String> = rows.iter()
+                .map(|(spec, description)| {
+                    let padding = " ".repeat(width - spec.len());
+                    let line = format!("  {}{}  {}", styles.spec(spec), padding, description);
+                    line.trim_end().to_string()
+                })
+                .collect();
+            lines.join("\n")
+        };
+        let section = |heading: &str| -> String {
+            sections.iter()
+                .find(|(h, _)| *h == heading)
+                .map(|(_, rows)| render_rows(rows))
+                .unwrap_or_default()
+        };
+        let all_args: Vec<String> = sections.iter()
+            .filter(|(_, rows)| !rows.is_empty())
+            .map(|(heading, rows)| format!("{}\n{}", styles.header(&format!("{}:", heading)), render_rows(rows)))
+            .collect();
+        
+        let (first, second) = if long { (&self.long_about, &self.about) } else { (&self.about, &self.long_about) };
+        let about = first.clone().or_else(|| second.clone()).unwrap_or_default();
+        let about_with_newline = if about.is_empty() { String::new() } else { format!("{}\n", about) };
+        // before_help is a paragraph of its own, apart from any about text
+        let before_help = match &self.before_help {
+            Some(text) if about.is_empty() => format!("{}\n", text),
+            Some(text) => format!("{}\n\n", text),
+            None => String::new(),
+        };
+        let after_help = self.after_help.as_ref().map(|text| format!("\n\n{}", text)).unwrap_or_default();
+        let template = self.help_template.as_deref().unwrap_or(DEFAULT_HELP_TEMPLATE);
+        let help = template
+            .replace("{name}", &self.name)
+            .replace("{bin}", self.display_name())
+            .replace("{version}", self.version.as_deref().unwrap_or_default())
+            .replace("{author}", self.author.as_deref().unwrap_or_default())
+            .replace("{about-with-newline}", &about_with_newline)
+            .replace("{about}", &about)
+            .replace("{usage-heading}", &styles.header("Usage:"))
+            .replace("{usage}", &self.usage_line())
+            .replace("{all-args}", &all_args.join("\n\n"))
+            .replace("{subcommands}", &section("Commands"))
+            .replace("{positionals}", &section("Arguments"))
+            .replace("{options}", &section("Options"))
+            .replace("{before-help}", &before_help)
+            .replace("{after-help}", &after_help);
+        help.trim_start_matches('\n').trim_end().to_string()
+    }
+    
+    // Whether to color output for stdout, or stderr for errors: Always,
+    // Never, or with Auto when the stream is a terminal and NO_COLOR isn't
+    // set
+    fn use_color(&self, stderr: bool) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+                let terminal = if stderr { io::stderr().is_terminal() } else { io::stdout().is_terminal() };
+                !no_color && terminal
+            }
+        }
+    }
+    
+    // Whether `arg` asks for the built-in version flag: the Command has a
+    // version, the flag isn't turned off, and no Arg of its own uses the name
+    fn is_version_flag(&self, arg: &str) -> bool {
+        if self.version.is_none() || self.disable_version_flag {
+            return false;
+        }
+        match arg {
+            "--version" => !self.args.iter().any(|a| a.long.as_deref() == Some("version")),
+            "-V" => !self.args.iter().any(|a| a.short == Some('V')),
+            _ => false,
+        }
+    }
+    
+    // The help or version to print for the Help and Version actions,
+    // which stop parsing; a long flag gets the long help
+    fn action_output(&self, action: ArgAction, long: bool) -> Option<Error> {
+        match action {
+            ArgAction::Help => Some(Error::new(ErrorKind::DisplayHelp, self.help_text(long))),
+            ArgAction::Version => {
+                let version = self.version.as_deref().unwrap_or_default();
+                Some(Error::new(ErrorKind::DisplayVersion, format!("{} {}", self.name, version)))
+            }
+            _ => None,
+        }
+    }
+    
+    // Fill in an Arg not given on the command line from its environment
+    // variable, then the config file, then its default value. A flag is set
+    // unless the variable is empty or falsey, such as `0` or `false`, and
+    // takes `true` or `false` from the config file.
+    fn fill_arg(&self, matches: &mut ArgMatches, arg_def: &Arg, config: Option<&BTreeMap<String, JsonValue>>) -> Result<(), Error> {
+        if matches.is_present(&arg_def.id) {
+            return Ok(());
+        }
+        let env_value = arg_def.env.as_ref().and_then(|name| std::env::var(name).ok());
+        let config_value = config.and_then(|c| c.get(&arg_def.id)).filter(|v| !v.is_null());
+        if let Some(value) = env_value {
+            if !arg_def.takes_values() {
+                let falsey = ["", "0", "n", "no", "f", "false", "off"];
+                if !falsey.contains(&value.to_lowercase().as_str()) {
+                    matches.flags.insert(arg_def.id.clone());
+                    matches.sources.insert(arg_def.id.clone(), ValueSource::EnvVariable);
+                }
+                return Ok(());
+            }
+            let values = arg_def.split_value(&value);
+            for value in &values {
+                self.check_value(arg_def, value)?;
+            }
+            matches.values.insert(arg_def.id.clone(), values);
+            matches.sources.insert(arg_def.id.clone(), ValueSource::EnvVariable);
+        } else if let Some(value) = config_value {
+            // A list gives one value per item, and anything else one value,
+            // split on the Arg's value_delimiter
+            let scalar = |v: &JsonValue| v.as_str().map_or_else(|| v.to_string(), str::to_string);
+            let values = match value {
+                JsonValue::Array(items) => items.iter().map(scalar).collect(),
+                _ => arg_def.split_value(&scalar(value)),
+            };
+            if !arg_def.takes_values() {
+                let possible = ["true".to_string(), "false".to_string()];
+                match values.as_slice() {
+                    [value] if value == "true" => {
+                        matches.flags.insert(arg_def.id.clone());
+                        matches.sources.insert(arg_def.id.clone(), ValueSource::ConfigFile);
+                    }
+                    [value] if value == "false" => {}
+                    _ => return Err(self.invalid_value(arg_def, &scalar(value), &possible)),
+                }
+                return Ok(());
+            }
+            for value in &values {
+                self.check_value(arg_def, value)?;
+            }
+            matches.values.insert(arg_def.id.clone(), values);
+            matches.sources.insert(arg_def.id.clone(), ValueSource::ConfigFile);
+        } else if let Some(ref default) = arg_def.default_value {
+            matches.values.insert(arg_def.id.clone(), arg_def.split_value(default));
+            matches.sources.insert(arg_def.id.clone(), ValueSource::DefaultValue);
+        } else if arg_def.action == ArgAction::SetFalse && !arg_def.is_positional() {
+            // A SetFalse flag is on until it's given
+            matches.flags.insert(arg_def.id.clone());
+            matches.sources.insert(arg_def.id.clone(), ValueSource::DefaultValue);
+        }
+        Ok(())
+    }
+    
+    // The config file named by the config_file_arg's value, parsed as TOML
+    // for a `.toml` file, YAML for a `.yaml` or `.yml` file and JSON
+    // otherwise
+    fn load_config(&self, matches: &ArgMatches) -> Result<Option<BTreeMap<String, JsonValue>>, Error> {
+        let Some(id) = &self.config_file_arg else {
+            return Ok(None);
+        };
+        let Some(path) = matches.value_of(id) else {
+            return Ok(None);
+        };
+        let args = vec![path.to_string()];
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && matches.value_source(id) == Some(ValueSource::DefaultValue) => {
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(self.error(ErrorKind::Io, format!("failed to read config file '{}': {}", path, e), args));
+            }
+        };
+        let parsed = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("toml") => serde_emulator::from_toml::<JsonValue>(&text),
+            Some("yaml" | "yml") => serde_emulator::from_yaml::<JsonValue>(&text),
+            _ => serde_emulator::from_json::<JsonValue>(&text),
+        };
+        match parsed {
+            Ok(JsonValue::Object(map)) => Ok(Some(map)),
+            Ok(_) => {
+                let message = format!("config file '{}' must map argument names to values", path);
+                Err(self.error(ErrorKind::Format, message, args))
+            }
+            Err(e) => Err(self.error(ErrorKind::Format, format!("invalid config file '{}': {}", path, e), args)),
+        }
+    }
+    
+    // Parse `args`, which follow the token at `index` on the command line:
+    // the program name at 0, or the name of this subcommand
+    fn parse_args(self, args: &[String], index: usize, prompter: &mut dyn Prompter) -> Result<ArgMatches, Error> {
+        if self.arg_required_else_help && args.is_empty() {
+            return Err(Error::new(ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand, self.help_text(false)));
+        }
         let mut matches = ArgMatches::new();
+        matches.last_index = index;
         let mut i = 0;
+        // The positional Arg taking values, and how many it has
+        let positionals: Vec<&Arg> = self.args.iter().filter(|a| a.is_positional()).collect();
+        let mut next_positional = 0;
+        let mut taken = 0;
+        // Set after `--`, or once a trailing_var_arg Arg starts, when every
+        // token is a positional value
+        let mut escaped = false;
+        // An Arg with num_args that took its most values from the tokens
+        // just before, which a stray value after them is one too many for
+        let mut filled: Option<&Arg> = None;
         
         while i < args.len() {
             let arg = &args[i];
+            let full_arg = filled.take();
             
-            // Check for subcommand
-            if let Some(subcmd) = self.subcommands.iter().find(|c| c.name == *arg) {
+            if arg == "--" && !escaped {
+                escaped = true;
+                // Values after `--` go to a last(true) Arg, if there is one
+                if let Some(last) = positionals.iter().position(|a| a.last).filter(|&last| last > next_positional) {
+                    next_positional = last;
+                    taken = 0;
+                }
+                matches.next_index();
+                i += 1;
+                continue;
+            }
+            while positionals.get(next_positional).is_some_and(|a| taken >= a.max_values()) {
+                next_positional += 1;
+                taken = 0;
+            }
+            // A token starting with `-` is a value for a positional Arg that
+            // allows hyphen values, unless it names one of the Command's flags
+            let hyphen_value = arg.starts_with('-')
+                && positionals.get(next_positional).is_some_and(|a| a.allow_hyphen_values && !a.last)
+                && !self.is_known_flag(arg);
+            let is_value = escaped || hyphen_value;
+            
+            // Check for subcommand, by name or alias
+            if let Some(subcmd) = self.find_subcommand(arg).filter(|_| !is_value) {
                 let subcmd_args = &args[i+1..];
+                let subcmd = self.enter_subcommand(subcmd);
+                let subcmd_index = matches.next_index();
                 matches.subcommand = Some((
                     subcmd.name.clone(),
-                    Box::new(subcmd.clone().parse_args(subcmd_args)),
+                    Box::new(subcmd.parse_args(subcmd_args, subcmd_index, prompter)?),
                 ));
                 break;
             }
             
-            // Check if it's a flag (starts with --)
-            if arg.starts_with("--") {
-                let flag_name = &arg[2..];
+            // Print help instead of parsing the rest, the Command's own or
+            // that of the subcommand named after `help`
+            if arg == "help" && self.has_help_subcommand() && !is_value {
+                return Err(self.subcommand_help(&args[i + 1..]));
+            }
+            if self.is_help_flag(arg) && !is_value {
+                return Err(self.action_output(ArgAction::Help, arg.starts_with("--")).unwrap());
+            }
+            
+            // Print the version instead of parsing the rest
+            if self.is_version_flag(arg) && !is_value {
+                return Err(self.action_output(ArgAction::Version, true).unwrap());
+            }
+            
+            // Check if it's a flag (starts with --), with its value attached
+            // as `--name=value`
+            if let Some(flag) = arg.strip_prefix("--").filter(|_| !is_value) {
+                let (flag_name, attached) = match flag.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (flag, None),
+                };
                 
                 // Find the argument definition
-                if let Some(arg_def) = self.args.iter().find(|a| a.long == Some(flag_name.to_string())) {
-                    if arg_def.takes_value {
-                        i += 1;
-                        if i < args.len() {
-                            matches.values.insert(arg_def.id.clone(), args[i].clone());
-                        }
-                    } else {
-                        matches.flags.insert(arg_def.id.clone());
+                if let Some(arg_def) = self.args.iter().find(|a| a.long.as_deref() == Some(flag_name)) {
+                    if let Some(output) = self.action_output(arg_def.action, true) {
+                        return Err(output);
+                    }
+                    let values = self.take_values(arg_def, attached, args, &mut i)?;
+                    if arg_def.num_args.is_some() && values.len() == arg_def.max_values() {
+                        filled = Some(arg_def);
                     }
+                    matches.add_occurrence(arg_def, values);
+                } else if self.allow_unknown_args {
+                    matches.unknown.push(arg.clone());
+                    matches.next_index();
+                } else {
+                    return Err(self.unknown_argument(&format!("--{}", flag_name)));
                 }
             } 
-            // Check if it's a short flag (starts with -)
-            else if arg.starts_with("-") && arg.len() == 2 {
-                let flag_char = arg.chars().nth(1).unwrap();
-                
-                // Find the argument definition
-                if let Some(arg_def) = self.args.iter().find(|a| a.short == Some(flag_char)) {
-                    if arg_def.takes_value {
-                        i += 1;
-                        if i < args.len() {
-                            matches.values.insert(arg_def.id.clone(), args[i].clone());
+            // Check if it's a short flag (starts with -), or several grouped
+            // as `-abc`; a lone `-` is a positional value, by convention
+            // standard input. The rest of a group after an Arg that takes a
+            // value is that value, as in `-c=file.toml` or `-cfile.toml`.
+            else if let Some(group) = arg.strip_prefix('-').filter(|g| !g.is_empty() && !is_value) {
+                for (pos, flag_char) in group.char_indices() {
+                    // Find the argument definition
+                    if let Some(arg_def) = self.args.iter().find(|a| a.short == Some(flag_char)) {
+                        if let Some(output) = self.action_output(arg_def.action, false) {
+                            return Err(output);
+                        }
+                        let rest = &group[pos + flag_char.len_utf8()..];
+                        if (arg_def.takes_values() && !rest.is_empty()) || rest.starts_with('=') {
+                            let attached = rest.strip_prefix('=').unwrap_or(rest).to_string();
+                            let values = self.take_values(arg_def, Some(attached), args, &mut i)?;
+                            matches.add_occurrence(arg_def, values);
+                            break;
+                        }
+                        let values = self.take_values(arg_def, None, args, &mut i)?;
+                        if arg_def.num_args.is_some() && values.len() == arg_def.max_values() {
+                            filled = Some(arg_def);
                         }
+                        matches.add_occurrence(arg_def, values);
+                    } else if self.allow_unknown_args {
+                        matches.unknown.push(format!("-{}", flag_char));
+                        matches.next_index();
                     } else {
-                        matches.flags.insert(arg_def.id.clone());
+                        return Err(self.unknown_argument(&format!("-{}", flag_char)));
                     }
                 }
             }
-            // It's a positional argument
+            // It's a positional argument, which also goes to the next
+            // positional Arg with room for it, if there is one; a last(true)
+            // Arg only takes values after `--`
             else {
+                match positionals.get(next_positional).filter(|a| escaped || !a.last) {
+                    Some(arg_def) => {
+                        let values = arg_def.split_value(arg);
+                        for value in &values {
+                            self.check_value(arg_def, value)?;
+                        }
+                        if taken == 0 {
+                            matches.add_occurrence(arg_def, Vec::new());
+                        }
+                        matches.add_values(&arg_def.id, values);
+                        taken += 1;
+                        escaped |= arg_def.trailing_var_arg;
+                    }
+                    None if !self.allow_unknown_args => {
+                        // One value too many for an Arg with num_args, an
+                        // option just before or the last positional
+                        let last = positionals.last().copied().filter(|a| a.num_args.is_some());
+                        return Err(match full_arg.or(last) {
+                            Some(arg_def) => self.unexpected_value(arg_def, arg),
+                            None => self.unknown_argument(arg),
+                        });
+                    }
+                    None => {
+                        matches.next_index();
+                    }
+                }
                 matches.positional.push(arg.clone());
             }
             
             i += 1;
         }
-        
-        // Fill in default values
-        for arg_def in &self.args {
-            if !matches.values.contains_key(&arg_def.id) {
-                if let Some(ref default) = arg_def.default_value {
-                    matches.values.insert(arg_def.id.clone(), default.clone());
-                }
-            }
+        
+        for arg_def in positionals.iter().filter(|a| matches.occurrences_of(&a.id) > 0) {
+            let count = matches.values.get(&arg_def.id).map_or(0, Vec::len);
+            self.check_value_count(arg_def, count)?;
+        }
+        
+        if self.subcommand_required && matches.subcommand.is_none() {
+            let mut names: Vec<&str> = self.subcommands.iter().map(|c| c.name.as_str()).collect();
+            if self.has_help_subcommand() {
+                names.push("help");
+            }
+            let message = format!(
+                "'{}' requires a subcommand but one was not provided\n  [subcommands: {}]",
+                self.display_name(),
+                names.join(", ")
+            );
+            return Err(self.error(ErrorKind::MissingSubcommand, message, vec![self.display_name().to_string()]));
+        }
+        
+        // Fill in Args not given on the command line, starting with the one
+        // naming the config file, which the rest are read from
+        let config_arg = self.config_file_arg.as_ref().and_then(|id| self.args.iter().find(|a| &a.id == id));
+        if let Some(arg_def) = config_arg {
+            self.fill_arg(&mut matches, arg_def, None)?;
+        }
+        let config = self.load_config(&matches)?;
+        for arg_def in &self.args {
+            self.fill_arg(&mut matches, arg_def, config.as_ref())?;
+        }
+        
+        // Global Args take the same value at every level
+        for arg_def in self.args.iter().filter(|a| a.global) {
+            matches.share_global(&arg_def.id);
+        }
+        
+        // Args given with ones they conflict with, or with another from a
+        // group that doesn't allow multiple
+        for arg_def in self.args.iter().filter(|a| matches.occurrences_of(&a.id) > 0) {
+            for id in &arg_def.conflicts_with {
+                if let Some(other) = self.given_args(&matches, id).into_iter().find(|o| o.id != arg_def.id) {
+                    return Err(self.conflict(arg_def, other));
+                }
+            }
+        }
+        for group in &self.groups {
+            let given = self.given_args(&matches, &group.id);
+            if !group.multiple && given.len() > 1 {
+                return Err(self.conflict(given[0], given[1]));
+            }
+            if !given.is_empty() {
+                let ids = given.iter().map(|a| a.id.clone()).collect();
+                matches.values.insert(group.id.clone(), ids);
+            }
+        }
+        
+        // Required Args, including those whose conditions hold, and groups,
+        // then any that given Args and groups require
+        let is_present = |id: &str| self.missing_display(&matches, id).is_none();
+        let required_args = self.args.iter().filter(|a| {
+            let unless = !a.required_unless.is_empty() && !a.required_unless.iter().any(|id| is_present(id));
+            let when = a.required_if_eq.iter().any(|(other, value)| {
+                matches.values_of(other).is_some_and(|values| values.contains(&value.as_str()))
+            });
+            a.required || unless || when
+        });
+        let given_groups: Vec<&ArgGroup> = self.groups.iter()
+            .filter(|g| !self.given_args(&matches, &g.id).is_empty())
+            .collect();
+        let required: Vec<String> = required_args.map(|a| &a.id)
+            .chain(self.groups.iter().filter(|g| g.required).map(|g| &g.id))
+            .chain(self.args.iter().filter(|a| matches.occurrences_of(&a.id) > 0).flat_map(|a| &a.requires))
+            .chain(given_groups.iter().flat_map(|g| &g.requires))
+            .cloned()
+            .collect();
+        
+        // Ask for missing values instead, if the Command prompts; an empty
+        // answer leaves the Arg missing
+        if self.prompt_missing {
+            for arg_def in self.args.iter().filter(|a| required.contains(&a.id) && a.takes_values()) {
+                if matches.is_present(&arg_def.id) {
+                    continue;
+                }
+                let label = arg_def.prompt.clone().unwrap_or_else(|| format!("{}: ", arg_def.id));
+                let answer = prompter.prompt(&label, arg_def.hide_input).unwrap_or_default();
+                if answer.is_empty() {
+                    continue;
+                }
+                let values = arg_def.split_value(&answer);
+                for value in &values {
+                    self.check_value(arg_def, value)?;
+                }
+                matches.values.insert(arg_def.id.clone(), values);
+                matches.sources.insert(arg_def.id.clone(), ValueSource::Prompt);
+            }
+        }
+        
+        let mut missing: Vec<String> = Vec::new();
+        for display in required.iter().filter_map(|id| self.missing_display(&matches, id)) {
+            if !missing.contains(&display) {
+                missing.push(display);
+            }
+        }
+        if !missing.is_empty() {
+            let message = format!(
+                "the following required arguments were not provided:\n  {}",
+                missing.join("\n  ")
+            );
+            return Err(self.error(ErrorKind::MissingRequiredArgument, message, missing));
+        }
+        
+        Ok(matches)
+    }
+}
+
+// Asks for values that prompt_missing finds missing. Closures taking the
+// prompt text and whether to hide the answer work as Prompters, for
+// scripted answers in tests.
+pub trait Prompter {
+    fn prompt(&mut self, label: &str, hidden: bool) -> io::Result<String>;
+}
+
+impl<F: FnMut(&str, bool) -> io::Result<String>> Prompter for F {
+    fn prompt(&mut self, label: &str, hidden: bool) -> io::Result<String> {
+        self(label, hidden)
+    }
+}
+
+// Prompts on stderr and reads a line from stdin, turning echo off with
+// `stty` for hidden answers on a terminal
+pub struct TerminalPrompter;
+
+impl Prompter for TerminalPrompter {
+    fn prompt(&mut self, label: &str, hidden: bool) -> io::Result<String> {
+        let mut stderr = io::stderr();
+        write!(stderr, "{}", label)?;
+        stderr.flush()?;
+        let echo_off = hidden && io::stdin().is_terminal() && set_echo(false);
+        let mut line = String::new();
+        let read = io::stdin().read_line(&mut line);
+        if echo_off {
+            set_echo(true);
+            writeln!(stderr)?;
+        }
+        read?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+// Turn terminal echo on or off, returning whether it worked
+fn set_echo(on: bool) -> bool {
+    std::process::Command::new("stty")
+        .arg(if on { "echo" } else { "-echo" })
+        .stdin(std::process::Stdio::inherit())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+// Stand-ins for the bytes of an OsString argument that aren't valid UTF-8,
+// so the parser can work on Strings: byte `b` becomes U+10FF00 + b. These
+// are private-use characters, which real arguments don't contain.
+const RAW_BYTE_BASE: u32 = 0x10FF00;
+
+fn escape_os(arg: &OsStr) -> String {
+    if let Some(arg) = arg.to_str() {
+        return arg.to_string();
+    }
+    let mut escaped = String::new();
+    for chunk in arg.as_encoded_bytes().utf8_chunks() {
+        escaped.push_str(chunk.valid());
+        escaped.extend(chunk.invalid().iter().filter_map(|&b| char::from_u32(RAW_BYTE_BASE + u32::from(b))));
+    }
+    escaped
+}
+
+// The OsString an escaped value stands for, or None if it's plain UTF-8
+fn unescape_os(value: &str) -> Option<OsString> {
+    if !value.chars().any(|c| u32::from(c) >= RAW_BYTE_BASE) {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    for c in value.chars() {
+        match u32::from(c).checked_sub(RAW_BYTE_BASE) {
+            Some(b) => bytes.push(b as u8),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Some(os_from_bytes(bytes))
+}
+
+#[cfg(unix)]
+fn os_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+// Elsewhere OsStrings aren't plain bytes, so the best left is a lossy copy
+#[cfg(not(unix))]
+fn os_from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+const DEFAULT_HELP_TEMPLATE: &str = "{before-help}{about-with-newline}\n{usage-heading} {usage}\n\n{all-args}{after-help}";
+
+// When to color help and errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    // When writing to a terminal and NO_COLOR isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+// ANSI styles for help and errors, all empty when color is off
+struct Styles {
+    header: &'static str,
+    literal: &'static str,
+    placeholder: &'static str,
+    error: &'static str,
+    reset: &'static str,
+}
+
+impl Styles {
+    fn new(color: bool) -> Self {
+        if color {
+            Styles { header: "\x1b[1;4m", literal: "\x1b[1m", placeholder: "\x1b[3m", error: "\x1b[1;31m", reset: "\x1b[0m" }
+        } else {
+            Styles { header: "", literal: "", placeholder: "", error: "", reset: "" }
+        }
+    }
+    
+    fn header(&self, text: &str) -> String {
+        format!("{}{}{}", self.header, text, self.reset)
+    }
+    
+    // Style a help row's spec, such as `-c, --config <CONFIG>`: names as
+    // literals and value names as placeholders
+    fn spec(&self, spec: &str) -> String {
+        let words: Vec<String> = spec.split(' ')
+            .map(|word| {
+                if word.is_empty() {
+                    String::new()
+                } else if word.starts_with('<') || word.starts_with('[') {
+                    format!("{}{}{}", self.placeholder, word, self.reset)
+                } else {
+                    let (name, comma) = word.strip_suffix(',').map_or((word, ""), |name| (name, ","));
+                    format!("{}{}{}{}", self.literal, name, self.reset, comma)
+                }
+            })
+            .collect();
+        words.join(" ")
+    }
+}
+
+// `text` without the ANSI escape sequences Styles adds, such as the colors
+// in help shown by an Error
+fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip `[`, the parameters and the final letter, such as `m`
+            chars.find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+// The candidate closest to `input` by edit distance, if it's close enough
+// to be a likely typo: at most a third of the candidate's characters
+// changed, added or removed
+fn did_you_mean<'a>(input: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (edit_distance(input, candidate), candidate))
+        .filter(|(distance, candidate)| *distance > 0 && distance * 3 <= candidate.chars().count())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Edit distance: the fewest single-character insertions, deletions,
+// substitutions and swaps of neighbours turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// Add a note such as `[default: 8080]` to help text, after a space
+fn join_help(help: &str, note: &str) -> String {
+    if help.is_empty() {
+        note.to_string()
+    } else {
+        format!("{} {}", help, note)
+    }
+}
+
+impl Clone for Command {
+    fn clone(&self) -> Self {
+        Command {
+            name: self.name.clone(),
+            about: self.about.clone(),
+            long_about: self.long_about.clone(),
+            version: self.version.clone(),
+            author: self.author.clone(),
+            override_usage: self.override_usage.clone(),
+            before_help: self.before_help.clone(),
+            after_help: self.after_help.clone(),
+            args: self.args.clone(),
+            groups: self.groups.clone(),
+            subcommands: self.subcommands.clone(),
+            aliases: self.aliases.clone(),
+            visible_aliases: self.visible_aliases.clone(),
+            disable_version_flag: self.disable_version_flag,
+            propagate_version: self.propagate_version,
+            allow_unknown_args: self.allow_unknown_args,
+            color: self.color,
+            help_template: self.help_template.clone(),
+            prompt_missing: self.prompt_missing,
+            config_file_arg: self.config_file_arg.clone(),
+            subcommand_required: self.subcommand_required,
+            arg_required_else_help: self.arg_required_else_help,
+            bin_name: self.bin_name.clone(),
+        }
+    }
+}
+
+// A check on an Arg's values, giving the reason a value is rejected
+type Validator = Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+// Arg represents a command-line argument
+#[derive(Clone)]
+pub struct Arg {
+    id: String,
+    long: Option<String>,
+    short: Option<char>,
+    help: Option<String>,
+    required: bool,
+    default_value: Option<String>,
+    action: ArgAction,
+    num_args: Option<ValueRange>,
+    value_parser: Option<ValueParser>,
+    validator: Option<Validator>,
+    conflicts_with: Vec<String>,
+    requires: Vec<String>,
+    required_unless: Vec<String>,
+    required_if_eq: Vec<(String, String)>,
+    env: Option<String>,
+    global: bool,
+    trailing_var_arg: bool,
+    last: bool,
+    raw: bool,
+    allow_hyphen_values: bool,
+    value_delimiter: Option<char>,
+    prompt: Option<String>,
+    hide_input: bool,
+    hide: bool,
+    display_order: Option<usize>,
+    help_heading: Option<String>,
+    value_name: Option<String>,
+    value_hint: ValueHint,
+}
+
+impl Arg {
+    pub fn new(id: &str) -> Self {
+        Arg {
+            id: id.to_string(),
+            long: None,
+            short: None,
+            help: None,
+            required: false,
+            default_value: None,
+            action: ArgAction::SetTrue,
+            num_args: None,
+            value_parser: None,
+            validator: None,
+            conflicts_with: Vec::new(),
+            requires: Vec::new(),
+            required_unless: Vec::new(),
+            required_if_eq: Vec::new(),
+            env: None,
+            global: false,
+            trailing_var_arg: false,
+            last: false,
+            raw: false,
+            allow_hyphen_values: false,
+            value_delimiter: None,
+            prompt: None,
+            hide_input: false,
+            hide: false,
+            display_order: None,
+            help_heading: None,
+            value_name: None,
+            value_hint: ValueHint::Unknown,
+        }
+    }
+    
+    pub fn long(mut self, name: &str) -> Self {
+        self.long = Some(name.to_string());
+        self
+    }
+    
+    pub fn short(mut self, c: char) -> Self {
+        self.short = Some(c);
+        self
+    }
+    
+    pub fn help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+    
+    // Shorthand for action(ArgAction::Set), or SetTrue with false; an
+    // Append Arg keeps its action
+    pub fn takes_value(mut self, takes: bool) -> Self {
+        self.set_takes_values(takes);
+        self
+    }
+    
+    pub fn required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+    
+    pub fn default_value(mut self, value: &str) -> Self {
+        self.default_value = Some(value.to_string());
+        self
+    }
+    
+    // Restrict the Arg's values to a set, given as a list such as
+    // `["json", "yaml"]` or with ValueParser::value_enum. This makes the
+    // Arg take a value.
+    pub fn value_parser(mut self, parser: impl Into<ValueParser>) -> Self {
+        self.value_parser = Some(parser.into());
+        self.set_takes_values(true);
+        self
+    }
+    
+    // Check each of the Arg's values with `validator`, failing with
+    // ValueValidation and the reason it gives, as in `port must be
+    // 1-65535`. Values from the environment, the config file and prompts
+    // are checked too; default values aren't. This makes the Arg take a
+    // value.
+    pub fn validator(mut self, validator: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static) -> Self {
+        self.validator = Some(Arc::new(validator));
+        self.set_takes_values(true);
+        self
+    }
+    
+    // Read the value from environment variable `name` when the Arg isn't
+    // given on the command line, before falling back to default_value
+    pub fn env(mut self, name: &str) -> Self {
+        self.env = Some(name.to_string());
+        self
+    }
+    
+    // Make the Arg available to every subcommand below the Command, with
+    // its value visible from the ArgMatches at each level
+    pub fn global(mut self, global: bool) -> Self {
+        self.global = global;
+        self
+    }
+    
+    // For a positional Arg: once it takes its first value, every token left
+    // is one of its values, flags included, as when wrapping another
+    // command. It takes any number of values.
+    pub fn trailing_var_arg(mut self, trailing: bool) -> Self {
+        self.trailing_var_arg = trailing;
+        self
+    }
+    
+    // For the final positional Arg: it only takes values after `--`, which
+    // go straight to it, as in `cargo run -- --release`
+    pub fn last(mut self, last: bool) -> Self {
+        self.last = last;
+        self
+    }
+    
+    // For a positional Arg: capture every token from its first value on
+    // verbatim, flags, `--` and delimiters included, as an exec wrapper
+    // passes its command through. With last(true) capturing starts after
+    // `--` instead.
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self.trailing_var_arg = raw;
+        self.allow_hyphen_values = raw;
+        self
+    }
+    
+    // Let the Arg's values start with `-`, as in `--offset -5`. A positional
+    // Arg with it takes any such token that isn't one of the Command's flags.
+    pub fn allow_hyphen_values(mut self, allow: bool) -> Self {
+        self.allow_hyphen_values = allow;
+        self
+    }
+    
+    // Split each value on `delimiter`, so `--features a,b,c` gives three
+    // values. This makes the Arg take a value.
+    pub fn value_delimiter(mut self, delimiter: char) -> Self {
+        self.value_delimiter = Some(delimiter);
+        self.set_takes_values(true);
+        self
+    }
+    
+    // The text to ask with when prompt_missing prompts for the Arg,
+    // `id: ` by default
+    pub fn prompt(mut self, prompt: &str) -> Self {
+        self.prompt = Some(prompt.to_string());
+        self
+    }
+    
+    // Don't echo the answer to a prompt, for secrets such as passwords
+    pub fn hide_input(mut self, hide: bool) -> Self {
+        self.hide_input = hide;
+        self
+    }
+    
+    // Leave the Arg out of help, usage, man pages and suggestions. It still
+    // parses as usual.
+    pub fn hide(mut self, hide: bool) -> Self {
+        self.hide = hide;
+        self
+    }
+    
+    // Where the Arg is listed in help, lowest first. Args without one
+    // follow, in the order they were added.
+    pub fn display_order(mut self, order: usize) -> Self {
+        self.display_order = Some(order);
+        self
+    }
+    
+    // List the Arg under its own heading in help, such as `Advanced`, after
+    // the Arguments and Options sections
+    pub fn help_heading(mut self, heading: &str) -> Self {
+        self.help_heading = Some(heading.to_string());
+        self
+    }
+    
+    // The placeholder for the Arg's value in help, usage, errors and man
+    // pages, as in `--output <FILE>`; the id in capitals by default
+    pub fn value_name(mut self, name: &str) -> Self {
+        self.value_name = Some(name.to_string());
+        self
+    }
+    
+    // What kind of value the Arg takes, such as a file path or a URL, for
+    // shell completion scripts to offer matching candidates
+    pub fn value_hint(mut self, hint: ValueHint) -> Self {
+        self.value_hint = hint;
+        self
+    }
+    
+    pub fn get_value_hint(&self) -> ValueHint {
+        self.value_hint
+    }
+    
+    // The Arg can't be given with `id`, an Arg or ArgGroup
+    pub fn conflicts_with(mut self, id: &str) -> Self {
+        self.conflicts_with.push(id.to_string());
+        self
+    }
+    
+    // Giving the Arg makes `id`, an Arg or ArgGroup, required
+    pub fn requires(mut self, id: &str) -> Self {
+        self.requires.push(id.to_string());
+        self
+    }
+    
+    // The Arg is required unless `id`, an Arg or ArgGroup, is present.
+    // Given more than once, any of them will do.
+    pub fn required_unless_present(mut self, id: &str) -> Self {
+        self.required_unless.push(id.to_string());
+        self
+    }
+    
+    // The Arg is required when Arg `other` has the value `value`
+    pub fn required_if_eq(mut self, other: &str, value: &str) -> Self {
+        self.required_if_eq.push((other.to_string(), value.to_string()));
+        self
+    }
+    
+    // What the Arg does when it appears: take a value with Set or Append,
+    // or act as a flag with the others. SetTrue, the default, makes it a
+    // plain flag.
+    pub fn action(mut self, action: ArgAction) -> Self {
+        self.action = action;
+        self
+    }
+    
+    // How many values the Arg takes each time it appears: a count such as
+    // `2`, or a range such as `1..` for `--files a b c`
+    pub fn num_args(mut self, range: impl Into<ValueRange>) -> Self {
+        let range = range.into();
+        self.set_takes_values(range.max > 0);
+        self.num_args = Some(range);
+        self
+    }
+    
+    // Whether the Arg takes values: with Set or Append, or as a positional
+    fn takes_values(&self) -> bool {
+        matches!(self.action, ArgAction::Set | ArgAction::Append) || self.is_positional()
+    }
+    
+    // Switch the action to Set for an Arg that should take values, or to
+    // SetTrue for one that shouldn't, unless it already fits
+    fn set_takes_values(&mut self, takes: bool) {
+        let takes_values = matches!(self.action, ArgAction::Set | ArgAction::Append);
+        if takes && !takes_values {
+            self.action = ArgAction::Set;
+        } else if !takes && takes_values {
+            self.action = ArgAction::SetTrue;
+        }
+    }
+    
+    // The fewest values the Arg takes each time it appears: num_args'
+    // minimum, or one for an option that takes values
+    fn min_values(&self) -> usize {
+        match self.num_args {
+            Some(range) => range.min,
+            None if self.takes_values() && !self.is_positional() => 1,
+            None => 0,
+        }
+    }
+    
+    // The most values the Arg takes each time it appears. A positional
+    // that appends takes every value left.
+    fn max_values(&self) -> usize {
+        match self.num_args {
+            Some(range) => range.max,
+            None if self.is_positional() && (self.action == ArgAction::Append || self.trailing_var_arg) => usize::MAX,
+            None => 1,
+        }
+    }
+    
+    // Split a value on the Arg's value_delimiter, if it has one and isn't
+    // raw. A part that starts with a quote, as in `a,"b,c"`, runs to the
+    // closing quote, keeping any delimiters and dropping the quotes.
+    fn split_value(&self, value: &str) -> Vec<String> {
+        let Some(delimiter) = self.value_delimiter.filter(|_| !self.raw) else {
+            return vec![value.to_string()];
+        };
+        let mut parts = Vec::new();
+        let mut part = String::new();
+        let mut at_start = true;
+        let mut quote = None;
+        for c in value.chars() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => part.push(c),
+                None if at_start && (c == '"' || c == '\'') => quote = Some(c),
+                None if c == delimiter => {
+                    parts.push(std::mem::take(&mut part));
+                    at_start = true;
+                    continue;
+                }
+                None => part.push(c),
+            }
+            at_start = false;
+        }
+        parts.push(part);
+        parts
+    }
+    
+    // An Arg with neither a long nor a short name takes the positional
+    // value in its place among the Command's positional Args
+    fn is_positional(&self) -> bool {
+        self.long.is_none() && self.short.is_none()
+    }
+    
+    fn placeholder(&self) -> String {
+        self.value_name.clone().unwrap_or_else(|| self.id.to_uppercase())
+    }
+    
+    // How the Arg is listed in help: `-c, --config <CONFIG>`, with long-only
+    // flags indented to line up, or `[FILE]` for a positional
+    fn help_spec(&self) -> String {
+        let multiple = if self.max_values() > 1 { "..." } else { "" };
+        if self.is_positional() {
+            return if self.required {
+                format!("<{}>{}", self.placeholder(), multiple)
+            } else {
+                format!("[{}]{}", self.placeholder(), multiple)
+            };
+        }
+        let mut spec = match (self.short, &self.long) {
+            (Some(short), Some(long)) => format!("-{}, --{}", short, long),
+            (Some(short), None) => format!("-{}", short),
+            (None, Some(long)) => format!("    --{}", long),
+            (None, None) => unreachable!(),
+        };
+        if self.takes_values() {
+            spec.push_str(&format!(" <{}>{}", self.placeholder(), multiple));
+        }
+        spec
+    }
+    
+    // The help text, followed by the default and possible values
+    fn help_description(&self) -> String {
+        let mut description = self.help.clone().unwrap_or_default();
+        if let Some(default) = &self.default_value {
+            description = join_help(&description, &format!("[default: {}]", default));
+        }
+        if let Some(parser) = &self.value_parser {
+            description = join_help(&description, &format!("[possible values: {}]", parser.possible_values().join(", ")));
         }
-        
-        matches
+        description
     }
-}
-
-impl Clone for Command {
-    fn clone(&self) -> Self {
-        Command {
-            name: self.name.clone(),
-            about: self.about.clone(),
-            version: self.version.clone(),
-            author: self.author.clone(),
-            args: self.args.clone(),
-            subcommands: self.subcommands.clone(),
+    
+    // How the Arg is written in usage and errors: `--input <INPUT>`,
+    // `--verbose`, or `<INPUT>` for a positional
+    fn display(&self) -> String {
+        let value = format!("<{}>", self.placeholder());
+        let name = match (&self.long, self.short) {
+            (Some(long), _) => format!("--{}", long),
+            (None, Some(short)) => format!("-{}", short),
+            (None, None) => return value,
+        };
+        if self.takes_values() {
+            format!("{} {}", name, value)
+        } else {
+            name
         }
     }
 }
 
-// Arg represents a command-line argument
-#[derive(Clone)]
-pub struct Arg {
+// ArgGroup names a set of Args, for choices such as `--json`/`--yaml`.
+// By default at most one of them may be given; `required(true)` means at
+// least one must be. Once parsed, the group's id holds the ids of the Args
+// given, so `value_of("format")` says which one it was.
+#[derive(Debug, Clone)]
+pub struct ArgGroup {
     id: String,
-    long: Option<String>,
-    short: Option<char>,
-    help: Option<String>,
-    takes_value: bool,
+    args: Vec<String>,
     required: bool,
-    default_value: Option<String>,
+    multiple: bool,
+    requires: Vec<String>,
 }
 
-impl Arg {
+impl ArgGroup {
     pub fn new(id: &str) -> Self {
-        Arg {
+        ArgGroup {
             id: id.to_string(),
-            long: None,
-            short: None,
-            help: None,
-            takes_value: false,
+            args: Vec::new(),
             required: false,
-            default_value: None,
+            multiple: false,
+            requires: Vec::new(),
         }
     }
     
-    pub fn long(mut self, name: &str) -> Self {
-        self.long = Some(name.to_string());
+    pub fn arg(mut self, id: &str) -> Self {
+        self.args.push(id.to_string());
         self
     }
     
-    pub fn short(mut self, c: char) -> Self {
-        self.short = Some(c);
+    pub fn args(mut self, ids: &[&str]) -> Self {
+        self.args.extend(ids.iter().map(|id| id.to_string()));
         self
     }
     
-    pub fn help(mut self, help: &str) -> Self {
-        self.help = Some(help.to_string());
+    pub fn required(mut self, required: bool) -> Self {
+        self.required = required;
         self
     }
     
-    pub fn takes_value(mut self, takes: bool) -> Self {
-        self.takes_value = takes;
+    // Giving any of the group's Args makes `id`, an Arg or ArgGroup,
+    // required
+    pub fn requires(mut self, id: &str) -> Self {
+        self.requires.push(id.to_string());
         self
     }
     
-    pub fn required(mut self, required: bool) -> Self {
-        self.required = required;
+    pub fn requires_all(mut self, ids: &[&str]) -> Self {
+        self.requires.extend(ids.iter().map(|id| id.to_string()));
         self
     }
     
-    pub fn default_value(mut self, value: &str) -> Self {
-        self.default_value = Some(value.to_string());
+    // Allow more than one of the group's Args together
+    pub fn multiple(mut self, multiple: bool) -> Self {
+        self.multiple = multiple;
         self
     }
 }
 
+// What an Arg does when it appears on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgAction {
+    // Take a value, replacing any from earlier occurrences
+    Set,
+    // Take a value, added to those from earlier occurrences
+    Append,
+    // A flag that's on once given, or `--flag=false` to turn it off
+    SetTrue,
+    // A flag that's on until given, as for `--no-color`
+    SetFalse,
+    // A flag counting its occurrences, as in `-vvv` for verbosity
+    Count,
+    // Print help, as the built-in --help does
+    Help,
+    // Print the version, as the built-in --version does
+    Version,
+}
+
+// The kind of value an Arg takes, set with value_hint, so completions can
+// suggest files, hosts, commands and so on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueHint {
+    // Nothing known about the value
+    #[default]
+    Unknown,
+    // Free text that can't be completed
+    Other,
+    AnyPath,
+    FilePath,
+    DirPath,
+    ExecutablePath,
+    // The name of a command on the PATH
+    CommandName,
+    // A whole command line in one value, as for `sh -c`
+    CommandString,
+    // A command and its arguments as the rest of the values, for a
+    // trailing_var_arg positional
+    CommandWithArguments,
+    Username,
+    Hostname,
+    Url,
+    EmailAddress,
+}
+
+// The number of values an Arg takes per occurrence, set with num_args
+// from a count or a range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueRange {
+    min: usize,
+    max: usize,
+}
+
+impl ValueRange {
+    pub fn min_values(&self) -> usize {
+        self.min
+    }
+    
+    pub fn max_values(&self) -> usize {
+        self.max
+    }
+}
+
+impl From<usize> for ValueRange {
+    fn from(count: usize) -> Self {
+        ValueRange { min: count, max: count }
+    }
+}
+
+impl From<Range<usize>> for ValueRange {
+    fn from(range: Range<usize>) -> Self {
+        ValueRange { min: range.start, max: range.end.saturating_sub(1) }
+    }
+}
+
+impl From<RangeInclusive<usize>> for ValueRange {
+    fn from(range: RangeInclusive<usize>) -> Self {
+        ValueRange { min: *range.start(), max: *range.end() }
+    }
+}
+
+impl From<RangeFrom<usize>> for ValueRange {
+    fn from(range: RangeFrom<usize>) -> Self {
+        ValueRange { min: range.start, max: usize::MAX }
+    }
+}
+
+impl From<RangeTo<usize>> for ValueRange {
+    fn from(range: RangeTo<usize>) -> Self {
+        ValueRange { min: 0, max: range.end.saturating_sub(1) }
+    }
+}
+
+impl From<RangeToInclusive<usize>> for ValueRange {
+    fn from(range: RangeToInclusive<usize>) -> Self {
+        ValueRange { min: 0, max: range.end }
+    }
+}
+
+impl From<RangeFull> for ValueRange {
+    fn from(_: RangeFull) -> Self {
+        ValueRange { min: 0, max: usize::MAX }
+    }
+}
+
+// The values an Arg accepts, from a list such as `["json", "yaml"]` or
+// a ValueEnum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueParser {
+    possible_values: Vec<String>,
+}
+
+impl ValueParser {
+    // Accept the values of a ValueEnum, such as one defined with value_enum!
+    pub fn value_enum<E: ValueEnum>() -> Self {
+        ValueParser { possible_values: E::possible_values() }
+    }
+    
+    pub fn possible_values(&self) -> &[String] {
+        &self.possible_values
+    }
+}
+
+impl<S: Into<String>, const N: usize> From<[S; N]> for ValueParser {
+    fn from(values: [S; N]) -> Self {
+        ValueParser { possible_values: values.into_iter().map(Into::into).collect() }
+    }
+}
+
+impl<S: Into<String>> From<Vec<S>> for ValueParser {
+    fn from(values: Vec<S>) -> Self {
+        ValueParser { possible_values: values.into_iter().map(Into::into).collect() }
+    }
+}
+
+// An enum parsed from a fixed set of CLI values. value_enum! implements
+// it along with FromStr, so get_one::<T>() returns the variant.
+pub trait ValueEnum: Sized + Clone + 'static {
+    fn value_variants() -> &'static [Self];
+    
+    // The CLI value for the variant
+    fn as_str(&self) -> &'static str;
+    
+    fn from_value(value: &str, ignore_case: bool) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .find(|v| if ignore_case { v.as_str().eq_ignore_ascii_case(value) } else { v.as_str() == value })
+            .cloned()
+    }
+    
+    fn possible_values() -> Vec<String> {
+        Self::value_variants().iter().map(|v| v.as_str().to_string()).collect()
+    }
+}
+
+// Define an enum with a CLI value per variant, implementing ValueEnum and
+// FromStr for it. The enum must derive Clone, and ValueEnum must be in
+// scope where it's used, as `use clap_emulator::*` brings in:
+//
+//     value_enum! {
+//         #[derive(Debug, Clone, Copy, PartialEq)]
+//         pub enum Format {
+//             Json => "json",
+//             Yaml => "yaml",
+//         }
+//     }
+#[macro_export]
+macro_rules! value_enum {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident { $($variant:ident => $value:literal),* $(,)? }) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($variant),*
+        }
+        
+        impl ValueEnum for $name {
+            fn value_variants() -> &'static [Self] {
+                &[$($name::$variant),*]
+            }
+            
+            fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $value),*
+                }
+            }
+        }
+        
+        impl ::std::str::FromStr for $name {
+            type Err = String;
+            
+            fn from_str(value: &str) -> Result<Self, String> {
+                <$name as ValueEnum>::from_value(value, false)
+                    .ok_or_else(|| format!("invalid value '{}'", value))
+            }
+        }
+    };
+}
+
+// Where an Arg's value came from, in increasing precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValueSource {
+    DefaultValue,
+    // A value from the file named by config_file_arg
+    ConfigFile,
+    EnvVariable,
+    // An answer to a prompt_missing prompt
+    Prompt,
+    CommandLine,
+}
+
+// Everything ArgMatches holds for one Arg, for sharing global Args
+// between levels
+#[derive(Clone)]
+struct ArgState {
+    values: Option<Vec<String>>,
+    indices: Option<Vec<usize>>,
+    flag: bool,
+    occurrences: u64,
+    source: Option<ValueSource>,
+}
+
 // ArgMatches holds parsed arguments
+#[derive(Debug)]
 pub struct ArgMatches {
-    values: HashMap<String, String>,
+    values: HashMap<String, Vec<String>>,
+    // How many times each Arg appeared on the command line
+    occurrences: HashMap<String, u64>,
+    // Where each Arg's flags and values came on the command line, and the
+    // last index given out while parsing
+    indices: HashMap<String, Vec<usize>>,
+    last_index: usize,
+    sources: HashMap<String, ValueSource>,
     flags: std::collections::HashSet<String>,
+    // Values that weren't valid UTF-8, as given; value_of has lossy copies
+    raw: HashMap<String, Vec<OsString>>,
     positional: Vec<String>,
+    unknown: Vec<String>,
     subcommand: Option<(String, Box<ArgMatches>)>,
 }
 
@@ -210,22 +2037,189 @@ impl ArgMatches {
     fn new() -> Self {
         ArgMatches {
             values: HashMap::new(),
+            occurrences: HashMap::new(),
+            indices: HashMap::new(),
+            last_index: 0,
+            sources: HashMap::new(),
             flags: std::collections::HashSet::new(),
+            raw: HashMap::new(),
             positional: Vec::new(),
+            unknown: Vec::new(),
             subcommand: None,
         }
     }
     
+    // Record one occurrence of `arg` and the values it was given. The flag
+    // takes the next index, then each value one after it.
+    fn add_occurrence(&mut self, arg: &Arg, values: Vec<String>) {
+        *self.occurrences.entry(arg.id.clone()).or_insert(0) += 1;
+        self.sources.insert(arg.id.clone(), ValueSource::CommandLine);
+        let flag_index = if arg.is_positional() { None } else { Some(self.next_index()) };
+        if !arg.takes_values() {
+            // A SetTrue flag turns on and a SetFalse one off, unless given
+            // `=true` or `=false`; the last occurrence wins
+            let on = match values.first() {
+                Some(value) => value == "true",
+                None => arg.action != ArgAction::SetFalse,
+            };
+            if on {
+                self.flags.insert(arg.id.clone());
+            } else {
+                self.flags.remove(&arg.id);
+            }
+            self.indices.entry(arg.id.clone()).or_default().extend(flag_index);
+        } else if !values.is_empty() {
+            if arg.action != ArgAction::Append {
+                self.values.remove(&arg.id);
+                self.indices.remove(&arg.id);
+            }
+            self.add_values(&arg.id, values);
+        }
+    }
+    
+    // Add values for Arg `id`, each at the next index
+    fn add_values(&mut self, id: &str, values: Vec<String>) {
+        let indices: Vec<usize> = values.iter().map(|_| self.next_index()).collect();
+        self.indices.entry(id.to_string()).or_default().extend(indices);
+        self.values.entry(id.to_string()).or_default().extend(values);
+    }
+    
+    // After try_get_matches_from_os, turn escaped values back into the
+    // OsStrings given, leaving lossy Strings in their place, at every level
+    fn unescape_raw(&mut self) {
+        for (id, values) in self.values.iter_mut() {
+            if !values.iter().any(|v| unescape_os(v).is_some()) {
+                continue;
+            }
+            let raw: Vec<OsString> = values.iter().map(|v| unescape_os(v).unwrap_or_else(|| OsString::from(v))).collect();
+            for (value, raw) in values.iter_mut().zip(&raw) {
+                *value = raw.to_string_lossy().into_owned();
+            }
+            self.raw.insert(id.clone(), raw);
+        }
+        for arg in self.positional.iter_mut().chain(self.unknown.iter_mut()) {
+            if let Some(raw) = unescape_os(arg) {
+                *arg = raw.to_string_lossy().into_owned();
+            }
+        }
+        if let Some((_, subcommand)) = &mut self.subcommand {
+            subcommand.unescape_raw();
+        }
+    }
+    
+    fn next_index(&mut self) -> usize {
+        self.last_index += 1;
+        self.last_index
+    }
+    
+    fn arg_state(&self, id: &str) -> ArgState {
+        ArgState {
+            values: self.values.get(id).cloned(),
+            indices: self.indices.get(id).cloned(),
+            flag: self.flags.contains(id),
+            occurrences: self.occurrences_of(id),
+            source: self.value_source(id),
+        }
+    }
+    
+    fn set_arg_state(&mut self, id: &str, state: &ArgState) {
+        match &state.values {
+            Some(values) => self.values.insert(id.to_string(), values.clone()),
+            None => self.values.remove(id),
+        };
+        match &state.indices {
+            Some(indices) => self.indices.insert(id.to_string(), indices.clone()),
+            None => self.indices.remove(id),
+        };
+        if state.flag {
+            self.flags.insert(id.to_string());
+        } else {
+            self.flags.remove(id);
+        }
+        self.occurrences.insert(id.to_string(), state.occurrences);
+        match state.source {
+            Some(source) => self.sources.insert(id.to_string(), source),
+            None => self.sources.remove(id),
+        };
+    }
+    
+    // Give global Arg `id` the same state here and in every subcommand
+    // below: that from the highest ValueSource, the deepest on a tie since
+    // it came last on the command line
+    fn share_global(&mut self, id: &str) {
+        let mut best = self.arg_state(id);
+        let mut level = self.subcommand.as_ref().map(|(_, m)| m.as_ref());
+        while let Some(m) = level {
+            let state = m.arg_state(id);
+            if state.source >= best.source {
+                best = state;
+            }
+            level = m.subcommand.as_ref().map(|(_, m)| m.as_ref());
+        }
+        if best.source.is_none() {
+            return;
+        }
+        let mut level = Some(self);
+        while let Some(m) = level {
+            m.set_arg_state(id, &best);
+            level = m.subcommand.as_mut().map(|(_, m)| m.as_mut());
+        }
+    }
+    
+    // The first value, parsed into T
     pub fn get_one<T: std::str::FromStr>(&self, id: &str) -> Option<T> {
-        self.values.get(id).and_then(|v| v.parse().ok())
+        self.value_of(id).and_then(|v| v.parse().ok())
+    }
+    
+    // Every value, parsed into T; None if there are none or one doesn't parse
+    pub fn get_many<T: std::str::FromStr>(&self, id: &str) -> Option<Vec<T>> {
+        self.values.get(id).and_then(|values| values.iter().map(|v| v.parse().ok()).collect())
     }
     
     pub fn value_of(&self, id: &str) -> Option<&str> {
-        self.values.get(id).map(|s| s.as_str())
+        self.values.get(id).and_then(|v| v.first()).map(|s| s.as_str())
+    }
+    
+    // The first value exactly as given, even if it isn't valid UTF-8, when
+    // parsed with try_get_matches_from_os
+    pub fn get_raw(&self, id: &str) -> Option<&OsStr> {
+        match self.raw.get(id) {
+            Some(raw) => raw.first().map(OsString::as_os_str),
+            None => self.value_of(id).map(OsStr::new),
+        }
+    }
+    
+    pub fn values_of(&self, id: &str) -> Option<Vec<&str>> {
+        self.values.get(id).map(|values| values.iter().map(|s| s.as_str()).collect())
+    }
+    
+    // Where the Arg's values, or each time it was given for a flag, came
+    // on the command line. Every flag and value takes the next index after
+    // the program name at 0, as if grouped short flags, attached values and
+    // delimited values were each separate arguments, so `-xf a,b` gives
+    // `x` [1] and `f` [3, 4]. None for values not from the command line.
+    pub fn indices_of(&self, id: &str) -> Option<Vec<usize>> {
+        self.indices.get(id).cloned()
+    }
+    
+    // Where the Arg's value came from, or None if it has none
+    pub fn value_source(&self, id: &str) -> Option<ValueSource> {
+        self.sources.get(id).copied()
+    }
+    
+    // How many times the Arg was given on the command line; defaults
+    // don't count
+    pub fn occurrences_of(&self, id: &str) -> u64 {
+        self.occurrences.get(id).copied().unwrap_or(0)
+    }
+    
+    // The count for an ArgAction::Count flag, capped at 255
+    pub fn get_count(&self, id: &str) -> u8 {
+        u8::try_from(self.occurrences_of(id)).unwrap_or(u8::MAX)
     }
     
     pub fn is_present(&self, id: &str) -> bool {
-        self.flags.contains(id) || self.values.contains_key(id)
+        self.flags.contains(id) || self.values.contains_key(id) || self.occurrences_of(id) > 0
     }
     
     pub fn get_flag(&self, id: &str) -> bool {
@@ -243,6 +2237,350 @@ impl ArgMatches {
     pub fn get_positional(&self, index: usize) -> Option<&str> {
         self.positional.get(index).map(|s| s.as_str())
     }
+    
+    // Flags skipped because the Command allows unknown args, in order
+    pub fn unknown_args(&self) -> &[String] {
+        &self.unknown
+    }
+}
+
+// Why parsing stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // --help, -h or the help subcommand was given; the message is the help
+    DisplayHelp,
+    // --version or -V was given; the message is `name version`
+    DisplayVersion,
+    // arg_required_else_help and no arguments; the message is the help,
+    // shown as an error
+    DisplayHelpOnMissingArgumentOrSubcommand,
+    // Required arguments were left out
+    MissingRequiredArgument,
+    // A flag the Command has no Arg for, or a value with no positional Arg
+    // left to take it
+    UnknownArgument,
+    // A value where the Command expects a subcommand name
+    InvalidSubcommand,
+    // subcommand_required and no subcommand
+    MissingSubcommand,
+    // A value for an Arg that takes no more, such as `-v=2` for a Count
+    // flag or one past num_args' maximum
+    TooManyValues,
+    // Fewer values than num_args' minimum
+    TooFewValues,
+    // A value outside the Arg's possible values
+    InvalidValue,
+    // A value the Arg's validator rejected; the message gives its reason
+    ValueValidation,
+    // Args given together that conflict, or are in a group that doesn't
+    // allow multiple
+    ArgumentConflict,
+    // The config file couldn't be read
+    Io,
+    // The config file couldn't be parsed
+    Format,
+}
+
+// Error returned by try_get_matches_from. Apart from DisplayHelp,
+// DisplayVersion and DisplayHelpOnMissingArgumentOrSubcommand it is shown
+// as `error: message` followed by the usage line.
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+    args: Vec<String>,
+    usage: Option<String>,
+    // The Command's help flag, if it has one, for the closing hint
+    help_flag: Option<&'static str>,
+    color: bool,
+}
+
+impl Error {
+    fn new(kind: ErrorKind, message: String) -> Self {
+        Error { kind, message, args: Vec::new(), usage: None, help_flag: None, color: false }
+    }
+    
+    fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+    
+    fn with_usage(mut self, usage: String) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+    
+    // Show values from try_get_matches_from_os that aren't valid UTF-8
+    // lossily, rather than escaped
+    fn unescape_raw(mut self) -> Self {
+        let lossy = |text: &str| unescape_os(text).map(|raw| raw.to_string_lossy().into_owned());
+        if let Some(message) = lossy(&self.message) {
+            self.message = message;
+        }
+        for arg in &mut self.args {
+            if let Some(value) = lossy(arg) {
+                *arg = value;
+            }
+        }
+        self
+    }
+    
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+    
+    // The error as Display shows it, as plain text whatever the color
+    // setting and terminal, for comparing against a snapshot in tests
+    pub fn render(&self) -> String {
+        let plain = Error { color: false, ..self.clone() };
+        strip_ansi(&plain.to_string())
+    }
+    
+    // The arguments the error is about, as written in usage, such as the
+    // missing ones
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+    
+    pub fn usage(&self) -> Option<&str> {
+        self.usage.as_deref()
+    }
+    
+    // Help and version requests go to stdout; real errors, and the help
+    // for arg_required_else_help, to stderr
+    pub fn use_stderr(&self) -> bool {
+        !matches!(self.kind, ErrorKind::DisplayHelp | ErrorKind::DisplayVersion)
+    }
+    
+    // 0 for help and version requests, 2 for usage errors, as clap exits
+    pub fn exit_code(&self) -> i32 {
+        if self.use_stderr() {
+            2
+        } else {
+            0
+        }
+    }
+    
+    // Print the error to stdout or stderr, as use_stderr says
+    pub fn print(&self) {
+        if self.use_stderr() {
+            eprintln!("{}", self);
+        } else {
+            println!("{}", self);
+        }
+    }
+    
+    // Print the error and exit the process with exit_code
+    pub fn exit(&self) -> ! {
+        self.print();
+        std::process::exit(self.exit_code())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let displays = [ErrorKind::DisplayHelp, ErrorKind::DisplayVersion, ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand];
+        if displays.contains(&self.kind) {
+            return write!(f, "{}", self.message);
+        }
+        let styles = Styles::new(self.color);
+        write!(f, "{}error:{} {}", styles.error, styles.reset, self.message)?;
+        if let Some(usage) = &self.usage {
+            let line = usage.strip_prefix("Usage:").unwrap_or(usage);
+            write!(f, "\n\n{}{}", styles.header("Usage:"), line)?;
+        }
+        if let Some(flag) = self.help_flag {
+            write!(f, "\n\nFor more information, try '{}{}{}'.", styles.literal, flag, styles.reset)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Man page generation, in the manner of clap_mangen: Man renders a Command
+// to roff, and generate_to writes a page for a Command and each of its
+// subcommands, named like `git-remote.1`
+pub mod mangen {
+    use super::{Arg, Command};
+    use std::fs;
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+    
+    pub struct Man {
+        cmd: Command,
+        section: String,
+    }
+    
+    impl Man {
+        pub fn new(cmd: Command) -> Self {
+            Man { cmd, section: "1".to_string() }
+        }
+        
+        // The manual section, `1` (user commands) by default
+        pub fn section(mut self, section: &str) -> Self {
+            self.section = section.to_string();
+            self
+        }
+        
+        // The page's file name, such as `git-remote.1` for a subcommand
+        pub fn file_name(&self) -> String {
+            format!("{}.{}", self.page_name(), self.section)
+        }
+        
+        // The NAME and SYNOPSIS sections, DESCRIPTION when the Command has a
+        // long_about, OPTIONS and SUBCOMMANDS, then VERSION and AUTHORS when
+        // the Command has them
+        pub fn render(&self, out: &mut dyn Write) -> io::Result<()> {
+            let cmd = &self.cmd;
+            let page = self.page_name();
+            let title = match &cmd.version {
+                Some(version) => format!("{} {}", page, version),
+                None => page.clone(),
+            };
+            writeln!(out, ".TH {} {} \"{}\"", escape(&page.to_uppercase()), self.section, escape(&title))?;
+            
+            writeln!(out, ".SH NAME")?;
+            match &cmd.about {
+                Some(about) => writeln!(out, "{} \\- {}", escape(&page), escape(about))?,
+                None => writeln!(out, "{}", escape(&page))?,
+            }
+            
+            // Options, then positionals, in both the synopsis and OPTIONS
+            let builtin_flags = cmd.builtin_flags();
+            let (positionals, named): (Vec<&Arg>, Vec<&Arg>) = cmd.help_args().into_iter().partition(|a| a.is_positional());
+            let args: Vec<&Arg> = named.into_iter().chain(&builtin_flags).chain(positionals).collect();
+            
+            writeln!(out, ".SH SYNOPSIS")?;
+            let mut synopsis = format!("\\fB{}\\fR", escape(cmd.display_name()));
+            for arg in &args {
+                synopsis.push(' ');
+                synopsis.push_str(&synopsis_item(arg));
+            }
+            if !cmd.subcommands.is_empty() {
+                synopsis.push_str(" [\\fIsubcommands\\fR]");
+            }
+            writeln!(out, "{}", synopsis)?;
+            
+            if let Some(long_about) = &cmd.long_about {
+                writeln!(out, ".SH DESCRIPTION")?;
+                for paragraph in long_about.split("\n\n") {
+                    writeln!(out, ".PP")?;
+                    for line in paragraph.lines() {
+                        writeln!(out, "{}", text(line))?;
+                    }
+                }
+            }
+            
+            writeln!(out, ".SH OPTIONS")?;
+            for arg in &args {
+                writeln!(out, ".TP")?;
+                writeln!(out, "{}", option_item(arg))?;
+                let description = arg.help_description();
+                if !description.is_empty() {
+                    writeln!(out, "{}", text(&description))?;
+                }
+            }
+            
+            if !cmd.subcommands.is_empty() {
+                writeln!(out, ".SH SUBCOMMANDS")?;
+                for subcmd in &cmd.subcommands {
+                    writeln!(out, ".TP")?;
+                    writeln!(out, "{}\\-{}({})", escape(&page), escape(&subcmd.name), self.section)?;
+                    if let Some(about) = &subcmd.about {
+                        writeln!(out, "{}", text(about))?;
+                    }
+                }
+            }
+            
+            if let Some(version) = &cmd.version {
+                writeln!(out, ".SH VERSION")?;
+                writeln!(out, "v{}", escape(version))?;
+            }
+            if let Some(author) = &cmd.author {
+                writeln!(out, ".SH AUTHORS")?;
+                writeln!(out, "{}", text(author))?;
+            }
+            Ok(())
+        }
+        
+        fn page_name(&self) -> String {
+            self.cmd.display_name().replace(' ', "-")
+        }
+    }
+    
+    // Write a page for `cmd` and, recursively, each of its subcommands into
+    // `out_dir`, returning the paths written
+    pub fn generate_to(cmd: Command, out_dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+        let mut written = Vec::new();
+        write_pages(&cmd, out_dir.as_ref(), &mut written)?;
+        Ok(written)
+    }
+    
+    fn write_pages(cmd: &Command, out_dir: &Path, written: &mut Vec<PathBuf>) -> io::Result<()> {
+        let man = Man::new(cmd.clone());
+        let path = out_dir.join(man.file_name());
+        let mut page = Vec::new();
+        man.render(&mut page)?;
+        fs::write(&path, page)?;
+        written.push(path);
+        for subcmd in &cmd.subcommands {
+            write_pages(&cmd.enter_subcommand(subcmd), out_dir, written)?;
+        }
+        Ok(())
+    }
+    
+    // Escape text for roff: backslashes, and hyphens so they aren't
+    // rendered as line-breaking dashes
+    fn escape(text: &str) -> String {
+        text.replace('\\', "\\\\").replace('-', "\\-")
+    }
+    
+    // Escape a line of running text, which mustn't start with a control
+    // character
+    fn text(line: &str) -> String {
+        let escaped = escape(line);
+        if escaped.starts_with('.') || escaped.starts_with('\'') {
+            format!("\\&{}", escaped)
+        } else {
+            escaped
+        }
+    }
+    
+    // The Arg's forms in bold, then its value name in italics
+    fn option_item(arg: &Arg) -> String {
+        let multiple = if arg.max_values() > 1 { "..." } else { "" };
+        let value = format!("\\fI{}\\fR", arg.placeholder());
+        if arg.is_positional() {
+            return format!("<{}>{}", value, multiple);
+        }
+        let forms: Vec<String> = arg.short.map(|short| format!("\\fB\\-{}\\fR", short))
+            .into_iter()
+            .chain(arg.long.as_ref().map(|long| format!("\\fB\\-\\-{}\\fR", escape(long))))
+            .collect();
+        let mut item = forms.join(", ");
+        if arg.takes_values() {
+            item.push_str(&format!(" <{}>{}", value, multiple));
+        }
+        item
+    }
+    
+    // How the Arg appears in the synopsis: forms separated by `|`, and in
+    // brackets unless required
+    fn synopsis_item(arg: &Arg) -> String {
+        let item = if arg.is_positional() {
+            option_item(arg)
+        } else {
+            option_item(arg).replacen(", ", "|", 1)
+        };
+        if arg.required {
+            item
+        } else if arg.is_positional() {
+            format!("[{}]", item.trim_start_matches('<').replacen('>', "", 1))
+        } else {
+            format!("[{}]", item)
+        }
+    }
 }
 
 fn main() {
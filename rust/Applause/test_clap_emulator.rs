@@ -1,6 +1,7 @@
 // Developed by PowerShield, as an alternative to Clap
 
 #[path = "clap_emulator.rs"]
+#[allow(dead_code)]
 mod clap_emulator;
 
 use clap_emulator::*;
@@ -356,6 +357,1440 @@ fn main() {
         Ok(())
     }));
     
+    // Test 21: Built-in --version flag
+    results.push(test_runner("Version flag", || {
+        let app = Command::new("myapp")
+            .version("1.2.3")
+            .arg(Arg::new("config").long("config").takes_value(true));
+        
+        for flag in ["--version", "-V"] {
+            match app.clone().try_get_matches_from(&["myapp", flag, "--config", "a.toml"]) {
+                Err(e) if e.kind() == ErrorKind::DisplayVersion && e.to_string() == "myapp 1.2.3" => {}
+                Err(e) => return Err(format!("Unexpected error for {}: {}", flag, e)),
+                Ok(_) => return Err(format!("{} should stop parsing", flag)),
+            }
+        }
+        
+        // A subcommand reports its own version
+        let app = Command::new("git")
+            .version("2.0")
+            .subcommand(Command::new("remote").version("0.9"));
+        match app.try_get_matches_from(&["git", "remote", "--version"]) {
+            Err(e) if e.to_string() == "remote 0.9" => {}
+            other => return Err(format!("Expected the subcommand version, got {:?}", other.err())),
+        }
+        
+        // As a value, or with no version set, it is not the flag
+        let matches = Command::new("myapp")
+            .version("1.0")
+            .arg(Arg::new("config").long("config").takes_value(true))
+            .try_get_matches_from(&["myapp", "--config", "--version"])
+            .map_err(|e| e.to_string())?;
+        if matches.value_of("config") != Some("--version") {
+            return Err("--version should be the option's value".to_string());
+        }
+        match Command::new("plain").try_get_matches_from(&["plain", "--version"]) {
+            Err(e) if e.kind() == ErrorKind::UnknownArgument => Ok(()),
+            other => Err(format!("Command without a version has no version flag, got {:?}", other)),
+        }
+    }));
+    
+    // Test 22: Disabling or overriding the version flag
+    results.push(test_runner("Disable or override version flag", || {
+        let disabled = Command::new("myapp")
+            .version("1.0")
+            .disable_version_flag(true)
+            .try_get_matches_from(&["myapp", "--version"]);
+        if !matches!(disabled, Err(ref e) if e.kind() == ErrorKind::UnknownArgument) {
+            return Err(format!("Disabled flag should be unknown, got {:?}", disabled));
+        }
+        
+        // An Arg of the Command's own takes the names over
+        let matches = Command::new("myapp")
+            .version("1.0")
+            .arg(Arg::new("verbose").short('V'))
+            .arg(Arg::new("version").long("version").takes_value(true))
+            .try_get_matches_from(&["myapp", "-V", "--version", "2"])
+            .map_err(|e| e.to_string())?;
+        if !matches.get_flag("verbose") || matches.value_of("version") != Some("2") {
+            return Err("Own args should override the version flag".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 23: Missing required arguments
+    results.push(test_runner("Required argument validation", || {
+        let app = Command::new("copy")
+            .arg(Arg::new("force").long("force"))
+            .arg(Arg::new("input").long("input").short('i').takes_value(true).required(true))
+            .arg(Arg::new("output").required(true))
+            .arg(Arg::new("mode").takes_value(true));
+        
+        let e = match app.clone().try_get_matches_from(&["copy", "--force"]) {
+            Err(e) => e,
+            Ok(_) => return Err("Missing arguments should be an error".to_string()),
+        };
+        if e.kind() != ErrorKind::MissingRequiredArgument || e.args() != ["--input <INPUT>", "<OUTPUT>"] {
+            return Err(format!("Unexpected error: {:?}", e));
+        }
+        let expected = "error: the following required arguments were not provided:\n  --input <INPUT>\n  <OUTPUT>\n\nUsage: copy [OPTIONS] --input <INPUT> <OUTPUT> [MODE]\n\nFor more information, try '--help'.";
+        if e.to_string() != expected {
+            return Err(format!("Unexpected message:\n{}", e));
+        }
+        
+        // Only what's missing is listed
+        match app.clone().try_get_matches_from(&["copy", "-i", "a.txt"]) {
+            Err(e) if e.args() == ["<OUTPUT>"] => {}
+            other => return Err(format!("Expected only <OUTPUT> missing, got {:?}", other.err())),
+        }
+        let matches = app.try_get_matches_from(&["copy", "b.txt", "-i", "a.txt", "fast"])
+            .map_err(|e| e.to_string())?;
+        if matches.value_of("output") != Some("b.txt") || matches.value_of("mode") != Some("fast") || matches.get_positional(1) != Some("fast") {
+            return Err("Positional args not assigned in order".to_string());
+        }
+        
+        // A default value satisfies the requirement
+        Command::new("serve")
+            .arg(Arg::new("port").long("port").takes_value(true).required(true).default_value("80"))
+            .try_get_matches_from(&["serve"])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }));
+    
+    // Test 24: Required arguments of subcommands
+    results.push(test_runner("Required arguments in subcommands", || {
+        let app = Command::new("git")
+            .subcommand(Command::new("commit")
+                .arg(Arg::new("message").short('m').takes_value(true).required(true)));
+        
+        match app.clone().try_get_matches_from(&["git", "commit"]) {
+            Err(e) if e.usage() == Some("Usage: git commit -m <MESSAGE>") => {}
+            other => return Err(format!("Unexpected result: {:?}", other.err())),
+        }
+        // Not checked unless the subcommand is used
+        let matches = app.try_get_matches_from(&["git"]).map_err(|e| e.to_string())?;
+        if matches.subcommand_name().is_some() {
+            return Err("No subcommand expected".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 25: Unknown flags and unexpected values
+    results.push(test_runner("Unknown arguments", || {
+        let app = Command::new("tool")
+            .arg(Arg::new("verbose").long("verbose").short('v'))
+            .arg(Arg::new("file"));
+        
+        let cases = [
+            (vec!["tool", "--color"], "--color"),
+            (vec!["tool", "-x"], "-x"),
+            (vec!["tool", "-vx"], "-x"),
+            (vec!["tool", "a.txt", "b.txt"], "b.txt"),
+        ];
+        for (args, token) in cases {
+            match app.clone().try_get_matches_from(&args) {
+                Err(e) if e.kind() == ErrorKind::UnknownArgument && e.args() == [token] => {
+                    let expected = format!("error: unexpected argument '{}' found\n\nUsage: tool [OPTIONS] [FILE]\n\nFor more information, try '--help'.", token);
+                    if e.to_string() != expected {
+                        return Err(format!("Unexpected message:\n{}", e));
+                    }
+                }
+                other => return Err(format!("Expected {} to be unknown, got {:?}", token, other)),
+            }
+        }
+        // A lone `-` is a value
+        let matches = app.try_get_matches_from(&["tool", "-"]).map_err(|e| e.to_string())?;
+        if matches.value_of("file") != Some("-") {
+            return Err("`-` should be the file".to_string());
+        }
+        
+        match Command::new("git").subcommand(Command::new("add")).try_get_matches_from(&["git", "ad"]) {
+            Err(e) if e.kind() == ErrorKind::InvalidSubcommand && e.to_string().starts_with("error: unrecognized subcommand 'ad'") => Ok(()),
+            other => Err(format!("Expected an invalid subcommand, got {:?}", other)),
+        }
+    }));
+    
+    // Test 26: Opting out with allow_unknown_args
+    results.push(test_runner("allow_unknown_args", || {
+        let matches = Command::new("wrapper")
+            .allow_unknown_args(true)
+            .arg(Arg::new("dry-run").long("dry-run"))
+            .try_get_matches_from(&["wrapper", "--color", "--dry-run", "-j", "build"])
+            .map_err(|e| e.to_string())?;
+        if !matches.get_flag("dry-run") || matches.unknown_args() != ["--color", "-j"] || matches.get_positional(0) != Some("build") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        Ok(())
+    }));
+    
+    // Test 27: Collecting repeated and multi-value arguments
+    results.push(test_runner("Multiple values", || {
+        let matches = Command::new("build")
+            .arg(Arg::new("include").long("include").short('I').action(ArgAction::Append))
+            .arg(Arg::new("files").long("files").num_args(1..))
+            .arg(Arg::new("level").long("level").takes_value(true))
+            .arg(Arg::new("verbose").long("verbose").short('v'))
+            .try_get_matches_from(&["build", "--include", "a", "-I", "b", "--files", "x.rs", "y.rs", "z.rs", "--level", "1", "--level", "2", "-v", "-v", "-v"])
+            .map_err(|e| e.to_string())?;
+        if matches.get_many::<String>("include") != Some(vec!["a".to_string(), "b".to_string()]) {
+            return Err(format!("Unexpected includes: {:?}", matches.values_of("include")));
+        }
+        if matches.values_of("files") != Some(vec!["x.rs", "y.rs", "z.rs"]) {
+            return Err(format!("Unexpected files: {:?}", matches.values_of("files")));
+        }
+        // Set keeps the last occurrence's value
+        if matches.get_many::<u32>("level") != Some(vec![2]) || matches.occurrences_of("level") != 2 {
+            return Err(format!("Unexpected level: {:?}", matches.values_of("level")));
+        }
+        if matches.occurrences_of("verbose") != 3 || matches.occurrences_of("include") != 2 || matches.occurrences_of("files") != 1 {
+            return Err("Wrong occurrence counts".to_string());
+        }
+        if matches.get_many::<u32>("files").is_some() || matches.get_many::<String>("missing").is_some() {
+            return Err("get_many should be None for unparseable or absent values".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 28: Positionals with several values
+    results.push(test_runner("Multiple positional values", || {
+        let app = Command::new("cp")
+            .arg(Arg::new("sources").num_args(1..=2))
+            .arg(Arg::new("dest"))
+            .arg(Arg::new("rest").action(ArgAction::Append));
+        let matches = app.clone().try_get_matches_from(&["cp", "a", "b", "out", "x", "y"]).map_err(|e| e.to_string())?;
+        if matches.values_of("sources") != Some(vec!["a", "b"]) || matches.value_of("dest") != Some("out") || matches.values_of("rest") != Some(vec!["x", "y"]) {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        // A fixed count stops at the flag after it; defaults don't count as occurrences
+        let matches = Command::new("move")
+            .arg(Arg::new("point").long("point").num_args(2))
+            .arg(Arg::new("speed").long("speed").default_value("1"))
+            .try_get_matches_from(&["move", "--point", "3", "4"])
+            .map_err(|e| e.to_string())?;
+        if matches.get_many::<i32>("point") != Some(vec![3, 4]) || matches.occurrences_of("speed") != 0 || matches.value_of("speed") != Some("1") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        Ok(())
+    }));
+    
+    // Test 29: Grouped short flags
+    results.push(test_runner("Grouped short flags", || {
+        let matches = Command::new("tar")
+            .arg(Arg::new("extract").short('x'))
+            .arg(Arg::new("verbose").short('v'))
+            .arg(Arg::new("gzip").short('z'))
+            .arg(Arg::new("file").short('f').takes_value(true))
+            .try_get_matches_from(&["tar", "-xzf", "archive.tgz"])
+            .map_err(|e| e.to_string())?;
+        if !matches.get_flag("extract") || !matches.get_flag("gzip") || matches.get_flag("verbose") {
+            return Err(format!("Unexpected flags: {:?}", matches));
+        }
+        if matches.value_of("file") != Some("archive.tgz") {
+            return Err(format!("Unexpected file: {:?}", matches.value_of("file")));
+        }
+        Ok(())
+    }));
+    
+    // Test 30: Counting flags with ArgAction::Count
+    results.push(test_runner("Count action", || {
+        let app = Command::new("tool")
+            .arg(Arg::new("verbose").short('v').long("verbose").action(ArgAction::Count))
+            .arg(Arg::new("quiet").short('q').action(ArgAction::Count));
+        let matches = app.clone().try_get_matches_from(&["tool", "-vvv", "--verbose", "-qv"]).map_err(|e| e.to_string())?;
+        if matches.get_count("verbose") != 5 || matches.get_count("quiet") != 1 || !matches.get_flag("verbose") {
+            return Err(format!("Unexpected counts: {} {}", matches.get_count("verbose"), matches.get_count("quiet")));
+        }
+        let matches = app.try_get_matches_from(&["tool"]).map_err(|e| e.to_string())?;
+        if matches.get_count("verbose") != 0 {
+            return Err("Count should be 0 when absent".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 31: Values attached with `=` or to a short flag
+    results.push(test_runner("Attached values", || {
+        let app = Command::new("tool")
+            .arg(Arg::new("config").long("config").short('c').takes_value(true))
+            .arg(Arg::new("define").long("define").short('D').action(ArgAction::Append))
+            .arg(Arg::new("verbose").long("verbose").short('v'));
+        let cases = [
+            vec!["tool", "--config=file.toml"],
+            vec!["tool", "-c=file.toml"],
+            vec!["tool", "-cfile.toml"],
+            vec!["tool", "-vcfile.toml"],
+            vec!["tool", "-c", "file.toml"],
+        ];
+        for args in cases {
+            let matches = app.clone().try_get_matches_from(&args).map_err(|e| e.to_string())?;
+            if matches.value_of("config") != Some("file.toml") {
+                return Err(format!("{:?} gave config {:?}", args, matches.value_of("config")));
+            }
+        }
+        // Only the first `=` splits, and an empty value is still a value
+        let matches = app.clone()
+            .try_get_matches_from(&["tool", "--define=key=value", "-Dmode=fast", "--config="])
+            .map_err(|e| e.to_string())?;
+        if matches.values_of("define") != Some(vec!["key=value", "mode=fast"]) || matches.value_of("config") != Some("") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        // A flag only takes `true` or `false`
+        match app.try_get_matches_from(&["tool", "--verbose=yes"]) {
+            Err(e) if e.kind() == ErrorKind::InvalidValue && e.args() == ["yes", "--verbose"] => {}
+            other => return Err(format!("Expected InvalidValue, got {:?}", other)),
+        }
+        Ok(())
+    }));
+    
+    // Test 32: Restricting values with value_parser
+    results.push(test_runner("Possible values", || {
+        let app = Command::new("convert")
+            .arg(Arg::new("format").long("format").value_parser(["json", "yaml", "toml"]))
+            .arg(Arg::new("target").value_parser(vec!["debug", "release"]));
+        let matches = app.clone().try_get_matches_from(&["convert", "--format=yaml", "release"]).map_err(|e| e.to_string())?;
+        if matches.value_of("format") != Some("yaml") || matches.value_of("target") != Some("release") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        match app.clone().try_get_matches_from(&["convert", "--format", "xml"]) {
+            Err(e) if e.kind() == ErrorKind::InvalidValue && e.args() == ["xml", "--format <FORMAT>"] => {
+                let expected = "error: invalid value 'xml' for '--format <FORMAT>'\n  [possible values: json, yaml, toml]\n\nUsage: convert [OPTIONS] [TARGET]\n\nFor more information, try '--help'.";
+                if e.to_string() != expected {
+                    return Err(format!("Unexpected message:\n{}", e));
+                }
+            }
+            other => return Err(format!("Expected InvalidValue, got {:?}", other)),
+        }
+        match app.try_get_matches_from(&["convert", "fast"]) {
+            Err(e) if e.kind() == ErrorKind::InvalidValue && e.args() == ["fast", "<TARGET>"] => Ok(()),
+            other => Err(format!("Expected InvalidValue, got {:?}", other)),
+        }
+    }));
+    
+    // Test 33: Parsing enums with value_enum!
+    results.push(test_runner("ValueEnum", || {
+        value_enum! {
+            #[derive(Debug, Clone, Copy, PartialEq)]
+            enum Level {
+                Low => "low",
+                High => "high",
+            }
+        }
+        
+        if Level::possible_values() != ["low", "high"] || Level::High.as_str() != "high" {
+            return Err("Unexpected possible values".to_string());
+        }
+        if Level::from_value("LOW", true) != Some(Level::Low) || Level::from_value("LOW", false).is_some() {
+            return Err("from_value should honour ignore_case".to_string());
+        }
+        let app = Command::new("fan").arg(Arg::new("level").long("level").value_parser(ValueParser::value_enum::<Level>()));
+        let matches = app.clone().try_get_matches_from(&["fan", "--level", "high"]).map_err(|e| e.to_string())?;
+        if matches.get_one::<Level>("level") != Some(Level::High) {
+            return Err(format!("Unexpected level: {:?}", matches.get_one::<Level>("level")));
+        }
+        match app.try_get_matches_from(&["fan", "--level", "max"]) {
+            Err(e) if e.kind() == ErrorKind::InvalidValue && e.to_string().contains("[possible values: low, high]") => Ok(()),
+            other => Err(format!("Expected InvalidValue, got {:?}", other)),
+        }
+    }));
+    
+    // Test 34: conflicts_with and requires
+    results.push(test_runner("Conflicts and requires", || {
+        let app = Command::new("deploy")
+            .arg(Arg::new("dry-run").long("dry-run").conflicts_with("force"))
+            .arg(Arg::new("force").long("force"))
+            .arg(Arg::new("user").long("user").takes_value(true).requires("password"))
+            .arg(Arg::new("password").long("password").takes_value(true));
+        match app.clone().try_get_matches_from(&["deploy", "--force", "--dry-run"]) {
+            Err(e) if e.kind() == ErrorKind::ArgumentConflict && e.args() == ["--dry-run", "--force"] => {
+                if !e.to_string().starts_with("error: the argument '--dry-run' cannot be used with '--force'") {
+                    return Err(format!("Unexpected message:\n{}", e));
+                }
+            }
+            other => return Err(format!("Expected ArgumentConflict, got {:?}", other)),
+        }
+        match app.clone().try_get_matches_from(&["deploy", "--user", "admin"]) {
+            Err(e) if e.kind() == ErrorKind::MissingRequiredArgument && e.args() == ["--password <PASSWORD>"] => {}
+            other => return Err(format!("Expected MissingRequiredArgument, got {:?}", other)),
+        }
+        app.try_get_matches_from(&["deploy", "--dry-run", "--user", "admin", "--password", "secret"]).map_err(|e| e.to_string())?;
+        Ok(())
+    }));
+    
+    // Test 35: Argument groups
+    results.push(test_runner("Argument groups", || {
+        let app = Command::new("export")
+            .arg(Arg::new("json").long("json"))
+            .arg(Arg::new("yaml").long("yaml"))
+            .arg(Arg::new("compact").long("compact").requires("format"))
+            .group(ArgGroup::new("format").args(&["json", "yaml"]).required(true));
+        let matches = app.clone().try_get_matches_from(&["export", "--yaml"]).map_err(|e| e.to_string())?;
+        if matches.value_of("format") != Some("yaml") || !matches.is_present("format") {
+            return Err(format!("Group should hold the given Arg: {:?}", matches));
+        }
+        match app.clone().try_get_matches_from(&["export", "--json", "--yaml"]) {
+            Err(e) if e.kind() == ErrorKind::ArgumentConflict && e.args() == ["--json", "--yaml"] => {}
+            other => return Err(format!("Expected ArgumentConflict, got {:?}", other)),
+        }
+        match app.try_get_matches_from(&["export"]) {
+            Err(e) if e.kind() == ErrorKind::MissingRequiredArgument && e.args() == ["<--json|--yaml>"] => {
+                if !e.to_string().ends_with("Usage: export [OPTIONS] <--json|--yaml>\n\nFor more information, try '--help'.") {
+                    return Err(format!("Unexpected message:\n{}", e));
+                }
+            }
+            other => return Err(format!("Expected MissingRequiredArgument, got {:?}", other)),
+        }
+        // multiple(true) allows several, and requires can name a group
+        let app = Command::new("export")
+            .arg(Arg::new("json").long("json"))
+            .arg(Arg::new("yaml").long("yaml"))
+            .arg(Arg::new("compact").long("compact").requires("format"))
+            .group(ArgGroup::new("format").arg("json").arg("yaml").multiple(true));
+        let matches = app.clone().try_get_matches_from(&["export", "--json", "--yaml"]).map_err(|e| e.to_string())?;
+        if matches.values_of("format") != Some(vec!["json", "yaml"]) {
+            return Err(format!("Unexpected group values: {:?}", matches.values_of("format")));
+        }
+        match app.try_get_matches_from(&["export", "--compact"]) {
+            Err(e) if e.kind() == ErrorKind::MissingRequiredArgument && e.args() == ["<--json|--yaml>"] => Ok(()),
+            other => Err(format!("Expected MissingRequiredArgument, got {:?}", other)),
+        }
+    }));
+    
+    // Test 36: Environment variable fallback and value sources
+    results.push(test_runner("Environment variables", || {
+        std::env::set_var("CLAP_EMULATOR_TEST_PORT", "9090");
+        std::env::set_var("CLAP_EMULATOR_TEST_DEBUG", "false");
+        std::env::set_var("CLAP_EMULATOR_TEST_COLOR", "1");
+        std::env::remove_var("CLAP_EMULATOR_TEST_HOST");
+        let app = Command::new("server")
+            .arg(Arg::new("port").long("port").takes_value(true).env("CLAP_EMULATOR_TEST_PORT").default_value("8080"))
+            .arg(Arg::new("host").long("host").takes_value(true).env("CLAP_EMULATOR_TEST_HOST").default_value("localhost"))
+            .arg(Arg::new("debug").long("debug").env("CLAP_EMULATOR_TEST_DEBUG"))
+            .arg(Arg::new("color").long("color").env("CLAP_EMULATOR_TEST_COLOR"))
+            .arg(Arg::new("quiet").long("quiet"));
+        
+        let matches = app.clone().try_get_matches_from(&["server"]).map_err(|e| e.to_string())?;
+        if matches.get_one::<u16>("port") != Some(9090) || matches.value_source("port") != Some(ValueSource::EnvVariable) {
+            return Err(format!("Port should come from the environment: {:?}", matches));
+        }
+        if matches.value_of("host") != Some("localhost") || matches.value_source("host") != Some(ValueSource::DefaultValue) {
+            return Err(format!("Host should be the default: {:?}", matches));
+        }
+        if matches.get_flag("debug") || !matches.get_flag("color") || matches.value_source("color") != Some(ValueSource::EnvVariable) {
+            return Err(format!("Unexpected flags: {:?}", matches));
+        }
+        if matches.value_source("quiet").is_some() || matches.occurrences_of("port") != 0 {
+            return Err("Only given Args should have sources and occurrences".to_string());
+        }
+        
+        let matches = app.try_get_matches_from(&["server", "--port", "7000"]).map_err(|e| e.to_string())?;
+        if matches.get_one::<u16>("port") != Some(7000) || matches.value_source("port") != Some(ValueSource::CommandLine) {
+            return Err(format!("The command line should win: {:?}", matches));
+        }
+        Ok(())
+    }));
+    
+    // Test 37: Subcommand aliases
+    results.push(test_runner("Subcommand aliases", || {
+        let app = Command::new("git")
+            .subcommand(Command::new("commit").alias("cm").visible_alias("ci")
+                .arg(Arg::new("message").short('m').takes_value(true)));
+        for name in ["commit", "cm", "ci"] {
+            let matches = app.clone().try_get_matches_from(&["git", name, "-m", "fix"]).map_err(|e| e.to_string())?;
+            match matches.subcommand() {
+                Some(("commit", sub)) if sub.value_of("message") == Some("fix") => {}
+                other => return Err(format!("{} gave {:?}", name, other.map(|(n, _)| n))),
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 38: Help for nested subcommands
+    results.push(test_runner("Nested help", || {
+        let app = Command::new("git")
+            .about("A version control system")
+            .version("2.0")
+            .arg(Arg::new("verbose").short('v').long("verbose").help("More output"))
+            .subcommand(Command::new("remote")
+                .about("Manage remotes")
+                .visible_alias("r")
+                .alias("rem")
+                .subcommand(Command::new("add")
+                    .about("Add a remote")
+                    .arg(Arg::new("name").required(true).help("Remote name"))
+                    .arg(Arg::new("url").help("Remote URL"))));
+        
+        let expected = "A version control system\n\n\
+            Usage: git [OPTIONS] [COMMAND]\n\n\
+            Commands:\n  \
+            remote         Manage remotes [aliases: r]\n  \
+            help           Print this message or the help of the given subcommand(s)\n\n\
+            Options:\n  \
+            -v, --verbose  More output\n  \
+            -h, --help     Print help\n  \
+            -V, --version  Print version";
+        match app.clone().try_get_matches_from(&["git", "--help"]) {
+            Err(e) if e.kind() == ErrorKind::DisplayHelp && e.to_string() == expected => {}
+            other => return Err(format!("Unexpected top-level help: {:?}", other)),
+        }
+        
+        let expected = "Add a remote\n\n\
+            Usage: git remote add <NAME> [URL]\n\n\
+            Arguments:\n  \
+            <NAME>      Remote name\n  \
+            [URL]       Remote URL\n\n\
+            Options:\n  \
+            -h, --help  Print help";
+        for args in [vec!["git", "remote", "add", "--help"], vec!["git", "r", "add", "-h"], vec!["git", "help", "rem", "add"]] {
+            match app.clone().try_get_matches_from(&args) {
+                Err(e) if e.kind() == ErrorKind::DisplayHelp && e.to_string() == expected => {}
+                other => return Err(format!("Unexpected help for {:?}: {:?}", args, other)),
+            }
+        }
+        
+        match app.try_get_matches_from(&["git", "help", "push"]) {
+            Err(e) if e.kind() == ErrorKind::InvalidSubcommand && e.args() == ["push"] => Ok(()),
+            other => Err(format!("Expected InvalidSubcommand, got {:?}", other)),
+        }
+    }));
+    
+    // Test 39: Global arguments
+    results.push(test_runner("Global arguments", || {
+        let app = Command::new("app")
+            .arg(Arg::new("verbose").long("verbose").short('v').global(true))
+            .arg(Arg::new("color").long("color").takes_value(true).default_value("auto").global(true))
+            .arg(Arg::new("local").long("local"))
+            .subcommand(Command::new("remote")
+                .subcommand(Command::new("add").arg(Arg::new("name"))));
+        
+        // Given after the subcommands, visible at every level
+        let matches = app.clone().try_get_matches_from(&["app", "remote", "add", "origin", "--verbose", "--color", "never"]).map_err(|e| e.to_string())?;
+        let remote = matches.subcommand().map(|(_, m)| m).ok_or("no subcommand")?;
+        let add = remote.subcommand().map(|(_, m)| m).ok_or("no nested subcommand")?;
+        for (level, m) in [("app", &matches), ("remote", remote), ("add", add)] {
+            if !m.get_flag("verbose") || m.value_of("color") != Some("never") || m.value_source("color") != Some(ValueSource::CommandLine) {
+                return Err(format!("Globals missing at {}: {:?}", level, m));
+            }
+        }
+        
+        // Given before, visible below; defaults everywhere otherwise
+        let matches = app.clone().try_get_matches_from(&["app", "-v", "remote", "add"]).map_err(|e| e.to_string())?;
+        let add = matches.subcommand().and_then(|(_, m)| m.subcommand()).map(|(_, m)| m).ok_or("no nested subcommand")?;
+        if !add.get_flag("verbose") || add.value_of("color") != Some("auto") || add.value_source("color") != Some(ValueSource::DefaultValue) {
+            return Err(format!("Unexpected nested matches: {:?}", add));
+        }
+        
+        // Only global Args cross the boundary
+        match app.try_get_matches_from(&["app", "remote", "--local"]) {
+            Err(e) if e.kind() == ErrorKind::UnknownArgument => Ok(()),
+            other => Err(format!("Expected UnknownArgument, got {:?}", other)),
+        }
+    }));
+    
+    // Test 40: The `--` separator and trailing_var_arg
+    results.push(test_runner("Trailing arguments", || {
+        let app = Command::new("mytool")
+            .subcommand(Command::new("run")
+                .arg(Arg::new("verbose").long("verbose"))
+                .arg(Arg::new("command").required(true).trailing_var_arg(true)));
+        for args in [vec!["mytool", "run", "--verbose", "--", "cargo", "build", "--release"], vec!["mytool", "run", "--verbose", "cargo", "build", "--release"]] {
+            let matches = app.clone().try_get_matches_from(&args).map_err(|e| e.to_string())?;
+            let (_, run) = matches.subcommand().ok_or("no subcommand")?;
+            if !run.get_flag("verbose") || run.values_of("command") != Some(vec!["cargo", "build", "--release"]) {
+                return Err(format!("{:?} gave {:?}", args, run));
+            }
+        }
+        // After `--`, flags, subcommand names and a second `--` are values
+        let app = Command::new("tool")
+            .arg(Arg::new("verbose").short('v'))
+            .arg(Arg::new("files").action(ArgAction::Append))
+            .subcommand(Command::new("sub"));
+        let matches = app.try_get_matches_from(&["tool", "-v", "--", "-v", "sub", "--"]).map_err(|e| e.to_string())?;
+        if matches.occurrences_of("verbose") != 1 || matches.values_of("files") != Some(vec!["-v", "sub", "--"]) || matches.subcommand().is_some() {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        Ok(())
+    }));
+    
+    // Test 41: allow_hyphen_values
+    results.push(test_runner("Hyphen values", || {
+        let app = Command::new("calc")
+            .arg(Arg::new("offset").long("offset").takes_value(true).allow_hyphen_values(true))
+            .arg(Arg::new("range").long("range").num_args(2).allow_hyphen_values(true))
+            .arg(Arg::new("verbose").short('v'))
+            .arg(Arg::new("number").allow_hyphen_values(true));
+        let matches = app.clone().try_get_matches_from(&["calc", "--offset", "-5", "--range", "-1", "-2", "-v", "-42"]).map_err(|e| e.to_string())?;
+        if matches.get_one::<i32>("offset") != Some(-5) || matches.get_many::<i32>("range") != Some(vec![-1, -2]) {
+            return Err(format!("Unexpected options: {:?}", matches));
+        }
+        if !matches.get_flag("verbose") || matches.get_one::<i32>("number") != Some(-42) {
+            return Err(format!("Unexpected positional: {:?}", matches));
+        }
+        // Without it, a negative number is an unknown flag
+        match Command::new("calc").arg(Arg::new("number")).try_get_matches_from(&["calc", "-42"]) {
+            Err(e) if e.kind() == ErrorKind::UnknownArgument => Ok(()),
+            other => Err(format!("Expected UnknownArgument, got {:?}", other)),
+        }
+    }));
+    
+    // Test 42: Exit codes and output streams
+    results.push(test_runner("Error exit codes", || {
+        let app = Command::new("tool")
+            .version("1.0")
+            .arg(Arg::new("host").short('h').takes_value(true))
+            .arg(Arg::new("name").required(true));
+        let cases = [
+            (vec!["tool", "--help"], ErrorKind::DisplayHelp, 0, false),
+            (vec!["tool", "-V"], ErrorKind::DisplayVersion, 0, false),
+            (vec!["tool"], ErrorKind::MissingRequiredArgument, 2, true),
+            (vec!["tool", "--bogus"], ErrorKind::UnknownArgument, 2, true),
+        ];
+        for (args, kind, code, stderr) in cases {
+            match app.clone().try_get_matches_from(&args) {
+                Err(e) if e.kind() == kind && e.exit_code() == code && e.use_stderr() == stderr => {}
+                other => return Err(format!("{:?} gave {:?}", args, other)),
+            }
+        }
+        // With `-h` taken, the hint points at `--help`, and there's none
+        // without either
+        let e = app.try_get_matches_from(&["tool"]).unwrap_err();
+        if !e.to_string().ends_with("Usage: tool [OPTIONS] <NAME>\n\nFor more information, try '--help'.") {
+            return Err(format!("Unexpected message:\n{}", e));
+        }
+        let e = Command::new("tool")
+            .arg(Arg::new("help").long("help"))
+            .arg(Arg::new("host").short('h'))
+            .try_get_matches_from(&["tool", "-x"])
+            .unwrap_err();
+        if e.to_string() != "error: unexpected argument '-x' found\n\nUsage: tool [OPTIONS]" {
+            return Err(format!("Unexpected message:\n{}", e));
+        }
+        Ok(())
+    }));
+    
+    // Test 43: Colored help and errors
+    results.push(test_runner("Colored output", || {
+        let app = Command::new("tool")
+            .arg(Arg::new("config").short('c').long("config").takes_value(true).help("Config file"))
+            .subcommand(Command::new("run"));
+        
+        let help = match app.clone().color(ColorChoice::Always).try_get_matches_from(&["tool", "run", "--help"]) {
+            Err(e) if e.kind() == ErrorKind::DisplayHelp => e.to_string(),
+            other => return Err(format!("Expected help, got {:?}", other)),
+        };
+        if !help.starts_with("\x1b[1;4mUsage:\x1b[0m tool run") || !help.contains("\x1b[1m-h\x1b[0m, \x1b[1m--help\x1b[0m  Print help") {
+            return Err(format!("Subcommand help should be colored: {:?}", help));
+        }
+        let help = match app.clone().color(ColorChoice::Always).try_get_matches_from(&["tool", "-h"]) {
+            Err(e) => e.to_string(),
+            Ok(_) => return Err("Expected help".to_string()),
+        };
+        // Padding ignores the escape codes
+        if !help.contains("  \x1b[1m-c\x1b[0m, \x1b[1m--config\x1b[0m \x1b[3m<CONFIG>\x1b[0m  Config file") {
+            return Err(format!("Unexpected options: {:?}", help));
+        }
+        
+        let error = app.clone().color(ColorChoice::Always).try_get_matches_from(&["tool", "--bogus"]).unwrap_err().to_string();
+        if !error.starts_with("\x1b[1;31merror:\x1b[0m unexpected argument '--bogus' found\n\n\x1b[1;4mUsage:\x1b[0m tool [OPTIONS] [COMMAND]") {
+            return Err(format!("Unexpected error: {:?}", error));
+        }
+        
+        // Never, and Auto when not writing to a terminal, are plain
+        for choice in [ColorChoice::Never, ColorChoice::Auto] {
+            let error = app.clone().color(choice).try_get_matches_from(&["tool", "--bogus"]).unwrap_err().to_string();
+            if error.contains('\x1b') {
+                return Err(format!("{:?} should be plain: {:?}", choice, error));
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 44: Help templates
+    results.push(test_runner("Help template", || {
+        let app = Command::new("tool")
+            .version("1.2")
+            .author("Jane Doe")
+            .about("Does things")
+            .help_template("{name} {version} by {author}\n{about}\n\n{usage-heading} {usage}\n\nFLAGS:\n{options}")
+            .arg(Arg::new("verbose").short('v').help("More output"))
+            .arg(Arg::new("file"));
+        let expected = "tool 1.2 by Jane Doe\n\
+            Does things\n\n\
+            Usage: tool [OPTIONS] [FILE]\n\n\
+            FLAGS:\n  \
+            -v             More output\n  \
+            -h, --help     Print help\n  \
+            -V, --version  Print version";
+        match app.try_get_matches_from(&["tool", "--help"]) {
+            Err(e) if e.to_string() == expected => Ok(()),
+            other => Err(format!("Unexpected help: {:?}", other.map(|_| ()))),
+        }
+    }));
+    
+    // Test 45: Man pages
+    results.push(test_runner("Man page generation", || {
+        let app = Command::new("git")
+            .about("A version control system")
+            .version("1.2")
+            .author("Jane Doe")
+            .arg(Arg::new("config").short('c').long("config").takes_value(true).help("Config file"))
+            .arg(Arg::new("dir").help(".git directory"))
+            .subcommand(Command::new("remote")
+                .about("Manage remotes")
+                .subcommand(Command::new("add").arg(Arg::new("name").required(true))));
+        
+        let mut page = Vec::new();
+        mangen::Man::new(app.clone()).render(&mut page).map_err(|e| e.to_string())?;
+        let expected = ".TH GIT 1 \"git 1.2\"\n\
+            .SH NAME\n\
+            git \\- A version control system\n\
+            .SH SYNOPSIS\n\
+            \\fBgit\\fR [\\fB\\-c\\fR|\\fB\\-\\-config\\fR <\\fICONFIG\\fR>] [\\fB\\-h\\fR|\\fB\\-\\-help\\fR] [\\fB\\-V\\fR|\\fB\\-\\-version\\fR] [\\fIDIR\\fR] [\\fIsubcommands\\fR]\n\
+            .SH OPTIONS\n\
+            .TP\n\
+            \\fB\\-c\\fR, \\fB\\-\\-config\\fR <\\fICONFIG\\fR>\n\
+            Config file\n\
+            .TP\n\
+            \\fB\\-h\\fR, \\fB\\-\\-help\\fR\n\
+            Print help\n\
+            .TP\n\
+            \\fB\\-V\\fR, \\fB\\-\\-version\\fR\n\
+            Print version\n\
+            .TP\n\
+            <\\fIDIR\\fR>\n\
+            \\&.git directory\n\
+            .SH SUBCOMMANDS\n\
+            .TP\n\
+            git\\-remote(1)\n\
+            Manage remotes\n\
+            .SH VERSION\n\
+            v1.2\n\
+            .SH AUTHORS\n\
+            Jane Doe\n";
+        let page = String::from_utf8(page).map_err(|e| e.to_string())?;
+        if page != expected {
+            return Err(format!("Unexpected page:\n{}", page));
+        }
+        
+        let dir = std::env::temp_dir().join(format!("clap_emulator_mangen_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let written = mangen::generate_to(app, &dir).map_err(|e| e.to_string())?;
+        let names: Vec<String> = written.iter().filter_map(|p| p.file_name()?.to_str().map(String::from)).collect();
+        let nested = std::fs::read_to_string(dir.join("git-remote-add.1")).map_err(|e| e.to_string())?;
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+        if names != ["git.1", "git-remote.1", "git-remote-add.1"] {
+            return Err(format!("Unexpected pages: {:?}", names));
+        }
+        if !nested.contains(".SH SYNOPSIS\n\\fBgit remote add\\fR [\\fB\\-h\\fR|\\fB\\-\\-help\\fR] <\\fINAME\\fR>\n") {
+            return Err(format!("Unexpected nested page:\n{}", nested));
+        }
+        Ok(())
+    }));
+    
+    // Test 46: Splitting values with value_delimiter
+    results.push(test_runner("Value delimiter", || {
+        std::env::set_var("CLAP_EMULATOR_TEST_TARGETS", "x86;arm");
+        let app = Command::new("cargo")
+            .arg(Arg::new("features").long("features").value_delimiter(',').action(ArgAction::Append))
+            .arg(Arg::new("targets").long("target").value_delimiter(';').env("CLAP_EMULATOR_TEST_TARGETS"))
+            .arg(Arg::new("profiles").long("profiles").value_delimiter(',').default_value("dev,test"))
+            .arg(Arg::new("levels").long("levels").value_delimiter(',').value_parser(["low", "high"]))
+            .arg(Arg::new("pairs").value_delimiter('='));
+        
+        let matches = app.clone()
+            .try_get_matches_from(&["cargo", "--features", "a,b", "--features=c,\"d,e\",'f'", "key=value"])
+            .map_err(|e| e.to_string())?;
+        if matches.get_many::<String>("features") != Some(vec!["a", "b", "c", "d,e", "f"].into_iter().map(String::from).collect()) {
+            return Err(format!("Unexpected features: {:?}", matches.values_of("features")));
+        }
+        if matches.values_of("targets") != Some(vec!["x86", "arm"]) || matches.values_of("profiles") != Some(vec!["dev", "test"]) {
+            return Err(format!("Environment and defaults should split too: {:?}", matches));
+        }
+        if matches.values_of("pairs") != Some(vec!["key", "value"]) {
+            return Err(format!("Unexpected positional values: {:?}", matches.values_of("pairs")));
+        }
+        // Quotes only count at the start of a part, and empty parts are kept
+        let matches = app.clone().try_get_matches_from(&["cargo", "--features", "it's,,x"]).map_err(|e| e.to_string())?;
+        if matches.values_of("features") != Some(vec!["it's", "", "x"]) {
+            return Err(format!("Unexpected features: {:?}", matches.values_of("features")));
+        }
+        // Each part is checked against the possible values
+        match app.try_get_matches_from(&["cargo", "--levels", "low,max"]) {
+            Err(e) if e.kind() == ErrorKind::InvalidValue && e.args()[0] == "max" => Ok(()),
+            other => Err(format!("Expected InvalidValue, got {:?}", other)),
+        }
+    }));
+    
+    // Test 47: Conditional requirements
+    results.push(test_runner("Conditional requirements", || {
+        let app = Command::new("connect")
+            .arg(Arg::new("config").long("config").takes_value(true))
+            .arg(Arg::new("host").long("host").takes_value(true).required_unless_present("config"))
+            .arg(Arg::new("auth").long("auth").value_parser(["none", "password", "key"]).default_value("none"))
+            .arg(Arg::new("password").long("password").takes_value(true).required_if_eq("auth", "password"))
+            .arg(Arg::new("user").long("user").takes_value(true))
+            .arg(Arg::new("key").long("key").takes_value(true))
+            .arg(Arg::new("tls").long("tls"))
+            .arg(Arg::new("ssh").long("ssh"))
+            .group(ArgGroup::new("secure").args(&["tls", "ssh"]).requires_all(&["user", "key"]));
+        
+        let cases: Vec<(Vec<&str>, Vec<&str>)> = vec![
+            (vec!["connect"], vec!["--host <HOST>"]),
+            (vec!["connect", "--config", "c.toml"], vec![]),
+            (vec!["connect", "--host", "h", "--auth", "password"], vec!["--password <PASSWORD>"]),
+            (vec!["connect", "--host", "h", "--auth", "password", "--password", "p"], vec![]),
+            (vec!["connect", "--host", "h", "--auth", "key"], vec![]),
+            (vec!["connect", "--host", "h", "--ssh", "--key", "id_rsa"], vec!["--user <USER>"]),
+            (vec!["connect", "--tls"], vec!["--host <HOST>", "--user <USER>", "--key <KEY>"]),
+        ];
+        for (args, expected) in cases {
+            match app.clone().try_get_matches_from(&args) {
+                Ok(_) if expected.is_empty() => {}
+                Err(e) if e.kind() == ErrorKind::MissingRequiredArgument && e.args() == expected.as_slice() => {}
+                other => return Err(format!("{:?} gave {:?}", args, other)),
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 48: Prompting for missing values
+    results.push(test_runner("Prompting for missing values", || {
+        let app = Command::new("login")
+            .prompt_missing(true)
+            .arg(Arg::new("user").long("user").takes_value(true).required(true))
+            .arg(Arg::new("password").long("password").takes_value(true).required(true).prompt("Password: ").hide_input(true))
+            .arg(Arg::new("region").long("region").value_parser(["eu", "us"]).required(true))
+            .arg(Arg::new("remember").long("remember"));
+        
+        let mut asked = Vec::new();
+        let mut answers = vec!["hunter2"].into_iter();
+        let mut prompter = |label: &str, hidden: bool| -> std::io::Result<String> {
+            asked.push((label.to_string(), hidden));
+            Ok(answers.next().unwrap_or_default().to_string())
+        };
+        let matches = app.clone()
+            .try_get_matches_from_with_prompter(&["login", "--user", "ann", "--region", "us"], &mut prompter)
+            .map_err(|e| e.to_string())?;
+        if asked != [("Password: ".to_string(), true)] {
+            return Err(format!("Only the missing value should be asked for: {:?}", asked));
+        }
+        if matches.value_of("password") != Some("hunter2") || matches.value_source("password") != Some(ValueSource::Prompt) {
+            return Err(format!("Unexpected password: {:?}", matches));
+        }
+        
+        // Answers are checked, and an empty one leaves the Arg missing
+        let mut invalid = |_: &str, _: bool| -> std::io::Result<String> { Ok("mars".to_string()) };
+        match app.clone().try_get_matches_from_with_prompter(&["login", "--user", "ann", "--password", "p"], &mut invalid) {
+            Err(e) if e.kind() == ErrorKind::InvalidValue => {}
+            other => return Err(format!("Expected InvalidValue, got {:?}", other)),
+        }
+        let mut empty = |_: &str, _: bool| -> std::io::Result<String> { Ok(String::new()) };
+        match app.try_get_matches_from_with_prompter(&["login", "--region", "eu"], &mut empty) {
+            Err(e) if e.kind() == ErrorKind::MissingRequiredArgument && e.args() == ["--user <USER>", "--password <PASSWORD>"] => {}
+            other => return Err(format!("Expected MissingRequiredArgument, got {:?}", other)),
+        }
+        
+        // Without prompt_missing nothing is asked
+        let mut never = |_: &str, _: bool| -> std::io::Result<String> { Err(std::io::Error::other("asked")) };
+        match Command::new("login")
+            .arg(Arg::new("user").long("user").takes_value(true).required(true))
+            .try_get_matches_from_with_prompter(&["login"], &mut never)
+        {
+            Err(e) if e.kind() == ErrorKind::MissingRequiredArgument => Ok(()),
+            other => Err(format!("Expected MissingRequiredArgument, got {:?}", other)),
+        }
+    }));
+    
+    // Test 49: Suggestions for typos
+    results.push(test_runner("Did you mean", || {
+        let app = Command::new("git")
+            .arg(Arg::new("verbose").long("verbose"))
+            .arg(Arg::new("config").long("config").takes_value(true))
+            .subcommand(Command::new("commit").alias("ci"))
+            .subcommand(Command::new("checkout"));
+        let cases = [
+            (vec!["git", "--verbos"], ErrorKind::UnknownArgument, Some("--verbose")),
+            (vec!["git", "--confg=x"], ErrorKind::UnknownArgument, Some("--config")),
+            (vec!["git", "--hlep"], ErrorKind::UnknownArgument, Some("--help")),
+            (vec!["git", "--quiet"], ErrorKind::UnknownArgument, None),
+            (vec!["git", "comit"], ErrorKind::InvalidSubcommand, Some("commit")),
+            (vec!["git", "chekout"], ErrorKind::InvalidSubcommand, Some("checkout")),
+            (vec!["git", "hepl"], ErrorKind::InvalidSubcommand, Some("help")),
+            (vec!["git", "push"], ErrorKind::InvalidSubcommand, None),
+            (vec!["git", "help", "comit"], ErrorKind::InvalidSubcommand, Some("commit")),
+        ];
+        for (args, kind, similar) in cases {
+            let e = match app.clone().try_get_matches_from(&args) {
+                Err(e) if e.kind() == kind => e,
+                other => return Err(format!("{:?} gave {:?}", args, other)),
+            };
+            let message = e.to_string();
+            let tip = message.lines().find_map(|l| l.strip_prefix("  tip: did you mean '")).map(|t| t.trim_end_matches("'?"));
+            if tip != similar {
+                return Err(format!("{:?} suggested {:?}:\n{}", args, tip, message));
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 50: Hidden args, display order and help headings
+    results.push(test_runner("Help ordering and headings", || {
+        let app = Command::new("tool")
+            .arg(Arg::new("verbose").short('v').help("More output"))
+            .arg(Arg::new("debug").long("debug").hide(true))
+            .arg(Arg::new("jobs").short('j').takes_value(true).help_heading("Advanced"))
+            .arg(Arg::new("config").short('c').takes_value(true).display_order(1))
+            .arg(Arg::new("file").required(true));
+        let expected = "Usage: tool [OPTIONS] <FILE>\n\n\
+            Arguments:\n  \
+            <FILE>\n\n\
+            Options:\n  \
+            -c <CONFIG>\n  \
+            -v           More output\n  \
+            -h, --help   Print help\n\n\
+            Advanced:\n  \
+            -j <JOBS>";
+        match app.clone().try_get_matches_from(&["tool", "--help"]) {
+            Err(e) if e.to_string() == expected => {}
+            Err(e) => return Err(format!("Unexpected help:\n{}", e)),
+            Ok(_) => return Err("Expected help".to_string()),
+        }
+        
+        // Hidden args still parse, but aren't suggested
+        let matches = app.clone().try_get_matches_from(&["tool", "--debug", "a"])
+            .map_err(|e| e.to_string())?;
+        if !matches.get_flag("debug") {
+            return Err("Expected --debug to parse".to_string());
+        }
+        match app.try_get_matches_from(&["tool", "--debg", "a"]) {
+            Err(e) if e.kind() == ErrorKind::UnknownArgument && !e.to_string().contains("tip:") => Ok(()),
+            other => Err(format!("Unexpected result: {:?}", other)),
+        }
+    }));
+    
+    // Test 51: Indices and value sources
+    results.push(test_runner("Indices and value sources", || {
+        let app = Command::new("tool")
+            .arg(Arg::new("x").short('x'))
+            .arg(Arg::new("file").short('f').takes_value(true).value_delimiter(','))
+            .arg(Arg::new("include").short('I').takes_value(true).action(ArgAction::Append))
+            .arg(Arg::new("level").long("level").takes_value(true).default_value("1"))
+            .arg(Arg::new("input"))
+            .subcommand(Command::new("run").arg(Arg::new("fast").long("fast")));
+        let matches = app.try_get_matches_from(&["tool", "-xf", "a,b", "-I", "lib", "in.txt", "-Iinc", "run", "--fast"])
+            .map_err(|e| e.to_string())?;
+        let cases = [
+            ("x", Some(vec![1])),
+            ("file", Some(vec![3, 4])),
+            ("include", Some(vec![6, 9])),
+            ("input", Some(vec![7])),
+            ("level", None),
+        ];
+        for (id, expected) in cases {
+            if matches.indices_of(id) != expected {
+                return Err(format!("{} had indices {:?}", id, matches.indices_of(id)));
+            }
+        }
+        let fast = matches.subcommand().and_then(|(_, m)| m.indices_of("fast"));
+        if fast != Some(vec![11]) {
+            return Err(format!("--fast had indices {:?}", fast));
+        }
+        if matches.value_source("file") != Some(ValueSource::CommandLine)
+            || matches.value_source("level") != Some(ValueSource::DefaultValue)
+            || matches.value_source("input") != Some(ValueSource::CommandLine) {
+            return Err("Unexpected value sources".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 52: Arg actions
+    results.push(test_runner("Arg actions", || {
+        let app = Command::new("tool")
+            .version("2.0")
+            .arg(Arg::new("verbose").short('v').long("verbose").action(ArgAction::SetTrue))
+            .arg(Arg::new("color").long("no-color").action(ArgAction::SetFalse))
+            .arg(Arg::new("quiet").short('q').action(ArgAction::Count))
+            .arg(Arg::new("name").long("name").action(ArgAction::Set))
+            .arg(Arg::new("usage").short('?').action(ArgAction::Help))
+            .arg(Arg::new("ver").long("ver").action(ArgAction::Version));
+        
+        let matches = app.clone().try_get_matches_from(&["tool"]).map_err(|e| e.to_string())?;
+        if matches.get_flag("verbose") || !matches.get_flag("color")
+            || matches.value_source("color") != Some(ValueSource::DefaultValue) {
+            return Err("Unexpected defaults".to_string());
+        }
+        let matches = app.clone()
+            .try_get_matches_from(&["tool", "-v", "--no-color", "--name", "a", "--name", "b"])
+            .map_err(|e| e.to_string())?;
+        if !matches.get_flag("verbose") || matches.get_flag("color") || matches.value_of("name") != Some("b") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        
+        // Explicit values for boolean flags, the last one winning
+        let matches = app.clone()
+            .try_get_matches_from(&["tool", "--verbose=false", "--no-color=true"])
+            .map_err(|e| e.to_string())?;
+        if matches.get_flag("verbose") || !matches.get_flag("color") || !matches.is_present("verbose") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        let matches = app.clone().try_get_matches_from(&["tool", "-v=false", "-v"]).map_err(|e| e.to_string())?;
+        if !matches.get_flag("verbose") || matches.occurrences_of("verbose") != 2 {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        match app.clone().try_get_matches_from(&["tool", "-q=2"]) {
+            Err(e) if e.kind() == ErrorKind::TooManyValues => {}
+            other => return Err(format!("Expected TooManyValues, got {:?}", other)),
+        }
+        
+        // Set needs a value, and a known flag isn't taken as one
+        let out = Command::new("tool")
+            .arg(Arg::new("o").long("out").takes_value(true).required(true))
+            .arg(Arg::new("verbose").long("verbose").action(ArgAction::SetTrue))
+            .arg(Arg::new("offset").long("offset").takes_value(true));
+        for args in [&["tool", "--out"][..], &["tool", "--out", "--verbose"][..]] {
+            match out.clone().try_get_matches_from(args) {
+                Err(e) if e.kind() == ErrorKind::TooFewValues && e.to_string().contains("a value is required for '--out") => {}
+                other => return Err(format!("Expected a missing value for {:?}, got {:?}", args, other)),
+            }
+        }
+        let matches = out.try_get_matches_from(&["tool", "--offset", "-5", "--out", "-", "--verbose"])
+            .map_err(|e| e.to_string())?;
+        if matches.value_of("offset") != Some("-5") || matches.value_of("o") != Some("-") || !matches.get_flag("verbose") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        
+        // Help and Version stop parsing
+        match app.clone().try_get_matches_from(&["tool", "-?", "--bogus"]) {
+            Err(e) if e.kind() == ErrorKind::DisplayHelp && e.to_string().contains("-?") => {}
+            other => return Err(format!("Expected help, got {:?}", other)),
+        }
+        match app.try_get_matches_from(&["tool", "--ver"]) {
+            Err(e) if e.kind() == ErrorKind::DisplayVersion && e.to_string() == "tool 2.0" => Ok(()),
+            other => Err(format!("Expected version, got {:?}", other)),
+        }
+    }));
+    
+    // Test 53: Layered config files
+    results.push(test_runner("Config files", || {
+        let dir = std::env::temp_dir().join(format!("clap_emulator_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let json = dir.join("app.json");
+        let yaml = dir.join("app.yaml");
+        let toml = dir.join("app.toml");
+        std::fs::write(&json, r#"{"host": "file.example", "port": 8000, "level": "debug", "tags": ["a", "b"], "verbose": true, "extra": 1}"#)
+            .map_err(|e| e.to_string())?;
+        std::fs::write(&yaml, "host: yaml.example\nverbose: false\n").map_err(|e| e.to_string())?;
+        std::fs::write(&toml, "# Staging\nhost = \"toml.example\"\ntags = [\"c\"]\n\n[extra]\nlevel = \"trace\"\n")
+            .map_err(|e| e.to_string())?;
+        std::env::set_var("CLAP_EMULATOR_TEST_CONFIG_PORT", "9000");
+        
+        let app = Command::new("server")
+            .config_file_arg("config")
+            .arg(Arg::new("config").long("config").takes_value(true).default_value("missing.json"))
+            .arg(Arg::new("host").long("host").takes_value(true).default_value("localhost"))
+            .arg(Arg::new("port").long("port").takes_value(true).env("CLAP_EMULATOR_TEST_CONFIG_PORT"))
+            .arg(Arg::new("level").long("level").value_parser(["info", "debug"]))
+            .arg(Arg::new("tags").long("tags").action(ArgAction::Append))
+            .arg(Arg::new("user").long("user").takes_value(true).default_value("guest"))
+            .arg(Arg::new("verbose").long("verbose"));
+        let json_path = json.to_string_lossy().to_string();
+        let yaml_path = yaml.to_string_lossy().to_string();
+        let toml_path = toml.to_string_lossy().to_string();
+        
+        // The command line beats the environment, which beats the file,
+        // which beats defaults
+        let matches = app.clone()
+            .try_get_matches_from(&["server", "--config", &json_path, "--level", "info"])
+            .map_err(|e| e.to_string())?;
+        let expected = [
+            ("level", "info", ValueSource::CommandLine),
+            ("port", "9000", ValueSource::EnvVariable),
+            ("host", "file.example", ValueSource::ConfigFile),
+            ("user", "guest", ValueSource::DefaultValue),
+        ];
+        for (id, value, source) in expected {
+            if matches.value_of(id) != Some(value) || matches.value_source(id) != Some(source) {
+                return Err(format!("{} was {:?} from {:?}", id, matches.value_of(id), matches.value_source(id)));
+            }
+        }
+        if matches.values_of("tags") != Some(vec!["a", "b"]) || !matches.get_flag("verbose") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        
+        let matches = app.clone().try_get_matches_from(&["server", "--config", &yaml_path]).map_err(|e| e.to_string())?;
+        if matches.value_of("host") != Some("yaml.example") || matches.get_flag("verbose") {
+            return Err(format!("Unexpected YAML matches: {:?}", matches));
+        }
+        let matches = app.clone().try_get_matches_from(&["server", "--config", &toml_path]).map_err(|e| e.to_string())?;
+        if matches.value_of("host") != Some("toml.example") || matches.values_of("tags") != Some(vec!["c"])
+            || matches.value_source("host") != Some(ValueSource::ConfigFile) || matches.is_present("level") {
+            return Err(format!("Unexpected TOML matches: {:?}", matches));
+        }
+        
+        // A missing default file is skipped, but a named one must exist
+        let matches = app.clone().try_get_matches_from(&["server"]).map_err(|e| e.to_string())?;
+        if matches.value_of("host") != Some("localhost") {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        match app.clone().try_get_matches_from(&["server", "--config", "missing.json"]) {
+            Err(e) if e.kind() == ErrorKind::Io => {}
+            other => return Err(format!("Expected Io, got {:?}", other)),
+        }
+        
+        // Values from the file are checked like any other
+        std::fs::write(&json, r#"{"level": "trace"}"#).map_err(|e| e.to_string())?;
+        let invalid = app.clone().try_get_matches_from(&["server", "--config", &json_path]);
+        std::fs::write(&json, "{not json").map_err(|e| e.to_string())?;
+        let malformed = app.try_get_matches_from(&["server", "--config", &json_path]);
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+        std::env::remove_var("CLAP_EMULATOR_TEST_CONFIG_PORT");
+        match (invalid, malformed) {
+            (Err(a), Err(b)) if a.kind() == ErrorKind::InvalidValue && b.kind() == ErrorKind::Format => Ok(()),
+            other => Err(format!("Expected InvalidValue and Format, got {:?}", other)),
+        }
+    }));
+    
+    // Test 54: subcommand_required and arg_required_else_help
+    results.push(test_runner("Required subcommands and bare help", || {
+        let app = Command::new("git")
+            .subcommand_required(true)
+            .arg(Arg::new("verbose").short('v'))
+            .subcommand(Command::new("clone")
+                .arg_required_else_help(true)
+                .arg(Arg::new("url")))
+            .subcommand(Command::new("status"));
+        
+        match app.clone().try_get_matches_from(&["git", "-v"]) {
+            Err(e) if e.kind() == ErrorKind::MissingSubcommand => {
+                let expected = "error: 'git' requires a subcommand but one was not provided\n  \
+                    [subcommands: clone, status, help]\n\n\
+                    Usage: git [OPTIONS] <COMMAND>\n\n\
+                    For more information, try '--help'.";
+                if e.to_string() != expected || e.exit_code() != 2 {
+                    return Err(format!("Unexpected error:\n{}", e));
+                }
+            }
+            other => return Err(format!("Expected MissingSubcommand, got {:?}", other)),
+        }
+        
+        // A bare subcommand prints its help as an error
+        match app.clone().try_get_matches_from(&["git", "clone"]) {
+            Err(e) if e.kind() == ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+                if !e.to_string().starts_with("Usage: git clone [URL]") || !e.use_stderr() || e.exit_code() != 2 {
+                    return Err(format!("Unexpected help:\n{}", e));
+                }
+            }
+            other => return Err(format!("Expected help, got {:?}", other)),
+        }
+        
+        let matches = app.try_get_matches_from(&["git", "clone", "repo"]).map_err(|e| e.to_string())?;
+        match matches.subcommand() {
+            Some(("clone", sub)) if sub.value_of("url") == Some("repo") => Ok(()),
+            _ => Err(format!("Unexpected matches: {:?}", matches)),
+        }
+    }));
+    
+    // Test 55: Value names and hints
+    results.push(test_runner("Value names and hints", || {
+        let output = Arg::new("output").short('o').long("output").takes_value(true)
+            .value_name("FILE").value_hint(ValueHint::FilePath);
+        if output.get_value_hint() != ValueHint::FilePath || Arg::new("x").get_value_hint() != ValueHint::Unknown {
+            return Err("Unexpected value hints".to_string());
+        }
+        let app = Command::new("cc")
+            .arg(output)
+            .arg(Arg::new("sources").value_name("SRC").required(true).num_args(1..).value_hint(ValueHint::FilePath));
+        let expected = "Usage: cc [OPTIONS] <SRC>\n\n\
+            Arguments:\n  \
+            <SRC>...\n\n\
+            Options:\n  \
+            -o, --output <FILE>\n  \
+            -h, --help           Print help";
+        match app.clone().try_get_matches_from(&["cc", "--help"]) {
+            Err(e) if e.to_string() == expected => {}
+            Err(e) => return Err(format!("Unexpected help:\n{}", e)),
+            Ok(_) => return Err("Expected help".to_string()),
+        }
+        match app.try_get_matches_from(&["cc", "-o", "a.out"]) {
+            Err(e) if e.args() == ["<SRC>"] => Ok(()),
+            other => Err(format!("Unexpected result: {:?}", other)),
+        }
+    }));
+    
+    // Test 56: num_args bounds
+    results.push(test_runner("Value count errors", || {
+        let app = Command::new("tool")
+            .arg(Arg::new("color").long("color").num_args(1..=3))
+            .arg(Arg::new("point").long("point").num_args(2).value_delimiter(','))
+            .arg(Arg::new("verbose").short('v'))
+            .arg(Arg::new("files").num_args(2..=3));
+        let matches = app.clone()
+            .try_get_matches_from(&["tool", "--color", "r", "g", "-v", "--point", "1,2", "a", "b"])
+            .map_err(|e| e.to_string())?;
+        if matches.values_of("color") != Some(vec!["r", "g"]) || matches.values_of("point") != Some(vec!["1", "2"])
+            || matches.values_of("files") != Some(vec!["a", "b"]) {
+            return Err(format!("Unexpected matches: {:?}", matches));
+        }
+        
+        let cases = [
+            (vec!["tool", "--color"], ErrorKind::TooFewValues, "a value is required for '--color <COLOR>' but none was supplied"),
+            (vec!["tool", "--point", "1", "-v"], ErrorKind::TooFewValues, "2 values required by '--point <POINT>'; only 1 was provided"),
+            (vec!["tool", "a"], ErrorKind::TooFewValues, "2 values required by '<FILES>'; only 1 was provided"),
+            (vec!["tool", "--point", "1,2,3"], ErrorKind::TooManyValues, "unexpected value '3' for '--point <POINT>' found; no more were expected"),
+            (vec!["tool", "a", "b", "c", "d"], ErrorKind::TooManyValues, "unexpected value 'd' for '<FILES>' found; no more were expected"),
+        ];
+        for (args, kind, message) in cases {
+            match app.clone().try_get_matches_from(&args) {
+                Err(e) if e.kind() == kind && e.to_string().starts_with(&format!("error: {}\n", message)) => {}
+                other => return Err(format!("{:?} gave {:?}", args, other)),
+            }
+        }
+        
+        // A stray value straight after a full option is one too many for
+        // it, when no positional is left to take it
+        let app = Command::new("paint").arg(Arg::new("rgb").long("rgb").num_args(3));
+        match app.try_get_matches_from(&["paint", "--rgb", "1", "2", "3", "4"]) {
+            Err(e) if e.kind() == ErrorKind::TooManyValues && e.args() == ["4", "--rgb <RGB>"] => Ok(()),
+            other => Err(format!("Expected TooManyValues, got {:?}", other)),
+        }
+    }));
+    
+    // Test 57: Usage overrides and extra help text
+    results.push(test_runner("Help prose", || {
+        let app = Command::new("grep")
+            .about("Search files")
+            .long_about("Search files for lines matching a pattern.\n\nPatterns are regular expressions.")
+            .override_usage("grep [OPTIONS] PATTERN [FILE]...")
+            .before_help("grep 3.11")
+            .after_help("Examples:\n  grep -i todo src/*.rs")
+            .arg(Arg::new("ignore-case").short('i').help("Ignore case"));
+        let help = |flag: &str| match app.clone().try_get_matches_from(&["grep", flag]) {
+            Err(e) if e.kind() == ErrorKind::DisplayHelp => Ok(e.to_string()),
+            other => Err(format!("Expected help, got {:?}", other)),
+        };
+        let short = "grep 3.11\n\n\
+            Search files\n\n\
+            Usage: grep [OPTIONS] PATTERN [FILE]...\n\n\
+            Options:\n  \
+            -i          Ignore case\n  \
+            -h, --help  Print help\n\n\
+            Examples:\n  \
+            grep -i todo src/*.rs";
+        if help("-h")? != short {
+            return Err(format!("Unexpected -h help:\n{}", help("-h")?));
+        }
+        let long = help("--help")?;
+        if !long.starts_with("grep 3.11\n\nSearch files for lines matching a pattern.\n\nPatterns are regular expressions.\n\nUsage:") {
+            return Err(format!("Unexpected --help help:\n{}", long));
+        }
+        
+        // Errors use the overridden usage, and man pages the long about
+        match app.clone().try_get_matches_from(&["grep", "-x"]) {
+            Err(e) if e.usage() == Some("Usage: grep [OPTIONS] PATTERN [FILE]...") => {}
+            other => return Err(format!("Unexpected result: {:?}", other)),
+        }
+        let mut page = Vec::new();
+        mangen::Man::new(app).render(&mut page).map_err(|e| e.to_string())?;
+        let page = String::from_utf8(page).map_err(|e| e.to_string())?;
+        if !page.contains(".SH DESCRIPTION\n.PP\nSearch files for lines matching a pattern.\n.PP\nPatterns are regular expressions.\n.SH OPTIONS") {
+            return Err(format!("Unexpected page:\n{}", page));
+        }
+        Ok(())
+    }));
+    
+    // Test 58: Arguments that aren't valid UTF-8
+    #[cfg(unix)]
+    results.push(test_runner("OsString arguments", || {
+        use std::ffi::{OsStr, OsString};
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        let name = OsString::from_vec(b"caf\xe9.txt".to_vec());
+        let app = Command::new("cat")
+            .arg(Arg::new("output").long("output").takes_value(true))
+            .arg(Arg::new("mode").long("mode").value_parser(["r", "w"]))
+            .arg(Arg::new("file"));
+        let attached = OsString::from_vec(b"--output=out\xff".to_vec());
+        let args = [OsString::from("cat"), attached, name.clone()];
+        let matches = app.clone().try_get_matches_from_os(&args).map_err(|e| e.to_string())?;
+        if matches.get_raw("file") != Some(name.as_os_str()) || matches.value_of("file") != Some("caf\u{FFFD}.txt") {
+            return Err(format!("Unexpected file: {:?}", matches.get_raw("file")));
+        }
+        if matches.get_raw("output").map(OsStr::as_bytes) != Some(b"out\xff".as_slice()) {
+            return Err(format!("Unexpected output: {:?}", matches.get_raw("output")));
+        }
+        let args = [OsString::from("cat"), OsString::from("plain.txt")];
+        let matches = app.clone().try_get_matches_from_os(&args).map_err(|e| e.to_string())?;
+        if matches.get_raw("file") != Some(OsStr::new("plain.txt")) {
+            return Err(format!("Unexpected file: {:?}", matches.get_raw("file")));
+        }
+        
+        // Errors show such values lossily
+        let args = [OsString::from("cat"), OsString::from("--mode"), OsString::from_vec(b"\xff".to_vec())];
+        match app.try_get_matches_from_os(&args) {
+            Err(e) if e.kind() == ErrorKind::InvalidValue && e.args()[0] == "\u{FFFD}" => Ok(()),
+            other => Err(format!("Expected InvalidValue, got {:?}", other)),
+        }
+    }));
+    
+    // Test 59: Rendering help and errors for snapshots
+    results.push(test_runner("Rendering help and errors", || {
+        let app = Command::new("tool")
+            .color(ColorChoice::Always)
+            .about("Does things")
+            .long_about("Does things, at length")
+            .arg(Arg::new("name").long("name").takes_value(true).required(true));
+        let short = "Does things\n\n\
+            Usage: tool --name <NAME>\n\n\
+            Options:\n      \
+            --name <NAME>\n  \
+            -h, --help         Print help";
+        if app.render_help() != short {
+            return Err(format!("Unexpected help:\n{}", app.render_help()));
+        }
+        if app.render_long_help() != short.replace("Does things", "Does things, at length") {
+            return Err(format!("Unexpected long help:\n{}", app.render_long_help()));
+        }
+        
+        let e = match app.clone().try_get_matches_from(&["tool"]) {
+            Err(e) => e,
+            Ok(_) => return Err("Expected an error".to_string()),
+        };
+        let expected = "error: the following required arguments were not provided:\n  \
+            --name <NAME>\n\n\
+            Usage: tool --name <NAME>\n\n\
+            For more information, try '--help'.";
+        if e.render() != expected || !e.to_string().contains('\x1b') {
+            return Err(format!("Unexpected error:\n{}", e.render()));
+        }
+        match app.try_get_matches_from(&["tool", "--help"]) {
+            Err(e) if e.render() == short.replace("Does things", "Does things, at length") => Ok(()),
+            other => Err(format!("Unexpected help: {:?}", other.map(|_| ()))),
+        }
+    }));
+    
+    // Test 60: Validators
+    results.push(test_runner("Validators", || {
+        let app = Command::new("server")
+            .arg(Arg::new("port").long("port").env("TEST_VALIDATOR_PORT").validator(|s| {
+                match s.parse::<u32>() {
+                    Ok(port) if (1..=65535).contains(&port) => Ok(()),
+                    _ => Err("port must be 1-65535".to_string()),
+                }
+            }))
+            .arg(Arg::new("level").long("level").value_parser(["low", "high"]).validator(|s| {
+                if s == "high" { Err("high is disabled".to_string()) } else { Ok(()) }
+            }));
+        
+        let matches = app.clone().try_get_matches_from(&["server", "--port", "8080"])
+            .map_err(|e| e.to_string())?;
+        if matches.get_one::<u16>("port") != Some(8080) {
+            return Err("Expected port 8080".to_string());
+        }
+        match app.clone().try_get_matches_from(&["server", "--port=70000"]) {
+            Err(e) if e.kind() == ErrorKind::ValueValidation && e.args() == ["70000", "--port <PORT>"] => {
+                if !e.render().starts_with("error: invalid value '70000' for '--port <PORT>': port must be 1-65535") {
+                    return Err(format!("Unexpected message: {}", e.render()));
+                }
+            }
+            other => return Err(format!("Expected ValueValidation, got {:?}", other)),
+        }
+        // Possible values are checked first
+        match app.clone().try_get_matches_from(&["server", "--level", "mid"]) {
+            Err(e) if e.kind() == ErrorKind::InvalidValue => {}
+            other => return Err(format!("Expected InvalidValue, got {:?}", other)),
+        }
+        match app.clone().try_get_matches_from(&["server", "--level", "high"]) {
+            Err(e) if e.kind() == ErrorKind::ValueValidation => {}
+            other => return Err(format!("Expected ValueValidation, got {:?}", other)),
+        }
+        
+        std::env::set_var("TEST_VALIDATOR_PORT", "0");
+        let result = app.try_get_matches_from(&["server"]);
+        std::env::remove_var("TEST_VALIDATOR_PORT");
+        match result {
+            Err(e) if e.kind() == ErrorKind::ValueValidation && e.args()[0] == "0" => Ok(()),
+            other => Err(format!("Expected ValueValidation from env, got {:?}", other)),
+        }
+    }));
+    
+    // Test 61: Propagating version and metadata to subcommands
+    results.push(test_runner("Propagating version and metadata", || {
+        let app = Command::new("app")
+            .version("2.1.0")
+            .author("Jane Doe")
+            .long_about("The app, at length")
+            .propagate_version(true)
+            .subcommand(Command::new("sub").help_template("{author}: {about}").subcommand(Command::new("deep")))
+            .subcommand(Command::new("own").version("0.3.0").about("Has its own").help_template("{author}: {about}"));
+        
+        match app.clone().try_get_matches_from(&["app", "sub", "--version"]) {
+            Err(e) if e.kind() == ErrorKind::DisplayVersion && e.to_string() == "sub 2.1.0" => {}
+            other => return Err(format!("Expected sub's version, got {:?}", other)),
+        }
+        match app.clone().try_get_matches_from(&["app", "sub", "deep", "-V"]) {
+            Err(e) if e.kind() == ErrorKind::DisplayVersion && e.to_string() == "deep 2.1.0" => {}
+            other => return Err(format!("Expected deep's version, got {:?}", other)),
+        }
+        match app.clone().try_get_matches_from(&["app", "own", "--version"]) {
+            Err(e) if e.to_string() == "own 0.3.0" => {}
+            other => return Err(format!("Expected own's version, got {:?}", other)),
+        }
+        let sub_help = app.clone().color(ColorChoice::Never);
+        match sub_help.clone().try_get_matches_from(&["app", "sub", "--help"]) {
+            Err(e) if e.to_string() == "Jane Doe: The app, at length" => {}
+            other => return Err(format!("Expected inherited metadata, got {:?}", other.map(|_| ()))),
+        }
+        match sub_help.try_get_matches_from(&["app", "own", "--help"]) {
+            Err(e) if e.to_string() == "Jane Doe: Has its own" => {}
+            other => return Err(format!("Expected own about, got {:?}", other.map(|_| ()))),
+        }
+        
+        // Without propagate_version, subcommands have no version flag
+        let app = Command::new("app").version("2.1.0").subcommand(Command::new("sub"));
+        match app.try_get_matches_from(&["app", "sub", "--version"]) {
+            Err(e) if e.kind() == ErrorKind::UnknownArgument => Ok(()),
+            other => Err(format!("Expected UnknownArgument, got {:?}", other.map(|_| ()))),
+        }
+    }));
+    
+    // Test 62: last(true) and raw(true) positionals
+    results.push(test_runner("Last and raw positionals", || {
+        let app = Command::new("run")
+            .arg(Arg::new("verbose").short('v'))
+            .arg(Arg::new("target"))
+            .arg(Arg::new("args").action(ArgAction::Append).last(true).raw(true));
+        
+        let matches = app.clone().try_get_matches_from(&["run", "-v", "app", "--", "--release", "-v", "--", "a,b"])
+            .map_err(|e| e.to_string())?;
+        if matches.value_of("target") != Some("app") || !matches.get_flag("verbose") {
+            return Err("Expected target and verbose".to_string());
+        }
+        if matches.get_many::<String>("args") != Some(vec!["--release".to_string(), "-v".to_string(), "--".to_string(), "a,b".to_string()]) {
+            return Err(format!("Unexpected args: {:?}", matches.get_many::<String>("args")));
+        }
+        // Values after `--` skip straight to the last Arg
+        let matches = app.clone().try_get_matches_from(&["run", "--", "x"]).map_err(|e| e.to_string())?;
+        if matches.value_of("target").is_some() || matches.value_of("args") != Some("x") {
+            return Err("Expected x for args only".to_string());
+        }
+        match app.clone().try_get_matches_from(&["run", "app", "extra"]) {
+            Err(e) if e.kind() == ErrorKind::UnknownArgument && e.render().contains("tip: to pass 'extra' as a value, use '-- extra'") => {}
+            other => return Err(format!("Expected UnknownArgument, got {:?}", other.map(|_| ()))),
+        }
+        if !app.render_help().starts_with("Usage: run [OPTIONS] [TARGET] [-- <ARGS>]") {
+            return Err(format!("Unexpected usage: {}", app.render_help()));
+        }
+        
+        // A raw Arg without last captures from its first value on
+        let matches = Command::new("sudo")
+            .arg(Arg::new("user").short('u').takes_value(true))
+            .arg(Arg::new("command").raw(true).value_delimiter(','))
+            .try_get_matches_from(&["sudo", "-u", "root", "ls", "-u", "--", "a,b"])
+            .map_err(|e| e.to_string())?;
+        let command = matches.get_many::<String>("command").unwrap_or_default();
+        if matches.value_of("user") != Some("root") || command != ["ls", "-u", "--", "a,b"] {
+            return Err(format!("Unexpected command: {:?}", command));
+        }
+        Ok(())
+    }));
+    
     // Print results
     println!("\n=== Test Results ===");
     let mut passed = 0;
@@ -1,39 +1,118 @@
 // Developed by PowerShield, as an alternative to Actix-web
 
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_emulator::Runtime;
+
+pub use tokio_emulator::{Future, Poll};
 
 // HttpRequest represents an HTTP request
 #[derive(Clone)]
 pub struct HttpRequest {
     pub method: String,
     pub path: String,
+    // The query string as sent, without the `?`
+    pub query: String,
     pub headers: HashMap<String, String>,
     pub body: Vec<u8>,
     pub query_params: HashMap<String, String>,
     pub path_params: HashMap<String, String>,
+    // The names of path_params in the order the route gives them
+    match_names: Vec<String>,
+    // The app's data, by type, for app_data and the Data extractor
+    app_data: HashMap<TypeId, Rc<dyn Any>>,
 }
 
 impl HttpRequest {
+    // A `?k=v&k2=v2` query on the path is split off into query and
+    // query_params
     pub fn new(method: &str, path: &str) -> Self {
-        HttpRequest {
+        let mut req = HttpRequest {
             method: method.to_string(),
-            path: path.to_string(),
+            path: String::new(),
+            query: String::new(),
             headers: HashMap::new(),
             body: Vec::new(),
             query_params: HashMap::new(),
             path_params: HashMap::new(),
+            match_names: Vec::new(),
+            app_data: HashMap::new(),
+        };
+        req.set_target(path);
+        req
+    }
+
+    // Set the path and query from a request target such as
+    // `/search?q=rust`. Keys and values are percent-decoded, with `+` as a
+    // space; for a repeated key query_params keeps the last value.
+    fn set_target(&mut self, target: &str) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        self.path = path.to_string();
+        self.query = query.to_string();
+        for (key, value) in parse_query(query) {
+            self.query_params.insert(key, value);
         }
     }
 
+    // Every value given for key in the query, in order, for keys such as
+    // `tag` in `?tag=a&tag=b`
+    pub fn query_values(&self, key: &str) -> Vec<String> {
+        parse_query(&self.query)
+            .into_iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .collect()
+    }
+
     pub fn header(&self, name: &str) -> Option<&String> {
         self.headers.get(name)
     }
 
+    // A header by name in any case, as clients may send `content-type`
+    fn find_header(&self, name: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    // Data of type T registered with App::app_data, usually a Data<U>
+    pub fn app_data<T: 'static>(&self) -> Option<&T> {
+        self.app_data.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    // The path parameters in the route's order, then any set by hand
+    fn ordered_path_params(&self) -> Vec<(String, String)> {
+        let mut params: Vec<(String, String)> = self
+            .match_names
+            .iter()
+            .filter_map(|name| Some((name.clone(), self.path_params.get(name)?.clone())))
+            .collect();
+        for (name, value) in &self.path_params {
+            if !self.match_names.contains(name) {
+                params.push((name.clone(), value.clone()));
+            }
+        }
+        params
+    }
+
     pub fn match_info(&self) -> &HashMap<String, String> {
         &self.path_params
     }
 
+    // The query as sent, or for a request whose query_params were filled in
+    // by hand, those joined as `k=v&k2=v2`
     pub fn query_string(&self) -> String {
+        if !self.query.is_empty() {
+            return self.query.clone();
+        }
         self.query_params
             .iter()
             .map(|(k, v)| format!("{}={}", k, v))
@@ -42,6 +121,40 @@ impl HttpRequest {
     }
 }
 
+// The decoded key-value pairs of a query string, in order. A pair
+// without `=` has an empty value.
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+// Decode `%XX` escapes and `+` for a space; a `%` not followed by two hex
+// digits is kept as is
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let hex = |i: usize| bytes.get(i).and_then(|&b| (b as char).to_digit(16));
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], hex(i + 1), hex(i + 2)) {
+            (b'+', _, _) => decoded.push(b' '),
+            (b'%', Some(high), Some(low)) => {
+                decoded.push((high * 16 + low) as u8);
+                i += 2;
+            }
+            (byte, _, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 // HttpResponse represents an HTTP response
 pub struct HttpResponse {
     pub status_code: u16,
@@ -49,6 +162,8 @@ pub struct HttpResponse {
     pub body: Vec<u8>,
 }
 
+// The status constructors are named after the status, as in actix
+#[allow(non_snake_case)]
 impl HttpResponse {
     pub fn new(status_code: u16) -> Self {
         HttpResponse {
@@ -116,15 +231,97 @@ impl HttpResponseBuilder {
 // Handler function type
 pub type Handler = fn(HttpRequest) -> HttpResponse;
 
+// An async handler, boxed so handlers returning different futures can be
+// stored together
+pub type AsyncHandler = Box<dyn Fn(HttpRequest) -> Box<dyn Future<Output = HttpResponse>>>;
+
+enum RouteHandler {
+    Sync(Box<dyn Fn(HttpRequest) -> HttpResponse>),
+    Async(AsyncHandler),
+}
+
+impl RouteHandler {
+    fn extract<Args, H>(handler: H) -> Self
+    where
+        H: HandlerFn<Args>,
+    {
+        RouteHandler::Sync(Box::new(move |req| handler.call(&req)))
+    }
+
+    fn future<F, Fut>(handler: F) -> Self
+    where
+        F: Fn(HttpRequest) -> Fut + 'static,
+        Fut: Future<Output = HttpResponse> + 'static,
+    {
+        RouteHandler::Async(Box::new(move |req| Box::new(handler(req))))
+    }
+}
+
+// Middleware wraps the rest of the app: it gets the request and `next`,
+// the middleware after it and then the route. It can change the request,
+// answer without calling next, or change the response next returns.
+pub trait Middleware: 'static {
+    fn call(&self, req: HttpRequest, next: &Next) -> HttpResponse;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(HttpRequest, &Next) -> HttpResponse + 'static,
+{
+    fn call(&self, req: HttpRequest, next: &Next) -> HttpResponse {
+        self(req, next)
+    }
+}
+
+// Middleware from App::wrap, which only sees the request
+struct Before<F>(F);
+
+impl<F> Middleware for Before<F>
+where
+    F: Fn(&mut HttpRequest) -> Option<HttpResponse> + 'static,
+{
+    fn call(&self, mut req: HttpRequest, next: &Next) -> HttpResponse {
+        match (self.0)(&mut req) {
+            Some(response) => response,
+            None => next.call(req),
+        }
+    }
+}
+
+// The rest of the app from a middleware's point of view
+pub struct Next<'a> {
+    app: &'a App,
+    middleware: &'a [Box<dyn Middleware>],
+}
+
+impl Next<'_> {
+    // Pass the request on and get back its response
+    pub fn call(&self, req: HttpRequest) -> HttpResponse {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => middleware.call(req, &Next { app: self.app, middleware: rest }),
+            None => self.app.dispatch(req),
+        }
+    }
+}
+
 // Route structure
 struct Route {
     method: String,
     path: String,
-    handler: Handler,
+    handler: RouteHandler,
 }
 
 impl Route {
-    fn matches(&self, method: &str, path: &str) -> Option<HashMap<String, String>> {
+    fn new(path: &str, method: &str, handler: RouteHandler) -> Self {
+        Route {
+            method: method.to_string(),
+            path: path.to_string(),
+            handler,
+        }
+    }
+
+    // The route's path parameters, in order, if it matches
+    fn matches(&self, method: &str, path: &str) -> Option<Vec<(String, String)>> {
         if self.method != method {
             return None;
         }
@@ -136,12 +333,12 @@ impl Route {
             return None;
         }
 
-        let mut params = HashMap::new();
+        let mut params = Vec::new();
 
         for (route_part, path_part) in route_parts.iter().zip(path_parts.iter()) {
             if route_part.starts_with('{') && route_part.ends_with('}') {
                 let param_name = &route_part[1..route_part.len() - 1];
-                params.insert(param_name.to_string(), path_part.to_string());
+                params.push((param_name.to_string(), path_part.to_string()));
             } else if route_part != path_part {
                 return None;
             }
@@ -154,7 +351,10 @@ impl Route {
 // App structure representing the web application
 pub struct App {
     routes: Vec<Route>,
-    middleware: Vec<Box<dyn Fn(&mut HttpRequest) -> Option<HttpResponse>>>,
+    middleware: Vec<Box<dyn Middleware>>,
+    // Polls async handlers' futures, as actix runs its handlers on tokio
+    runtime: RefCell<Runtime>,
+    app_data: HashMap<TypeId, Rc<dyn Any>>,
 }
 
 impl App {
@@ -162,39 +362,99 @@ impl App {
         App {
             routes: Vec::new(),
             middleware: Vec::new(),
+            runtime: RefCell::new(Runtime::new()),
+            app_data: HashMap::new(),
         }
     }
 
     pub fn route(mut self, path: &str, method: &str, handler: Handler) -> Self {
-        self.routes.push(Route {
-            method: method.to_string(),
-            path: path.to_string(),
-            handler,
-        });
+        self.routes.push(Route::new(path, method, RouteHandler::Sync(Box::new(handler))));
         self
     }
 
+    // Register a handler taking up to six extractors, such as
+    // `|path: Path<u32>, body: Json<NewUser>| ...`, after Route::to in
+    // actix. If one can't be extracted its error response is sent instead.
+    pub fn route_to<Args, H>(mut self, path: &str, method: &str, handler: H) -> Self
+    where
+        H: HandlerFn<Args>,
+    {
+        self.routes.push(Route::new(path, method, RouteHandler::extract(handler)));
+        self
+    }
+
+    // Make data available to every request, through HttpRequest::app_data
+    // and the Data extractor when it's a Data<T>
+    pub fn app_data<U: 'static>(mut self, data: U) -> Self {
+        self.app_data.insert(TypeId::of::<U>(), Rc::new(data));
+        self
+    }
+
+    // Register a handler returning a future, which handle_request polls to
+    // completion on the app's runtime
+    pub fn route_async<F, Fut>(mut self, path: &str, method: &str, handler: F) -> Self
+    where
+        F: Fn(HttpRequest) -> Fut + 'static,
+        Fut: Future<Output = HttpResponse> + 'static,
+    {
+        self.routes.push(Route::new(path, method, RouteHandler::future(handler)));
+        self
+    }
+
+    // Mount a scope's routes, nested scopes included, under its prefix
+    pub fn service(mut self, scope: Scope) -> Self {
+        self.routes.extend(scope.routes);
+        self
+    }
+
+    // Run middleware before routing that may change the request or answer
+    // it itself. Middleware runs in the order it's added.
     pub fn wrap<F>(mut self, middleware: F) -> Self
     where
         F: Fn(&mut HttpRequest) -> Option<HttpResponse> + 'static,
     {
+        self.middleware.push(Box::new(Before(middleware)));
+        self
+    }
+
+    // Run middleware that calls `next` for the response, and may change
+    // it, as for timing headers or logging the status
+    pub fn wrap_fn<F>(mut self, middleware: F) -> Self
+    where
+        F: Fn(HttpRequest, &Next) -> HttpResponse + 'static,
+    {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    // Run a Middleware implemented by a type, such as middleware::Logger
+    pub fn wrap_middleware<M: Middleware>(mut self, middleware: M) -> Self {
         self.middleware.push(Box::new(middleware));
         self
     }
 
     pub fn handle_request(&self, mut req: HttpRequest) -> HttpResponse {
-        // Apply middleware
-        for mw in &self.middleware {
-            if let Some(response) = mw(&mut req) {
-                return response;
-            }
+        req.app_data = self.app_data.clone();
+        Next { app: self, middleware: &self.middleware }.call(req)
+    }
+
+    // Route a request that has been through the middleware
+    fn dispatch(&self, mut req: HttpRequest) -> HttpResponse {
+        // A path set by hand may still carry its query
+        if req.path.contains('?') {
+            let target = std::mem::take(&mut req.path);
+            req.set_target(&target);
         }
 
         // Find matching route
         for route in &self.routes {
             if let Some(params) = route.matches(&req.method, &req.path) {
-                req.path_params = params;
-                return (route.handler)(req);
+                req.match_names = params.iter().map(|(name, _)| name.clone()).collect();
+                req.path_params = params.into_iter().collect();
+                return match &route.handler {
+                    RouteHandler::Sync(handler) => handler(req),
+                    RouteHandler::Async(handler) => self.runtime.borrow_mut().block_on(handler(req)),
+                };
             }
         }
 
@@ -202,31 +462,290 @@ impl App {
         HttpResponse::NotFound().body("Not Found")
     }
 
+    // Serve the app over HTTP/1.1 at bind_addr until the process exits
     pub fn run(self, bind_addr: &str) -> Result<(), String> {
-        println!("Server running at {}", bind_addr);
-        println!("(Simulated - no actual server started)");
+        let listener = bind(bind_addr)?;
+        self.serve(listener, IO_TIMEOUT)
+    }
+
+    // Answer connections on listener one at a time, a request each. A client
+    // that stops sending holds up the others for at most timeout.
+    fn serve(&self, listener: TcpListener, timeout: Duration) -> Result<(), String> {
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        println!("Server running at http://{}", addr);
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| self.handle_connection(stream, timeout));
+            if let Err(e) = result {
+                eprintln!("Connection error: {}", e);
+            }
+        }
         Ok(())
     }
+
+    fn handle_connection(&self, mut stream: TcpStream, timeout: Duration) -> io::Result<()> {
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let response = match read_request(&mut stream) {
+            Ok(Some(req)) => self.handle_request(req),
+            // The client closed the connection without sending anything
+            Ok(None) => return Ok(()),
+            Err(e) => match e.kind() {
+                io::ErrorKind::InvalidData => HttpResponse::BadRequest().body(e.to_string()),
+                io::ErrorKind::FileTooLarge => HttpResponseBuilder::new(413).body(e.to_string()),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                    HttpResponseBuilder::new(408).body("Request Timeout")
+                }
+                _ => return Err(e),
+            },
+        };
+        write_response(&mut stream, &response)
+    }
+}
+
+fn bind(addr: &str) -> Result<TcpListener, String> {
+    TcpListener::bind(addr).map_err(|e| format!("Failed to bind {}: {}", addr, e))
+}
+
+// The largest request body the server reads, larger ones get a 413
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+// How long the server waits by default on a client to send or receive
+// before giving up
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn bad_request(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn payload_too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::FileTooLarge, "Payload Too Large")
+}
+
+// Read one HTTP/1.x request: the request line, the headers and a body
+// given by Content-Length or chunked transfer encoding. A malformed
+// request is an InvalidData error, and a body over MAX_BODY_SIZE a
+// FileTooLarge error.
+fn read_request(stream: &mut TcpStream) -> io::Result<Option<HttpRequest>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let (method, target) = match parts.as_slice() {
+        [method, target, version] if version.starts_with("HTTP/1.") => (*method, *target),
+        _ => return Err(bad_request("Malformed request line")),
+    };
+    let mut req = HttpRequest::new(method, target);
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(bad_request("Unexpected end of headers"));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| bad_request("Malformed header"))?;
+        // Repeated headers are combined into one comma-separated value
+        req.headers
+            .entry(name.trim().to_string())
+            .and_modify(|v| *v = format!("{}, {}", v, value.trim()))
+            .or_insert_with(|| value.trim().to_string());
+    }
+
+    let header = |name: &str| req.find_header(name).cloned();
+    let chunked = header("Transfer-Encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked"));
+    let length = match header("Content-Length") {
+        Some(length) if !chunked => {
+            let length: usize = length.parse().map_err(|_| bad_request("Invalid Content-Length"))?;
+            if length > MAX_BODY_SIZE {
+                return Err(payload_too_large());
+            }
+            Some(length)
+        }
+        _ => None,
+    };
+    // curl asks before sending a large body
+    if header("Expect").is_some_and(|v| v.eq_ignore_ascii_case("100-continue")) {
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+    let body = if chunked {
+        read_chunked_body(&mut reader)?
+    } else if let Some(length) = length {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        body
+    } else {
+        Vec::new()
+    };
+    req.body = body;
+    Ok(Some(req))
+}
+
+// Each chunk is its size in hex on a line, then the data and a line break;
+// a zero size ends the body, after any trailer lines. The chunks together
+// may be at most MAX_BODY_SIZE.
+fn read_chunked_body(reader: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let size = line.trim().split(';').next().unwrap_or_default();
+        let size = usize::from_str_radix(size, 16).map_err(|_| bad_request("Invalid chunk size"))?;
+        if size == 0 {
+            loop {
+                let mut trailer = String::new();
+                if reader.read_line(&mut trailer)? == 0 || trailer.trim().is_empty() {
+                    return Ok(body);
+                }
+            }
+        }
+        let start = body.len();
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= MAX_BODY_SIZE)
+            .ok_or_else(payload_too_large)?;
+        body.resize(end, 0);
+        reader.read_exact(&mut body[start..])?;
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf)?;
+    }
+}
+
+// Write the status line, the response's headers with Content-Length, and
+// the body; the connection closes after each response
+fn write_response(stream: &mut TcpStream, response: &HttpResponse) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status_code, reason_phrase(response.status_code));
+    for (name, value) in &response.headers {
+        if !name.eq_ignore_ascii_case("Content-Length") && !name.eq_ignore_ascii_case("Connection") {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+fn reason_phrase(status_code: u16) -> &'static str {
+    match status_code {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+// A value a handler can take as a parameter, extracted from the request.
+// An Err is the response to send instead of calling the handler.
+pub trait FromRequest: Sized {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse>;
+}
+
+impl FromRequest for HttpRequest {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        Ok(req.clone())
+    }
 }
 
+// An optional extractor is None where it would fail
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        Ok(T::from_request(req).ok())
+    }
+}
+
+// A handler taking extractors, for App::route_to. It's implemented for
+// closures and functions of up to six FromRequest parameters returning an
+// HttpResponse.
+pub trait HandlerFn<Args>: 'static {
+    fn call(&self, req: &HttpRequest) -> HttpResponse;
+}
+
+macro_rules! handler_fn {
+    ($($arg:ident),*) => {
+        impl<F, $($arg,)*> HandlerFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> HttpResponse + 'static,
+            $($arg: FromRequest,)*
+        {
+            #[allow(non_snake_case, unused_variables)]
+            fn call(&self, req: &HttpRequest) -> HttpResponse {
+                $(
+                    let $arg = match $arg::from_request(req) {
+                        Ok(value) => value,
+                        Err(response) => return response,
+                    };
+                )*
+                self($($arg),*)
+            }
+        }
+    };
+}
+
+handler_fn!();
+handler_fn!(A);
+handler_fn!(A, B);
+handler_fn!(A, B, C);
+handler_fn!(A, B, C, D);
+handler_fn!(A, B, C, D, E);
+handler_fn!(A, B, C, D, E, G);
+
 // JSON extraction helper
 pub struct Json<T> {
     pub inner: T,
 }
 
+impl<T> Json<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
 impl<T: serde::de::DeserializeOwned> Json<T> {
+    // The FromRequest extraction, with the error response's body as the
+    // error
     pub fn from_request(req: &HttpRequest) -> Result<Self, String> {
-        let json_str = String::from_utf8(req.body.clone())
-            .map_err(|_| "Invalid UTF-8".to_string())?;
-        
-        let data: T = serde_json::from_str(&json_str)
-            .map_err(|e| format!("JSON parse error: {}", e))?;
-        
-        Ok(Json { inner: data })
+        <Self as FromRequest>::from_request(req)
+            .map_err(|response| String::from_utf8_lossy(&response.body).into_owned())
     }
+}
 
-    pub fn into_inner(self) -> T {
-        self.inner
+// The body parsed as JSON; a body that isn't is a 400
+impl<T: serde::de::DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        serde_json::from_slice(&req.body)
+            .map(|inner| Json { inner })
+            .map_err(|e| HttpResponse::BadRequest().body(format!("Json deserialize error: {}", e)))
     }
 }
 
@@ -235,32 +754,369 @@ pub struct Path<T> {
     pub inner: T,
 }
 
+impl<T> Path<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Deref for Path<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
 impl Path<String> {
+    // One path parameter by name, whatever else the route has
     pub fn from_request(req: &HttpRequest, param_name: &str) -> Result<Self, String> {
-        req.path_params
-            .get(param_name)
-            .map(|s| Path { inner: s.clone() })
+        let params = <Path<HashMap<String, String>> as FromRequest>::from_request(req)
+            .map_err(|response| String::from_utf8_lossy(&response.body).into_owned())?;
+        params
+            .into_inner()
+            .remove(param_name)
+            .map(|inner| Path { inner })
             .ok_or_else(|| format!("Path parameter '{}' not found", param_name))
     }
 }
 
+// The path parameters as one value such as a u32 for `/users/{id}`, a
+// tuple in the route's order, or a struct or map by name. Parameters that
+// don't fit are a 404, as in actix.
+impl<T: serde::de::DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let params = req.ordered_path_params();
+        T::deserialize(params::Params(&params))
+            .map(|inner| Path { inner })
+            .map_err(|e| HttpResponse::NotFound().body(format!("Path deserialize error: {}", e)))
+    }
+}
+
 // Query parameter extraction
 pub struct Query<T> {
     pub inner: T,
 }
 
+impl<T> Query<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
 impl Query<HashMap<String, String>> {
+    // All the query parameters, which extracting as a map can't fail
     pub fn from_request(req: &HttpRequest) -> Self {
-        Query {
-            inner: req.query_params.clone(),
+        <Self as FromRequest>::from_request(req).unwrap_or(Query { inner: HashMap::new() })
+    }
+}
+
+// The query parameters as a struct or map, with values parsed into its
+// field types; ones that don't fit are a 400
+impl<T: serde::de::DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let params: Vec<(String, String)> = req.query_params.clone().into_iter().collect();
+        T::deserialize(params::Params(&params))
+            .map(|inner| Query { inner })
+            .map_err(|e| HttpResponse::BadRequest().body(format!("Query deserialize error: {}", e)))
+    }
+}
+
+// Shared application state, registered with App::app_data(Data::new(..))
+pub struct Data<T> {
+    inner: Arc<T>,
+}
+
+impl<T> Data<T> {
+    pub fn new(value: T) -> Self {
+        Data { inner: Arc::new(value) }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> Arc<T> {
+        self.inner
+    }
+}
+
+impl<T> Clone for Data<T> {
+    fn clone(&self) -> Self {
+        Data { inner: Arc::clone(&self.inner) }
+    }
+}
+
+impl<T> Deref for Data<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+// Data the app wasn't given is a 500, since it's a setup mistake
+impl<T: 'static> FromRequest for Data<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        req.app_data::<Data<T>>().cloned().ok_or_else(|| {
+            HttpResponse::InternalServerError().body(format!(
+                "App data is not configured for {}",
+                std::any::type_name::<T>()
+            ))
+        })
+    }
+}
+
+// A header with a fixed name, parsed from its value, for the Header
+// extractor
+pub trait TypedHeader: Sized {
+    const NAME: &'static str;
+
+    fn parse(value: &str) -> Result<Self, String>;
+}
+
+// A typed header such as Header<ContentType>; a missing or invalid one is
+// a 400
+pub struct Header<T> {
+    pub inner: T,
+}
+
+impl<T> Header<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Deref for Header<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: TypedHeader> FromRequest for Header<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let value = req
+            .find_header(T::NAME)
+            .ok_or_else(|| HttpResponse::BadRequest().body(format!("Missing header {}", T::NAME)))?;
+        T::parse(value)
+            .map(|inner| Header { inner })
+            .map_err(|e| HttpResponse::BadRequest().body(format!("Invalid header {}: {}", T::NAME, e)))
+    }
+}
+
+// Typed headers for the Header extractor
+pub mod header {
+    use super::TypedHeader;
+
+    // The body's media type, such as `application/json`
+    pub struct ContentType(pub String);
+
+    impl TypedHeader for ContentType {
+        const NAME: &'static str = "Content-Type";
+
+        fn parse(value: &str) -> Result<Self, String> {
+            Ok(ContentType(value.to_string()))
+        }
+    }
+
+    // The credentials, such as `Bearer token123`
+    pub struct Authorization(pub String);
+
+    impl TypedHeader for Authorization {
+        const NAME: &'static str = "Authorization";
+
+        fn parse(value: &str) -> Result<Self, String> {
+            Ok(Authorization(value.to_string()))
+        }
+    }
+
+    // The size of the body in bytes
+    pub struct ContentLength(pub usize);
+
+    impl TypedHeader for ContentLength {
+        const NAME: &'static str = "Content-Length";
+
+        fn parse(value: &str) -> Result<Self, String> {
+            value.trim().parse().map(ContentLength).map_err(|_| format!("'{}' is not a length", value))
+        }
+    }
+}
+
+// A serde Deserializer over path or query parameters, which are all
+// text: each value is parsed into the type its field asks for
+mod params {
+    use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+    use serde::de::{Deserializer, Error as _, IntoDeserializer, Visitor};
+    use serde::forward_to_deserialize_any;
+
+    // Every parameter, as a struct, map or tuple, or the only one as a
+    // single value
+    pub(super) struct Params<'a>(pub &'a [(String, String)]);
+
+    // One parameter's value
+    struct Value<'a>(&'a str);
+
+    impl<'a> Params<'a> {
+        fn single(&self) -> Result<Value<'a>, Error> {
+            match self.0 {
+                [(_, value)] => Ok(Value(value)),
+                params => Err(Error::custom(format!("expected 1 parameter, got {}", params.len()))),
+            }
+        }
+    }
+
+    macro_rules! single_value {
+        ($($method:ident)*) => {$(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.single()?.$method(visitor)
+            }
+        )*};
+    }
+
+    impl<'de, 'a> Deserializer<'de> for Params<'a> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            self.deserialize_map(visitor)
+        }
+
+        fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let mut map = MapDeserializer::new(self.0.iter().map(|(k, v)| (k.as_str(), Value(v))));
+            let value = visitor.visit_map(&mut map)?;
+            map.end()?;
+            Ok(value)
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.deserialize_map(visitor)
+        }
+
+        fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let mut seq = SeqDeserializer::new(self.0.iter().map(|(_, v)| Value(v)));
+            let value = visitor.visit_seq(&mut seq)?;
+            seq.end()?;
+            Ok(value)
+        }
+
+        fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+            self.deserialize_seq(visitor)
+        }
+
+        fn deserialize_tuple_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.deserialize_seq(visitor)
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            name: &'static str,
+            variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.single()?.deserialize_enum(name, variants, visitor)
+        }
+
+        single_value! {
+            deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+            deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+            deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_option
+        }
+
+        forward_to_deserialize_any! {
+            i128 u128 bytes byte_buf unit unit_struct identifier ignored_any
+        }
+    }
+
+    macro_rules! parse_value {
+        ($($method:ident => $visit:ident)*) => {$(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(Error::custom(format!("can not parse '{}'", self.0))),
+                }
+            }
+        )*};
+    }
+
+    impl<'de, 'a> Deserializer<'de> for Value<'a> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_str(self.0)
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(self.0))
+        }
+
+        parse_value! {
+            deserialize_bool => visit_bool
+            deserialize_i8 => visit_i8
+            deserialize_i16 => visit_i16
+            deserialize_i32 => visit_i32
+            deserialize_i64 => visit_i64
+            deserialize_u8 => visit_u8
+            deserialize_u16 => visit_u16
+            deserialize_u32 => visit_u32
+            deserialize_u64 => visit_u64
+            deserialize_f32 => visit_f32
+            deserialize_f64 => visit_f64
+            deserialize_char => visit_char
+        }
+
+        forward_to_deserialize_any! {
+            i128 u128 str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+            identifier ignored_any
+        }
+    }
+
+    impl<'de, 'a> IntoDeserializer<'de, Error> for Value<'a> {
+        type Deserializer = Self;
+
+        fn into_deserializer(self) -> Self {
+            self
         }
     }
 }
 
 // Web module for common utilities
 pub mod web {
-    use super::*;
-
     pub fn get() -> String {
         "GET".to_string()
     }
@@ -289,7 +1145,9 @@ pub mod web {
 // Middleware helpers
 pub mod middleware {
     use super::*;
+    use std::time::Instant;
 
+    // Logs each request's method and path, for App::wrap
     pub fn logger() -> impl Fn(&mut HttpRequest) -> Option<HttpResponse> {
         move |req: &mut HttpRequest| {
             println!("{} {}", req.method, req.path);
@@ -297,12 +1155,91 @@ pub mod middleware {
         }
     }
 
+    // Logs each request once it's answered, for App::wrap_fn. In `format`,
+    // %m is the method, %U the path, %s the response status, %T the time
+    // taken and %% a percent sign, so "%m %U %s %T" logs `GET /data 200 1ms`.
+    pub fn logger_with(format: &str) -> impl Fn(HttpRequest, &Next) -> HttpResponse {
+        let format = format.to_string();
+        move |req: HttpRequest, next: &Next| {
+            let (method, path) = (req.method.clone(), req.path.clone());
+            let start = Instant::now();
+            let response = next.call(req);
+            let elapsed = start.elapsed();
+
+            let mut line = String::new();
+            let mut chars = format.chars();
+            while let Some(c) = chars.next() {
+                if c != '%' {
+                    line.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('m') => line.push_str(&method),
+                    Some('U') => line.push_str(&path),
+                    Some('s') => line.push_str(&response.status_code.to_string()),
+                    Some('T') => line.push_str(&format!("{:?}", elapsed)),
+                    Some('%') => line.push('%'),
+                    Some(other) => {
+                        line.push('%');
+                        line.push(other);
+                    }
+                    None => line.push('%'),
+                }
+            }
+            println!("{}", line);
+            response
+        }
+    }
+
+    // CORS for App::wrap. It only sees the request, so it leaves the
+    // response headers to the handlers; use cors_with to add them.
     pub fn cors() -> impl Fn(&mut HttpRequest) -> Option<HttpResponse> {
         move |_req: &mut HttpRequest| {
             // CORS handling would go here
             None
         }
     }
+
+    // Lets `origin`, such as "*" for any, read responses, unless the
+    // handler says otherwise. For App::wrap_fn.
+    pub fn cors_with(origin: &str) -> impl Fn(HttpRequest, &Next) -> HttpResponse {
+        let origin = origin.to_string();
+        move |req: HttpRequest, next: &Next| {
+            let mut response = next.call(req);
+            response
+                .headers
+                .entry("Access-Control-Allow-Origin".to_string())
+                .or_insert_with(|| origin.clone());
+            response
+        }
+    }
+
+    // Adds headers to every response that doesn't set them itself
+    #[derive(Default)]
+    pub struct DefaultHeaders {
+        headers: Vec<(String, String)>,
+    }
+
+    impl DefaultHeaders {
+        pub fn new() -> Self {
+            DefaultHeaders::default()
+        }
+
+        pub fn add(mut self, name: &str, value: &str) -> Self {
+            self.headers.push((name.to_string(), value.to_string()));
+            self
+        }
+    }
+
+    impl Middleware for DefaultHeaders {
+        fn call(&self, req: HttpRequest, next: &Next) -> HttpResponse {
+            let mut response = next.call(req);
+            for (name, value) in &self.headers {
+                response.headers.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+            response
+        }
+    }
 }
 
 // Macro-like helpers for routing
@@ -313,46 +1250,88 @@ pub fn scope(prefix: &str) -> Scope {
     }
 }
 
+// A group of routes sharing a path prefix, mounted with App::service
 pub struct Scope {
     prefix: String,
-    routes: Vec<(String, String, Handler)>,
+    routes: Vec<Route>,
 }
 
 impl Scope {
-    pub fn route(mut self, path: &str, method: &str, handler: Handler) -> Self {
-        let full_path = format!("{}{}", self.prefix, path);
-        self.routes.push((full_path, method.to_string(), handler));
-        self
+    pub fn route(self, path: &str, method: &str, handler: Handler) -> Self {
+        self.add(path, method, RouteHandler::Sync(Box::new(handler)))
+    }
+
+    pub fn route_to<Args, H>(self, path: &str, method: &str, handler: H) -> Self
+    where
+        H: HandlerFn<Args>,
+    {
+        self.add(path, method, RouteHandler::extract(handler))
+    }
+
+    pub fn route_async<F, Fut>(self, path: &str, method: &str, handler: F) -> Self
+    where
+        F: Fn(HttpRequest) -> Fut + 'static,
+        Fut: Future<Output = HttpResponse> + 'static,
+    {
+        self.add(path, method, RouteHandler::future(handler))
     }
 
     pub fn service(mut self, nested_scope: Scope) -> Self {
-        for (path, method, handler) in nested_scope.routes {
-            let full_path = format!("{}{}", self.prefix, path);
-            self.routes.push((full_path, method, handler));
+        for mut route in nested_scope.routes {
+            route.path = format!("{}{}", self.prefix, route.path);
+            self.routes.push(route);
         }
         self
     }
+
+    fn add(mut self, path: &str, method: &str, handler: RouteHandler) -> Self {
+        let full_path = format!("{}{}", self.prefix, path);
+        self.routes.push(Route::new(&full_path, method, handler));
+        self
+    }
 }
 
 // HttpServer for running the application
-pub struct HttpServer;
+pub struct HttpServer {
+    factory: Box<dyn Fn() -> App>,
+    listener: Option<TcpListener>,
+    timeout: Duration,
+}
 
 impl HttpServer {
-    pub fn new<F>(_factory: F) -> Self
+    pub fn new<F>(factory: F) -> Self
     where
         F: Fn() -> App + 'static,
     {
-        HttpServer
+        HttpServer {
+            factory: Box::new(factory),
+            listener: None,
+            timeout: IO_TIMEOUT,
+        }
+    }
+
+    // How long to wait on a client before answering 408 Request Timeout,
+    // five seconds by default
+    pub fn client_request_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
     }
 
-    pub fn bind(self, addr: &str) -> Result<Self, String> {
-        println!("Binding to {}", addr);
+    pub fn bind(mut self, addr: &str) -> Result<Self, String> {
+        self.listener = Some(bind(addr)?);
         Ok(self)
     }
 
+    // Serve on a listener bound elsewhere, such as to port 0 in tests
+    pub fn listen(mut self, listener: TcpListener) -> Result<Self, String> {
+        self.listener = Some(listener);
+        Ok(self)
+    }
+
+    // Build the app with the factory and serve it until the process exits
     pub fn run(self) -> Result<(), String> {
-        println!("Server started (simulated)");
-        Ok(())
+        let listener = self.listener.ok_or_else(|| "No address bound".to_string())?;
+        (self.factory)().serve(listener, self.timeout)
     }
 }
 
@@ -389,6 +1368,73 @@ mod tests {
         assert_eq!(String::from_utf8_lossy(&resp.body), "User 123");
     }
 
+    #[test]
+    fn test_query_string_parsing() {
+        let req = HttpRequest::new("GET", "/search?q=rust+web&tag=a&tag=b%2Cc&empty&bad=%zz");
+
+        assert_eq!(req.path, "/search");
+        assert_eq!(req.query_string(), "q=rust+web&tag=a&tag=b%2Cc&empty&bad=%zz");
+        assert_eq!(req.query_params.get("q").unwrap(), "rust web");
+        assert_eq!(req.query_params.get("tag").unwrap(), "b,c");
+        assert_eq!(req.query_params.get("empty").unwrap(), "");
+        assert_eq!(req.query_params.get("bad").unwrap(), "%zz");
+        assert_eq!(req.query_values("tag"), vec!["a", "b,c"]);
+    }
+
+    #[test]
+    fn test_path_extractor_shapes() {
+        #[derive(serde::Deserialize)]
+        struct Info {
+            id: u64,
+            active: bool,
+        }
+
+        let mut req = HttpRequest::new("GET", "/");
+        req.path_params.insert("name".to_string(), "alice".to_string());
+        assert_eq!(*<Path<String> as FromRequest>::from_request(&req).ok().unwrap(), "alice");
+        assert_eq!(*Path::<String>::from_request(&req, "name").unwrap(), "alice");
+
+        req.path_params.insert("id".to_string(), "42".to_string());
+        req.path_params.insert("active".to_string(), "true".to_string());
+        let info = Path::<Info>::from_request(&req).ok().unwrap();
+        assert_eq!((info.id, info.active), (42, true));
+        let all = Path::<HashMap<String, String>>::from_request(&req).ok().unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(Path::<u32>::from_request(&req).is_err());
+    }
+
+    #[test]
+    fn test_builtin_middleware() {
+        let app = App::new()
+            .wrap(middleware::logger())
+            .wrap(middleware::cors())
+            .wrap_fn(middleware::logger_with("%m %U -> %s in %T (100%%)"))
+            .wrap_fn(middleware::cors_with("https://example.com"))
+            .route("/", "GET", |_req| HttpResponse::Ok().body("home"));
+
+        let resp = app.handle_request(HttpRequest::new("GET", "/"));
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.headers["Access-Control-Allow-Origin"], "https://example.com");
+    }
+
+    #[test]
+    fn test_inherent_from_request() {
+        let mut req = HttpRequest::new("GET", "/?page=2");
+        req.path_params.insert("name".to_string(), "alice".to_string());
+        assert_eq!(Path::<String>::from_request(&req, "name").unwrap().into_inner(), "alice");
+        assert_eq!(
+            Path::<String>::from_request(&req, "id").err().unwrap(),
+            "Path parameter 'id' not found"
+        );
+        assert_eq!(Query::<HashMap<String, String>>::from_request(&req).get("page").unwrap(), "2");
+
+        req.body = br#"{"id": 7}"#.to_vec();
+        assert_eq!(Json::<HashMap<String, u32>>::from_request(&req).unwrap()["id"], 7);
+        req.body = b"{".to_vec();
+        let err = Json::<HashMap<String, u32>>::from_request(&req).err().unwrap();
+        assert!(err.starts_with("Json deserialize error:"), "{}", err);
+    }
+
     #[test]
     fn test_not_found() {
         let app = App::new()
@@ -1,5 +1,6 @@
 // Developed by PowerShield, as an alternative to Actix-web
 
+#[allow(dead_code)]
 mod actix_web_emulator;
 
 use actix_web_emulator::*;
@@ -287,5 +288,265 @@ fn main() {
         println!("❌ Request headers failed");
     }
 
+    // Test 16: HTTP server
+    println!("\nTest 16: HTTP Server");
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        HttpServer::new(|| {
+            App::new()
+                .route("/hello", "GET", |_req| HttpResponse::Ok().body("Hello over TCP"))
+                .route("/echo", "POST", |req| {
+                    let agent = req.header("User-Agent").cloned().unwrap_or_default();
+                    HttpResponse::Created().header("X-Agent", &agent).body(req.body)
+                })
+        })
+        .client_request_timeout(std::time::Duration::from_millis(200))
+        .listen(listener)
+        .and_then(|server| server.run())
+    });
+
+    let send = |raw: &str| {
+        use std::io::{Read, Write};
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let get = send("GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let post = send("POST /echo HTTP/1.1\r\nUser-Agent: test\r\nContent-Length: 5\r\n\r\nhello");
+    let chunked = send("POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n");
+    let missing = send("GET /missing HTTP/1.1\r\n\r\n");
+    let malformed = send("NONSENSE\r\n\r\n");
+    let oversized = send("POST /echo HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n");
+    let huge_chunk = send("POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n");
+    // A client that connects and sends nothing times out instead of
+    // holding up the next one
+    let idle = std::net::TcpStream::connect(addr).unwrap();
+    let after_idle = send("GET /hello HTTP/1.1\r\n\r\n");
+    let idle_response = {
+        use std::io::Read;
+        let mut response = String::new();
+        (&idle).read_to_string(&mut response).unwrap();
+        response
+    };
+
+    if get.starts_with("HTTP/1.1 200 OK\r\n") && get.contains("Content-Length: 14\r\n") && get.ends_with("\r\n\r\nHello over TCP")
+        && post.starts_with("HTTP/1.1 201 Created\r\n") && post.contains("X-Agent: test\r\n") && post.ends_with("\r\n\r\nhello")
+        && chunked.ends_with("\r\n\r\nabcde")
+        && missing.starts_with("HTTP/1.1 404 Not Found\r\n")
+        && malformed.starts_with("HTTP/1.1 400 Bad Request\r\n")
+        && oversized.starts_with("HTTP/1.1 413 Payload Too Large\r\n")
+        && huge_chunk.starts_with("HTTP/1.1 413 Payload Too Large\r\n")
+        && idle_response.starts_with("HTTP/1.1 408 Request Timeout\r\n")
+        && after_idle.ends_with("\r\n\r\nHello over TCP") {
+        println!("✓ HTTP server works");
+    } else {
+        println!("❌ HTTP server failed");
+        println!("  {:?}", [get, post, chunked, missing, malformed, oversized, huge_chunk, idle_response, after_idle]);
+    }
+
+    // Test 17: Async handlers
+    println!("\nTest 17: Async Handlers");
+    // Waits a few polls, as for a database, before answering
+    struct Lookup {
+        id: String,
+        sleep: tokio_emulator::Sleep,
+    }
+
+    impl Future for Lookup {
+        type Output = HttpResponse;
+
+        fn poll(&mut self) -> Poll<HttpResponse> {
+            match self.sleep.poll() {
+                Poll::Ready(()) => Poll::Ready(HttpResponse::Ok().body(format!("Looked up {}", self.id))),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    let app = App::new()
+        .route_async("/users/{id}", "GET", |req| Lookup {
+            id: req.path_params["id"].clone(),
+            sleep: tokio_emulator::Sleep::new(3),
+        })
+        .route_async("/ping", "GET", |_req| tokio_emulator::async_block(|| HttpResponse::Ok().body("pong")))
+        .route("/sync", "GET", |_req| HttpResponse::Ok().body("sync"));
+
+    let lookup = app.handle_request(HttpRequest::new("GET", "/users/7"));
+    let ping = app.handle_request(HttpRequest::new("GET", "/ping"));
+    let sync = app.handle_request(HttpRequest::new("GET", "/sync"));
+
+    if String::from_utf8_lossy(&lookup.body) == "Looked up 7"
+        && String::from_utf8_lossy(&ping.body) == "pong"
+        && String::from_utf8_lossy(&sync.body) == "sync" {
+        println!("✓ Async handlers work");
+    } else {
+        println!("❌ Async handlers failed");
+    }
+
+    // Test 18: Query strings in the path
+    println!("\nTest 18: Query Strings");
+    let app = App::new()
+        .route("/search", "GET", |req| {
+            let q = req.query_params.get("q").cloned().unwrap_or_default();
+            HttpResponse::Ok().body(format!("{} [{}]", q, req.query_values("tag").join(", ")))
+        });
+
+    let resp = app.handle_request(HttpRequest::new("GET", "/search?q=caf%C3%A9+au+lait&tag=x&tag=y"));
+
+    if resp.status_code == 200 && String::from_utf8_lossy(&resp.body) == "café au lait [x, y]" {
+        println!("✓ Query strings work");
+    } else {
+        println!("❌ Query strings failed");
+    }
+
+    // Test 19: Extractors
+    println!("\nTest 19: Extractors");
+    #[derive(serde::Deserialize)]
+    struct NewPost {
+        title: String,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Paging {
+        page: u32,
+        per_page: Option<u32>,
+    }
+
+    struct Config {
+        site: String,
+    }
+
+    fn create_post(user: Path<(String, u32)>, post: Json<NewPost>, config: Data<Config>) -> HttpResponse {
+        let (name, id) = user.into_inner();
+        HttpResponse::Created().body(format!("{} #{} posted '{}' on {}", name, id, post.title, config.site))
+    }
+
+    let app = App::new()
+        .app_data(Data::new(Config { site: "blog".to_string() }))
+        .route_to("/users/{name}/{id}/posts", "POST", create_post)
+        .route_to("/posts/{id}", "GET", |id: Path<u32>, paging: Query<Paging>| {
+            HttpResponse::Ok().body(format!("post {} page {} of {}", *id, paging.page, paging.per_page.unwrap_or(10)))
+        })
+        .route_to("/whoami", "GET", |auth: Option<Header<header::Authorization>>, ct: Header<header::ContentType>| {
+            let auth = auth.map_or("anonymous".to_string(), |h| h.into_inner().0);
+            HttpResponse::Ok().body(format!("{} ({})", auth, ct.0))
+        })
+        .route_to("/health", "GET", || HttpResponse::Ok().body("ok"))
+        .route_to("/missing-data", "GET", |_data: Data<u32>| HttpResponse::Ok().finish());
+
+    let body = |resp: &HttpResponse| String::from_utf8_lossy(&resp.body).to_string();
+    let mut post = HttpRequest::new("POST", "/users/alice/7/posts");
+    post.body = br#"{"title": "Hello"}"#.to_vec();
+    let created = app.handle_request(post);
+    let mut bad_json = HttpRequest::new("POST", "/users/alice/7/posts");
+    bad_json.body = b"{".to_vec();
+    let bad_json = app.handle_request(bad_json);
+    let listed = app.handle_request(HttpRequest::new("GET", "/posts/3?page=2"));
+    let bad_id = app.handle_request(HttpRequest::new("GET", "/posts/abc?page=2"));
+    let bad_query = app.handle_request(HttpRequest::new("GET", "/posts/3?page=x"));
+    let mut whoami = HttpRequest::new("GET", "/whoami");
+    whoami.headers.insert("content-type".to_string(), "text/plain".to_string());
+    let whoami = app.handle_request(whoami);
+    let no_header = app.handle_request(HttpRequest::new("GET", "/whoami"));
+    let health = app.handle_request(HttpRequest::new("GET", "/health"));
+    let missing_data = app.handle_request(HttpRequest::new("GET", "/missing-data"));
+
+    if created.status_code == 201 && body(&created) == "alice #7 posted 'Hello' on blog"
+        && bad_json.status_code == 400
+        && body(&listed) == "post 3 page 2 of 10"
+        && bad_id.status_code == 404
+        && bad_query.status_code == 400
+        && body(&whoami) == "anonymous (text/plain)"
+        && no_header.status_code == 400 && body(&no_header) == "Missing header Content-Type"
+        && body(&health) == "ok"
+        && missing_data.status_code == 500 {
+        println!("✓ Extractors work");
+    } else {
+        println!("❌ Extractors failed");
+        for resp in [&created, &bad_json, &listed, &bad_id, &bad_query, &whoami, &no_header, &health, &missing_data] {
+            println!("  {} {}", resp.status_code, body(resp));
+        }
+    }
+
+    // Test 20: Middleware with response post-processing
+    println!("\nTest 20: Response Middleware");
+    let app = App::new()
+        .wrap_fn(|req, next| {
+            let mut resp = next.call(req);
+            resp.headers.insert("X-Response-Time".to_string(), "1ms".to_string());
+            resp.headers.insert("X-Order".to_string(), "outer".to_string());
+            resp
+        })
+        .wrap_fn(|req, next| {
+            let mut resp = next.call(req);
+            resp.headers.insert("X-Order".to_string(), "inner".to_string());
+            if resp.status_code == 404 {
+                resp.body = b"Nothing here".to_vec();
+            }
+            resp
+        })
+        .wrap(middleware::logger())
+        .wrap_fn(middleware::logger_with("%m %U %s %T"))
+        .wrap(middleware::cors())
+        .wrap_fn(middleware::cors_with("*"))
+        .wrap_middleware(middleware::DefaultHeaders::new().add("X-Version", "1").add("Cache-Control", "no-cache"))
+        .wrap(|req| {
+            if req.path == "/private" {
+                return Some(HttpResponse::NotFound().finish());
+            }
+            None
+        })
+        .route("/data", "GET", |_req| HttpResponse::Ok().header("Cache-Control", "max-age=60").body("data"));
+
+    let data = app.handle_request(HttpRequest::new("GET", "/data"));
+    let private = app.handle_request(HttpRequest::new("GET", "/private"));
+    let header = |resp: &HttpResponse, name: &str| resp.headers.get(name).cloned().unwrap_or_default();
+
+    if header(&data, "X-Response-Time") == "1ms"
+        && header(&data, "X-Order") == "outer"
+        && header(&data, "Access-Control-Allow-Origin") == "*"
+        && header(&data, "X-Version") == "1"
+        && header(&data, "Cache-Control") == "max-age=60"
+        && private.status_code == 404
+        && String::from_utf8_lossy(&private.body) == "Nothing here"
+        && header(&private, "X-Version") == "1" {
+        println!("✓ Response middleware works");
+    } else {
+        println!("❌ Response middleware failed");
+    }
+
+    // Test 21: Scopes mounted with App::service
+    println!("\nTest 21: Scopes");
+    let users = scope("/users")
+        .route("", "GET", |_req| HttpResponse::Ok().body("all users"))
+        .route_to("/{id}", "GET", |id: Path<u32>| HttpResponse::Ok().body(format!("user {}", id.into_inner())))
+        .route_async("/{id}/posts", "GET", |req| {
+            let id = req.match_info().get("id").cloned().unwrap_or_default();
+            tokio_emulator::async_block(move || HttpResponse::Ok().body(format!("posts of {}", id)))
+        });
+    let app = App::new()
+        .route("/", "GET", |_req| HttpResponse::Ok().body("home"))
+        .service(scope("/api").service(scope("/v1").service(users)));
+
+    let body = |method: &str, path: &str| {
+        let resp = app.handle_request(HttpRequest::new(method, path));
+        (resp.status_code, String::from_utf8_lossy(&resp.body).to_string())
+    };
+
+    if body("GET", "/") == (200, "home".to_string())
+        && body("GET", "/api/v1/users") == (200, "all users".to_string())
+        && body("GET", "/api/v1/users/7") == (200, "user 7".to_string())
+        && body("GET", "/api/v1/users/7/posts") == (200, "posts of 7".to_string())
+        && body("GET", "/api/v1/users/abc").0 == 404
+        && body("GET", "/users/7").0 == 404
+        && body("POST", "/api/v1/users").0 == 404 {
+        println!("✓ Scopes work");
+    } else {
+        println!("❌ Scopes failed");
+    }
+
     println!("\n=== All Tests Completed ===");
 }
@@ -264,6 +264,23 @@ fn main() {
         }
     }));
     
+    // Test 21: Boxed futures
+    results.push(test_runner("Boxed futures", || {
+        let mut rt = Runtime::new();
+        let futures: Vec<Box<dyn Future<Output = i32>>> = vec![
+            Box::new(async_block(|| 1)),
+            Box::new(async_block(|| 2)),
+        ];
+        let total: i32 = futures.into_iter().map(|f| rt.block_on(f)).sum();
+        let sleep: Box<dyn Future<Output = ()>> = Box::new(Sleep::new(2));
+        rt.block_on(sleep);
+        if total == 3 {
+            Ok(())
+        } else {
+            Err(format!("Expected 3, got {}", total))
+        }
+    }));
+    
     // Print results
     println!("\n=== Test Results ===");
     let mut passed = 0;
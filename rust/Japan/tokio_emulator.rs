@@ -10,6 +10,16 @@ pub trait Future {
     fn poll(&mut self) -> Poll<Self::Output>;
 }
 
+// A boxed future polls the one inside, so futures of different types can
+// be stored together as Box<dyn Future<Output = T>>
+impl<F: Future + ?Sized> Future for Box<F> {
+    type Output = F::Output;
+    
+    fn poll(&mut self) -> Poll<Self::Output> {
+        (**self).poll()
+    }
+}
+
 // Poll represents the state of a future
 #[derive(Debug, PartialEq)]
 pub enum Poll<T> {
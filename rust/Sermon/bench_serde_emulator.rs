@@ -0,0 +1,112 @@
+// Developed by PowerShield, as an alternative to Serde
+
+// Benchmarks for Serde Emulator
+// Counts heap allocations made while serializing records to JSON, each into
+// a new String with `to_json` and into one reused buffer with
+// `JsonSerializer::with_buffer`
+
+use serde_emulator::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Counts allocations and the bytes they ask for
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        BYTES.fetch_add(layout.size(), Ordering::SeqCst);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        BYTES.fetch_add(new_size, Ordering::SeqCst);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run `f`, returning its result, the allocations and bytes allocated, and the elapsed time
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, usize, f64) {
+    let (allocations, bytes) = (ALLOCATIONS.load(Ordering::SeqCst), BYTES.load(Ordering::SeqCst));
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst) - allocations;
+    let bytes = BYTES.load(Ordering::SeqCst) - bytes;
+    (result, allocations, bytes, elapsed)
+}
+
+struct Event {
+    id: u64,
+    kind: String,
+    score: f64,
+    tags: Vec<String>,
+    origin: Option<(i32, i32)>,
+}
+
+derive_serialize!(Event { id, kind, score, tags, origin });
+
+fn events(count: usize) -> Vec<Event> {
+    (0..count)
+        .map(|i| Event {
+            id: i as u64,
+            kind: format!("event-{}", i % 7),
+            score: i as f64 / 3.0,
+            tags: vec!["alpha".to_string(), "beta".to_string(), "gamma".to_string()],
+            origin: if i % 2 == 0 { Some((i as i32, -(i as i32))) } else { None },
+        })
+        .collect()
+}
+
+fn main() {
+    println!("Serde Emulator benchmarks");
+    println!("=========================\n");
+
+    for count in [10_000, 50_000] {
+        let events = events(count);
+
+        let fresh = measure(|| {
+            let mut total = 0;
+            for event in &events {
+                total += to_json(event).unwrap().len();
+            }
+            total
+        });
+        let reused = measure(|| {
+            let mut buffer = String::new();
+            let mut total = 0;
+            for event in &events {
+                buffer.clear();
+                event.serialize(JsonSerializer::with_buffer(&mut buffer)).unwrap();
+                total += buffer.len();
+            }
+            total
+        });
+        assert_eq!(fresh.0, reused.0);
+
+        println!("{} records:", count);
+        for (name, (_, allocations, bytes, ms)) in [("to_json", fresh), ("with_buffer", reused)] {
+            println!(
+                "  {:<12} {:>9} allocations ({:>6.1} per record) {:>12} bytes  {:>8.2} ms",
+                name,
+                allocations,
+                allocations as f64 / count as f64,
+                bytes,
+                ms
+            );
+        }
+        println!();
+    }
+}
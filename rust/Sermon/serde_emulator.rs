@@ -1,25 +1,105 @@
 // Developed by PowerShield, as an alternative to Serde
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList, VecDeque};
 use std::fmt;
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::num::{IntErrorKind, ParseIntError};
+use std::ops::{Index, IndexMut};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // Serializer trait - converts Rust data structures to formats
 pub trait Serializer {
     type Ok;
-    type Error;
+    type Error: CustomError;
     
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error>;
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error>;
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error>;
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error>;
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error>;
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error>;
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error>;
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error>;
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error>;
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error>;
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error>;
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error>;
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error>;
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error>;
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error>;
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error>;
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error>;
     fn serialize_none(self) -> Result<Self::Ok, Self::Error>;
     fn serialize_some<T: Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error>;
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error>;
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error>;
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error>;
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, Self::Error>;
+    
+    // A number given as its JSON text, such as an arbitrary-precision
+    // Number. Formats that can't keep the text write the native type that
+    // holds it, falling back to the nearest f64.
+    fn serialize_number(self, text: &str) -> Result<Self::Ok, Self::Error>
+    where
+        Self: Sized,
+    {
+        if let Ok(v) = text.parse() {
+            self.serialize_i64(v)
+        } else if let Ok(v) = text.parse() {
+            self.serialize_u64(v)
+        } else if let Ok(v) = text.parse() {
+            self.serialize_i128(v)
+        } else if let Ok(v) = text.parse() {
+            self.serialize_u128(v)
+        } else {
+            match text.parse() {
+                Ok(v) => self.serialize_f64(v),
+                Err(_) => Err(Self::Error::custom(format!("invalid number: {}", text))),
+            }
+        }
+    }
+    
+    // A wrapper such as `struct UserId(u64)`, written as the value inside.
+    // The name is there for formats that record it.
+    fn serialize_newtype_struct<T: Serialize>(self, _name: &'static str, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        Self: Sized,
+    {
+        value.serialize(self)
+    }
+    
+    // A fragment of JSON text from RawJson. JSON serializers write it
+    // untouched; other formats parse it and write the value it holds.
+    fn serialize_raw_json(self, json: &str) -> Result<Self::Ok, Self::Error>
+    where
+        Self: Sized,
+    {
+        let value = JsonDeserializer::new(json)
+            .arbitrary_precision(true)
+            .parse::<JsonValue>()
+            .map_err(Self::Error::custom)?;
+        value.serialize(self)
+    }
+    
+    // A struct with no fields, such as `struct Marker;`, written as a unit
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error>
+    where
+        Self: Sized,
+    {
+        self.serialize_unit()
+    }
     
     type SerializeSeq: SerializeSeq<Ok = Self::Ok, Error = Self::Error>;
+    type SerializeTuple: SerializeTuple<Ok = Self::Ok, Error = Self::Error>;
     type SerializeMap: SerializeMap<Ok = Self::Ok, Error = Self::Error>;
+    type SerializeStruct: SerializeStruct<Ok = Self::Ok, Error = Self::Error>;
 }
 
 // SerializeSeq trait for serializing sequences
@@ -31,6 +111,16 @@ pub trait SerializeSeq {
     fn end(self) -> Result<Self::Ok, Self::Error>;
 }
 
+// SerializeTuple trait for serializing tuples and fixed-size arrays, whose
+// length is known up front
+pub trait SerializeTuple {
+    type Ok;
+    type Error;
+    
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Self::Error>;
+    fn end(self) -> Result<Self::Ok, Self::Error>;
+}
+
 // SerializeMap trait for serializing maps
 pub trait SerializeMap {
     type Ok;
@@ -46,6 +136,16 @@ pub trait SerializeMap {
     fn end(self) -> Result<Self::Ok, Self::Error>;
 }
 
+// SerializeStruct trait for serializing structs, whose field names are known
+// at compile time
+pub trait SerializeStruct {
+    type Ok;
+    type Error;
+    
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>;
+    fn end(self) -> Result<Self::Ok, Self::Error>;
+}
+
 // Serialize trait - types implement this to be serializable
 pub trait Serialize {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
@@ -53,18 +153,58 @@ pub trait Serialize {
 
 // Deserializer trait - converts formats to Rust data structures
 pub trait Deserializer<'de> {
-    type Error;
+    type Error: CustomError;
     
     fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>;
     fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>;
+    
+    // Newtype structs are read as the value inside, which the visitor reads
+    // from this same deserializer
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        Self: Sized,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+    
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        Self: Sized,
+    {
+        self.deserialize_unit(visitor)
+    }
+    
+    // The next value as JSON text, for RawJson. JSON deserializers hand the
+    // visitor the input as written; other formats read the value and pass
+    // it on written out as JSON.
+    fn deserialize_raw_json<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        Self: Sized,
+    {
+        let value = JsonValue::deserialize(self)?;
+        visitor.visit_string(to_json(&value).map_err(Self::Error::custom)?)
+    }
 }
 
 // Visitor trait for deserializing
@@ -73,64 +213,159 @@ pub trait Visitor<'de>: Sized {
     
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result;
     
-    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
-        Err(self.invalid_type("boolean"))
+    fn visit_bool<E: CustomError>(self, v: bool) -> Result<Self::Value, E> {
+        Err(self.invalid_type(&format!("boolean `{}`", v)))
+    }
+    
+    // Narrower numbers are passed on to the widest method of their kind, so
+    // visitors only need to implement those
+    
+    fn visit_i8<E: CustomError>(self, v: i8) -> Result<Self::Value, E> {
+        self.visit_i64(i64::from(v))
+    }
+    
+    fn visit_i16<E: CustomError>(self, v: i16) -> Result<Self::Value, E> {
+        self.visit_i64(i64::from(v))
+    }
+    
+    fn visit_i32<E: CustomError>(self, v: i32) -> Result<Self::Value, E> {
+        self.visit_i64(i64::from(v))
+    }
+    
+    fn visit_i64<E: CustomError>(self, v: i64) -> Result<Self::Value, E> {
+        Err(self.invalid_type(&format!("integer `{}`", v)))
+    }
+    
+    fn visit_i128<E: CustomError>(self, v: i128) -> Result<Self::Value, E> {
+        Err(self.invalid_type(&format!("integer `{}`", v)))
+    }
+    
+    fn visit_u8<E: CustomError>(self, v: u8) -> Result<Self::Value, E> {
+        self.visit_u64(u64::from(v))
+    }
+    
+    fn visit_u16<E: CustomError>(self, v: u16) -> Result<Self::Value, E> {
+        self.visit_u64(u64::from(v))
     }
     
-    fn visit_i32<E>(self, v: i32) -> Result<Self::Value, E> {
-        Err(self.invalid_type("i32"))
+    fn visit_u32<E: CustomError>(self, v: u32) -> Result<Self::Value, E> {
+        self.visit_u64(u64::from(v))
     }
     
-    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
-        Err(self.invalid_type("i64"))
+    fn visit_u64<E: CustomError>(self, v: u64) -> Result<Self::Value, E> {
+        Err(self.invalid_type(&format!("integer `{}`", v)))
     }
     
-    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
-        Err(self.invalid_type("f64"))
+    fn visit_u128<E: CustomError>(self, v: u128) -> Result<Self::Value, E> {
+        Err(self.invalid_type(&format!("integer `{}`", v)))
     }
     
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
-        Err(self.invalid_type("string"))
+    fn visit_f32<E: CustomError>(self, v: f32) -> Result<Self::Value, E> {
+        self.visit_f64(f64::from(v))
     }
     
-    fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+    fn visit_f64<E: CustomError>(self, v: f64) -> Result<Self::Value, E> {
+        Err(self.invalid_type(&format!("floating point `{}`", v)))
+    }
+    
+    // A number as written in the input, from a JSON deserializer in
+    // arbitrary-precision mode. By default it is parsed as usual.
+    fn visit_number<E: CustomError>(self, text: &str) -> Result<Self::Value, E> {
+        visit_number_text(self, text)
+    }
+    
+    fn visit_char<E: CustomError>(self, v: char) -> Result<Self::Value, E> {
+        self.visit_str(v.encode_utf8(&mut [0; 4]))
+    }
+    
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<Self::Value, E> {
+        Err(self.invalid_type(&format!("string {:?}", v)))
+    }
+    
+    fn visit_string<E: CustomError>(self, v: String) -> Result<Self::Value, E> {
         self.visit_str(&v)
     }
     
-    fn visit_none<E>(self) -> Result<Self::Value, E> {
-        Err(self.invalid_type("none"))
+    // A string that lives as long as the input, so the value can borrow it.
+    // Deserializers call this when the text needs no unescaping.
+    fn visit_borrowed_str<E: CustomError>(self, v: &'de str) -> Result<Self::Value, E> {
+        self.visit_str(v)
+    }
+    
+    fn visit_bytes<E: CustomError>(self, _v: &[u8]) -> Result<Self::Value, E> {
+        Err(self.invalid_type("byte array"))
+    }
+    
+    fn visit_byte_buf<E: CustomError>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        self.visit_bytes(&v)
+    }
+    
+    fn visit_borrowed_bytes<E: CustomError>(self, v: &'de [u8]) -> Result<Self::Value, E> {
+        self.visit_bytes(v)
+    }
+    
+    fn visit_unit<E: CustomError>(self) -> Result<Self::Value, E> {
+        Err(self.invalid_type("unit value"))
     }
     
-    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
-        Err(self.invalid_type("some"))
+    fn visit_none<E: CustomError>(self) -> Result<Self::Value, E> {
+        Err(self.invalid_type("null"))
     }
     
-    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+    fn visit_some<D: Deserializer<'de>>(self, _deserializer: D) -> Result<Self::Value, D::Error> {
+        Err(self.invalid_type("optional value"))
+    }
+    
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, _deserializer: D) -> Result<Self::Value, D::Error> {
+        Err(self.invalid_type("newtype struct"))
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, _seq: A) -> Result<Self::Value, A::Error> {
         Err(self.invalid_type("sequence"))
     }
     
-    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+    fn visit_map<A: MapAccess<'de>>(self, _map: A) -> Result<Self::Value, A::Error> {
         Err(self.invalid_type("map"))
     }
     
-    fn invalid_type<E>(&self, type_name: &str) -> E {
-        panic!("invalid type: expected {}", type_name)
+    // Errors for visitors to return, naming what they expected. `unexpected`
+    // describes the input, such as "integer `5`" or "map".
+    
+    fn invalid_type<E: CustomError>(&self, unexpected: &str) -> E {
+        E::custom(format!("invalid type: {}, expected {}", unexpected, expected(self)))
+    }
+    
+    fn invalid_value<E: CustomError>(&self, unexpected: &str) -> E {
+        E::custom(format!("invalid value: {}, expected {}", unexpected, expected(self)))
+    }
+    
+    fn invalid_length<E: CustomError>(&self, len: usize) -> E {
+        E::custom(format!("invalid length {}, expected {}", len, expected(self)))
     }
 }
 
 // SeqAccess for deserializing sequences
 pub trait SeqAccess<'de> {
-    type Error;
+    type Error: CustomError;
     
-    fn next_element<T: Deserialize<'de>>(&mut self) -> Result<Option<T>, Self::Error>;
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>;
+    fn next_element<T: Deserialize<'de>>(&mut self) -> Result<Option<T>, Self::Error> {
+        self.next_element_seed(PhantomData)
+    }
 }
 
 // MapAccess for deserializing maps
 pub trait MapAccess<'de> {
-    type Error;
+    type Error: CustomError;
     
-    fn next_key<K: Deserialize<'de>>(&mut self) -> Result<Option<K>, Self::Error>;
-    fn next_value<V: Deserialize<'de>>(&mut self) -> Result<V, Self::Error>;
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>;
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error>;
+    fn next_key<K: Deserialize<'de>>(&mut self) -> Result<Option<K>, Self::Error> {
+        self.next_key_seed(PhantomData)
+    }
+    fn next_value<V: Deserialize<'de>>(&mut self) -> Result<V, Self::Error> {
+        self.next_value_seed(PhantomData)
+    }
     fn next_entry<K: Deserialize<'de>, V: Deserialize<'de>>(&mut self) -> Result<Option<(K, V)>, Self::Error> {
         match self.next_key()? {
             Some(key) => {
@@ -147,243 +382,8663 @@ pub trait Deserialize<'de>: Sized {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
 }
 
-// Error type for serialization/deserialization
+// A type that deserializes from input of any lifetime, so it borrows
+// nothing and can outlive the input. Shorthand for the
+// `for<'de> Deserialize<'de>` bound, with Serde's name.
+pub trait DeserializeOwned: for<'de> Deserialize<'de> {}
+
+impl<T: for<'de> Deserialize<'de>> DeserializeOwned for T {}
+
+// Deserialize with state that a Deserialize impl has no way to get, such as
+// the serializer transcode writes to. SeqAccess and MapAccess read values
+// with a seed; `PhantomData<T>` is the seed for a plain T.
+pub trait DeserializeSeed<'de>: Sized {
+    type Value;
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error>;
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for PhantomData<T> {
+    type Value = T;
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        T::deserialize(deserializer)
+    }
+}
+
+// Errors that deserializers and visitors can build from a message. The
+// Error type below is the usual one; String works for simple formats.
+pub trait CustomError: Sized + fmt::Display {
+    fn custom<T: fmt::Display>(msg: T) -> Self;
+}
+
+impl CustomError for String {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        msg.to_string()
+    }
+}
+
+// Error type for serialization/deserialization. Besides the message it can
+// carry where the error happened: a path to the value such as
+// `users[3].age`, and for JSON input the byte offset, line and column.
 #[derive(Debug)]
 pub struct Error {
     message: String,
+    path: String,
+    offset: Option<usize>,
+    line: usize,
+    column: usize,
+}
+
+impl CustomError for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error {
+            message: msg.to_string(),
+            path: String::new(),
+            offset: None,
+            line: 0,
+            column: 0,
+        }
+    }
 }
 
 impl Error {
-    pub fn custom(msg: String) -> Self {
-        Error { message: msg }
+    // The message without the location
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    
+    // Path to the value that failed, empty for the top-level value
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    
+    // Byte offset into the input, if known
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+    
+    // 1-based line and column (in characters) of the offset
+    pub fn line_column(&self) -> Option<(usize, usize)> {
+        self.offset.map(|_| (self.line, self.column))
+    }
+    
+    // Record that the error happened inside array element `index`
+    fn at_index(mut self, index: usize) -> Self {
+        let inner = if self.path.is_empty() || self.path.starts_with('[') {
+            self.path
+        } else {
+            format!(".{}", self.path)
+        };
+        self.path = format!("[{}]{}", index, inner);
+        self
+    }
+    
+    // Record that the error happened inside the value of field `key`
+    fn at_key(mut self, key: &str) -> Self {
+        self.path = if self.path.is_empty() || self.path.starts_with('[') {
+            format!("{}{}", key, self.path)
+        } else {
+            format!("{}.{}", key, self.path)
+        };
+        self
+    }
+    
+    // Move a position within one line of a stream to the whole stream:
+    // the line starts `start` bytes in and is line `line`
+    fn in_stream(mut self, line: usize, start: usize) -> Self {
+        if let Some(offset) = self.offset {
+            self.offset = Some(start + offset);
+            self.line = line;
+        }
+        self
+    }
+    
+    // Record the position in `input`, unless an inner error already has
+    fn at_offset(mut self, input: &str, offset: usize) -> Self {
+        if self.offset.is_none() {
+            let mut offset = offset.min(input.len());
+            while !input.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            let before = &input[..offset];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            self.offset = Some(offset);
+            self.line = before.matches('\n').count() + 1;
+            self.column = before[line_start..].chars().count() + 1;
+        }
+        self
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "{}", self.message)?;
+        match (self.path.is_empty(), self.offset) {
+            (false, Some(_)) => write!(f, " at {}, line {} column {}", self.path, self.line, self.column),
+            (false, None) => write!(f, " at {}", self.path),
+            (true, Some(_)) => write!(f, " at line {} column {}", self.line, self.column),
+            (true, None) => Ok(()),
+        }
     }
 }
 
 impl std::error::Error for Error {}
 
-// JSON Serializer implementation
-pub struct JsonSerializer {
-    output: String,
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::custom(format!("I/O error: {}", e))
+    }
+}
+
+// How many arrays and objects (or sequences and maps) may be nested inside
+// each other by default. Real documents stay well inside it, and hostile
+// ones get an error instead of overflowing the stack.
+const DEFAULT_MAX_DEPTH: usize = 128;
+
+fn depth_exceeded(max_depth: usize) -> Error {
+    Error::custom(format!("recursion limit exceeded: nested more than {} levels deep", max_depth))
+}
+
+// What deserializers do with a key that appears more than once in the same
+// object or map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeys {
+    // Every entry reaches the visitor, so maps and derived structs keep the
+    // last value
+    #[default]
+    LastWins,
+    // Entries whose key was already seen are skipped
+    FirstWins,
+    // A repeated key is an error
+    Error,
+}
+
+impl DuplicateKeys {
+    // Whether to skip the entry for `key`, given the keys seen so far in
+    // its object
+    fn skip(self, seen: &mut HashSet<String>, key: &str) -> Result<bool, Error> {
+        if self == DuplicateKeys::LastWins || seen.insert(key.to_string()) {
+            return Ok(false);
+        }
+        match self {
+            DuplicateKeys::FirstWins => Ok(true),
+            _ => Err(Error::custom(format!("duplicate key `{}`", key))),
+        }
+    }
+}
+
+// JSON Serializer implementation. It builds and returns a String, or
+// with `with_buffer` appends to one the caller keeps.
+pub struct JsonSerializer<B = String> {
+    output: B,
+    options: JsonOptions,
+    // Arrays and objects around the value being serialized
+    depth: usize,
+}
+
+// Output options, passed on to the serializers for nested values
+#[derive(Debug, Clone, Copy)]
+struct JsonOptions {
+    canonical: bool,
+    non_finite: NonFinite,
+    max_depth: usize,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        JsonOptions {
+            canonical: false,
+            non_finite: NonFinite::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+// How NaN and the infinities, which JSON has no numbers for, are written
+// and read back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonFinite {
+    // Serializing one is an error, and reading accepts only JSON numbers
+    #[default]
+    Error,
+    // Written as `null`; `null` reads as NaN into a float
+    Null,
+    // Written as the strings "NaN", "Infinity" and "-Infinity", which read
+    // back into a float
+    String,
+    // Written as the bare words NaN, Infinity and -Infinity, as JavaScript
+    // and Python's json module do. Not valid JSON, but read back anywhere.
+    Literal,
+}
+
+impl NonFinite {
+    // The text to write for a float that isn't finite
+    fn text(self, v: f64) -> Result<&'static str, Error> {
+        let word = if v.is_nan() {
+            "NaN"
+        } else if v > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        };
+        match self {
+            NonFinite::Error => Err(Error::custom(format!("{} is not a valid JSON number", v))),
+            NonFinite::Null => Ok("null"),
+            NonFinite::String => Ok(match word {
+                "NaN" => "\"NaN\"",
+                "Infinity" => "\"Infinity\"",
+                _ => "\"-Infinity\"",
+            }),
+            NonFinite::Literal => Ok(word),
+        }
+    }
+}
+
+// Where a JsonSerializer writes: its own String, which serializing returns,
+// or a caller's buffer, which it appends to and returns nothing
+pub trait JsonOutput {
+    type Ok;
+    
+    fn buffer(&mut self) -> &mut String;
+    fn finish(self) -> Self::Ok;
+}
+
+impl JsonOutput for String {
+    type Ok = String;
+    
+    fn buffer(&mut self) -> &mut String {
+        self
+    }
+    
+    fn finish(self) -> String {
+        self
+    }
+}
+
+impl JsonOutput for &mut String {
+    type Ok = ();
+    
+    fn buffer(&mut self) -> &mut String {
+        self
+    }
+    
+    fn finish(self) {}
 }
 
 impl JsonSerializer {
     pub fn new() -> Self {
+        Self::nested(String::new(), JsonOptions::default(), 0)
+    }
+}
+
+impl<'b> JsonSerializer<&'b mut String> {
+    // Append to an existing String instead of allocating a new one, so a
+    // hot loop can clear and reuse one buffer. If serializing fails, the
+    // buffer is left with whatever was written before the error.
+    pub fn with_buffer(buffer: &'b mut String) -> Self {
+        Self::nested(buffer, JsonOptions::default(), 0)
+    }
+}
+
+impl<B: JsonOutput> JsonSerializer<B> {
+    fn nested(output: B, options: JsonOptions, depth: usize) -> Self {
         JsonSerializer {
-            output: String::new(),
+            output,
+            options,
+            depth,
+        }
+    }
+    
+    // The deepest nesting of arrays and objects to write before failing
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.options.max_depth = max_depth;
+        self
+    }
+    
+    // The depth for the contents of an array or object started here
+    fn enter(&self) -> Result<usize, Error> {
+        if self.depth >= self.options.max_depth {
+            return Err(depth_exceeded(self.options.max_depth));
+        }
+        Ok(self.depth + 1)
+    }
+    
+    // Sort object keys and write each number in a single form, so equal
+    // values always serialize to the same bytes
+    pub fn canonical(mut self, enabled: bool) -> Self {
+        self.options.canonical = enabled;
+        self
+    }
+    
+    pub fn non_finite(mut self, policy: NonFinite) -> Self {
+        self.options.non_finite = policy;
+        self
+    }
+    
+    fn write(mut self, text: &str) -> Result<B::Ok, Error> {
+        self.output.buffer().push_str(text);
+        Ok(self.output.finish())
+    }
+    
+    // Formats straight into the output, without an intermediate String
+    fn write_display(mut self, v: impl fmt::Display) -> Result<B::Ok, Error> {
+        fmt::Write::write_fmt(self.output.buffer(), format_args!("{}", v)).map_err(Error::custom)?;
+        Ok(self.output.finish())
+    }
+    
+    fn write_float(self, v: impl fmt::Display + fmt::LowerExp) -> Result<B::Ok, Error> {
+        if self.options.canonical {
+            if let Some(text) = canonical_number(&format!("{:e}", v)) {
+                return self.write(&text);
+            }
         }
+        self.write_display(v)
     }
 }
 
-impl Serializer for JsonSerializer {
-    type Ok = String;
+impl Default for JsonSerializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: JsonOutput> Serializer for JsonSerializer<B> {
+    type Ok = B::Ok;
     type Error = Error;
-    type SerializeSeq = JsonSeqSerializer;
-    type SerializeMap = JsonMapSerializer;
+    type SerializeSeq = JsonSeqSerializer<B>;
+    type SerializeTuple = JsonSeqSerializer<B>;
+    type SerializeMap = JsonMapSerializer<B>;
+    type SerializeStruct = JsonMapSerializer<B>;
     
-    fn serialize_bool(mut self, v: bool) -> Result<String, Error> {
-        self.output = v.to_string();
-        Ok(self.output)
+    fn serialize_bool(self, v: bool) -> Result<B::Ok, Error> {
+        self.write(if v { "true" } else { "false" })
     }
     
-    fn serialize_i32(mut self, v: i32) -> Result<String, Error> {
-        self.output = v.to_string();
-        Ok(self.output)
+    fn serialize_i8(self, v: i8) -> Result<B::Ok, Error> {
+        self.write_display(v)
     }
     
-    fn serialize_i64(mut self, v: i64) -> Result<String, Error> {
-        self.output = v.to_string();
-        Ok(self.output)
+    fn serialize_i16(self, v: i16) -> Result<B::Ok, Error> {
+        self.write_display(v)
     }
     
-    fn serialize_f64(mut self, v: f64) -> Result<String, Error> {
-        self.output = v.to_string();
-        Ok(self.output)
+    fn serialize_i32(self, v: i32) -> Result<B::Ok, Error> {
+        self.write_display(v)
     }
     
-    fn serialize_str(mut self, v: &str) -> Result<String, Error> {
-        self.output = format!("\"{}\"", v);
-        Ok(self.output)
+    fn serialize_i64(self, v: i64) -> Result<B::Ok, Error> {
+        self.write_display(v)
     }
     
-    fn serialize_none(mut self) -> Result<String, Error> {
-        self.output = "null".to_string();
-        Ok(self.output)
+    fn serialize_i128(self, v: i128) -> Result<B::Ok, Error> {
+        self.write_display(v)
     }
     
-    fn serialize_some<T: Serialize>(self, value: &T) -> Result<String, Error> {
+    fn serialize_u8(self, v: u8) -> Result<B::Ok, Error> {
+        self.write_display(v)
+    }
+    
+    fn serialize_u16(self, v: u16) -> Result<B::Ok, Error> {
+        self.write_display(v)
+    }
+    
+    fn serialize_u32(self, v: u32) -> Result<B::Ok, Error> {
+        self.write_display(v)
+    }
+    
+    fn serialize_u64(self, v: u64) -> Result<B::Ok, Error> {
+        self.write_display(v)
+    }
+    
+    fn serialize_u128(self, v: u128) -> Result<B::Ok, Error> {
+        self.write_display(v)
+    }
+    
+    fn serialize_f32(self, v: f32) -> Result<B::Ok, Error> {
+        if !v.is_finite() {
+            return self.serialize_f64(v.into());
+        }
+        self.write_float(v)
+    }
+    
+    fn serialize_f64(self, v: f64) -> Result<B::Ok, Error> {
+        if !v.is_finite() {
+            let text = self.options.non_finite.text(v)?;
+            return self.write(text);
+        }
+        self.write_float(v)
+    }
+    
+    fn serialize_number(self, text: &str) -> Result<B::Ok, Error> {
+        match canonical_number(text) {
+            Some(canonical) if self.options.canonical => self.write(&canonical),
+            _ => self.write(text),
+        }
+    }
+    
+    fn serialize_raw_json(self, json: &str) -> Result<B::Ok, Error> {
+        self.write(json)
+    }
+    
+    fn serialize_char(self, v: char) -> Result<B::Ok, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+    
+    fn serialize_str(mut self, v: &str) -> Result<B::Ok, Error> {
+        escape_json_into(self.output.buffer(), v);
+        Ok(self.output.finish())
+    }
+    
+    // JSON has no byte strings, so bytes are written as an array of numbers
+    fn serialize_bytes(self, v: &[u8]) -> Result<B::Ok, Error> {
+        let mut seq = self.serialize_seq(Some(v.len()))?;
+        for byte in v {
+            SerializeSeq::serialize_element(&mut seq, byte)?;
+        }
+        SerializeSeq::end(seq)
+    }
+    
+    fn serialize_unit(self) -> Result<B::Ok, Error> {
+        self.write("null")
+    }
+    
+    fn serialize_none(self) -> Result<B::Ok, Error> {
+        self.write("null")
+    }
+    
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<B::Ok, Error> {
         value.serialize(self)
     }
     
-    fn serialize_seq(self, _len: Option<usize>) -> Result<JsonSeqSerializer, Error> {
+    fn serialize_seq(mut self, _len: Option<usize>) -> Result<JsonSeqSerializer<B>, Error> {
+        let depth = self.enter()?;
+        self.output.buffer().push('[');
         Ok(JsonSeqSerializer {
-            output: String::from("["),
+            output: self.output,
             first: true,
+            options: self.options,
+            depth,
         })
     }
     
-    fn serialize_map(self, _len: Option<usize>) -> Result<JsonMapSerializer, Error> {
+    // Tuples and arrays are JSON arrays
+    fn serialize_tuple(self, len: usize) -> Result<JsonSeqSerializer<B>, Error> {
+        self.serialize_seq(Some(len))
+    }
+    
+    fn serialize_map(mut self, _len: Option<usize>) -> Result<JsonMapSerializer<B>, Error> {
+        let depth = self.enter()?;
+        self.output.buffer().push('{');
         Ok(JsonMapSerializer {
-            output: String::from("{"),
+            output: self.output,
             first: true,
+            entries: Vec::new(),
             key: None,
+            options: self.options,
+            depth,
         })
     }
+    
+    // JSON has no named records, so a struct is written as an object
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<JsonMapSerializer<B>, Error> {
+        self.serialize_map(Some(len))
+    }
 }
 
-pub struct JsonSeqSerializer {
-    output: String,
+// Elements are written straight into the same output as the array around
+// them
+pub struct JsonSeqSerializer<B = String> {
+    output: B,
     first: bool,
+    options: JsonOptions,
+    depth: usize,
 }
 
-impl SerializeSeq for JsonSeqSerializer {
-    type Ok = String;
+impl<B: JsonOutput> SerializeSeq for JsonSeqSerializer<B> {
+    type Ok = B::Ok;
     type Error = Error;
     
     fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let output = self.output.buffer();
         if !self.first {
-            self.output.push_str(", ");
+            output.push_str(", ");
         }
         self.first = false;
-        
-        let serialized = to_json(value)?;
-        self.output.push_str(&serialized);
-        Ok(())
+        value.serialize(JsonSerializer::nested(output, self.options, self.depth))
     }
     
-    fn end(mut self) -> Result<String, Error> {
-        self.output.push(']');
-        Ok(self.output)
+    fn end(mut self) -> Result<B::Ok, Error> {
+        self.output.buffer().push(']');
+        Ok(self.output.finish())
+    }
+}
+
+impl<B: JsonOutput> SerializeTuple for JsonSeqSerializer<B> {
+    type Ok = B::Ok;
+    type Error = Error;
+    
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+    
+    fn end(self) -> Result<B::Ok, Error> {
+        SerializeSeq::end(self)
     }
 }
 
-pub struct JsonMapSerializer {
-    output: String,
+// Entries are written straight into the output, except in canonical mode,
+// where they're kept as serialized key and value text until the end and
+// sorted by key. Each is kept with its key unquoted, which is what it's
+// sorted by, as JsonValue's objects are.
+pub struct JsonMapSerializer<B = String> {
+    output: B,
     first: bool,
-    key: Option<String>,
+    entries: Vec<(String, String, String)>,
+    key: Option<(String, String)>,
+    options: JsonOptions,
+    depth: usize,
 }
 
-impl SerializeMap for JsonMapSerializer {
-    type Ok = String;
+impl<B: JsonOutput> JsonMapSerializer<B> {
+    fn separate(&mut self) -> &mut String {
+        let output = self.output.buffer();
+        if !self.first {
+            output.push_str(", ");
+        }
+        self.first = false;
+        output
+    }
+}
+
+impl<B: JsonOutput> SerializeMap for JsonMapSerializer<B> {
+    type Ok = B::Ok;
     type Error = Error;
     
     fn serialize_key<T: Serialize>(&mut self, key: &T) -> Result<(), Error> {
-        if !self.first {
-            self.output.push_str(", ");
+        let (options, depth) = (self.options, self.depth);
+        let key = object_key(key)?;
+        if options.canonical {
+            let serialized = key.as_str().serialize(JsonSerializer::nested(String::new(), options, depth))?;
+            self.key = Some((key, serialized));
+            return Ok(());
         }
-        self.first = false;
-        
-        let serialized = to_json(key)?;
-        self.key = Some(serialized);
+        let output = self.separate();
+        key.as_str().serialize(JsonSerializer::nested(&mut *output, options, depth))?;
+        output.push_str(": ");
         Ok(())
     }
     
     fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
-        if let Some(key) = self.key.take() {
-            self.output.push_str(&key);
-            self.output.push_str(": ");
-            let serialized = to_json(value)?;
-            self.output.push_str(&serialized);
+        if !self.options.canonical {
+            return value.serialize(JsonSerializer::nested(self.output.buffer(), self.options, self.depth));
+        }
+        if let Some((unquoted, key)) = self.key.take() {
+            let serialized = value.serialize(JsonSerializer::nested(String::new(), self.options, self.depth))?;
+            self.entries.push((unquoted, key, serialized));
         }
         Ok(())
     }
     
-    fn end(mut self) -> Result<String, Error> {
-        self.output.push('}');
-        Ok(self.output)
+    fn end(mut self) -> Result<B::Ok, Error> {
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, key, value) in entries {
+            let output = self.separate();
+            output.push_str(&key);
+            output.push_str(": ");
+            output.push_str(&value);
+        }
+        self.output.buffer().push('}');
+        Ok(self.output.finish())
     }
 }
 
-// Helper function to serialize to JSON
+impl<B: JsonOutput> SerializeStruct for JsonMapSerializer<B> {
+    type Ok = B::Ok;
+    type Error = Error;
+    
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.serialize_entry(&key, value)
+    }
+    
+    fn end(self) -> Result<B::Ok, Error> {
+        SerializeMap::end(self)
+    }
+}
+
+// Quote a string for JSON, escaping quotes, backslashes and control
+// characters
+fn escape_json(v: &str) -> String {
+    let mut output = String::with_capacity(v.len() + 2);
+    escape_json_into(&mut output, v);
+    output
+}
+
+fn escape_json_into(output: &mut String, v: &str) {
+    output.reserve(v.len() + 2);
+    output.push('"');
+    for c in v.chars() {
+        match json_escape_sequence(c) {
+            Some(escaped) => output.push_str(&escaped),
+            None => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+// The escape sequence for a character, or None if it is written as is
+fn json_escape_sequence(c: char) -> Option<String> {
+    let escaped = match c {
+        '"' => "\\\"",
+        '\\' => "\\\\",
+        '\n' => "\\n",
+        '\r' => "\\r",
+        '\t' => "\\t",
+        '\u{8}' => "\\b",
+        '\u{c}' => "\\f",
+        c if c < ' ' => return Some(format!("\\u{:04x}", c as u32)),
+        _ => return None,
+    };
+    Some(escaped.to_string())
+}
+
+// Helper function to serialize to JSON
 pub fn to_json<T: Serialize>(value: &T) -> Result<String, Error> {
     value.serialize(JsonSerializer::new())
 }
 
-// Implement Serialize for common types
-impl Serialize for bool {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_bool(*self)
+// Serialize to JSON with sorted keys and normalized numbers, for output
+// that can be hashed, signed or compared byte for byte
+pub fn to_canonical_json<T: Serialize>(value: &T) -> Result<String, Error> {
+    value.serialize(JsonSerializer::new().canonical(true))
+}
+
+// Rewrite a number in the one form canonical output uses for its value:
+// plain digits for integers up to 21 digits, a plain decimal down to 1e-6,
+// and exponent form (`1.5e-7`, `1e22`) past those. `text` is JSON number
+// text or Rust's `{:e}` format; None if it is neither, such as `NaN`.
+fn canonical_number(text: &str) -> Option<String> {
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().ok()?),
+        None => (text, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() || !integer.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // The significant digits, and the exponent that goes with them
+    let digits = format!("{}{}", integer, fraction);
+    let digits = digits.trim_start_matches('0');
+    let significant = digits.trim_end_matches('0');
+    if significant.is_empty() {
+        return Some("0".to_string());
+    }
+    let exponent = exponent - fraction.len() as i64 + (digits.len() - significant.len()) as i64;
+    // How many digits come before the decimal point
+    let point = significant.len() as i64 + exponent;
+    Some(if point > 21 || point <= -6 {
+        let (first, rest) = significant.split_at(1);
+        let dot = if rest.is_empty() { "" } else { "." };
+        format!("{}{}{}{}e{}", sign, first, dot, rest, point - 1)
+    } else if exponent >= 0 {
+        format!("{}{}{}", sign, significant, "0".repeat(exponent as usize))
+    } else if point > 0 {
+        let (whole, fraction) = significant.split_at(point as usize);
+        format!("{}{}.{}", sign, whole, fraction)
+    } else {
+        format!("{}0.{}{}", sign, "0".repeat(-point as usize), significant)
+    })
+}
+
+// Streaming JSON serializer. Writes the same text as to_json, straight to
+// an io::Write instead of building a String.
+pub struct JsonWriter<W: Write> {
+    writer: W,
+    non_finite: NonFinite,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonWriter {
+            writer,
+            non_finite: NonFinite::default(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Start an array or object, written with `open`
+    fn enter(&mut self, open: &str) -> Result<(), Error> {
+        if self.depth >= self.max_depth {
+            return Err(depth_exceeded(self.max_depth));
+        }
+        self.depth += 1;
+        self.write(open)
+    }
+
+    fn leave(&mut self, close: &str) -> Result<(), Error> {
+        self.depth -= 1;
+        self.write(close)
+    }
+
+    pub fn non_finite(mut self, policy: NonFinite) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), Error> {
+        Ok(self.writer.write_all(text.as_bytes())?)
+    }
+
+    fn write_display<T: fmt::Display>(&mut self, v: T) -> Result<(), Error> {
+        Ok(write!(self.writer, "{}", v)?)
+    }
+
+    // Write a quoted string, copying runs that need no escaping in one go
+    fn write_str(&mut self, v: &str) -> Result<(), Error> {
+        self.write("\"")?;
+        let mut start = 0;
+        for (i, c) in v.char_indices() {
+            if let Some(escaped) = json_escape_sequence(c) {
+                self.write(&v[start..i])?;
+                self.write(&escaped)?;
+                start = i + c.len_utf8();
+            }
+        }
+        self.write(&v[start..])?;
+        self.write("\"")
+    }
+}
+
+impl<'a, W: Write> Serializer for &'a mut JsonWriter<W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = JsonWriterCompound<'a, W>;
+    type SerializeTuple = JsonWriterCompound<'a, W>;
+    type SerializeMap = JsonWriterCompound<'a, W>;
+    type SerializeStruct = JsonWriterCompound<'a, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), Error> {
+        self.write_display(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        if !v.is_finite() {
+            return self.serialize_f64(v.into());
+        }
+        self.write_display(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        if !v.is_finite() {
+            let text = self.non_finite.text(v)?;
+            return self.write(text);
+        }
+        self.write_display(v)
+    }
+
+    fn serialize_number(self, text: &str) -> Result<(), Error> {
+        self.write(text)
+    }
+
+    fn serialize_raw_json(self, json: &str) -> Result<(), Error> {
+        self.write(json)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.write_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.write_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        let mut seq = self.serialize_seq(Some(v.len()))?;
+        for byte in v {
+            SerializeSeq::serialize_element(&mut seq, byte)?;
+        }
+        SerializeSeq::end(seq)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.write("null")
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.write("null")
+    }
+
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<JsonWriterCompound<'a, W>, Error> {
+        self.enter("[")?;
+        Ok(JsonWriterCompound { ser: self, first: true })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<JsonWriterCompound<'a, W>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<JsonWriterCompound<'a, W>, Error> {
+        self.enter("{")?;
+        Ok(JsonWriterCompound { ser: self, first: true })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<JsonWriterCompound<'a, W>, Error> {
+        self.serialize_map(Some(len))
+    }
+}
+
+// Writes the elements of an array or the entries of an object as they come
+pub struct JsonWriterCompound<'a, W: Write> {
+    ser: &'a mut JsonWriter<W>,
+    first: bool,
+}
+
+impl<W: Write> JsonWriterCompound<'_, W> {
+    fn separator(&mut self) -> Result<(), Error> {
+        if !self.first {
+            self.ser.write(", ")?;
+        }
+        self.first = false;
+        Ok(())
+    }
+}
+
+impl<W: Write> SerializeSeq for JsonWriterCompound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.separator()?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.ser.leave("]")
+    }
+}
+
+impl<W: Write> SerializeTuple for JsonWriterCompound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl<W: Write> SerializeMap for JsonWriterCompound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.separator()?;
+        object_key(key)?.as_str().serialize(&mut *self.ser)
+    }
+
+    fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.ser.write(": ")?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.ser.leave("}")
+    }
+}
+
+impl<W: Write> SerializeStruct for JsonWriterCompound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.serialize_entry(&key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        SerializeMap::end(self)
+    }
+}
+
+// Serialize as JSON to a writer, through a buffer so small writes don't
+// each reach the underlying writer
+pub fn to_writer<W: Write, T: Serialize>(writer: W, value: &T) -> Result<(), Error> {
+    let mut ser = JsonWriter::new(BufWriter::new(writer));
+    value.serialize(&mut ser)?;
+    ser.into_inner().flush()?;
+    Ok(())
+}
+
+// Implement Serialize for common types
+impl Serialize for bool {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bool(*self)
+    }
+}
+
+impl Serialize for i32 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(*self)
+    }
+}
+
+impl Serialize for i64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(*self)
+    }
+}
+
+impl Serialize for f64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(*self)
+    }
+}
+
+// Implement Serialize for the remaining primitives
+macro_rules! serialize_primitives {
+    ($($ty:ty => $method:ident,)*) => {
+        $(
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.$method(*self)
+                }
+            }
+        )*
+    };
+}
+
+serialize_primitives! {
+    i8 => serialize_i8,
+    i16 => serialize_i16,
+    i128 => serialize_i128,
+    u8 => serialize_u8,
+    u16 => serialize_u16,
+    u32 => serialize_u32,
+    u64 => serialize_u64,
+    u128 => serialize_u128,
+    f32 => serialize_f32,
+    char => serialize_char,
+}
+
+impl Serialize for () {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_unit()
+    }
+}
+
+impl Serialize for str {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+impl Serialize for String {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for &T {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<T: Serialize> Serialize for Option<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Some(value) => serializer.serialize_some(value),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Vec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for element in self {
+            seq.serialize_element(element)?;
+        }
+        seq.end()
+    }
+}
+
+impl<T: Serialize, const N: usize> Serialize for [T; N] {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for element in self {
+            tuple.serialize_element(element)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<K: Serialize, V: Serialize> Serialize for HashMap<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (key, value) in self {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<K: Serialize, V: Serialize> Serialize for BTreeMap<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (key, value) in self {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+// Results are written like an externally tagged enum: {"Ok": value} or
+// {"Err": error}
+impl<T: Serialize, E: Serialize> Serialize for Result<T, E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            Ok(value) => map.serialize_entry(&"Ok", value)?,
+            Err(error) => map.serialize_entry(&"Err", error)?,
+        }
+        map.end()
+    }
+}
+
+// Implement Deserialize for common types
+struct BoolVisitor;
+
+impl<'de> Visitor<'de> for BoolVisitor {
+    type Value = bool;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a boolean")
+    }
+
+    fn visit_bool<E: CustomError>(self, v: bool) -> Result<bool, E> {
+        Ok(v)
+    }
+}
+
+impl<'de> Deserialize<'de> for bool {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bool(BoolVisitor)
+    }
+}
+
+// Convert between integer types, or None when the value does not fit
+fn convert<T: TryFrom<U>, U>(v: U) -> Option<T> {
+    T::try_from(v).ok()
+}
+
+// Implement Deserialize for integer types. Each visitor accepts any integer
+// that fits, whichever method the format passes it to.
+macro_rules! deserialize_integers {
+    ($($ty:ident => $visitor:ident, $method:ident, $expecting:expr;)*) => {
+        $(
+            struct $visitor;
+
+            impl<'de> Visitor<'de> for $visitor {
+                type Value = $ty;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    write!(formatter, $expecting)
+                }
+
+                fn visit_i64<E: CustomError>(self, v: i64) -> Result<$ty, E> {
+                    convert(v).ok_or_else(|| self.invalid_value(&v.to_string()))
+                }
+
+                fn visit_i128<E: CustomError>(self, v: i128) -> Result<$ty, E> {
+                    convert(v).ok_or_else(|| self.invalid_value(&v.to_string()))
+                }
+
+                fn visit_u64<E: CustomError>(self, v: u64) -> Result<$ty, E> {
+                    convert(v).ok_or_else(|| self.invalid_value(&v.to_string()))
+                }
+
+                fn visit_u128<E: CustomError>(self, v: u128) -> Result<$ty, E> {
+                    convert(v).ok_or_else(|| self.invalid_value(&v.to_string()))
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.$method($visitor)
+                }
+            }
+        )*
+    };
+}
+
+deserialize_integers! {
+    i8 => I8Visitor, deserialize_i8, "an i8";
+    i16 => I16Visitor, deserialize_i16, "an i16";
+    i32 => I32Visitor, deserialize_i32, "an i32";
+    i64 => I64Visitor, deserialize_i64, "an i64";
+    i128 => I128Visitor, deserialize_i128, "an i128";
+    u8 => U8Visitor, deserialize_u8, "a u8";
+    u16 => U16Visitor, deserialize_u16, "a u16";
+    u32 => U32Visitor, deserialize_u32, "a u32";
+    u64 => U64Visitor, deserialize_u64, "a u64";
+    u128 => U128Visitor, deserialize_u128, "a u128";
+}
+
+struct F64Visitor;
+
+impl<'de> Visitor<'de> for F64Visitor {
+    type Value = f64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an f64")
+    }
+
+    fn visit_i32<E: CustomError>(self, v: i32) -> Result<f64, E> {
+        Ok(f64::from(v))
+    }
+
+    fn visit_i64<E: CustomError>(self, v: i64) -> Result<f64, E> {
+        Ok(v as f64)
+    }
+
+    fn visit_i128<E: CustomError>(self, v: i128) -> Result<f64, E> {
+        Ok(v as f64)
+    }
+
+    fn visit_u64<E: CustomError>(self, v: u64) -> Result<f64, E> {
+        Ok(v as f64)
+    }
+
+    fn visit_u128<E: CustomError>(self, v: u128) -> Result<f64, E> {
+        Ok(v as f64)
+    }
+
+    fn visit_f64<E: CustomError>(self, v: f64) -> Result<f64, E> {
+        Ok(v)
+    }
+}
+
+impl<'de> Deserialize<'de> for f64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_f64(F64Visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for f32 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_f32(F64Visitor).map(|v| v as f32)
+    }
+}
+
+struct CharVisitor;
+
+impl<'de> Visitor<'de> for CharVisitor {
+    type Value = char;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a character")
+    }
+
+    fn visit_char<E: CustomError>(self, v: char) -> Result<char, E> {
+        Ok(v)
+    }
+
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<char, E> {
+        let mut chars = v.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(self.invalid_value(&format!("string {:?}", v))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for char {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_char(CharVisitor)
+    }
+}
+
+struct UnitVisitor;
+
+impl<'de> Visitor<'de> for UnitVisitor {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "unit")
+    }
+
+    fn visit_unit<E: CustomError>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_none<E: CustomError>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for () {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_unit(UnitVisitor)
+    }
+}
+
+struct StringVisitor;
+
+impl<'de> Visitor<'de> for StringVisitor {
+    type Value = String;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a string")
+    }
+
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_string<E: CustomError>(self, v: String) -> Result<String, E> {
+        Ok(v)
+    }
+}
+
+impl<'de> Deserialize<'de> for String {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_string(StringVisitor)
+    }
+}
+
+struct BorrowedStrVisitor;
+
+impl<'de> Visitor<'de> for BorrowedStrVisitor {
+    type Value = &'de str;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a borrowed string")
+    }
+
+    fn visit_borrowed_str<E: CustomError>(self, v: &'de str) -> Result<&'de str, E> {
+        Ok(v)
+    }
+
+    // Escaped text had to be copied, so there is nothing to borrow; use
+    // String or Cow<str> for input that may contain escapes
+    fn visit_str<E: CustomError>(self, _v: &str) -> Result<&'de str, E> {
+        Err(self.invalid_type("escaped string"))
+    }
+}
+
+// Borrows from the input, which must outlive the value. Fails on strings
+// that can't be borrowed, such as JSON strings with escapes.
+impl<'de: 'a, 'a> Deserialize<'de> for &'a str {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(BorrowedStrVisitor)
+    }
+}
+
+struct CowStrVisitor;
+
+impl<'de> Visitor<'de> for CowStrVisitor {
+    type Value = Cow<'de, str>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a string")
+    }
+
+    fn visit_borrowed_str<E: CustomError>(self, v: &'de str) -> Result<Cow<'de, str>, E> {
+        Ok(Cow::Borrowed(v))
+    }
+
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<Cow<'de, str>, E> {
+        Ok(Cow::Owned(v.to_string()))
+    }
+
+    fn visit_string<E: CustomError>(self, v: String) -> Result<Cow<'de, str>, E> {
+        Ok(Cow::Owned(v))
+    }
+}
+
+// Borrows when the input allows it and copies otherwise
+impl<'de: 'a, 'a> Deserialize<'de> for Cow<'a, str> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(CowStrVisitor)
+    }
+}
+
+impl<T: Serialize + ToOwned + ?Sized> Serialize for Cow<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+struct OptionVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for OptionVisitor<T> {
+    type Value = Option<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an optional value")
+    }
+
+    fn visit_none<E: CustomError>(self) -> Result<Option<T>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<T>, D::Error> {
+        T::deserialize(deserializer).map(Some)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Option<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_option(OptionVisitor(PhantomData))
+    }
+}
+
+struct VecVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for VecVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Vec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(VecVisitor(PhantomData))
+    }
+}
+
+struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>, const N: usize> Visitor<'de> for ArrayVisitor<T, N> {
+    type Value = [T; N];
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an array of length {}", N)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<[T; N], A::Error> {
+        let mut values = Vec::with_capacity(N);
+        while values.len() < N {
+            match seq.next_element()? {
+                Some(value) => values.push(value),
+                None => return Err(self.invalid_length(values.len())),
+            }
+        }
+        values.try_into().map_err(|values: Vec<T>| self.invalid_length(values.len()))
+    }
+}
+
+impl<'de, T: Deserialize<'de>, const N: usize> Deserialize<'de> for [T; N] {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(N, ArrayVisitor(PhantomData))
+    }
+}
+
+// Implement Serialize and Deserialize for tuples, written as sequences of
+// their elements
+macro_rules! tuple_impls {
+    ($($len:expr => $visitor:ident ($($n:tt $name:ident)+))+) => {
+        $(
+            impl<$($name: Serialize),+> Serialize for ($($name,)+) {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    let mut tuple = serializer.serialize_tuple($len)?;
+                    $(
+                        tuple.serialize_element(&self.$n)?;
+                    )+
+                    tuple.end()
+                }
+            }
+
+            struct $visitor<$($name),+>(PhantomData<($($name,)+)>);
+
+            impl<'de, $($name: Deserialize<'de>),+> Visitor<'de> for $visitor<$($name),+> {
+                type Value = ($($name,)+);
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    write!(formatter, "a tuple of size {}", $len)
+                }
+
+                fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                    Ok(($(
+                        match seq.next_element::<$name>()? {
+                            Some(value) => value,
+                            None => return Err(self.invalid_length($n)),
+                        },
+                    )+))
+                }
+            }
+
+            impl<'de, $($name: Deserialize<'de>),+> Deserialize<'de> for ($($name,)+) {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_tuple($len, $visitor(PhantomData))
+                }
+            }
+        )+
+    };
+}
+
+tuple_impls! {
+    1 => Tuple1Visitor (0 T0)
+    2 => Tuple2Visitor (0 T0 1 T1)
+    3 => Tuple3Visitor (0 T0 1 T1 2 T2)
+    4 => Tuple4Visitor (0 T0 1 T1 2 T2 3 T3)
+    5 => Tuple5Visitor (0 T0 1 T1 2 T2 3 T3 4 T4)
+    6 => Tuple6Visitor (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5)
+    7 => Tuple7Visitor (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6)
+    8 => Tuple8Visitor (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7)
+    9 => Tuple9Visitor (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8)
+    10 => Tuple10Visitor (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9)
+    11 => Tuple11Visitor (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10)
+    12 => Tuple12Visitor (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11)
+}
+
+struct HashMapVisitor<K, V>(PhantomData<(K, V)>);
+
+impl<'de, K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>> Visitor<'de> for HashMapVisitor<K, V> {
+    type Value = HashMap<K, V>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<HashMap<K, V>, A::Error> {
+        let mut values = HashMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            values.insert(key, value);
+        }
+        Ok(values)
+    }
+}
+
+impl<'de, K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>> Deserialize<'de> for HashMap<K, V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(HashMapVisitor(PhantomData))
+    }
+}
+
+struct BTreeMapVisitor<K, V>(PhantomData<(K, V)>);
+
+impl<'de, K: Deserialize<'de> + Ord, V: Deserialize<'de>> Visitor<'de> for BTreeMapVisitor<K, V> {
+    type Value = BTreeMap<K, V>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<BTreeMap<K, V>, A::Error> {
+        let mut values = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            values.insert(key, value);
+        }
+        Ok(values)
+    }
+}
+
+impl<'de, K: Deserialize<'de> + Ord, V: Deserialize<'de>> Deserialize<'de> for BTreeMap<K, V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(BTreeMapVisitor(PhantomData))
+    }
+}
+
+// Collects a sequence into any collection that can be extended one element
+// at a time
+struct CollectVisitor<C, T>(PhantomData<(C, T)>);
+
+impl<'de, C: Default + Extend<T>, T: Deserialize<'de>> Visitor<'de> for CollectVisitor<C, T> {
+    type Value = C;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<C, A::Error> {
+        let mut values = C::default();
+        while let Some(value) = seq.next_element()? {
+            values.extend(Some(value));
+        }
+        Ok(values)
+    }
+}
+
+// Sets and the other sequence collections are written like a Vec. Sets keep
+// the last of any duplicates.
+macro_rules! seq_impls {
+    ($($ty:ident => [$($bound:path),*],)*) => {
+        $(
+            impl<T: Serialize> Serialize for $ty<T> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    let mut seq = serializer.serialize_seq(Some(self.len()))?;
+                    for element in self {
+                        seq.serialize_element(element)?;
+                    }
+                    seq.end()
+                }
+            }
+
+            impl<'de, T: Deserialize<'de> $(+ $bound)*> Deserialize<'de> for $ty<T> {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_seq(CollectVisitor(PhantomData))
+                }
+            }
+        )*
+    };
+}
+
+seq_impls! {
+    VecDeque => [],
+    LinkedList => [],
+    HashSet => [Eq, Hash],
+    BTreeSet => [Ord],
+}
+
+// Smart pointers are written as the value they point to
+macro_rules! pointer_impls {
+    ($($ty:ident),*) => {
+        $(
+            impl<T: Serialize + ?Sized> Serialize for $ty<T> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    (**self).serialize(serializer)
+                }
+            }
+
+            impl<'de, T: Deserialize<'de>> Deserialize<'de> for $ty<T> {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    T::deserialize(deserializer).map($ty::new)
+                }
+            }
+        )*
+    };
+}
+
+pointer_impls!(Box, Rc, Arc);
+
+struct ResultVisitor<T, E>(PhantomData<(T, E)>);
+
+impl<'de, T: Deserialize<'de>, E: Deserialize<'de>> Visitor<'de> for ResultVisitor<T, E> {
+    type Value = Result<T, E>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a map with a single `Ok` or `Err` key")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Result<T, E>, A::Error> {
+        let result = match map.next_key::<String>()?.as_deref() {
+            Some("Ok") => Ok(map.next_value()?),
+            Some("Err") => Err(map.next_value()?),
+            Some(variant) => return Err(unknown_variant(variant, &["Ok", "Err"])),
+            None => return Err(self.invalid_length(0)),
+        };
+        if map.next_key::<String>()?.is_some() {
+            return Err(self.invalid_type("map with more than one key"));
+        }
+        Ok(result)
+    }
+}
+
+impl<'de, T: Deserialize<'de>, E: Deserialize<'de>> Deserialize<'de> for Result<T, E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ResultVisitor(PhantomData))
+    }
+}
+
+// A byte buffer that serializes with serialize_bytes, where a Vec<u8> is a
+// sequence of numbers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ByteBuf(pub Vec<u8>);
+
+impl Serialize for ByteBuf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+struct ByteBufVisitor;
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a byte array")
+    }
+
+    fn visit_bytes<E: CustomError>(self, v: &[u8]) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: CustomError>(self, v: Vec<u8>) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(v))
+    }
+
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(v.as_bytes().to_vec()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+        let mut bytes = Vec::new();
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(ByteBuf(bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(ByteBufVisitor)
+    }
+}
+
+struct BorrowedBytesVisitor;
+
+impl<'de> Visitor<'de> for BorrowedBytesVisitor {
+    type Value = &'de [u8];
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "borrowed bytes")
+    }
+
+    fn visit_borrowed_bytes<E: CustomError>(self, v: &'de [u8]) -> Result<&'de [u8], E> {
+        Ok(v)
+    }
+
+    fn visit_borrowed_str<E: CustomError>(self, v: &'de str) -> Result<&'de [u8], E> {
+        Ok(v.as_bytes())
+    }
+}
+
+// Borrows MessagePack binary data, or the UTF-8 of an unescaped string
+impl<'de: 'a, 'a> Deserialize<'de> for &'a [u8] {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(BorrowedBytesVisitor)
+    }
+}
+
+// JSON Deserializer implementation
+pub struct JsonDeserializer<'de> {
+    input: &'de str,
+    pos: usize,
+    arbitrary_precision: bool,
+    non_finite: NonFinite,
+    depth: usize,
+    max_depth: usize,
+    duplicate_keys: DuplicateKeys,
+    lenient: bool,
+    // Set while a map key is read, where lenient mode allows bare names
+    in_key: bool,
+}
+
+impl<'de> JsonDeserializer<'de> {
+    pub fn new(input: &'de str) -> Self {
+        JsonDeserializer {
+            input,
+            pos: 0,
+            arbitrary_precision: false,
+            non_finite: NonFinite::default(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            duplicate_keys: DuplicateKeys::default(),
+            lenient: false,
+            in_key: false,
+        }
+    }
+    
+    pub fn duplicate_keys(mut self, policy: DuplicateKeys) -> Self {
+        self.duplicate_keys = policy;
+        self
+    }
+    
+    // The deepest nesting of arrays and objects to read before failing
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+    
+    // Step past the `[` or `{` at the current position
+    fn enter(&mut self) -> Result<(), Error> {
+        if self.depth >= self.max_depth {
+            return Err(depth_exceeded(self.max_depth));
+        }
+        self.depth += 1;
+        self.pos += 1;
+        Ok(())
+    }
+    
+    // Read NaN and the infinities into floats in the form the policy
+    // writes them
+    pub fn non_finite(mut self, policy: NonFinite) -> Self {
+        self.non_finite = policy;
+        self
+    }
+    
+    // Pass numbers to visitors as their text, so JsonValue and Number keep
+    // big integers and long decimals exactly instead of rounding to f64
+    pub fn arbitrary_precision(mut self, enabled: bool) -> Self {
+        self.arbitrary_precision = enabled;
+        self
+    }
+    
+    // Accept the JSON5-style extensions people write in config files by
+    // hand: `//` and `/* */` comments, trailing commas, unquoted keys and
+    // single-quoted strings
+    pub fn lenient(mut self, enabled: bool) -> Self {
+        self.lenient = enabled;
+        self
+    }
+    
+    // Deserialize the whole input, as from_json does, with this
+    // deserializer's options
+    pub fn parse<T: Deserialize<'de>>(mut self) -> Result<T, Error> {
+        T::deserialize(&mut self)
+            .and_then(|value| self.end().map(|()| value))
+            .map_err(|e| e.at_offset(self.input, self.pos))
+    }
+    
+    // Fail unless only whitespace is left after the value
+    pub fn end(&mut self) -> Result<(), Error> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected("end of input")),
+        }
+    }
+    
+    // Skip whitespace, and comments in lenient mode, and look at the next
+    // byte without consuming it
+    fn peek(&mut self) -> Option<u8> {
+        let bytes = self.input.as_bytes();
+        loop {
+            while self.pos < bytes.len() && matches!(bytes[self.pos], b' ' | b'\t' | b'\n' | b'\r') {
+                self.pos += 1;
+            }
+            if !self.lenient || bytes.get(self.pos) != Some(&b'/') {
+                break;
+            }
+            let rest = &self.input[self.pos..];
+            // An unterminated block comment is left for the caller to reject
+            let len = match bytes.get(self.pos + 1) {
+                Some(b'/') => rest.find('\n').unwrap_or(rest.len()),
+                Some(b'*') => match rest[2..].find("*/") {
+                    Some(end) => end + 4,
+                    None => break,
+                },
+                _ => break,
+            };
+            self.pos += len;
+        }
+        bytes.get(self.pos).copied()
+    }
+    
+    // Whether a string starts here: a JSON string, or in lenient mode a
+    // single-quoted one or, as a key, a bare name
+    fn at_string(&mut self) -> bool {
+        match self.peek() {
+            Some(b'"') => true,
+            Some(b'\'') => self.lenient,
+            Some(b) => self.lenient && self.in_key && (b.is_ascii_alphabetic() || matches!(b, b'_' | b'$') || !b.is_ascii()),
+            None => false,
+        }
+    }
+    
+    // In lenient mode, step past a `,` that comes just before `close`
+    fn skip_trailing_comma(&mut self, close: u8) {
+        if self.lenient && self.peek() == Some(b',') {
+            let comma = self.pos;
+            self.pos += 1;
+            if self.peek() != Some(close) {
+                self.pos = comma;
+            }
+        }
+    }
+    
+    fn expect(&mut self, byte: u8) -> Result<(), Error> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", byte as char)))
+        }
+    }
+    
+    fn keyword(&mut self, word: &str) -> Result<(), Error> {
+        if self.input[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", word)))
+        }
+    }
+    
+    fn unexpected(&self, expected: &str) -> Error {
+        match self.input[self.pos..].chars().next() {
+            Some(c) => Error::custom(format!("expected {}, found `{}`", expected, c)),
+            None => Error::custom(format!("expected {}, found end of input", expected)),
+        }
+    }
+    
+    // The error for a value of the wrong JSON type for the visitor
+    fn invalid_type<V: Visitor<'de>>(&mut self, visitor: &V) -> Error {
+        let found = match self.peek() {
+            Some(b'n') => "null",
+            Some(b't') | Some(b'f') => "boolean",
+            Some(b'"') => "string",
+            Some(b'\'') if self.lenient => "string",
+            Some(b'[') => "sequence",
+            Some(b'{') => "map",
+            Some(b'-') | Some(b'0'..=b'9') => "number",
+            _ => return self.unexpected("a JSON value"),
+        };
+        Error::custom(format!("invalid type: {}, expected {}", found, expected(visitor)))
+    }
+    
+    // A float the non-finite policy reads from the input, if there is one.
+    // Anything else is left for the usual parsing.
+    fn non_finite_float(&mut self) -> Result<Option<f64>, Error> {
+        let word = match (self.non_finite, self.peek()) {
+            (NonFinite::Null, Some(b'n')) => {
+                self.keyword("null")?;
+                return Ok(Some(f64::NAN));
+            }
+            (NonFinite::String, Some(b'"')) => {
+                let start = self.pos;
+                let word = self.parse_str()?;
+                if !matches!(&*word, "NaN" | "Infinity" | "-Infinity") {
+                    self.pos = start;
+                    return Ok(None);
+                }
+                word
+            }
+            (NonFinite::Literal, Some(b'N' | b'I' | b'-')) => {
+                let rest = &self.input[self.pos..];
+                match ["NaN", "Infinity", "-Infinity"].into_iter().find(|word| rest.starts_with(word)) {
+                    Some(word) => {
+                        self.pos += word.len();
+                        Cow::Borrowed(word)
+                    }
+                    None => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(match &*word {
+            "NaN" => f64::NAN,
+            "Infinity" => f64::INFINITY,
+            _ => f64::NEG_INFINITY,
+        }))
+    }
+    
+    // Parse a number into T, failing if it does not fit. An object key is
+    // a string holding the number.
+    fn parse_number<T: FromStr, V: Visitor<'de>>(&mut self, visitor: &V) -> Result<T, Error> {
+        if self.in_key && self.at_string() {
+            let text = self.parse_string()?;
+            return text.parse().map_err(|_| {
+                Error::custom(format!("invalid value: string {:?}, expected {}", text, expected(visitor)))
+            });
+        }
+        if !matches!(self.peek(), Some(b'-') | Some(b'0'..=b'9')) {
+            return Err(self.invalid_type(visitor));
+        }
+        let text = self.number_text()?;
+        text.parse().map_err(|_| {
+            Error::custom(format!("invalid value: {}, expected {}", text, expected(visitor)))
+        })
+    }
+    
+    // Parse a float, which under the Error policy must not overflow to an
+    // infinity the input didn't ask for
+    fn parse_float<T: FromStr + Copy + Into<f64>, V: Visitor<'de>>(&mut self, visitor: &V) -> Result<T, Error> {
+        let start = self.pos;
+        let v: T = self.parse_number(visitor)?;
+        self.check_range(&self.input[start..self.pos], v.into())?;
+        Ok(v)
+    }
+    
+    // Under the Error policy, a number too large for its float type is an
+    // error rather than an infinity
+    fn check_range(&self, text: &str, v: f64) -> Result<(), Error> {
+        if self.non_finite == NonFinite::Error && v.is_infinite() {
+            return Err(Error::custom(format!("number out of range: {}", text)));
+        }
+        Ok(())
+    }
+    
+    // The text of the number at the current position, checked against the
+    // JSON grammar: -?(0|[1-9][0-9]*)(.[0-9]+)?([eE][+-]?[0-9]+)?
+    fn number_text(&mut self) -> Result<&'de str, Error> {
+        let input = self.input;
+        let bytes = input.as_bytes();
+        let start = self.pos;
+        if bytes.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        let integer = self.digits();
+        if integer == 0 || (integer > 1 && bytes[self.pos - integer] == b'0') {
+            return Err(Error::custom(format!("invalid number: {}", &input[start..self.pos])));
+        }
+        if bytes.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if self.digits() == 0 {
+                return Err(self.unexpected("a digit"));
+            }
+        }
+        if matches!(bytes.get(self.pos), Some(b'e') | Some(b'E')) {
+            self.pos += 1;
+            if matches!(bytes.get(self.pos), Some(b'+') | Some(b'-')) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.unexpected("a digit"));
+            }
+        }
+        Ok(&input[start..self.pos])
+    }
+    
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.input.as_bytes().get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+    
+    // Parse a string literal, decoding escape sequences
+    fn parse_string(&mut self) -> Result<String, Error> {
+        self.parse_str().map(Cow::into_owned)
+    }
+    
+    // Parse an object key, which lenient mode lets be a bare name
+    fn parse_key(&mut self) -> Result<String, Error> {
+        self.in_key = true;
+        let key = if self.at_string() {
+            self.parse_string()
+        } else {
+            Err(self.unexpected("a string key"))
+        };
+        self.in_key = false;
+        key
+    }
+    
+    // Parse a string, borrowing it from the input unless it has escapes
+    fn parse_str(&mut self) -> Result<Cow<'de, str>, Error> {
+        let quote = match self.peek() {
+            Some(b'\'') if self.lenient => '\'',
+            Some(b) if self.lenient && self.in_key && b != b'"' => return self.bare_key().map(Cow::Borrowed),
+            _ => '"',
+        };
+        self.expect(quote as u8)?;
+        let input = self.input;
+        let rest = &input[self.pos..];
+        if let Some(end) = rest.find(|c| c == quote || c == '\\' || c < ' ') {
+            if rest.as_bytes()[end] == quote as u8 {
+                self.pos += end + 1;
+                return Ok(Cow::Borrowed(&rest[..end]));
+            }
+        }
+        self.parse_escaped_string(quote).map(Cow::Owned)
+    }
+    
+    // An unquoted key in lenient mode: letters, digits, `_` and `$`, not
+    // starting with a digit
+    fn bare_key(&mut self) -> Result<&'de str, Error> {
+        let input = self.input;
+        let rest = &input[self.pos..];
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')).unwrap_or(rest.len());
+        if len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(self.unexpected("a key"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+    
+    // The rest of a string that has escapes, after its opening quote
+    fn parse_escaped_string(&mut self, quote: char) -> Result<String, Error> {
+        let mut output = String::new();
+        loop {
+            let rest = &self.input[self.pos..];
+            let end = match rest.find(|c| c == quote || c == '\\' || c < ' ') {
+                Some(end) => end,
+                None => return Err(Error::custom("EOF while parsing a string".to_string())),
+            };
+            output.push_str(&rest[..end]);
+            self.pos += end;
+            match rest.as_bytes()[end] {
+                b'\\' => self.pos += 1,
+                b if b == quote as u8 => {
+                    self.pos += 1;
+                    return Ok(output);
+                }
+                _ => return Err(Error::custom("control character in string".to_string())),
+            }
+            if self.input.as_bytes().get(self.pos) == Some(&b'u') {
+                self.pos += 1;
+                output.push(self.parse_unicode_escape()?);
+                continue;
+            }
+            let escaped = match self.input.as_bytes().get(self.pos) {
+                Some(b'"') => '"',
+                Some(b'\'') if self.lenient => '\'',
+                Some(b'\\') => '\\',
+                Some(b'/') => '/',
+                Some(b'b') => '\u{8}',
+                Some(b'f') => '\u{c}',
+                Some(b'n') => '\n',
+                Some(b'r') => '\r',
+                Some(b't') => '\t',
+                _ => return Err(self.unexpected("an escape sequence")),
+            };
+            output.push(escaped);
+            self.pos += 1;
+        }
+    }
+    
+    // Decode the XXXX of a \uXXXX escape, combining a UTF-16 surrogate pair
+    // written as two escapes into one character
+    fn parse_unicode_escape(&mut self) -> Result<char, Error> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xD800..=0xDBFF => {
+                if !self.input[self.pos..].starts_with("\\u") {
+                    return Err(Error::custom("unpaired surrogate in string".to_string()));
+                }
+                self.pos += 2;
+                let low = self.hex4()?;
+                if !(0xDC00..=0xDFFF).contains(&low) {
+                    return Err(Error::custom("unpaired surrogate in string".to_string()));
+                }
+                0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+            }
+            0xDC00..=0xDFFF => {
+                return Err(Error::custom("unpaired surrogate in string".to_string()))
+            }
+            code => code,
+        };
+        Ok(char::from_u32(code).unwrap())
+    }
+    
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let digits = self.input.get(self.pos..self.pos + 4).unwrap_or("");
+        match u32::from_str_radix(digits, 16) {
+            Ok(v) if digits.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                self.pos += 4;
+                Ok(v)
+            }
+            _ => Err(self.unexpected("four hex digits")),
+        }
+    }
+}
+
+// Hand a JSON number to the visitor as the first of i64, u64 and f64 that
+// can hold it
+fn visit_number_text<'de, V: Visitor<'de>, E: CustomError>(visitor: V, text: &str) -> Result<V::Value, E> {
+    if let Ok(v) = text.parse() {
+        visitor.visit_i64(v)
+    } else if let Ok(v) = text.parse() {
+        visitor.visit_u64(v)
+    } else {
+        match text.parse() {
+            Ok(v) => visitor.visit_f64(v),
+            Err(_) => Err(E::custom(format!("invalid number: {}", text))),
+        }
+    }
+}
+
+// Describe what a visitor expects, for error messages
+fn expected<'de, V: Visitor<'de>>(visitor: &V) -> String {
+    struct Expecting<F: Fn(&mut fmt::Formatter) -> fmt::Result>(F);
+    
+    impl<F: Fn(&mut fmt::Formatter) -> fmt::Result> fmt::Display for Expecting<F> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            (self.0)(f)
+        }
+    }
+    
+    Expecting(|f: &mut fmt::Formatter| visitor.expecting(f)).to_string()
+}
+
+impl<'de> Deserializer<'de> for &mut JsonDeserializer<'de> {
+    type Error = Error;
+    
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.at_string() {
+            return self.deserialize_string(visitor);
+        }
+        if self.non_finite == NonFinite::Literal {
+            if let Some(v) = self.non_finite_float()? {
+                return visitor.visit_f64(v);
+            }
+        }
+        match self.peek() {
+            Some(b'n') => {
+                self.keyword("null")?;
+                visitor.visit_none()
+            }
+            Some(b't') | Some(b'f') => self.deserialize_bool(visitor),
+            Some(b'[') => self.deserialize_seq(visitor),
+            Some(b'{') => self.deserialize_map(visitor),
+            Some(b'-') | Some(b'0'..=b'9') => {
+                let text = self.number_text()?;
+                if self.arbitrary_precision {
+                    visitor.visit_number(text)
+                } else {
+                    self.check_range(text, text.parse().unwrap_or(0.0))?;
+                    visit_number_text(visitor, text)
+                }
+            }
+            _ => Err(self.unexpected("a JSON value")),
+        }
+    }
+    
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.peek() {
+            Some(b't') => {
+                self.keyword("true")?;
+                visitor.visit_bool(true)
+            }
+            Some(b'f') => {
+                self.keyword("false")?;
+                visitor.visit_bool(false)
+            }
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+    
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_i8(v)
+    }
+    
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_i16(v)
+    }
+    
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_i32(v)
+    }
+    
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_i64(v)
+    }
+    
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_i128(v)
+    }
+    
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_u8(v)
+    }
+    
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_u16(v)
+    }
+    
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_u32(v)
+    }
+    
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_u64(v)
+    }
+    
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.parse_number(&visitor)?;
+        visitor.visit_u128(v)
+    }
+    
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = match self.non_finite_float()? {
+            Some(v) => v as f32,
+            None => self.parse_float(&visitor)?,
+        };
+        visitor.visit_f32(v)
+    }
+    
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = match self.non_finite_float()? {
+            Some(v) => v,
+            None => self.parse_float(&visitor)?,
+        };
+        visitor.visit_f64(v)
+    }
+    
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if !self.at_string() {
+            return Err(self.invalid_type(&visitor));
+        }
+        let v = self.parse_string()?;
+        let mut chars = v.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::custom(format!(
+                "invalid value: string {:?}, expected {}",
+                v,
+                expected(&visitor)
+            ))),
+        }
+    }
+    
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if !self.at_string() {
+            return Err(self.invalid_type(&visitor));
+        }
+        match self.parse_str()? {
+            Cow::Borrowed(v) => visitor.visit_borrowed_str(v),
+            Cow::Owned(v) => visitor.visit_str(&v),
+        }
+    }
+    
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if !self.at_string() {
+            return Err(self.invalid_type(&visitor));
+        }
+        let v = self.parse_string()?;
+        visitor.visit_string(v)
+    }
+    
+    // Bytes are read from an array of numbers, as written by the serializer,
+    // or from the UTF-8 of a string
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.peek() {
+            Some(_) if self.at_string() => match self.parse_str()? {
+                Cow::Borrowed(v) => visitor.visit_borrowed_bytes(v.as_bytes()),
+                Cow::Owned(v) => visitor.visit_byte_buf(v.into_bytes()),
+            },
+            Some(b'[') => self.deserialize_seq(visitor),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+    
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.peek() != Some(b'n') {
+            return Err(self.invalid_type(&visitor));
+        }
+        self.keyword("null")?;
+        visitor.visit_unit()
+    }
+    
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.peek() == Some(b'n') {
+            self.keyword("null")?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+    
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.peek() != Some(b'[') {
+            return Err(self.invalid_type(&visitor));
+        }
+        self.enter()?;
+        let value = visitor.visit_seq(JsonSeqAccess { de: &mut *self, len: None, read: 0 });
+        self.depth -= 1;
+        let value = value?;
+        self.expect(b']')?;
+        Ok(value)
+    }
+    
+    // A tuple is an array with exactly `len` elements
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        if self.peek() != Some(b'[') {
+            return Err(self.invalid_type(&visitor));
+        }
+        self.enter()?;
+        let value = visitor.visit_seq(JsonSeqAccess { de: &mut *self, len: Some(len), read: 0 });
+        self.depth -= 1;
+        let value = value?;
+        self.skip_trailing_comma(b']');
+        match self.peek() {
+            Some(b']') => {
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(b',') => Err(Error::custom(format!("invalid length, expected an array of {} elements", len))),
+            _ => Err(self.unexpected("`]`")),
+        }
+    }
+    
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.peek() != Some(b'{') {
+            return Err(self.invalid_type(&visitor));
+        }
+        self.enter()?;
+        let value = visitor.visit_map(JsonMapAccess {
+            de: &mut *self,
+            first: true,
+            key: String::new(),
+            seen: HashSet::new(),
+        });
+        self.depth -= 1;
+        let value = value?;
+        self.expect(b'}')?;
+        Ok(value)
+    }
+    
+    // Read past the value to find where it ends, then hand over its text.
+    // Lenient input may not be JSON, so it is written out again instead.
+    fn deserialize_raw_json<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.lenient {
+            let value = JsonValue::deserialize(&mut *self)?;
+            return visitor.visit_string(to_json(&value)?);
+        }
+        self.peek();
+        let start = self.pos;
+        JsonValue::deserialize(&mut *self)?;
+        visitor.visit_borrowed_str(&self.input[start..self.pos])
+    }
+}
+
+// Reads the elements of a JSON array, up to but not including the `]`. For
+// a tuple, `len` is the number of elements it must have.
+struct JsonSeqAccess<'a, 'de> {
+    de: &'a mut JsonDeserializer<'de>,
+    len: Option<usize>,
+    read: usize,
+}
+
+fn invalid_length(found: usize, len: usize) -> Error {
+    Error::custom(format!("invalid length {}, expected an array of {} elements", found, len))
+}
+
+impl<'de> SeqAccess<'de> for JsonSeqAccess<'_, 'de> {
+    type Error = Error;
+    
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        let first = self.read == 0;
+        if !first {
+            self.de.skip_trailing_comma(b']');
+        }
+        match self.de.peek() {
+            Some(b']') => {
+                return match self.len {
+                    Some(len) if self.read < len => Err(invalid_length(self.read, len)),
+                    _ => Ok(None),
+                }
+            }
+            Some(b',') if !first => self.de.pos += 1,
+            _ if !first => return Err(self.de.unexpected("`,` or `]`")),
+            _ => {}
+        }
+        self.read += 1;
+        let index = self.read - 1;
+        seed.deserialize(&mut *self.de).map(Some).map_err(|e| e.at_index(index))
+    }
+}
+
+// Reads the entries of a JSON object, up to but not including the `}`
+struct JsonMapAccess<'a, 'de> {
+    de: &'a mut JsonDeserializer<'de>,
+    first: bool,
+    // The current key, for the paths of errors in its value
+    key: String,
+    // Keys read so far, unless duplicates are simply passed on
+    seen: HashSet<String>,
+}
+
+impl<'de> MapAccess<'de> for JsonMapAccess<'_, 'de> {
+    type Error = Error;
+    
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        loop {
+            if !self.first {
+                self.de.skip_trailing_comma(b'}');
+            }
+            match self.de.peek() {
+                Some(b'}') => return Ok(None),
+                Some(b',') if !self.first => self.de.pos += 1,
+                _ if !self.first => return Err(self.de.unexpected("`,` or `}`")),
+                _ => {}
+            }
+            self.first = false;
+            self.de.peek();
+            let start = self.de.pos;
+            self.key = self.de.parse_key()?;
+            let skip = self.de.duplicate_keys.skip(&mut self.seen, &self.key);
+            // Errors point at the key
+            if skip.is_err() {
+                self.de.pos = start;
+            }
+            if skip? {
+                self.de.expect(b':')?;
+                JsonValue::deserialize(&mut *self.de).map_err(|e| e.at_key(&self.key))?;
+                continue;
+            }
+            self.de.pos = start;
+            self.de.in_key = true;
+            let key = seed.deserialize(&mut *self.de);
+            self.de.in_key = false;
+            return key.map(Some).map_err(|e| e.at_key(&self.key));
+        }
+    }
+    
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        self.de.expect(b':')?;
+        seed.deserialize(&mut *self.de).map_err(|e| e.at_key(&self.key))
+    }
+}
+
+// Helper function to deserialize from JSON
+pub fn from_json<'de, T: Deserialize<'de>>(input: &'de str) -> Result<T, Error> {
+    JsonDeserializer::new(input).parse()
+}
+
+// from_json and to_json under serde_json's names, so code written against
+// serde_json ports with fewer changes
+pub fn from_str<'de, T: Deserialize<'de>>(input: &'de str) -> Result<T, Error> {
+    from_json(input)
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    value.serialize(JsonSerializer::new())
+}
+
+// Deserialize JSON from a reader. The parser works on a complete string, so
+// the input is read into memory first; values can't borrow from it.
+pub fn from_reader<R: Read, T: DeserializeOwned>(mut reader: R) -> Result<T, Error> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    from_json(&input)
+}
+
+// Newline-delimited JSON: one value per line, as in logs and event streams.
+// Each value is serialized into a reused line buffer and written in one go.
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+    line: String,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        NdjsonWriter { writer, line: String::new() }
+    }
+    
+    // Write a value and the newline after it. Nothing is written for a
+    // value that fails to serialize.
+    pub fn write<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.line.clear();
+        value.serialize(JsonSerializer::with_buffer(&mut self.line))?;
+        // Only a RawJson fragment can hold a line break, and outside a
+        // string it is just whitespace
+        if self.line.contains(['\n', '\r']) {
+            self.line = self.line.replace(['\n', '\r'], " ");
+        }
+        self.line.push('\n');
+        Ok(self.writer.write_all(self.line.as_bytes())?)
+    }
+    
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.writer.flush()?)
+    }
+    
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+// Reads newline-delimited JSON a line at a time. Blank lines are skipped,
+// and a line that fails to parse doesn't stop the lines after it.
+pub struct NdjsonReader<R: Read> {
+    reader: BufReader<R>,
+    line: String,
+    // Lines and bytes read so far, for error positions
+    line_number: usize,
+    offset: usize,
+}
+
+impl<R: Read> NdjsonReader<R> {
+    pub fn new(reader: R) -> Self {
+        NdjsonReader { reader: BufReader::new(reader), line: String::new(), line_number: 0, offset: 0 }
+    }
+    
+    // The value on the next non-blank line, or None at the end of the input.
+    // Errors give the line and offset in the whole stream.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        loop {
+            self.line.clear();
+            let start = self.offset;
+            let len = self.reader.read_line(&mut self.line)?;
+            if len == 0 {
+                return Ok(None);
+            }
+            self.offset += len;
+            self.line_number += 1;
+            if !self.line.trim().is_empty() {
+                return from_json(&self.line).map(Some).map_err(|e| e.in_stream(self.line_number, start));
+            }
+        }
+    }
+    
+    // Iterate over the remaining values
+    pub fn values<T: DeserializeOwned>(&mut self) -> NdjsonValues<'_, R, T> {
+        NdjsonValues { reader: self, marker: PhantomData }
+    }
+}
+
+pub struct NdjsonValues<'a, R: Read, T> {
+    reader: &'a mut NdjsonReader<R>,
+    marker: PhantomData<T>,
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for NdjsonValues<'_, R, T> {
+    type Item = Result<T, Error>;
+    
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        self.reader.read().transpose()
+    }
+}
+
+// YAML support. Values are serialized into a YamlNode tree that is then
+// written in block style; documents are parsed into the same tree, which
+// drives the visitors.
+#[derive(Debug, Clone, PartialEq)]
+enum YamlNode {
+    Null,
+    // Unquoted scalar text; its type is resolved when it is deserialized
+    Plain(String),
+    // A scalar that is always a string
+    Str(String),
+    Seq(Vec<YamlNode>),
+    Map(Vec<(YamlNode, YamlNode)>),
+}
+
+// The type a plain scalar resolves to under the YAML core schema
+enum Resolved {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str,
+}
+
+fn resolve_plain(text: &str) -> Resolved {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Resolved::Null,
+        "true" | "True" | "TRUE" => return Resolved::Bool(true),
+        "false" | "False" | "FALSE" => return Resolved::Bool(false),
+        _ => {}
+    }
+    if let Some(v) = parse_yaml_float(text) {
+        if let Ok(v) = text.parse::<i64>() {
+            return Resolved::Int(v);
+        }
+        if let Ok(v) = text.parse::<u64>() {
+            return Resolved::UInt(v);
+        }
+        return Resolved::Float(v);
+    }
+    Resolved::Str
+}
+
+// Parse a YAML number, including .inf and .nan
+fn parse_yaml_float(text: &str) -> Option<f64> {
+    match text {
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => return Some(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => return Some(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => return Some(f64::NAN),
+        _ => {}
+    }
+    // Rust also accepts "inf" and "NaN", which YAML reads as strings
+    let numeric = text.bytes().any(|b| b.is_ascii_digit())
+        && text.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-'));
+    if numeric {
+        text.parse().ok()
+    } else {
+        None
+    }
+}
+
+// Whether a string can be written without quotes and still read back as the
+// same string
+fn is_plain_safe(s: &str) -> bool {
+    let first = match s.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+    let last = s.chars().last().unwrap();
+    let yaml_11_bool = ["y", "n", "yes", "no", "on", "off"]
+        .iter()
+        .any(|word| s.eq_ignore_ascii_case(word));
+    matches!(resolve_plain(s), Resolved::Str)
+        && !yaml_11_bool
+        && !"-?:,[]{}#&*!|>'\"%@`".contains(first)
+        && !first.is_whitespace()
+        && !last.is_whitespace()
+        && last != ':'
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.chars().any(char::is_control)
+}
+
+fn yaml_float(v: f64) -> String {
+    if v.is_nan() {
+        ".nan".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { ".inf" } else { "-.inf" }.to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+// Write a scalar or an empty collection on one line
+fn yaml_inline(node: &YamlNode) -> String {
+    match node {
+        YamlNode::Null => "null".to_string(),
+        YamlNode::Plain(text) => text.clone(),
+        YamlNode::Str(s) if is_plain_safe(s) => s.clone(),
+        YamlNode::Str(s) => escape_json(s),
+        YamlNode::Seq(_) => "[]".to_string(),
+        YamlNode::Map(_) => "{}".to_string(),
+    }
+}
+
+// Write `node` starting at the current position, which is at column
+// `indent`, ending with a newline
+fn write_yaml(node: &YamlNode, indent: usize, output: &mut String) {
+    match node {
+        YamlNode::Seq(items) if !items.is_empty() => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(&" ".repeat(indent));
+                }
+                output.push_str("- ");
+                write_yaml(item, indent + 2, output);
+            }
+        }
+        YamlNode::Map(entries) if !entries.is_empty() => {
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(&" ".repeat(indent));
+                }
+                output.push_str(&yaml_inline(key));
+                output.push(':');
+                match value {
+                    YamlNode::Seq(items) if !items.is_empty() => {
+                        output.push('\n');
+                        output.push_str(&" ".repeat(indent + 2));
+                        write_yaml(value, indent + 2, output);
+                    }
+                    YamlNode::Map(entries) if !entries.is_empty() => {
+                        output.push('\n');
+                        output.push_str(&" ".repeat(indent + 2));
+                        write_yaml(value, indent + 2, output);
+                    }
+                    _ => {
+                        output.push(' ');
+                        output.push_str(&yaml_inline(value));
+                        output.push('\n');
+                    }
+                }
+            }
+        }
+        _ => {
+            output.push_str(&yaml_inline(node));
+            output.push('\n');
+        }
+    }
+}
+
+fn render_yaml(node: &YamlNode) -> String {
+    let mut output = String::new();
+    write_yaml(node, 0, &mut output);
+    output
+}
+
+// Serializes a value into a YamlNode
+struct NodeSerializer;
+
+impl Serializer for NodeSerializer {
+    type Ok = YamlNode;
+    type Error = Error;
+    type SerializeSeq = NodeSeq;
+    type SerializeTuple = NodeSeq;
+    type SerializeMap = NodeMap;
+    type SerializeStruct = NodeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(yaml_float(f64::from(v))))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Plain(yaml_float(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Seq(v.iter().map(|b| YamlNode::Plain(b.to_string())).collect()))
+    }
+
+    fn serialize_unit(self) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Null)
+    }
+
+    fn serialize_none(self) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Null)
+    }
+
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<YamlNode, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<NodeSeq, Error> {
+        Ok(NodeSeq(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<NodeSeq, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<NodeMap, Error> {
+        Ok(NodeMap {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<NodeMap, Error> {
+        self.serialize_map(Some(len))
+    }
+}
+
+struct NodeSeq(Vec<YamlNode>);
+
+impl SerializeSeq for NodeSeq {
+    type Ok = YamlNode;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.push(value.serialize(NodeSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Seq(self.0))
+    }
+}
+
+impl SerializeTuple for NodeSeq {
+    type Ok = YamlNode;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<YamlNode, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct NodeMap {
+    entries: Vec<(YamlNode, YamlNode)>,
+    key: Option<YamlNode>,
+}
+
+impl SerializeMap for NodeMap {
+    type Ok = YamlNode;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        let key = key.serialize(NodeSerializer)?;
+        if matches!(&key, YamlNode::Seq(_) | YamlNode::Map(_)) {
+            return Err(Error::custom("YAML map keys must be scalars".to_string()));
+        }
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        if let Some(key) = self.key.take() {
+            self.entries.push((key, value.serialize(NodeSerializer)?));
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<YamlNode, Error> {
+        Ok(YamlNode::Map(self.entries))
+    }
+}
+
+impl SerializeStruct for NodeMap {
+    type Ok = YamlNode;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.entries.push((YamlNode::Str(key.to_string()), value.serialize(NodeSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<YamlNode, Error> {
+        SerializeMap::end(self)
+    }
+}
+
+// YAML Serializer implementation. Each method builds the value's node and
+// writes it as a block-style document.
+pub struct YamlSerializer;
+
+impl YamlSerializer {
+    pub fn new() -> Self {
+        YamlSerializer
+    }
+}
+
+impl Default for YamlSerializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+macro_rules! yaml_scalar_methods {
+    ($($method:ident($($arg:ident: $ty:ty)?);)*) => {
+        $(
+            fn $method(self, $($arg: $ty)?) -> Result<String, Error> {
+                NodeSerializer.$method($($arg)?).map(|node| render_yaml(&node))
+            }
+        )*
+    };
+}
+
+impl Serializer for YamlSerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = YamlSeqSerializer;
+    type SerializeTuple = YamlSeqSerializer;
+    type SerializeMap = YamlMapSerializer;
+    type SerializeStruct = YamlMapSerializer;
+
+    yaml_scalar_methods! {
+        serialize_bool(v: bool);
+        serialize_i8(v: i8);
+        serialize_i16(v: i16);
+        serialize_i32(v: i32);
+        serialize_i64(v: i64);
+        serialize_i128(v: i128);
+        serialize_u8(v: u8);
+        serialize_u16(v: u16);
+        serialize_u32(v: u32);
+        serialize_u64(v: u64);
+        serialize_u128(v: u128);
+        serialize_f32(v: f32);
+        serialize_f64(v: f64);
+        serialize_char(v: char);
+        serialize_str(v: &str);
+        serialize_bytes(v: &[u8]);
+        serialize_unit();
+        serialize_none();
+    }
+
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<YamlSeqSerializer, Error> {
+        Ok(YamlSeqSerializer(NodeSerializer.serialize_seq(len)?))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<YamlSeqSerializer, Error> {
+        Ok(YamlSeqSerializer(NodeSerializer.serialize_tuple(len)?))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<YamlMapSerializer, Error> {
+        Ok(YamlMapSerializer(NodeSerializer.serialize_map(len)?))
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<YamlMapSerializer, Error> {
+        Ok(YamlMapSerializer(NodeSerializer.serialize_struct(name, len)?))
+    }
+}
+
+pub struct YamlSeqSerializer(NodeSeq);
+
+impl SerializeSeq for YamlSeqSerializer {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(&mut self.0, value)
+    }
+
+    fn end(self) -> Result<String, Error> {
+        SerializeSeq::end(self.0).map(|node| render_yaml(&node))
+    }
+}
+
+impl SerializeTuple for YamlSeqSerializer {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(&mut self.0, value)
+    }
+
+    fn end(self) -> Result<String, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+pub struct YamlMapSerializer(NodeMap);
+
+impl SerializeMap for YamlMapSerializer {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.0.serialize_key(key)
+    }
+
+    fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.serialize_value(value)
+    }
+
+    fn end(self) -> Result<String, Error> {
+        SerializeMap::end(self.0).map(|node| render_yaml(&node))
+    }
+}
+
+impl SerializeStruct for YamlMapSerializer {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.0.serialize_field(key, value)
+    }
+
+    fn end(self) -> Result<String, Error> {
+        SerializeMap::end(self)
+    }
+}
+
+// Helper function to serialize to YAML
+pub fn to_yaml<T: Serialize>(value: &T) -> Result<String, Error> {
+    value.serialize(YamlSerializer::new())
+}
+
+// One non-blank line of a YAML document, without its comment
+struct YamlLine {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+// Parses block-style YAML: mappings, sequences, scalars and flow
+// collections on a single line
+struct YamlParser {
+    lines: Vec<YamlLine>,
+    pos: usize,
+    // How many sequences and mappings enclose the current line
+    depth: usize,
+}
+
+fn yaml_error(line: usize, message: &str) -> Error {
+    Error::custom(format!("line {}: {}", line, message))
+}
+
+fn parse_yaml(input: &str) -> Result<YamlNode, Error> {
+    let mut lines = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let text = strip_yaml_comment(line);
+        let trimmed = text.trim_start_matches(' ');
+        if trimmed.starts_with('\t') {
+            return Err(yaml_error(i + 1, "tabs are not allowed in indentation"));
+        }
+        let trimmed = trimmed.trim_end();
+        if trimmed.is_empty() || trimmed == "---" || trimmed == "..." {
+            continue;
+        }
+        lines.push(YamlLine {
+            number: i + 1,
+            indent: text.len() - text.trim_start_matches(' ').len(),
+            text: trimmed.to_string(),
+        });
+    }
+    if lines.is_empty() {
+        return Ok(YamlNode::Null);
+    }
+    let indent = lines[0].indent;
+    let mut parser = YamlParser {
+        lines,
+        pos: 0,
+        depth: 0,
+    };
+    let node = parser.block(indent)?;
+    match parser.lines.get(parser.pos) {
+        Some(line) => Err(yaml_error(line.number, "unexpected indentation")),
+        None => Ok(node),
+    }
+}
+
+// Remove a comment: a `#` at the start or after a space or tab, outside
+// quotes. Other Unicode whitespace, such as U+2028, is part of the text.
+fn strip_yaml_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if c == '\\' && previous != '\\' => {}
+            Some(q) if c == q && !(q == '"' && previous == '\\') => quote = None,
+            Some(_) => {}
+            None if (c == '"' || c == '\'') && (previous == ' ' || "[{,:-".contains(previous)) => {
+                quote = Some(c)
+            }
+            None if c == '#' && matches!(previous, ' ' | '\t') => return &line[..i],
+            None => {}
+        }
+        // An escaped backslash doesn't escape what follows it
+        previous = if previous == '\\' && c == '\\' { '\0' } else { c };
+    }
+    line
+}
+
+fn is_seq_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+// Split `key: value` at the first `:` outside quotes that is followed by a
+// space or ends the line
+fn split_yaml_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with('[') || text.starts_with('{') {
+        return None;
+    }
+    let mut quote = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some('"') if c == '\\' => {
+                chars.next();
+            }
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if i == 0 && (c == '"' || c == '\'') => quote = Some(c),
+            None if c == ':' && chars.peek().is_none_or(|&(_, next)| next == ' ') => {
+                return Some((&text[..i], text[i + 1..].trim()));
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+impl YamlParser {
+    fn block(&mut self, indent: usize) -> Result<YamlNode, Error> {
+        let line = &self.lines[self.pos];
+        if is_seq_item(&line.text) {
+            self.sequence(indent)
+        } else if split_yaml_key(&line.text).is_some() {
+            self.mapping(indent)
+        } else {
+            let number = line.number;
+            let text = line.text.clone();
+            self.pos += 1;
+            parse_yaml_inline(&text, number, self.depth)
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), Error> {
+        if self.depth >= DEFAULT_MAX_DEPTH {
+            return Err(depth_exceeded(DEFAULT_MAX_DEPTH));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // The block below a `key:` or `-` with nothing after it, if any
+    fn nested(&mut self, indent: usize) -> Result<YamlNode, Error> {
+        match self.lines.get(self.pos) {
+            Some(line) if line.indent > indent => {
+                let indent = line.indent;
+                self.block(indent)
+            }
+            _ => Ok(YamlNode::Null),
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<YamlNode, Error> {
+        self.enter()?;
+        let mut items = Vec::new();
+        while let Some(line) = self.lines.get_mut(self.pos) {
+            if line.indent != indent || !is_seq_item(&line.text) {
+                break;
+            }
+            let rest = line.text[1..].trim_start().to_string();
+            if rest.is_empty() {
+                self.pos += 1;
+                items.push(self.nested(indent)?);
+            } else {
+                // Parse what follows the dash as a block at its own column,
+                // so `- key: value` starts a mapping
+                let column = indent + line.text.len() - rest.len();
+                line.indent = column;
+                line.text = rest;
+                items.push(self.block(column)?);
+            }
+        }
+        self.depth -= 1;
+        Ok(YamlNode::Seq(items))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<YamlNode, Error> {
+        self.enter()?;
+        let mut entries = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.indent != indent {
+                break;
+            }
+            let number = line.number;
+            let (key, value) = match split_yaml_key(&line.text) {
+                Some((key, value)) if !is_seq_item(&line.text) => (key.to_string(), value.to_string()),
+                _ => return Err(yaml_error(number, "expected `key: value`")),
+            };
+            self.pos += 1;
+            let key = parse_yaml_inline(&key, number, self.depth)?;
+            let value = if !value.is_empty() {
+                parse_yaml_inline(&value, number, self.depth)?
+            } else {
+                match self.lines.get(self.pos) {
+                    // A sequence may sit at the same indentation as its key
+                    Some(next) if next.indent == indent && is_seq_item(&next.text) => {
+                        self.sequence(indent)?
+                    }
+                    _ => self.nested(indent)?,
+                }
+            };
+            entries.push((key, value));
+        }
+        self.depth -= 1;
+        Ok(YamlNode::Map(entries))
+    }
+}
+
+// Parse a value written on line `number`: a quoted or plain scalar, or a
+// flow collection such as `[1, 2]` or `{a: 1}`, nested `depth` levels deep
+fn parse_yaml_inline(text: &str, number: usize, depth: usize) -> Result<YamlNode, Error> {
+    let mut flow = YamlFlow {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: number,
+        depth,
+    };
+    let node = match flow.peek() {
+        Some('[') | Some('{') | Some('"') | Some('\'') => flow.value(false)?,
+        _ => return Ok(YamlNode::Plain(text.trim().to_string())),
+    };
+    flow.skip_spaces();
+    match flow.peek() {
+        None => Ok(node),
+        Some(c) => Err(flow.error(&format!("unexpected `{}` after value", c))),
+    }
+}
+
+struct YamlFlow {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    depth: usize,
+}
+
+impl YamlFlow {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        yaml_error(self.line, message)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        self.skip_spaces();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c)))
+        }
+    }
+
+    // A value inside a flow collection; plain scalars end at `,`, `]` or
+    // `}`, and at `:` when `key` is set
+    fn value(&mut self, key: bool) -> Result<YamlNode, Error> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(c @ ('[' | '{')) => {
+                if self.depth >= DEFAULT_MAX_DEPTH {
+                    return Err(depth_exceeded(DEFAULT_MAX_DEPTH));
+                }
+                self.depth += 1;
+                self.pos += 1;
+                let node = if c == '[' { self.sequence() } else { self.mapping() };
+                self.depth -= 1;
+                node
+            }
+            Some('"') => self.double_quoted().map(YamlNode::Str),
+            Some('\'') => self.single_quoted().map(YamlNode::Str),
+            _ => {
+                let start = self.pos;
+                while let Some(c) = self.peek() {
+                    if c == ',' || c == ']' || c == '}' || (key && c == ':') {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                Ok(YamlNode::Plain(text.trim().to_string()))
+            }
+        }
+    }
+
+    // The rest of a `[...]` sequence, after its `[`
+    fn sequence(&mut self) -> Result<YamlNode, Error> {
+        let mut items = Vec::new();
+        self.skip_spaces();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(YamlNode::Seq(items));
+        }
+        loop {
+            items.push(self.value(false)?);
+            self.skip_spaces();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(YamlNode::Seq(items));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    // The rest of a `{...}` mapping, after its `{`
+    fn mapping(&mut self) -> Result<YamlNode, Error> {
+        let mut entries = Vec::new();
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(YamlNode::Map(entries));
+        }
+        loop {
+            let key = self.value(true)?;
+            self.expect(':')?;
+            entries.push((key, self.value(false)?));
+            self.skip_spaces();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(YamlNode::Map(entries));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn single_quoted(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut output = String::new();
+        loop {
+            match self.peek() {
+                Some('\'') if self.chars.get(self.pos + 1) == Some(&'\'') => {
+                    output.push('\'');
+                    self.pos += 2;
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    return Ok(output);
+                }
+                Some(c) => {
+                    output.push(c);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("unterminated single-quoted string")),
+            }
+        }
+    }
+
+    fn double_quoted(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut output = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated double-quoted string"))?;
+            self.pos += 1;
+            match c {
+                '"' => return Ok(output),
+                '\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated double-quoted string"))?;
+                    self.pos += 1;
+                    let decoded = match escape {
+                        '0' => '\0',
+                        'a' => '\u{7}',
+                        'b' => '\u{8}',
+                        't' => '\t',
+                        'n' => '\n',
+                        'v' => '\u{b}',
+                        'f' => '\u{c}',
+                        'r' => '\r',
+                        'e' => '\u{1b}',
+                        ' ' => ' ',
+                        '"' => '"',
+                        '/' => '/',
+                        '\\' => '\\',
+                        'x' => self.hex(2)?,
+                        'u' => self.hex(4)?,
+                        'U' => self.hex(8)?,
+                        other => return Err(self.error(&format!("unknown escape `\\{}`", other))),
+                    };
+                    output.push(decoded);
+                }
+                c => output.push(c),
+            }
+        }
+    }
+
+    fn hex(&mut self, digits: usize) -> Result<char, Error> {
+        let text: String = self.chars.iter().skip(self.pos).take(digits).collect();
+        let code = match u32::from_str_radix(&text, 16) {
+            Ok(code) if text.len() == digits && text.chars().all(|c| c.is_ascii_hexdigit()) => code,
+            _ => return Err(self.error(&format!("expected {} hex digits", digits))),
+        };
+        self.pos += digits;
+        char::from_u32(code).ok_or_else(|| self.error(&format!("invalid character code {:x}", code)))
+    }
+}
+
+impl YamlNode {
+    fn describe(&self) -> &'static str {
+        match self {
+            YamlNode::Null => "null",
+            YamlNode::Plain(text) => match resolve_plain(text) {
+                Resolved::Null => "null",
+                Resolved::Bool(_) => "boolean",
+                Resolved::Int(_) | Resolved::UInt(_) | Resolved::Float(_) => "number",
+                Resolved::Str => "string",
+            },
+            YamlNode::Str(_) => "string",
+            YamlNode::Seq(_) => "sequence",
+            YamlNode::Map(_) => "map",
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        match self {
+            YamlNode::Null => true,
+            YamlNode::Plain(text) => matches!(resolve_plain(text), Resolved::Null),
+            _ => false,
+        }
+    }
+
+    fn invalid_type<'de, V: Visitor<'de>>(&self, visitor: &V) -> Error {
+        Error::custom(format!(
+            "invalid type: {}, expected {}",
+            self.describe(),
+            expected(visitor)
+        ))
+    }
+
+    // Parse a plain scalar as the number type a visitor asked for
+    fn number<'de, T: FromStr, V: Visitor<'de>>(self, visitor: &V) -> Result<T, Error> {
+        match self {
+            YamlNode::Plain(ref text) if !matches!(resolve_plain(text), Resolved::Str) => {
+                text.parse().map_err(|_| {
+                    Error::custom(format!("invalid value: {}, expected {}", text, expected(visitor)))
+                })
+            }
+            other => Err(other.invalid_type(visitor)),
+        }
+    }
+
+    fn float<'de, V: Visitor<'de>>(self, visitor: &V) -> Result<f64, Error> {
+        match self {
+            YamlNode::Plain(ref text) => match parse_yaml_float(text) {
+                Some(v) => Ok(v),
+                None => Err(self.invalid_type(visitor)),
+            },
+            other => Err(other.invalid_type(visitor)),
+        }
+    }
+
+    // Any scalar as text, so `version: 1.0` reads into a String
+    fn text<'de, V: Visitor<'de>>(self, visitor: &V) -> Result<String, Error> {
+        match self {
+            YamlNode::Plain(text) | YamlNode::Str(text) => Ok(text),
+            other => Err(other.invalid_type(visitor)),
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for YamlNode {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            YamlNode::Null => visitor.visit_none(),
+            YamlNode::Str(s) => visitor.visit_string(s),
+            YamlNode::Seq(items) => visitor.visit_seq(NodeSeqAccess {
+                items: items.into_iter(),
+                index: 0,
+            }),
+            YamlNode::Map(entries) => visitor.visit_map(NodeMapAccess {
+                entries: entries.into_iter(),
+                value: None,
+                key: String::new(),
+            }),
+            YamlNode::Plain(text) => match resolve_plain(&text) {
+                Resolved::Null => visitor.visit_none(),
+                Resolved::Bool(v) => visitor.visit_bool(v),
+                Resolved::Int(v) => visitor.visit_i64(v),
+                Resolved::UInt(v) => visitor.visit_u64(v),
+                Resolved::Float(v) => visitor.visit_f64(v),
+                Resolved::Str => visitor.visit_string(text),
+            },
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            YamlNode::Plain(ref text) => match resolve_plain(text) {
+                Resolved::Bool(v) => visitor.visit_bool(v),
+                _ => Err(self.invalid_type(&visitor)),
+            },
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_i8(v)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_i16(v)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_i32(v)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_i64(v)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_i128(v)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_u8(v)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_u16(v)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_u32(v)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_u64(v)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?;
+        visitor.visit_u128(v)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.float(&visitor)?;
+        visitor.visit_f32(v as f32)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.float(&visitor)?;
+        visitor.visit_f64(v)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.text(&visitor)?;
+        let mut chars = v.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::custom(format!(
+                "invalid value: string {:?}, expected {}",
+                v,
+                expected(&visitor)
+            ))),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.text(&visitor)?;
+        visitor.visit_str(&v)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.text(&visitor)?;
+        visitor.visit_string(v)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            YamlNode::Seq(_) => self.deserialize_seq(visitor),
+            other => {
+                let v = other.text(&visitor)?;
+                visitor.visit_byte_buf(v.into_bytes())
+            }
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.is_null() {
+            visitor.visit_unit()
+        } else {
+            Err(self.invalid_type(&visitor))
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.is_null() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            YamlNode::Seq(items) => visitor.visit_seq(NodeSeqAccess {
+                items: items.into_iter(),
+                index: 0,
+            }),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            YamlNode::Seq(ref items) if items.len() != len => Err(Error::custom(format!(
+                "invalid length {}, expected a sequence of {} elements",
+                items.len(),
+                len
+            ))),
+            other => other.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            YamlNode::Map(entries) => visitor.visit_map(NodeMapAccess {
+                entries: entries.into_iter(),
+                value: None,
+                key: String::new(),
+            }),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+}
+
+struct NodeSeqAccess {
+    items: std::vec::IntoIter<YamlNode>,
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for NodeSeqAccess {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        let index = self.index;
+        self.index += 1;
+        self.items
+            .next()
+            .map(|item| seed.deserialize(item).map_err(|e| e.at_index(index)))
+            .transpose()
+    }
+}
+
+struct NodeMapAccess {
+    entries: std::vec::IntoIter<(YamlNode, YamlNode)>,
+    value: Option<YamlNode>,
+    key: String,
+}
+
+impl<'de> MapAccess<'de> for NodeMapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                self.key = yaml_inline(&key);
+                seed.deserialize(key).map(Some).map_err(|e| e.at_key(&self.key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value).map_err(|e| e.at_key(&self.key)),
+            None => Err(Error::custom("value requested before key".to_string())),
+        }
+    }
+}
+
+// YAML Deserializer implementation. The document is parsed when the first
+// deserialize method is called.
+pub struct YamlDeserializer<'de> {
+    input: &'de str,
+}
+
+impl<'de> YamlDeserializer<'de> {
+    pub fn new(input: &'de str) -> Self {
+        YamlDeserializer { input }
+    }
+}
+
+macro_rules! yaml_deserialize_methods {
+    ($($method:ident($($len:ident)?);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($len: usize,)? visitor: V) -> Result<V::Value, Error> {
+                parse_yaml(self.input)?.$method($($len,)? visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for YamlDeserializer<'de> {
+    type Error = Error;
+
+    yaml_deserialize_methods! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_unit();
+        deserialize_option();
+        deserialize_seq();
+        deserialize_tuple(len);
+        deserialize_map();
+    }
+}
+
+// Helper function to deserialize from YAML
+pub fn from_yaml<'de, T: Deserialize<'de>>(input: &'de str) -> Result<T, Error> {
+    T::deserialize(YamlDeserializer::new(input))
+}
+
+// CSV support. Each record is serialized through the map/struct path into a
+// YamlNode, so field names become the header row; rows are read back into
+// the same node shape and deserialized as maps.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn csv_scalar(record: usize, key: &str, node: &YamlNode) -> Result<String, Error> {
+    match node {
+        YamlNode::Null => Ok(String::new()),
+        YamlNode::Plain(text) | YamlNode::Str(text) => Ok(text.clone()),
+        _ => Err(Error::custom(format!(
+            "record {}: field `{}` is not a scalar",
+            record, key
+        ))),
+    }
+}
+
+fn write_csv_row<'a>(fields: impl Iterator<Item = &'a str>, output: &mut String) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        output.push_str(&csv_field(field));
+    }
+    output.push('\n');
+}
+
+// Serialize records as CSV with a header row taken from the first record's
+// field names. Every record must be a struct or map with the same fields.
+pub fn to_csv<T: Serialize>(records: &[T]) -> Result<String, Error> {
+    let mut output = String::new();
+    let mut header: Vec<String> = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        let entries = match record.serialize(NodeSerializer)? {
+            YamlNode::Map(entries) => entries,
+            _ => {
+                return Err(Error::custom(format!(
+                    "record {}: CSV records must be structs or maps",
+                    i + 1
+                )))
+            }
+        };
+        let mut row = Vec::with_capacity(entries.len());
+        for (key, value) in &entries {
+            let key = csv_scalar(i + 1, "key", key)?;
+            let value = csv_scalar(i + 1, &key, value)?;
+            row.push((key, value));
+        }
+        if i == 0 {
+            header = row.iter().map(|(key, _)| key.clone()).collect();
+            write_csv_row(header.iter().map(String::as_str), &mut output);
+        }
+        // Match fields by name, since map records may list them in any order
+        let mut fields = Vec::with_capacity(header.len());
+        for key in &header {
+            match row.iter().find(|(k, _)| k == key) {
+                Some((_, value)) => fields.push(value.as_str()),
+                None => {
+                    return Err(Error::custom(format!(
+                        "record {}: missing field `{}`",
+                        i + 1,
+                        key
+                    )))
+                }
+            }
+        }
+        if row.len() != header.len() {
+            return Err(Error::custom(format!(
+                "record {}: has {} fields, expected {}",
+                i + 1,
+                row.len(),
+                header.len()
+            )));
+        }
+        write_csv_row(fields.into_iter(), &mut output);
+    }
+    Ok(output)
+}
+
+// Split CSV text into rows of fields, each with the line it starts on.
+// Quoted fields may contain commas, doubled quotes and newlines.
+fn parse_csv_rows(input: &str) -> Result<Vec<(usize, Vec<String>)>, Error> {
+    let mut rows = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        loop {
+            match chars.next() {
+                Some('"') if field.is_empty() => {
+                    loop {
+                        match chars.next() {
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                field.push('"');
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                field.push(c);
+                            }
+                            None => {
+                                return Err(Error::custom(format!(
+                                    "line {}: unterminated quoted field",
+                                    start
+                                )))
+                            }
+                        }
+                    }
+                    match chars.peek() {
+                        None | Some(',') | Some('\n') | Some('\r') => {}
+                        Some(c) => {
+                            return Err(Error::custom(format!(
+                                "line {}: unexpected `{}` after quoted field",
+                                line, c
+                            )))
+                        }
+                    }
+                }
+                Some(',') => fields.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line += 1;
+                    fields.push(field);
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        // Blank lines separate nothing in CSV; skip them
+        if fields.len() > 1 || !fields[0].is_empty() {
+            rows.push((start, fields));
+        }
+    }
+    Ok(rows)
+}
+
+// Deserialize CSV text into one value per row. The first row is the header;
+// each following row is presented as a map from header names to fields, and
+// empty fields read as null.
+pub fn from_csv<'de, T: Deserialize<'de>>(input: &str) -> Result<Vec<T>, Error> {
+    let mut rows = parse_csv_rows(input)?.into_iter();
+    let header = match rows.next() {
+        Some((_, header)) => header,
+        None => return Ok(Vec::new()),
+    };
+    let mut records = Vec::new();
+    for (line, fields) in rows {
+        if fields.len() != header.len() {
+            return Err(Error::custom(format!(
+                "line {}: expected {} fields, found {}",
+                line,
+                header.len(),
+                fields.len()
+            )));
+        }
+        let entries = header
+            .iter()
+            .zip(fields)
+            .map(|(key, field)| {
+                let value = if field.is_empty() {
+                    YamlNode::Null
+                } else {
+                    YamlNode::Plain(field)
+                };
+                (YamlNode::Str(key.clone()), value)
+            })
+            .collect();
+        let record = T::deserialize(YamlNode::Map(entries))
+            .map_err(|e| Error::custom(format!("line {}: {}", line, e)))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// MessagePack support, following the type tags of the MessagePack spec so
+// the bytes can be read by other MessagePack implementations.
+fn msgpack_int(output: &mut Vec<u8>, v: i128) -> Result<(), Error> {
+    match v {
+        0..=0x7f => output.push(v as u8),
+        -32..=-1 => output.push(v as i8 as u8),
+        0x80..=0xff => output.extend_from_slice(&[0xcc, v as u8]),
+        0x100..=0xffff => {
+            output.push(0xcd);
+            output.extend_from_slice(&(v as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            output.push(0xce);
+            output.extend_from_slice(&(v as u32).to_be_bytes());
+        }
+        0x1_0000_0000..=0xffff_ffff_ffff_ffff => {
+            output.push(0xcf);
+            output.extend_from_slice(&(v as u64).to_be_bytes());
+        }
+        -0x80..=-33 => output.extend_from_slice(&[0xd0, v as i8 as u8]),
+        -0x8000..=-0x81 => {
+            output.push(0xd1);
+            output.extend_from_slice(&(v as i16).to_be_bytes());
+        }
+        -0x8000_0000..=-0x8001 => {
+            output.push(0xd2);
+            output.extend_from_slice(&(v as i32).to_be_bytes());
+        }
+        -0x8000_0000_0000_0000..=-0x8000_0001 => {
+            output.push(0xd3);
+            output.extend_from_slice(&(v as i64).to_be_bytes());
+        }
+        _ => {
+            return Err(Error::custom(format!(
+                "integer {} is out of range for MessagePack",
+                v
+            )))
+        }
+    }
+    Ok(())
+}
+
+// Write the marker for a str, bin, array or map of `len` items. `fix` is
+// the tag of the short form and `tags` those of the 8, 16 and 32-bit forms.
+fn msgpack_header(output: &mut Vec<u8>, len: usize, fix: Option<(u8, usize)>, tags: [Option<u8>; 3]) -> Result<(), Error> {
+    match (fix, tags) {
+        (Some((tag, max)), _) if len <= max => output.push(tag | len as u8),
+        (_, [Some(tag), _, _]) if len <= 0xff => output.extend_from_slice(&[tag, len as u8]),
+        (_, [_, Some(tag), _]) if len <= 0xffff => {
+            output.push(tag);
+            output.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        (_, [_, _, Some(tag)]) if len <= 0xffff_ffff => {
+            output.push(tag);
+            output.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+        _ => return Err(Error::custom(format!("length {} is too large for MessagePack", len))),
+    }
+    Ok(())
+}
+
+fn msgpack_str(output: &mut Vec<u8>, v: &str) -> Result<(), Error> {
+    msgpack_header(output, v.len(), Some((0xa0, 31)), [Some(0xd9), Some(0xda), Some(0xdb)])?;
+    output.extend_from_slice(v.as_bytes());
+    Ok(())
+}
+
+// MessagePack Serializer implementation
+pub struct MsgPackSerializer {
+    output: Vec<u8>,
+}
+
+impl MsgPackSerializer {
+    pub fn new() -> Self {
+        MsgPackSerializer { output: Vec::new() }
+    }
+
+    fn int(mut self, v: i128) -> Result<Vec<u8>, Error> {
+        msgpack_int(&mut self.output, v)?;
+        Ok(self.output)
+    }
+}
+
+impl Default for MsgPackSerializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serializer for MsgPackSerializer {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    type SerializeSeq = MsgPackSeqSerializer;
+    type SerializeTuple = MsgPackSeqSerializer;
+    type SerializeMap = MsgPackMapSerializer;
+    type SerializeStruct = MsgPackMapSerializer;
+
+    fn serialize_bool(mut self, v: bool) -> Result<Vec<u8>, Error> {
+        self.output.push(if v { 0xc3 } else { 0xc2 });
+        Ok(self.output)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Vec<u8>, Error> {
+        self.int(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Vec<u8>, Error> {
+        self.int(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Vec<u8>, Error> {
+        self.int(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Vec<u8>, Error> {
+        self.int(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Vec<u8>, Error> {
+        self.int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Vec<u8>, Error> {
+        self.int(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Vec<u8>, Error> {
+        self.int(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Vec<u8>, Error> {
+        self.int(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Vec<u8>, Error> {
+        self.int(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Vec<u8>, Error> {
+        match i128::try_from(v) {
+            Ok(v) => self.int(v),
+            Err(_) => Err(Error::custom(format!("integer {} is out of range for MessagePack", v))),
+        }
+    }
+
+    fn serialize_f32(mut self, v: f32) -> Result<Vec<u8>, Error> {
+        self.output.push(0xca);
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(self.output)
+    }
+
+    fn serialize_f64(mut self, v: f64) -> Result<Vec<u8>, Error> {
+        self.output.push(0xcb);
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(self.output)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Vec<u8>, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(mut self, v: &str) -> Result<Vec<u8>, Error> {
+        msgpack_str(&mut self.output, v)?;
+        Ok(self.output)
+    }
+
+    fn serialize_bytes(mut self, v: &[u8]) -> Result<Vec<u8>, Error> {
+        msgpack_header(&mut self.output, v.len(), None, [Some(0xc4), Some(0xc5), Some(0xc6)])?;
+        self.output.extend_from_slice(v);
+        Ok(self.output)
+    }
+
+    fn serialize_unit(mut self) -> Result<Vec<u8>, Error> {
+        self.output.push(0xc0);
+        Ok(self.output)
+    }
+
+    fn serialize_none(self) -> Result<Vec<u8>, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<Vec<u8>, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<MsgPackSeqSerializer, Error> {
+        // The length is written by `end`, once every element has been counted
+        Ok(MsgPackSeqSerializer {
+            output: self.output,
+            items: Vec::new(),
+            len: 0,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<MsgPackSeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MsgPackMapSerializer, Error> {
+        Ok(MsgPackMapSerializer {
+            output: self.output,
+            entries: Vec::new(),
+            len: 0,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MsgPackMapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+}
+
+pub struct MsgPackSeqSerializer {
+    output: Vec<u8>,
+    items: Vec<u8>,
+    len: usize,
+}
+
+impl SerializeSeq for MsgPackSeqSerializer {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.extend(to_msgpack(value)?);
+        self.len += 1;
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<Vec<u8>, Error> {
+        msgpack_header(&mut self.output, self.len, Some((0x90, 15)), [None, Some(0xdc), Some(0xdd)])?;
+        self.output.extend(self.items);
+        Ok(self.output)
+    }
+}
+
+impl SerializeTuple for MsgPackSeqSerializer {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+pub struct MsgPackMapSerializer {
+    output: Vec<u8>,
+    entries: Vec<u8>,
+    len: usize,
+}
+
+impl SerializeMap for MsgPackMapSerializer {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.entries.extend(to_msgpack(key)?);
+        self.len += 1;
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.entries.extend(to_msgpack(value)?);
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<Vec<u8>, Error> {
+        msgpack_header(&mut self.output, self.len, Some((0x80, 15)), [None, Some(0xde), Some(0xdf)])?;
+        self.output.extend(self.entries);
+        Ok(self.output)
+    }
+}
+
+impl SerializeStruct for MsgPackMapSerializer {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        msgpack_str(&mut self.entries, key)?;
+        self.len += 1;
+        self.serialize_value(value)
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        SerializeMap::end(self)
+    }
+}
+
+// Helper function to serialize to MessagePack
+pub fn to_msgpack<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    value.serialize(MsgPackSerializer::new())
+}
+
+// A decoded MessagePack marker. Scalars carry their value; strings, binary,
+// arrays and maps carry their length and are followed by their contents.
+enum Marker {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(usize),
+    Bin(usize),
+    Array(usize),
+    Map(usize),
+}
+
+impl Marker {
+    fn describe(&self) -> &'static str {
+        match self {
+            Marker::Nil => "nil",
+            Marker::Bool(_) => "boolean",
+            Marker::Int(_) | Marker::UInt(_) => "integer",
+            Marker::Float(_) => "float",
+            Marker::Str(_) => "string",
+            Marker::Bin(_) => "binary",
+            Marker::Array(_) => "array",
+            Marker::Map(_) => "map",
+        }
+    }
+}
+
+// MessagePack Deserializer implementation
+pub struct MsgPackDeserializer<'de> {
+    input: &'de [u8],
+    pos: usize,
+    depth: usize,
+    max_depth: usize,
+    duplicate_keys: DuplicateKeys,
+}
+
+impl<'de> MsgPackDeserializer<'de> {
+    pub fn new(input: &'de [u8]) -> Self {
+        MsgPackDeserializer {
+            input,
+            pos: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            duplicate_keys: DuplicateKeys::default(),
+        }
+    }
+
+    // Only string and integer keys are compared
+    pub fn duplicate_keys(mut self, policy: DuplicateKeys) -> Self {
+        self.duplicate_keys = policy;
+        self
+    }
+
+    // The deepest nesting of arrays and maps to read before failing
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Check that the whole input was consumed
+    pub fn end(&self) -> Result<(), Error> {
+        if self.pos == self.input.len() {
+            Ok(())
+        } else {
+            Err(Error::custom(format!(
+                "trailing bytes at offset {}",
+                self.pos
+            )))
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'de [u8], Error> {
+        match self.input.get(self.pos..self.pos + len) {
+            Some(bytes) => {
+                self.pos += len;
+                Ok(bytes)
+            }
+            None => Err(Error::custom("unexpected end of input".to_string())),
+        }
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+
+    fn length(&mut self, size: usize) -> Result<usize, Error> {
+        Ok(match size {
+            1 => self.take_array::<1>()?[0] as usize,
+            2 => u16::from_be_bytes(self.take_array()?) as usize,
+            _ => u32::from_be_bytes(self.take_array()?) as usize,
+        })
+    }
+
+    fn marker(&mut self) -> Result<Marker, Error> {
+        let offset = self.pos;
+        let tag = self.take_array::<1>()?[0];
+        Ok(match tag {
+            0x00..=0x7f => Marker::UInt(tag.into()),
+            0x80..=0x8f => Marker::Map((tag & 0x0f).into()),
+            0x90..=0x9f => Marker::Array((tag & 0x0f).into()),
+            0xa0..=0xbf => Marker::Str((tag & 0x1f).into()),
+            0xc0 => Marker::Nil,
+            0xc2 => Marker::Bool(false),
+            0xc3 => Marker::Bool(true),
+            0xc4 => Marker::Bin(self.length(1)?),
+            0xc5 => Marker::Bin(self.length(2)?),
+            0xc6 => Marker::Bin(self.length(4)?),
+            0xca => Marker::Float(f32::from_be_bytes(self.take_array()?).into()),
+            0xcb => Marker::Float(f64::from_be_bytes(self.take_array()?)),
+            0xcc => Marker::UInt(self.take_array::<1>()?[0].into()),
+            0xcd => Marker::UInt(u16::from_be_bytes(self.take_array()?).into()),
+            0xce => Marker::UInt(u32::from_be_bytes(self.take_array()?).into()),
+            0xcf => Marker::UInt(u64::from_be_bytes(self.take_array()?)),
+            0xd0 => Marker::Int(i8::from_be_bytes(self.take_array()?).into()),
+            0xd1 => Marker::Int(i16::from_be_bytes(self.take_array()?).into()),
+            0xd2 => Marker::Int(i32::from_be_bytes(self.take_array()?).into()),
+            0xd3 => Marker::Int(i64::from_be_bytes(self.take_array()?)),
+            0xd9 => Marker::Str(self.length(1)?),
+            0xda => Marker::Str(self.length(2)?),
+            0xdb => Marker::Str(self.length(4)?),
+            0xdc => Marker::Array(self.length(2)?),
+            0xdd => Marker::Array(self.length(4)?),
+            0xde => Marker::Map(self.length(2)?),
+            0xdf => Marker::Map(self.length(4)?),
+            0xe0..=0xff => Marker::Int((tag as i8).into()),
+            0xc7..=0xc9 | 0xd4..=0xd8 => {
+                return Err(Error::custom(format!(
+                    "unsupported extension type at offset {}",
+                    offset
+                )))
+            }
+            _ => {
+                return Err(Error::custom(format!(
+                    "invalid marker 0x{:02x} at offset {}",
+                    tag, offset
+                )))
+            }
+        })
+    }
+
+    fn str(&mut self, len: usize) -> Result<&'de str, Error> {
+        let offset = self.pos;
+        std::str::from_utf8(self.take(len)?)
+            .map_err(|_| Error::custom(format!("invalid UTF-8 in string at offset {}", offset)))
+    }
+
+    // Read a marker and hand its value to the visitor, whatever its type
+    fn visit<V: Visitor<'de>>(&mut self, marker: Marker, visitor: V) -> Result<V::Value, Error> {
+        match marker {
+            Marker::Nil => visitor.visit_none(),
+            Marker::Bool(v) => visitor.visit_bool(v),
+            Marker::Int(v) => visitor.visit_i64(v),
+            Marker::UInt(v) => visitor.visit_u64(v),
+            Marker::Float(v) => visitor.visit_f64(v),
+            Marker::Str(len) => visitor.visit_borrowed_str(self.str(len)?),
+            Marker::Bin(len) => visitor.visit_borrowed_bytes(self.take(len)?),
+            Marker::Array(_) | Marker::Map(_) if self.depth >= self.max_depth => {
+                Err(depth_exceeded(self.max_depth))
+            }
+            Marker::Array(len) => {
+                self.depth += 1;
+                let mut access = MsgPackAccess {
+                    de: self,
+                    len,
+                    remaining: len,
+                    key: String::new(),
+                    seen: HashSet::new(),
+                };
+                let value = visitor.visit_seq(&mut access);
+                access.de.depth -= 1;
+                let value = value?;
+                access.finish("array")?;
+                Ok(value)
+            }
+            Marker::Map(len) => {
+                self.depth += 1;
+                let mut access = MsgPackAccess {
+                    de: self,
+                    len,
+                    remaining: len,
+                    key: String::new(),
+                    seen: HashSet::new(),
+                };
+                let value = visitor.visit_map(&mut access);
+                access.de.depth -= 1;
+                let value = value?;
+                access.finish("map")?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn invalid_type<V: Visitor<'de>>(marker: &Marker, visitor: &V) -> Error {
+        Error::custom(format!(
+            "invalid type: {}, expected {}",
+            marker.describe(),
+            expected(visitor)
+        ))
+    }
+
+    // Read a value the visitor can only accept as one of the given kinds
+    fn visit_if<V: Visitor<'de>>(
+        &mut self,
+        visitor: V,
+        accepts: fn(&Marker) -> bool,
+    ) -> Result<V::Value, Error> {
+        let marker = self.marker()?;
+        if accepts(&marker) {
+            self.visit(marker, visitor)
+        } else {
+            Err(Self::invalid_type(&marker, &visitor))
+        }
+    }
+}
+
+fn is_number(marker: &Marker) -> bool {
+    matches!(marker, Marker::Int(_) | Marker::UInt(_) | Marker::Float(_))
+}
+
+fn is_string(marker: &Marker) -> bool {
+    matches!(marker, Marker::Str(_))
+}
+
+macro_rules! msgpack_deserialize_methods {
+    ($($method:ident => $accepts:expr;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.visit_if(visitor, $accepts)
+            }
+        )*
+    };
+}
+
+// Integers are range-checked here, so the visitor gets a value of its type
+macro_rules! msgpack_integer_methods {
+    ($($method:ident => $visit:ident;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                let value = match self.marker()? {
+                    Marker::Int(v) => v.try_into().map_err(|_| v.to_string()),
+                    Marker::UInt(v) => v.try_into().map_err(|_| v.to_string()),
+                    other => return Err(MsgPackDeserializer::invalid_type(&other, &visitor)),
+                };
+                match value {
+                    Ok(v) => visitor.$visit(v),
+                    Err(v) => Err(Error::custom(format!(
+                        "invalid value: {}, expected {}",
+                        v,
+                        expected(&visitor)
+                    ))),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for &mut MsgPackDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let marker = self.marker()?;
+        self.visit(marker, visitor)
+    }
+
+    msgpack_integer_methods! {
+        deserialize_i8 => visit_i8;
+        deserialize_i16 => visit_i16;
+        deserialize_i32 => visit_i32;
+        deserialize_i64 => visit_i64;
+        deserialize_i128 => visit_i128;
+        deserialize_u8 => visit_u8;
+        deserialize_u16 => visit_u16;
+        deserialize_u32 => visit_u32;
+        deserialize_u64 => visit_u64;
+        deserialize_u128 => visit_u128;
+    }
+
+    msgpack_deserialize_methods! {
+        deserialize_bool => |m| matches!(m, Marker::Bool(_));
+        deserialize_f64 => is_number;
+        deserialize_char => is_string;
+        deserialize_str => is_string;
+        deserialize_string => is_string;
+        deserialize_bytes => |m| matches!(m, Marker::Bin(_) | Marker::Str(_) | Marker::Array(_));
+        deserialize_seq => |m| matches!(m, Marker::Array(_));
+        deserialize_map => |m| matches!(m, Marker::Map(_));
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.marker()? {
+            Marker::Nil => visitor.visit_unit(),
+            other => Err(MsgPackDeserializer::invalid_type(&other, &visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.input.get(self.pos) == Some(&0xc0) {
+            self.pos += 1;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.marker()? {
+            Marker::Array(found) if found == len => self.visit(Marker::Array(found), visitor),
+            Marker::Array(found) => Err(invalid_length(found, len)),
+            other => Err(MsgPackDeserializer::invalid_type(&other, &visitor)),
+        }
+    }
+}
+
+// SeqAccess and MapAccess over the counted elements of an array or map
+struct MsgPackAccess<'a, 'de> {
+    de: &'a mut MsgPackDeserializer<'de>,
+    len: usize,
+    remaining: usize,
+    // The current map key, for the paths of errors in its value
+    key: String,
+    // Keys read so far, unless duplicates are simply passed on
+    seen: HashSet<String>,
+}
+
+impl MsgPackAccess<'_, '_> {
+    fn finish(&self, kind: &str) -> Result<(), Error> {
+        if self.remaining == 0 {
+            Ok(())
+        } else {
+            Err(Error::custom(format!(
+                "invalid length {}, only {} elements of the {} were read",
+                self.len,
+                self.len - self.remaining,
+                kind
+            )))
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for &mut MsgPackAccess<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let index = self.len - self.remaining - 1;
+        seed.deserialize(&mut *self.de).map(Some).map_err(|e| e.at_index(index))
+    }
+}
+
+impl<'de> MapAccess<'de> for &mut MsgPackAccess<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        loop {
+            if self.remaining == 0 {
+                return Ok(None);
+            }
+            self.remaining -= 1;
+            // Look ahead at the key so errors in its value can name it
+            let start = self.de.pos;
+            let key = match self.de.marker() {
+                Ok(Marker::Str(len)) => self.de.str(len).ok().map(str::to_string),
+                Ok(Marker::Int(v)) => Some(v.to_string()),
+                Ok(Marker::UInt(v)) => Some(v.to_string()),
+                _ => None,
+            };
+            let skip = match &key {
+                Some(key) => self.de.duplicate_keys.skip(&mut self.seen, key)?,
+                None => false,
+            };
+            self.key = key.unwrap_or_default();
+            if skip {
+                JsonValue::deserialize(&mut *self.de).map_err(|e| e.at_key(&self.key))?;
+                continue;
+            }
+            self.de.pos = start;
+            return seed.deserialize(&mut *self.de).map(Some).map_err(|e| e.at_key(&self.key));
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de).map_err(|e| e.at_key(&self.key))
+    }
+}
+
+// Helper function to deserialize from MessagePack
+pub fn from_msgpack<'de, T: Deserialize<'de>>(input: &'de [u8]) -> Result<T, Error> {
+    let mut deserializer = MsgPackDeserializer::new(input);
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+// URL-encoded form support (`key=value&key=value`), as sent by HTML forms.
+// Like CSV, values go through YamlNode, so only flat structs and maps work.
+fn percent_encode(text: &str, output: &mut String) {
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => output.push(byte as char),
+            b' ' => output.push('+'),
+            _ => output.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+}
+
+fn percent_decode(text: &str) -> Result<String, Error> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = text.get(i + 1..i + 3).filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+                match hex {
+                    Some(hex) => decoded.push(u8::from_str_radix(hex, 16).unwrap()),
+                    None => return Err(Error::custom(format!("invalid percent-encoding in `{}`", text))),
+                }
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).map_err(|_| Error::custom(format!("invalid UTF-8 in `{}`", text)))
+}
+
+// Serialize a flat struct or map as a URL-encoded form. `None` fields are
+// left out.
+pub fn to_urlencoded<T: Serialize>(value: &T) -> Result<String, Error> {
+    let entries = match value.serialize(NodeSerializer)? {
+        YamlNode::Map(entries) => entries,
+        _ => return Err(Error::custom("URL-encoded forms must be structs or maps".to_string())),
+    };
+    let mut output = String::new();
+    for (key, value) in entries {
+        let key = match key {
+            YamlNode::Plain(text) | YamlNode::Str(text) => text,
+            _ => return Err(Error::custom("form keys must be scalars".to_string())),
+        };
+        let value = match value {
+            YamlNode::Null => continue,
+            YamlNode::Plain(text) | YamlNode::Str(text) => text,
+            _ => return Err(Error::custom(format!("form field `{}` is not a scalar", key))),
+        };
+        if !output.is_empty() {
+            output.push('&');
+        }
+        percent_encode(&key, &mut output);
+        output.push('=');
+        percent_encode(&value, &mut output);
+    }
+    Ok(output)
+}
+
+// Deserialize a URL-encoded form as a map from keys to values. A key
+// without `=` has an empty value, which reads as `None` for options.
+pub fn from_urlencoded<'de, T: Deserialize<'de>>(input: &str) -> Result<T, Error> {
+    let mut entries = Vec::new();
+    for pair in input.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        // Plain text: an empty value reads as None or as an empty string
+        let value = YamlNode::Plain(percent_decode(value)?);
+        entries.push((YamlNode::Str(percent_decode(key)?), value));
+    }
+    T::deserialize(YamlNode::Map(entries))
+}
+
+// TOML support, for reading config files. A document is parsed into a
+// JsonValue object, which then drives the visitors; dates and times are
+// read as their text.
+struct TomlParser<'a> {
+    input: &'a str,
+    pos: usize,
+    // Tables named by a `[header]`, which can't be named again
+    defined: HashSet<Vec<String>>,
+    // Arrays of tables named by a `[[header]]`, which add a table each time
+    arrays: HashSet<Vec<String>>,
+    // How many arrays and inline tables enclose the current value
+    depth: usize,
+}
+
+impl<'a> TomlParser<'a> {
+    fn new(input: &'a str) -> Self {
+        TomlParser {
+            input,
+            pos: 0,
+            defined: HashSet::new(),
+            arrays: HashSet::new(),
+            depth: 0,
+        }
+    }
+    
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+    
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+    
+    fn error(&self, message: &str) -> Error {
+        let line = self.input[..self.pos].matches('\n').count() + 1;
+        Error::custom(format!("{} at line {}", message, line))
+    }
+    
+    fn expect(&mut self, text: &str) -> Result<(), Error> {
+        if !self.rest().starts_with(text) {
+            return Err(self.error(&format!("expected `{}`", text)));
+        }
+        self.pos += text.len();
+        Ok(())
+    }
+    
+    // Skip spaces and tabs
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    }
+    
+    // Skip whitespace, newlines and comments, as allowed between the lines
+    // of a document and the items of an array
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('#') => self.pos += self.rest().find('\n').unwrap_or(self.rest().len()),
+                Some('\n') => self.pos += 1,
+                Some('\r') if self.rest().starts_with("\r\n") => self.pos += 2,
+                _ => return,
+            }
+        }
+    }
+    
+    // The end of a line: an optional comment, then a newline or the end
+    fn end_line(&mut self) -> Result<(), Error> {
+        self.skip_spaces();
+        if self.peek() == Some('#') {
+            self.pos += self.rest().find('\n').unwrap_or(self.rest().len());
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some('\r') if self.rest().starts_with("\r\n") => {
+                self.pos += 2;
+                Ok(())
+            }
+            Some(_) => Err(self.error("expected a newline")),
+        }
+    }
+    
+    fn parse(mut self) -> Result<JsonValue, Error> {
+        let mut root = BTreeMap::new();
+        let mut table = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => return Ok(JsonValue::Object(root)),
+                Some('[') if self.rest().starts_with("[[") => {
+                    self.pos += 2;
+                    table = self.key()?;
+                    self.expect("]]")?;
+                    let (last, parent) = table.split_last().unwrap();
+                    let parent = self.table_at(&mut root, parent)?;
+                    match parent.get_mut(last) {
+                        Some(JsonValue::Array(items)) if self.arrays.contains(&table) => {
+                            items.push(JsonValue::Object(BTreeMap::new()));
+                        }
+                        None => {
+                            parent.insert(last.clone(), JsonValue::Array(vec![JsonValue::Object(BTreeMap::new())]));
+                            self.arrays.insert(table.clone());
+                        }
+                        Some(_) => return Err(self.error(&format!("`{}` is not an array of tables", table.join(".")))),
+                    }
+                    // The new table's own tables can be named again
+                    self.defined.retain(|path| !path.starts_with(&table));
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    table = self.key()?;
+                    self.expect("]")?;
+                    if self.arrays.contains(&table) || !self.defined.insert(table.clone()) {
+                        return Err(self.error(&format!("table `{}` is defined twice", table.join("."))));
+                    }
+                    self.table_at(&mut root, &table)?;
+                }
+                Some(_) => {
+                    let current = self.table_at(&mut root, &table)?;
+                    self.key_value(current)?;
+                }
+            }
+            self.end_line()?;
+        }
+    }
+    
+    // The table at `path` under `root`, creating any that are missing. A
+    // name holding an array of tables means its last table.
+    fn table_at<'m>(&self, root: &'m mut BTreeMap<String, JsonValue>, path: &[String]) -> Result<&'m mut BTreeMap<String, JsonValue>, Error> {
+        let mut table = root;
+        for name in path {
+            let value = table.entry(name.clone()).or_insert_with(|| JsonValue::Object(BTreeMap::new()));
+            table = match value {
+                JsonValue::Object(map) => map,
+                JsonValue::Array(items) => match items.last_mut() {
+                    Some(JsonValue::Object(map)) => map,
+                    _ => return Err(self.error(&format!("`{}` is not a table", name))),
+                },
+                _ => return Err(self.error(&format!("`{}` is not a table", name))),
+            };
+        }
+        Ok(table)
+    }
+    
+    // `key = value`, stored in `table`. A dotted key creates the tables it
+    // passes through.
+    fn key_value(&mut self, table: &mut BTreeMap<String, JsonValue>) -> Result<(), Error> {
+        let key = self.key()?;
+        self.expect("=")?;
+        self.skip_spaces();
+        let value = self.value()?;
+        let (last, parent) = key.split_last().unwrap();
+        let table = self.table_at(table, parent)?;
+        if table.contains_key(last) {
+            return Err(self.error(&format!("key `{}` is defined twice", key.join("."))));
+        }
+        table.insert(last.clone(), value);
+        Ok(())
+    }
+    
+    // A key of bare or quoted parts joined by dots
+    fn key(&mut self) -> Result<Vec<String>, Error> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let rest = self.rest();
+                    let len = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-').len();
+                    if len == 0 {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.pos += len;
+                    rest[..len].to_string()
+                }
+            };
+            parts.push(part);
+            // Each part of a dotted key is a table of its own
+            if self.depth + parts.len() > DEFAULT_MAX_DEPTH {
+                return Err(depth_exceeded(DEFAULT_MAX_DEPTH));
+            }
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.pos += 1;
+        }
+    }
+    
+    fn value(&mut self) -> Result<JsonValue, Error> {
+        let rest = self.rest();
+        if rest.starts_with("\"\"\"") {
+            return self.multiline_string("\"\"\"").map(JsonValue::String);
+        }
+        if rest.starts_with("'''") {
+            return self.multiline_string("'''").map(JsonValue::String);
+        }
+        match self.peek() {
+            Some('"') => self.basic_string().map(JsonValue::String),
+            Some('\'') => self.literal_string().map(JsonValue::String),
+            Some(c @ ('[' | '{')) => {
+                if self.depth >= DEFAULT_MAX_DEPTH {
+                    return Err(depth_exceeded(DEFAULT_MAX_DEPTH));
+                }
+                self.depth += 1;
+                let value = if c == '[' { self.array() } else { self.inline_table() };
+                self.depth -= 1;
+                value
+            }
+            _ => self.scalar(),
+        }
+    }
+    
+    fn array(&mut self) -> Result<JsonValue, Error> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(JsonValue::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+    
+    // `{ key = value, ... }` on one line
+    fn inline_table(&mut self) -> Result<JsonValue, Error> {
+        self.pos += 1;
+        let mut table = BTreeMap::new();
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(table));
+        }
+        loop {
+            self.key_value(&mut table)?;
+            self.skip_spaces();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(table));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+    
+    // A boolean, number, date or time, which runs to the next delimiter
+    fn scalar(&mut self) -> Result<JsonValue, Error> {
+        let rest = self.rest();
+        let len = rest.find([',', ']', '}', '#', '\n', '\r']).unwrap_or(rest.len());
+        // A space may separate the date and time of a datetime
+        let mut text = rest[..len].trim_end_matches([' ', '\t']);
+        if !is_toml_datetime(text) {
+            text = text.split([' ', '\t']).next().unwrap_or_default();
+        }
+        let len = text.len();
+        let value = match text {
+            "true" => JsonValue::Bool(true),
+            "false" => JsonValue::Bool(false),
+            "inf" | "+inf" => JsonValue::Number(Number::Float(f64::INFINITY)),
+            "-inf" => JsonValue::Number(Number::Float(f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => JsonValue::Number(Number::Float(f64::NAN)),
+            _ if is_toml_datetime(text) => JsonValue::String(text.to_string()),
+            "" => return Err(self.error("expected a value")),
+            _ => toml_number(text).map(JsonValue::Number).map_err(|message| self.error(&message))?,
+        };
+        self.pos += len;
+        Ok(value)
+    }
+    
+    // A `'literal'` string, taken as written
+    fn literal_string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let rest = self.rest();
+        match rest.find(['\'', '\n']) {
+            Some(end) if rest[end..].starts_with('\'') => {
+                self.pos += end + 1;
+                Ok(rest[..end].to_string())
+            }
+            _ => Err(self.error("unterminated string")),
+        }
+    }
+    
+    // A `"basic"` string, with escapes
+    fn basic_string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') => text.push(self.escape()?),
+                Some(c) => {
+                    text.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+    }
+    
+    // A `"""` or `'''` string, which may span lines. A newline straight
+    // after the opening quotes is dropped, and in a basic string a
+    // backslash at the end of a line joins it to the next non-blank text.
+    fn multiline_string(&mut self, quotes: &str) -> Result<String, Error> {
+        self.pos += 3;
+        if self.rest().starts_with("\r\n") {
+            self.pos += 2;
+        } else if self.rest().starts_with('\n') {
+            self.pos += 1;
+        }
+        let basic = quotes == "\"\"\"";
+        let mut text = String::new();
+        loop {
+            let rest = self.rest();
+            if rest.starts_with(quotes) {
+                // Up to two quotes may come right before the closing ones
+                let extra = rest[3..].len() - rest[3..].trim_start_matches(&quotes[..1]).len();
+                if extra > 2 {
+                    return Err(self.error("too many quotes"));
+                }
+                text.push_str(&rest[..extra]);
+                self.pos += 3 + extra;
+                return Ok(text);
+            }
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some('\\') if basic => {
+                    let after = self.rest()[1..].trim_start_matches([' ', '\t']);
+                    if after.starts_with('\n') || after.starts_with("\r\n") {
+                        self.pos += 1;
+                        self.skip_blank_lines();
+                    } else {
+                        text.push(self.escape()?);
+                    }
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+    }
+    
+    fn skip_blank_lines(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\r', '\n']).len();
+    }
+    
+    // The escape at the current `\`
+    fn escape(&mut self) -> Result<char, Error> {
+        let mut chars = self.rest()[1..].chars();
+        let (c, len) = match chars.next() {
+            Some('b') => ('\u{8}', 2),
+            Some('t') => ('\t', 2),
+            Some('n') => ('\n', 2),
+            Some('f') => ('\u{c}', 2),
+            Some('r') => ('\r', 2),
+            Some('e') => ('\u{1b}', 2),
+            Some('"') => ('"', 2),
+            Some('\\') => ('\\', 2),
+            Some(u @ ('u' | 'U')) => {
+                let digits = if u == 'u' { 4 } else { 8 };
+                let hex = self.rest().get(2..2 + digits).filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+                match hex.and_then(|hex| char::from_u32(u32::from_str_radix(hex, 16).unwrap())) {
+                    Some(c) => (c, 2 + digits),
+                    None => return Err(self.error("invalid unicode escape")),
+                }
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        self.pos += len;
+        Ok(c)
+    }
+}
+
+// Whether `text` is a TOML date, time or datetime, such as `1979-05-27`,
+// `07:32:00` or `1979-05-27T07:32:00Z`
+fn is_toml_datetime(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let date = bytes.len() >= 10 && bytes[4] == b'-' && bytes[7] == b'-';
+    let time = bytes.len() >= 8 && bytes[2] == b':' && bytes[5] == b':';
+    (date || time) && bytes[0].is_ascii_digit()
+}
+
+// Parse a TOML integer or float: underscores between digits, and integers
+// in hex, octal or binary with a `0x`, `0o` or `0b` prefix. Integers must
+// fit in an i64 rather than turning into floats.
+fn toml_number(text: &str) -> Result<Number, String> {
+    let invalid = || format!("invalid value `{}`", text);
+    let integer = |result: Result<i64, ParseIntError>| match result {
+        Ok(v) => Ok(Number::from(v)),
+        Err(e) if matches!(e.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
+            Err(format!("number out of range: {}", text))
+        }
+        Err(_) => Err(invalid()),
+    };
+    let valid_underscores = !text.starts_with('_')
+        && !text.ends_with('_')
+        && !text.contains("__")
+        && text.match_indices('_').all(|(i, _)| {
+            text.as_bytes()[i - 1].is_ascii_alphanumeric() && text.as_bytes()[i + 1].is_ascii_alphanumeric()
+        });
+    if !valid_underscores {
+        return Err(invalid());
+    }
+    let digits = text.replace('_', "");
+    for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(rest) = digits.strip_prefix(prefix) {
+            if rest.starts_with(['+', '-']) {
+                return Err(invalid());
+            }
+            return integer(i64::from_str_radix(rest, radix));
+        }
+    }
+    let unsigned = digits.strip_prefix(['+', '-']).unwrap_or(&digits);
+    let leading_zero = unsigned.len() > 1 && unsigned.starts_with('0') && unsigned.as_bytes()[1].is_ascii_digit();
+    if leading_zero || !unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    if unsigned.bytes().all(|b| b.is_ascii_digit()) {
+        return integer(digits.parse());
+    }
+    // A float needs digits on both sides of its point
+    let point = unsigned.split_once('.');
+    if point.is_some_and(|(_, after)| !after.starts_with(|c: char| c.is_ascii_digit())) {
+        return Err(invalid());
+    }
+    digits.parse::<f64>().ok().filter(|v| v.is_finite()).map(Number::Float).ok_or_else(invalid)
+}
+
+// Deserialize a TOML document, such as a config file
+pub fn from_toml<'de, T: Deserialize<'de>>(input: &str) -> Result<T, Error> {
+    T::deserialize(TomlParser::new(input).parse()?)
+}
+
+// A JSON number, keeping integers exact. Non-negative integers are always
+// UInt, so equal numbers compare equal whichever format they came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    // The text of a number that none of the others hold exactly, such as a
+    // 30-digit integer or `0.10`, from arbitrary-precision parsing or FromStr
+    Arbitrary(String),
+}
+
+impl From<i64> for Number {
+    fn from(v: i64) -> Self {
+        match u64::try_from(v) {
+            Ok(v) => Number::UInt(v),
+            Err(_) => Number::Int(v),
+        }
+    }
+}
+
+impl From<u64> for Number {
+    fn from(v: u64) -> Self {
+        Number::UInt(v)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(v: f64) -> Self {
+        Number::Float(v)
+    }
+}
+
+impl Number {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Number::Int(v) => Some(*v),
+            Number::UInt(v) => i64::try_from(*v).ok(),
+            Number::Float(_) => None,
+            Number::Arbitrary(text) => text.parse().ok(),
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Number::Int(v) => u64::try_from(*v).ok(),
+            Number::UInt(v) => Some(*v),
+            Number::Float(_) => None,
+            Number::Arbitrary(text) => text.parse().ok(),
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Number::Int(v) => *v as f64,
+            Number::UInt(v) => *v as f64,
+            Number::Float(v) => *v,
+            Number::Arbitrary(text) => text.parse().unwrap_or(f64::NAN),
+        }
+    }
+}
+
+// Parse JSON number text, keeping it exact: integers that fit in 64 bits
+// and floats that print back the same become Int, UInt or Float, and
+// anything else is kept as Arbitrary
+impl FromStr for Number {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Number, Error> {
+        let mut deserializer = JsonDeserializer::new(s);
+        if deserializer.number_text().is_err() || deserializer.pos != s.len() {
+            return Err(Error::custom(format!("invalid number: {}", s)));
+        }
+        if let Ok(v) = s.parse::<i64>() {
+            return Ok(v.into());
+        }
+        if let Ok(v) = s.parse::<u64>() {
+            return Ok(v.into());
+        }
+        match s.parse::<f64>() {
+            Ok(v) if v.to_string() == s => Ok(Number::Float(v)),
+            _ => Ok(Number::Arbitrary(s.to_string())),
+        }
+    }
+}
+
+impl Serialize for Number {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Number::Int(v) => serializer.serialize_i64(*v),
+            Number::UInt(v) => serializer.serialize_u64(*v),
+            Number::Float(v) => serializer.serialize_f64(*v),
+            Number::Arbitrary(text) => serializer.serialize_number(text),
+        }
+    }
+}
+
+struct NumberVisitor;
+
+impl<'de> Visitor<'de> for NumberVisitor {
+    type Value = Number;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a number")
+    }
+
+    fn visit_i64<E: CustomError>(self, v: i64) -> Result<Number, E> {
+        Ok(v.into())
+    }
+
+    fn visit_i128<E: CustomError>(self, v: i128) -> Result<Number, E> {
+        self.visit_number(&v.to_string())
+    }
+
+    fn visit_u64<E: CustomError>(self, v: u64) -> Result<Number, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u128<E: CustomError>(self, v: u128) -> Result<Number, E> {
+        self.visit_number(&v.to_string())
+    }
+
+    fn visit_f64<E: CustomError>(self, v: f64) -> Result<Number, E> {
+        Ok(v.into())
+    }
+
+    fn visit_number<E: CustomError>(self, text: &str) -> Result<Number, E> {
+        text.parse().map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Int(v) => write!(f, "{}", v),
+            Number::UInt(v) => write!(f, "{}", v),
+            Number::Float(v) => write!(f, "{}", v),
+            Number::Arbitrary(text) => f.write_str(text),
+        }
+    }
+}
+
+// A JSON value kept as the text it was read from, so a service can pass a
+// payload along without re-normalizing its spacing, key order or numbers.
+// JSON writes it back verbatim; other formats get the value it holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawJson(String);
+
+impl RawJson {
+    // Wrap JSON text, failing unless it is a single JSON value
+    pub fn from_string(json: String) -> Result<RawJson, Error> {
+        JsonDeserializer::new(&json).arbitrary_precision(true).parse::<JsonValue>()?;
+        Ok(RawJson(json))
+    }
+    
+    pub fn get(&self) -> &str {
+        &self.0
+    }
+    
+    pub fn into_string(self) -> String {
+        self.0
+    }
+    
+    // Deserialize the fragment, for a peek at a payload that is otherwise
+    // passed through
+    pub fn parse<'a, T: Deserialize<'a>>(&'a self) -> Result<T, Error> {
+        from_json(&self.0)
+    }
+}
+
+impl Serialize for RawJson {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_raw_json(&self.0)
+    }
+}
+
+struct RawJsonVisitor;
+
+impl<'de> Visitor<'de> for RawJsonVisitor {
+    type Value = RawJson;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a JSON value")
+    }
+
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<RawJson, E> {
+        Ok(RawJson(v.to_string()))
+    }
+
+    fn visit_string<E: CustomError>(self, v: String) -> Result<RawJson, E> {
+        Ok(RawJson(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for RawJson {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_raw_json(RawJsonVisitor)
+    }
+}
+
+impl fmt::Display for RawJson {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// Dynamically typed JSON value, for data whose shape isn't known ahead of time
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum JsonValue {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+// Shared by lookups and indexing: a missing key, an index out of bounds or
+// a value of the wrong kind all look up nothing
+static NULL: JsonValue = JsonValue::Null;
+
+// Types that can index into a JsonValue: `usize` for arrays, strings for
+// objects
+pub trait JsonIndex {
+    fn index_into<'v>(&self, value: &'v JsonValue) -> Option<&'v JsonValue>;
+    fn index_into_mut<'v>(&self, value: &'v mut JsonValue) -> Option<&'v mut JsonValue>;
+    fn index_or_insert<'v>(&self, value: &'v mut JsonValue) -> &'v mut JsonValue;
+}
+
+impl JsonIndex for usize {
+    fn index_into<'v>(&self, value: &'v JsonValue) -> Option<&'v JsonValue> {
+        match value {
+            JsonValue::Array(items) => items.get(*self),
+            _ => None,
+        }
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut JsonValue) -> Option<&'v mut JsonValue> {
+        match value {
+            JsonValue::Array(items) => items.get_mut(*self),
+            _ => None,
+        }
+    }
+
+    fn index_or_insert<'v>(&self, value: &'v mut JsonValue) -> &'v mut JsonValue {
+        match value {
+            JsonValue::Array(items) => {
+                let len = items.len();
+                items
+                    .get_mut(*self)
+                    .unwrap_or_else(|| panic!("index {} out of bounds for JSON array of length {}", self, len))
+            }
+            other => panic!("cannot index into JSON {} with {}", other.describe(), self),
+        }
+    }
+}
+
+impl JsonIndex for str {
+    fn index_into<'v>(&self, value: &'v JsonValue) -> Option<&'v JsonValue> {
+        match value {
+            JsonValue::Object(map) => map.get(self),
+            _ => None,
+        }
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut JsonValue) -> Option<&'v mut JsonValue> {
+        match value {
+            JsonValue::Object(map) => map.get_mut(self),
+            _ => None,
+        }
+    }
+
+    fn index_or_insert<'v>(&self, value: &'v mut JsonValue) -> &'v mut JsonValue {
+        if value.is_null() {
+            *value = JsonValue::Object(BTreeMap::new());
+        }
+        match value {
+            JsonValue::Object(map) => map.entry(self.to_string()).or_default(),
+            other => panic!("cannot index into JSON {} with {:?}", other.describe(), self),
+        }
+    }
+}
+
+impl JsonIndex for String {
+    fn index_into<'v>(&self, value: &'v JsonValue) -> Option<&'v JsonValue> {
+        self.as_str().index_into(value)
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut JsonValue) -> Option<&'v mut JsonValue> {
+        self.as_str().index_into_mut(value)
+    }
+
+    fn index_or_insert<'v>(&self, value: &'v mut JsonValue) -> &'v mut JsonValue {
+        self.as_str().index_or_insert(value)
+    }
+}
+
+impl<T: JsonIndex + ?Sized> JsonIndex for &T {
+    fn index_into<'v>(&self, value: &'v JsonValue) -> Option<&'v JsonValue> {
+        (**self).index_into(value)
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut JsonValue) -> Option<&'v mut JsonValue> {
+        (**self).index_into_mut(value)
+    }
+
+    fn index_or_insert<'v>(&self, value: &'v mut JsonValue) -> &'v mut JsonValue {
+        (**self).index_or_insert(value)
+    }
+}
+
+impl JsonValue {
+    // Look up an array element or object field
+    pub fn get<I: JsonIndex>(&self, index: I) -> Option<&JsonValue> {
+        index.index_into(self)
+    }
+
+    pub fn get_mut<I: JsonIndex>(&mut self, index: I) -> Option<&mut JsonValue> {
+        index.index_into_mut(self)
+    }
+
+    // Look up a nested value by RFC 6901 JSON Pointer, such as
+    // "/users/0/name"; "" is the whole value. In a key, `~1` stands for `/`
+    // and `~0` for `~`. A malformed pointer looks up nothing.
+    pub fn pointer(&self, pointer: &str) -> Option<&JsonValue> {
+        pointer_tokens(pointer)?.iter().try_fold(self, |value, token| match value {
+            JsonValue::Object(map) => map.get(token),
+            JsonValue::Array(items) => items.get(pointer_index(token)?),
+            _ => None,
+        })
+    }
+
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut JsonValue> {
+        pointer_tokens(pointer)?.iter().try_fold(self, |value, token| match value {
+            JsonValue::Object(map) => map.get_mut(token),
+            JsonValue::Array(items) => items.get_mut(pointer_index(token)?),
+            _ => None,
+        })
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, JsonValue::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            JsonValue::Number(n) => n.as_i64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            JsonValue::Number(n) => n.as_u64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(n) => Some(n.as_f64()),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, JsonValue>> {
+        match self {
+            JsonValue::Object(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "boolean",
+            JsonValue::Number(_) => "number",
+            JsonValue::String(_) => "string",
+            JsonValue::Array(_) => "array",
+            JsonValue::Object(_) => "object",
+        }
+    }
+}
+
+// The unescaped reference tokens of a JSON Pointer, or None if it doesn't
+// start with `/` or has a `~` that isn't `~0` or `~1`
+fn pointer_tokens(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    pointer
+        .strip_prefix('/')?
+        .split('/')
+        .map(|token| {
+            let mut unescaped = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '~' => match chars.next() {
+                        Some('0') => unescaped.push('~'),
+                        Some('1') => unescaped.push('/'),
+                        _ => return None,
+                    },
+                    c => unescaped.push(c),
+                }
+            }
+            Some(unescaped)
+        })
+        .collect()
+}
+
+// Array indexes are plain decimal with no leading zeros; `-`, the element
+// past the end, never exists to look up
+fn pointer_index(token: &str) -> Option<usize> {
+    let digits = !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit());
+    if !digits || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+// `value["key"]` and `value[0]` give Null when nothing is there
+impl<I: JsonIndex> Index<I> for JsonValue {
+    type Output = JsonValue;
+
+    fn index(&self, index: I) -> &JsonValue {
+        index.index_into(self).unwrap_or(&NULL)
+    }
+}
+
+// Assigning to `value["key"]` inserts the field, turning Null into an
+// object; indexing past the end of an array or into a scalar panics
+impl<I: JsonIndex> IndexMut<I> for JsonValue {
+    fn index_mut(&mut self, index: I) -> &mut JsonValue {
+        index.index_or_insert(self)
+    }
+}
+
+// Apply an RFC 7396 JSON Merge Patch: each key in an object patch replaces
+// the target's, null removes it, and nested objects merge recursively. A
+// patch that isn't an object replaces the whole target.
+pub fn merge_patch(target: &mut JsonValue, patch: &JsonValue) {
+    let JsonValue::Object(fields) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !matches!(target, JsonValue::Object(_)) {
+        *target = JsonValue::Object(BTreeMap::new());
+    }
+    if let JsonValue::Object(map) = target {
+        for (key, value) in fields {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                merge_patch(map.entry(key.clone()).or_default(), value);
+            }
+        }
+    }
+}
+
+// Apply an RFC 6902 JSON Patch, an array of add, remove, replace, move, copy
+// and test operations. It's all or nothing: if any operation fails, the
+// target is left as it was, and the error's path is the operation's index.
+pub fn apply_patch(target: &mut JsonValue, patch: &JsonValue) -> Result<(), Error> {
+    let operations = patch
+        .as_array()
+        .ok_or_else(|| Error::custom(format!("invalid type: {}, expected a JSON Patch array", patch.describe())))?;
+    let mut patched = target.clone();
+    for (i, operation) in operations.iter().enumerate() {
+        apply_operation(&mut patched, operation).map_err(|e| Error::custom(e).at_index(i))?;
+    }
+    *target = patched;
+    Ok(())
+}
+
+fn apply_operation(target: &mut JsonValue, operation: &JsonValue) -> Result<(), String> {
+    let member = |name: &str| {
+        operation
+            .get(name)
+            .ok_or_else(|| format!("missing field `{}`", name))
+    };
+    let pointer = |name: &str| {
+        let value = member(name)?;
+        let text = value
+            .as_str()
+            .ok_or_else(|| format!("invalid type: {}, expected a JSON Pointer", value.describe()))?;
+        pointer_tokens(text).ok_or_else(|| format!("invalid JSON Pointer `{}`", text))
+    };
+    let op = member("op")?;
+    let op = op
+        .as_str()
+        .ok_or_else(|| format!("invalid type: {}, expected an operation name", op.describe()))?;
+    match op {
+        "add" => patch_add(target, &pointer("path")?, member("value")?.clone()),
+        "remove" => patch_remove(target, &pointer("path")?).map(drop),
+        "replace" => {
+            let value = member("value")?.clone();
+            *pointer_target(target, &pointer("path")?)? = value;
+            Ok(())
+        }
+        "move" => {
+            let (from, path) = (pointer("from")?, pointer("path")?);
+            if path.len() > from.len() && path.starts_with(&from) {
+                return Err("cannot move a value into one of its children".to_string());
+            }
+            let value = patch_remove(target, &from)?;
+            patch_add(target, &path, value)
+        }
+        "copy" => {
+            let value = pointer_target(target, &pointer("from")?)?.clone();
+            patch_add(target, &pointer("path")?, value)
+        }
+        "test" => {
+            let path = pointer("path")?;
+            if *pointer_target(target, &path)? == *member("value")? {
+                Ok(())
+            } else {
+                Err(format!("test failed: the value at `{}` is different", pointer_text(&path)))
+            }
+        }
+        other => Err(format!("unknown variant `{}`, expected one of `add`, `remove`, `replace`, `move`, `copy`, `test`", other)),
+    }
+}
+
+// The value a JSON Pointer's tokens lead to, which must exist
+fn pointer_target<'v>(value: &'v mut JsonValue, tokens: &[String]) -> Result<&'v mut JsonValue, String> {
+    tokens
+        .iter()
+        .try_fold(value, |value, token| match value {
+            JsonValue::Object(map) => map.get_mut(token),
+            JsonValue::Array(items) => items.get_mut(pointer_index(token)?),
+            _ => None,
+        })
+        .ok_or_else(|| format!("nothing at `{}`", pointer_text(tokens)))
+}
+
+// Adding to an object sets the key; adding to an array inserts before the
+// index, or appends for `-`
+fn patch_add(target: &mut JsonValue, tokens: &[String], value: JsonValue) -> Result<(), String> {
+    let Some((last, parent)) = tokens.split_last() else {
+        *target = value;
+        return Ok(());
+    };
+    match pointer_target(target, parent)? {
+        JsonValue::Object(map) => {
+            map.insert(last.clone(), value);
+            Ok(())
+        }
+        JsonValue::Array(items) => {
+            let index = if last == "-" { Some(items.len()) } else { pointer_index(last) };
+            match index {
+                Some(index) if index <= items.len() => {
+                    items.insert(index, value);
+                    Ok(())
+                }
+                _ => Err(format!("index out of bounds at `{}`", pointer_text(tokens))),
+            }
+        }
+        other => Err(format!("cannot add to a {} at `{}`", other.describe(), pointer_text(parent))),
+    }
+}
+
+fn patch_remove(target: &mut JsonValue, tokens: &[String]) -> Result<JsonValue, String> {
+    let Some((last, parent)) = tokens.split_last() else {
+        return Ok(std::mem::take(target));
+    };
+    let removed = match pointer_target(target, parent)? {
+        JsonValue::Object(map) => map.remove(last),
+        JsonValue::Array(items) => pointer_index(last).filter(|&i| i < items.len()).map(|i| items.remove(i)),
+        _ => None,
+    };
+    removed.ok_or_else(|| format!("nothing at `{}`", pointer_text(tokens)))
+}
+
+// Turn tokens back into a JSON Pointer, for error messages
+fn pointer_text(tokens: &[String]) -> String {
+    tokens
+        .iter()
+        .map(|token| format!("/{}", token.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+// NaN and the infinities print as null, and there's no depth limit, since
+// formatting a value that was built can't fail
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.serialize(JsonSerializer::new().non_finite(NonFinite::Null).max_depth(usize::MAX)) {
+            Ok(json) => f.write_str(&json),
+            Err(_) => Err(fmt::Error),
+        }
+    }
+}
+
+impl Serialize for JsonValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            JsonValue::Null => serializer.serialize_unit(),
+            JsonValue::Bool(v) => serializer.serialize_bool(*v),
+            JsonValue::Number(n) => n.serialize(serializer),
+            JsonValue::String(s) => serializer.serialize_str(s),
+            JsonValue::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            JsonValue::Object(map) => {
+                let mut state = serializer.serialize_map(Some(map.len()))?;
+                for (key, value) in map {
+                    state.serialize_entry(key, value)?;
+                }
+                state.end()
+            }
+        }
+    }
+}
+
+struct JsonValueVisitor;
+
+impl<'de> Visitor<'de> for JsonValueVisitor {
+    type Value = JsonValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "any JSON value")
+    }
+
+    fn visit_bool<E: CustomError>(self, v: bool) -> Result<JsonValue, E> {
+        Ok(JsonValue::Bool(v))
+    }
+
+    fn visit_i64<E: CustomError>(self, v: i64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Number(v.into()))
+    }
+
+    // Integers past 64 bits are kept as Number::Arbitrary
+    fn visit_i128<E: CustomError>(self, v: i128) -> Result<JsonValue, E> {
+        NumberVisitor.visit_i128(v).map(JsonValue::Number)
+    }
+
+    fn visit_u64<E: CustomError>(self, v: u64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Number(v.into()))
+    }
+
+    fn visit_u128<E: CustomError>(self, v: u128) -> Result<JsonValue, E> {
+        NumberVisitor.visit_u128(v).map(JsonValue::Number)
+    }
+
+    fn visit_f64<E: CustomError>(self, v: f64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Number(v.into()))
+    }
+
+    fn visit_number<E: CustomError>(self, text: &str) -> Result<JsonValue, E> {
+        NumberVisitor.visit_number(text).map(JsonValue::Number)
+    }
+
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<JsonValue, E> {
+        Ok(JsonValue::String(v.to_string()))
+    }
+
+    fn visit_string<E: CustomError>(self, v: String) -> Result<JsonValue, E> {
+        Ok(JsonValue::String(v))
+    }
+
+    fn visit_bytes<E: CustomError>(self, v: &[u8]) -> Result<JsonValue, E> {
+        Ok(JsonValue::Array(v.iter().map(|&b| JsonValue::Number(u64::from(b).into())).collect()))
+    }
+
+    fn visit_unit<E: CustomError>(self) -> Result<JsonValue, E> {
+        Ok(JsonValue::Null)
+    }
+
+    fn visit_none<E: CustomError>(self) -> Result<JsonValue, E> {
+        Ok(JsonValue::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<JsonValue, D::Error> {
+        JsonValue::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<JsonValue, D::Error> {
+        JsonValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsonValue, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(JsonValue::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonValue, A::Error> {
+        let mut object = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry::<String, JsonValue>()? {
+            object.insert(key, value);
+        }
+        Ok(JsonValue::Object(object))
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(JsonValueVisitor)
+    }
+}
+
+// Serializes a value into a JsonValue
+pub struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = JsonValue;
+    type Error = Error;
+    type SerializeSeq = ValueSeqSerializer;
+    type SerializeTuple = ValueSeqSerializer;
+    type SerializeMap = ValueMapSerializer;
+    type SerializeStruct = ValueMapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<JsonValue, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<JsonValue, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<JsonValue, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Number(v.into()))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<JsonValue, Error> {
+        JsonValueVisitor.visit_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<JsonValue, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<JsonValue, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<JsonValue, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Number(v.into()))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<JsonValue, Error> {
+        JsonValueVisitor.visit_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<JsonValue, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Number(v.into()))
+    }
+
+    fn serialize_number(self, text: &str) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Number(text.parse()?))
+    }
+
+    fn serialize_char(self, v: char) -> Result<JsonValue, Error> {
+        Ok(JsonValue::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<JsonValue, Error> {
+        Ok(JsonValue::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<JsonValue, Error> {
+        JsonValueVisitor.visit_bytes(v)
+    }
+
+    fn serialize_unit(self) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Null)
+    }
+
+    fn serialize_none(self) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Null)
+    }
+
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<JsonValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ValueSeqSerializer, Error> {
+        Ok(ValueSeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ValueSeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<ValueMapSerializer, Error> {
+        Ok(ValueMapSerializer {
+            object: BTreeMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<ValueMapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+}
+
+pub struct ValueSeqSerializer {
+    items: Vec<JsonValue>,
+}
+
+impl SerializeSeq for ValueSeqSerializer {
+    type Ok = JsonValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Array(self.items))
+    }
+}
+
+impl SerializeTuple for ValueSeqSerializer {
+    type Ok = JsonValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<JsonValue, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+// The text of a JSON object key. Number keys are written as strings, as
+// serde_json does, and read back from them.
+fn object_key<T: Serialize>(key: &T) -> Result<String, Error> {
+    match to_value(key)? {
+        JsonValue::String(s) => Ok(s),
+        JsonValue::Number(n) => Ok(n.to_string()),
+        other => Err(Error::custom(format!(
+            "JSON object keys must be strings, found {}",
+            other.describe()
+        ))),
+    }
+}
+
+pub struct ValueMapSerializer {
+    object: BTreeMap<String, JsonValue>,
+    key: Option<String>,
+}
+
+impl SerializeMap for ValueMapSerializer {
+    type Ok = JsonValue;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(object_key(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        if let Some(key) = self.key.take() {
+            self.object.insert(key, to_value(value)?);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsonValue, Error> {
+        Ok(JsonValue::Object(self.object))
+    }
+}
+
+impl SerializeStruct for ValueMapSerializer {
+    type Ok = JsonValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.object.insert(key.to_string(), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsonValue, Error> {
+        SerializeMap::end(self)
+    }
+}
+
+// Convert any Serialize type into a JsonValue
+pub fn to_value<T: Serialize>(value: &T) -> Result<JsonValue, Error> {
+    value.serialize(ValueSerializer)
+}
+
+// Serializer that describes the shape of a value as a JSON Schema (draft
+// 2020-12) instead of writing its data. The schema comes from the one value
+// it's given, so a None field is only known to be null, the items of an
+// empty Vec can be anything, and differing elements become an `anyOf`.
+pub struct SchemaGenerator;
+
+const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// Generate the JSON Schema for a value's type
+pub fn schema_for<T: Serialize>(value: &T) -> Result<JsonValue, Error> {
+    let mut schema = value.serialize(SchemaGenerator)?;
+    if let JsonValue::Object(map) = &mut schema {
+        map.insert("$schema".to_string(), JsonValue::String(SCHEMA_DIALECT.to_string()));
+    }
+    Ok(schema)
+}
+
+fn schema_of<T: Serialize + ?Sized>(value: &T) -> Result<JsonValue, Error> {
+    value.serialize(SchemaGenerator)
+}
+
+// A schema object from its keywords
+fn schema_object<const N: usize>(keywords: [(&str, JsonValue); N]) -> JsonValue {
+    JsonValue::Object(keywords.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn type_schema(name: &str) -> JsonValue {
+    schema_object([("type", JsonValue::String(name.to_string()))])
+}
+
+fn unsigned_schema() -> JsonValue {
+    schema_object([("type", JsonValue::String("integer".to_string())), ("minimum", JsonValue::Number(Number::UInt(0)))])
+}
+
+// One schema for several sample values: nothing to go on accepts anything,
+// and different shapes are alternatives
+fn any_of(mut schemas: Vec<JsonValue>) -> JsonValue {
+    let mut unique = Vec::new();
+    for schema in schemas.drain(..) {
+        if !unique.contains(&schema) {
+            unique.push(schema);
+        }
+    }
+    match unique.len() {
+        0 => JsonValue::Object(BTreeMap::new()),
+        1 => unique.remove(0),
+        _ => schema_object([("anyOf", JsonValue::Array(unique))]),
+    }
+}
+
+fn accepts_null(schema: &JsonValue) -> bool {
+    match &schema["type"] {
+        JsonValue::String(name) => name == "null",
+        JsonValue::Array(names) => names.iter().any(|name| name.as_str() == Some("null")),
+        _ => schema["anyOf"].as_array().is_some_and(|schemas| schemas.iter().any(accepts_null)),
+    }
+}
+
+// Some(value) has the value's schema, widened to take null
+fn nullable(mut schema: JsonValue) -> JsonValue {
+    if accepts_null(&schema) {
+        return schema;
+    }
+    match schema.get_mut("type") {
+        Some(name @ JsonValue::String(_)) => {
+            *name = JsonValue::Array(vec![name.clone(), JsonValue::String("null".to_string())]);
+            schema
+        }
+        _ => schema_object([("anyOf", JsonValue::Array(vec![schema, type_schema("null")]))]),
+    }
+}
+
+impl Serializer for SchemaGenerator {
+    type Ok = JsonValue;
+    type Error = Error;
+    type SerializeSeq = SchemaSeqGenerator;
+    type SerializeTuple = SchemaSeqGenerator;
+    type SerializeMap = SchemaMapGenerator;
+    type SerializeStruct = SchemaMapGenerator;
+
+    fn serialize_bool(self, _v: bool) -> Result<JsonValue, Error> {
+        Ok(type_schema("boolean"))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<JsonValue, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<JsonValue, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<JsonValue, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<JsonValue, Error> {
+        Ok(type_schema("integer"))
+    }
+
+    fn serialize_i128(self, _v: i128) -> Result<JsonValue, Error> {
+        Ok(type_schema("integer"))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<JsonValue, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<JsonValue, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<JsonValue, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<JsonValue, Error> {
+        Ok(unsigned_schema())
+    }
+
+    fn serialize_u128(self, _v: u128) -> Result<JsonValue, Error> {
+        Ok(unsigned_schema())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<JsonValue, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<JsonValue, Error> {
+        Ok(type_schema("number"))
+    }
+
+    fn serialize_number(self, _text: &str) -> Result<JsonValue, Error> {
+        Ok(type_schema("number"))
+    }
+
+    fn serialize_char(self, _v: char) -> Result<JsonValue, Error> {
+        let one = || JsonValue::Number(Number::UInt(1));
+        Ok(schema_object([("type", JsonValue::String("string".to_string())), ("minLength", one()), ("maxLength", one())]))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<JsonValue, Error> {
+        Ok(type_schema("string"))
+    }
+
+    // Bytes are written as an array of numbers
+    fn serialize_bytes(self, _v: &[u8]) -> Result<JsonValue, Error> {
+        let byte = schema_object([
+            ("type", JsonValue::String("integer".to_string())),
+            ("minimum", JsonValue::Number(Number::UInt(0))),
+            ("maximum", JsonValue::Number(Number::UInt(255))),
+        ]);
+        Ok(schema_object([("type", JsonValue::String("array".to_string())), ("items", byte)]))
+    }
+
+    fn serialize_unit(self) -> Result<JsonValue, Error> {
+        Ok(type_schema("null"))
+    }
+
+    fn serialize_none(self) -> Result<JsonValue, Error> {
+        Ok(type_schema("null"))
+    }
+
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<JsonValue, Error> {
+        value.serialize(self).map(nullable)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SchemaSeqGenerator, Error> {
+        Ok(SchemaSeqGenerator {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            tuple: false,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SchemaSeqGenerator, Error> {
+        Ok(SchemaSeqGenerator {
+            items: Vec::with_capacity(len),
+            tuple: true,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SchemaMapGenerator, Error> {
+        Ok(SchemaMapGenerator {
+            title: None,
+            properties: BTreeMap::new(),
+            values: Vec::new(),
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<SchemaMapGenerator, Error> {
+        Ok(SchemaMapGenerator {
+            title: Some(name),
+            properties: BTreeMap::new(),
+            values: Vec::new(),
+        })
+    }
+}
+
+pub struct SchemaSeqGenerator {
+    items: Vec<JsonValue>,
+    tuple: bool,
+}
+
+impl SerializeSeq for SchemaSeqGenerator {
+    type Ok = JsonValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(schema_of(value)?);
+        Ok(())
+    }
+
+    // A tuple's elements each have their own schema, and there are exactly
+    // that many
+    fn end(self) -> Result<JsonValue, Error> {
+        let array = JsonValue::String("array".to_string());
+        if self.tuple {
+            let len = JsonValue::Number(Number::UInt(self.items.len() as u64));
+            return Ok(schema_object([
+                ("type", array),
+                ("prefixItems", JsonValue::Array(self.items)),
+                ("minItems", len.clone()),
+                ("maxItems", len),
+            ]));
+        }
+        Ok(schema_object([("type", array), ("items", any_of(self.items))]))
+    }
+}
+
+impl SerializeTuple for SchemaSeqGenerator {
+    type Ok = JsonValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<JsonValue, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+// Maps describe their values, since any key is allowed; structs describe
+// each field, and the ones that can't be null are required
+pub struct SchemaMapGenerator {
+    title: Option<&'static str>,
+    properties: BTreeMap<String, JsonValue>,
+    values: Vec<JsonValue>,
+}
+
+impl SerializeMap for SchemaMapGenerator {
+    type Ok = JsonValue;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize>(&mut self, _key: &T) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.values.push(schema_of(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsonValue, Error> {
+        let object = JsonValue::String("object".to_string());
+        let Some(title) = self.title else {
+            return Ok(schema_object([("type", object), ("additionalProperties", any_of(self.values))]));
+        };
+        let required = self
+            .properties
+            .iter()
+            .filter(|(_, schema)| !accepts_null(schema))
+            .map(|(name, _)| JsonValue::String(name.clone()))
+            .collect();
+        Ok(schema_object([
+            ("title", JsonValue::String(title.to_string())),
+            ("type", object),
+            ("properties", JsonValue::Object(self.properties)),
+            ("required", JsonValue::Array(required)),
+        ]))
+    }
+}
+
+impl SerializeStruct for SchemaMapGenerator {
+    type Ok = JsonValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.properties.insert(key.to_string(), schema_of(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsonValue, Error> {
+        SerializeMap::end(self)
+    }
+}
+
+impl JsonValue {
+    fn invalid_type<'de, V: Visitor<'de>>(&self, visitor: &V) -> Error {
+        Error::custom(format!(
+            "invalid type: {}, expected {}",
+            self.describe(),
+            expected(visitor)
+        ))
+    }
+
+    fn number<'de, V: Visitor<'de>>(self, visitor: &V) -> Result<Number, Error> {
+        match self {
+            JsonValue::Number(n) => Ok(n),
+            other => Err(other.invalid_type(visitor)),
+        }
+    }
+}
+
+// Integers are range-checked here, so the visitor gets a value of its type
+macro_rules! value_integer_methods {
+    ($($method:ident => $visit:ident;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                let value = match self.number(&visitor)? {
+                    Number::Int(v) => v.try_into().map_err(|_| v.to_string()),
+                    Number::UInt(v) => v.try_into().map_err(|_| v.to_string()),
+                    Number::Float(v) => Err(v.to_string()),
+                    Number::Arbitrary(text) => text.parse().map_err(|_| text),
+                };
+                match value {
+                    Ok(v) => visitor.$visit(v),
+                    Err(v) => Err(Error::custom(format!(
+                        "invalid value: {}, expected {}",
+                        v,
+                        expected(&visitor)
+                    ))),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for JsonValue {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::Null => visitor.visit_none(),
+            JsonValue::Bool(v) => visitor.visit_bool(v),
+            JsonValue::Number(Number::Int(v)) => visitor.visit_i64(v),
+            JsonValue::Number(Number::UInt(v)) => visitor.visit_u64(v),
+            JsonValue::Number(Number::Float(v)) => visitor.visit_f64(v),
+            JsonValue::Number(Number::Arbitrary(text)) => visitor.visit_number(&text),
+            JsonValue::String(s) => visitor.visit_string(s),
+            JsonValue::Array(items) => visitor.visit_seq(ValueSeqAccess {
+                items: items.into_iter(),
+                index: 0,
+            }),
+            JsonValue::Object(map) => visitor.visit_map(ValueMapAccess {
+                entries: map.into_iter(),
+                value: None,
+                key: String::new(),
+            }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::Bool(v) => visitor.visit_bool(v),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    value_integer_methods! {
+        deserialize_i8 => visit_i8;
+        deserialize_i16 => visit_i16;
+        deserialize_i32 => visit_i32;
+        deserialize_i64 => visit_i64;
+        deserialize_i128 => visit_i128;
+        deserialize_u8 => visit_u8;
+        deserialize_u16 => visit_u16;
+        deserialize_u32 => visit_u32;
+        deserialize_u64 => visit_u64;
+        deserialize_u128 => visit_u128;
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.number(&visitor)?.as_f64();
+        visitor.visit_f64(v)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::String(s) => visitor.visit_string(s),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::String(s) => visitor.visit_byte_buf(s.into_bytes()),
+            JsonValue::Array(_) => self.deserialize_seq(visitor),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::Null => visitor.visit_unit(),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::Null => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::Array(items) => visitor.visit_seq(ValueSeqAccess {
+                items: items.into_iter(),
+                index: 0,
+            }),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::Array(ref items) if items.len() != len => Err(invalid_length(items.len(), len)),
+            other => other.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            JsonValue::Object(_) => self.deserialize_any(visitor),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+}
+
+struct ValueSeqAccess {
+    items: std::vec::IntoIter<JsonValue>,
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for ValueSeqAccess {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        let index = self.index;
+        self.index += 1;
+        self.items
+            .next()
+            .map(|item| seed.deserialize(item).map_err(|e| e.at_index(index)))
+            .transpose()
+    }
+}
+
+struct ValueMapAccess {
+    entries: std::collections::btree_map::IntoIter<String, JsonValue>,
+    value: Option<JsonValue>,
+    key: String,
+}
+
+impl<'de> MapAccess<'de> for ValueMapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                self.key = key.clone();
+                seed.deserialize(JsonValue::String(key)).map(Some).map_err(|e| e.at_key(&self.key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value).map_err(|e| e.at_key(&self.key)),
+            None => Err(Error::custom("value requested before key".to_string())),
+        }
+    }
+}
+
+// Convert a JsonValue into any Deserialize type
+pub fn from_value<'de, T: Deserialize<'de>>(value: JsonValue) -> Result<T, Error> {
+    T::deserialize(value)
+}
+
+// Stream a document from one format into another, such as JSON into
+// MessagePack, without building a JsonValue in between: each value the
+// deserializer reads is handed straight to the serializer
+pub fn transcode<'de, D: Deserializer<'de>, S: Serializer>(deserializer: D, serializer: S) -> Result<S::Ok, S::Error> {
+    Transcoder::new(deserializer).serialize(serializer)
+}
+
+// A deserializer wrapped up as a Serialize value, which serializes as
+// whatever it reads. It can only be serialized once.
+pub struct Transcoder<D>(Cell<Option<D>>);
+
+impl<'de, D: Deserializer<'de>> Transcoder<D> {
+    pub fn new(deserializer: D) -> Self {
+        Transcoder(Cell::new(Some(deserializer)))
+    }
+}
+
+impl<'de, D: Deserializer<'de>> Serialize for Transcoder<D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.take() {
+            Some(deserializer) => deserializer
+                .deserialize_any(TranscodeVisitor(serializer))
+                .map_err(S::Error::custom),
+            None => Err(S::Error::custom("a Transcoder can only be serialized once")),
+        }
+    }
+}
+
+// Passes each value it visits on to the serializer. Errors from the
+// serializer are carried back through the deserializer as messages.
+struct TranscodeVisitor<S>(S);
+
+macro_rules! transcode_methods {
+    ($($visit:ident => $serialize:ident: $ty:ty;)*) => {
+        $(
+            fn $visit<E: CustomError>(self, v: $ty) -> Result<S::Ok, E> {
+                self.0.$serialize(v).map_err(E::custom)
+            }
+        )*
+    };
+}
+
+impl<'de, S: Serializer> Visitor<'de> for TranscodeVisitor<S> {
+    type Value = S::Ok;
+    
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "any value")
+    }
+    
+    transcode_methods! {
+        visit_bool => serialize_bool: bool;
+        visit_i8 => serialize_i8: i8;
+        visit_i16 => serialize_i16: i16;
+        visit_i32 => serialize_i32: i32;
+        visit_i64 => serialize_i64: i64;
+        visit_i128 => serialize_i128: i128;
+        visit_u8 => serialize_u8: u8;
+        visit_u16 => serialize_u16: u16;
+        visit_u32 => serialize_u32: u32;
+        visit_u64 => serialize_u64: u64;
+        visit_u128 => serialize_u128: u128;
+        visit_f32 => serialize_f32: f32;
+        visit_f64 => serialize_f64: f64;
+        visit_char => serialize_char: char;
+        visit_str => serialize_str: &str;
+        visit_number => serialize_number: &str;
+        visit_bytes => serialize_bytes: &[u8];
+    }
+    
+    fn visit_unit<E: CustomError>(self) -> Result<S::Ok, E> {
+        self.0.serialize_unit().map_err(E::custom)
+    }
+    
+    fn visit_none<E: CustomError>(self) -> Result<S::Ok, E> {
+        self.0.serialize_none().map_err(E::custom)
+    }
+    
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Ok, D::Error> {
+        self.0.serialize_some(&Transcoder::new(deserializer)).map_err(D::Error::custom)
+    }
+    
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Ok, D::Error> {
+        Transcoder::new(deserializer).serialize(self.0).map_err(D::Error::custom)
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<S::Ok, A::Error> {
+        let mut output = self.0.serialize_seq(None).map_err(A::Error::custom)?;
+        while seq.next_element_seed(ElementSeed(&mut output))?.is_some() {}
+        output.end().map_err(A::Error::custom)
+    }
+    
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<S::Ok, A::Error> {
+        let mut output = self.0.serialize_map(None).map_err(A::Error::custom)?;
+        while map.next_key_seed(KeySeed(&mut output))?.is_some() {
+            map.next_value_seed(ValueSeed(&mut output))?;
+        }
+        output.end().map_err(A::Error::custom)
+    }
+}
+
+// Seeds that transcode one element, key or value into the output
+struct ElementSeed<'a, T>(&'a mut T);
+struct KeySeed<'a, M>(&'a mut M);
+struct ValueSeed<'a, M>(&'a mut M);
+
+impl<'de, T: SerializeSeq> DeserializeSeed<'de> for ElementSeed<'_, T>
+where
+    T::Error: fmt::Display,
+{
+    type Value = ();
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.0.serialize_element(&Transcoder::new(deserializer)).map_err(D::Error::custom)
+    }
+}
+
+impl<'de, M: SerializeMap> DeserializeSeed<'de> for KeySeed<'_, M>
+where
+    M::Error: fmt::Display,
+{
+    type Value = ();
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.0.serialize_key(&Transcoder::new(deserializer)).map_err(D::Error::custom)
+    }
+}
+
+impl<'de, M: SerializeMap> DeserializeSeed<'de> for ValueSeed<'_, M>
+where
+    M::Error: fmt::Display,
+{
+    type Value = ();
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.0.serialize_value(&Transcoder::new(deserializer)).map_err(D::Error::custom)
+    }
+}
+
+// A UTC timestamp with nanosecond precision, for the years 0000 to 9999.
+// It serializes as an RFC 3339 string; the rfc3339, unix_seconds and
+// unix_millis modules choose a format per field with `#[serde(with = ...)]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    secs: i64,
+    nanos: u32,
+}
+
+// Unix times of 0000-01-01T00:00:00Z and 10000-01-01T00:00:00Z
+const MIN_UNIX_SECONDS: i64 = -62_167_219_200;
+const MAX_UNIX_SECONDS: i64 = 253_402_300_800;
+
+impl DateTime {
+    // None if the time is outside the supported years or nanos isn't below
+    // one second
+    pub fn from_unix(secs: i64, nanos: u32) -> Option<DateTime> {
+        if (MIN_UNIX_SECONDS..MAX_UNIX_SECONDS).contains(&secs) && nanos < 1_000_000_000 {
+            Some(DateTime { secs, nanos })
+        } else {
+            None
+        }
+    }
+    
+    pub fn from_unix_millis(millis: i64) -> Option<DateTime> {
+        let nanos = millis.rem_euclid(1000) as u32 * 1_000_000;
+        DateTime::from_unix(millis.div_euclid(1000), nanos)
+    }
+    
+    pub fn now() -> DateTime {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock is set before 1970");
+        DateTime {
+            secs: since_epoch.as_secs() as i64,
+            nanos: since_epoch.subsec_nanos(),
+        }
+    }
+    
+    pub fn unix_seconds(self) -> i64 {
+        self.secs
+    }
+    
+    pub fn unix_millis(self) -> i64 {
+        self.secs * 1000 + i64::from(self.nanos / 1_000_000)
+    }
+    
+    pub fn subsec_nanos(self) -> u32 {
+        self.nanos
+    }
+    
+    pub fn to_rfc3339(self) -> String {
+        self.to_string()
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, and back, using
+// Howard Hinnant's algorithms: years start in March so the leap day comes last
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Fractional seconds are written in groups of three digits, and left out
+// when they're zero
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.secs.div_euclid(86_400));
+        let time = self.secs.rem_euclid(86_400);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60,
+            time % 60
+        )?;
+        match self.nanos {
+            0 => {}
+            n if n % 1_000_000 == 0 => write!(f, ".{:03}", n / 1_000_000)?,
+            n if n % 1000 == 0 => write!(f, ".{:06}", n / 1000)?,
+            n => write!(f, ".{:09}", n)?,
+        }
+        write!(f, "Z")
+    }
+}
+
+// Parse `YYYY-MM-DDTHH:MM:SS[.fraction](Z|+HH:MM|-HH:MM)`. A lowercase `t`
+// or `z`, or a space between date and time, are accepted too. Digits past
+// nanoseconds are dropped.
+fn parse_rfc3339(text: &str) -> Option<DateTime> {
+    let bytes = text.as_bytes();
+    let number = |start: usize, len: usize| -> Option<u32> {
+        let digits = text.get(start..start + len)?;
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+    let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+    if bytes.len() < 20 || separators.iter().any(|&(i, c)| bytes[i] != c) || !matches!(bytes[10], b'T' | b't' | b' ') {
+        return None;
+    }
+    let year = i64::from(number(0, 4)?);
+    let (month, day) = (number(5, 2)?, number(8, 2)?);
+    let (hour, minute, second) = (number(11, 2)?, number(14, 2)?, number(17, 2)?);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    
+    let mut rest = &text[19..];
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return None;
+        }
+        for (i, digit) in fraction.bytes().take(9).take_while(u8::is_ascii_digit).enumerate() {
+            nanos += u32::from(digit - b'0') * 10u32.pow(8 - i as u32);
+        }
+        rest = &fraction[len..];
+    }
+    let offset = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let (hours, minutes) = (number(text.len() - 5, 2)?, number(text.len() - 2, 2)?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = i64::from(hours * 3600 + minutes * 60);
+            if *sign == b'-' { -offset } else { offset }
+        }
+        _ => return None,
+    };
+    let secs = days_from_civil(year, month, day) * 86_400
+        + i64::from(hour * 3600 + minute * 60 + second)
+        - offset;
+    DateTime::from_unix(secs, nanos)
+}
+
+impl FromStr for DateTime {
+    type Err = Error;
+    
+    fn from_str(s: &str) -> Result<DateTime, Error> {
+        parse_rfc3339(s).ok_or_else(|| Error::custom(format!("invalid RFC 3339 timestamp {:?}", s)))
+    }
+}
+
+impl Serialize for DateTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+struct DateTimeVisitor;
+
+impl<'de> Visitor<'de> for DateTimeVisitor {
+    type Value = DateTime;
+    
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an RFC 3339 timestamp")
+    }
+    
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<DateTime, E> {
+        parse_rfc3339(v).ok_or_else(|| self.invalid_value(&format!("string {:?}", v)))
+    }
+}
+
+impl<'de> Deserialize<'de> for DateTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(DateTimeVisitor)
+    }
+}
+
+// Reads a Unix timestamp as a whole number of seconds or milliseconds
+struct UnixVisitor {
+    millis: bool,
+}
+
+impl<'de> Visitor<'de> for UnixVisitor {
+    type Value = DateTime;
+    
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let unit = if self.millis { "milliseconds" } else { "seconds" };
+        write!(formatter, "a Unix timestamp in {}", unit)
+    }
+    
+    fn visit_i64<E: CustomError>(self, v: i64) -> Result<DateTime, E> {
+        let time = if self.millis { DateTime::from_unix_millis(v) } else { DateTime::from_unix(v, 0) };
+        time.ok_or_else(|| self.invalid_value(&format!("integer {}", v)))
+    }
+    
+    fn visit_u64<E: CustomError>(self, v: u64) -> Result<DateTime, E> {
+        match i64::try_from(v) {
+            Ok(v) => self.visit_i64(v),
+            Err(_) => Err(self.invalid_value(&format!("integer {}", v))),
+        }
+    }
+}
+
+// DateTime field formats for `#[serde(with = ...)]`. This one is the same
+// as DateTime's own impls, for fields that want to say so.
+pub mod rfc3339 {
+    use super::{DateTime, Deserialize, Deserializer, Serialize, Serializer};
+    
+    pub fn serialize<S: Serializer>(value: &DateTime, serializer: S) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime, D::Error> {
+        DateTime::deserialize(deserializer)
+    }
+}
+
+// Whole seconds since the Unix epoch; fractions of a second are dropped
+pub mod unix_seconds {
+    use super::{DateTime, Deserializer, Serializer, UnixVisitor};
+    
+    pub fn serialize<S: Serializer>(value: &DateTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(value.unix_seconds())
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime, D::Error> {
+        deserializer.deserialize_i64(UnixVisitor { millis: false })
+    }
+}
+
+// Whole milliseconds since the Unix epoch
+pub mod unix_millis {
+    use super::{DateTime, Deserializer, Serializer, UnixVisitor};
+    
+    pub fn serialize<S: Serializer>(value: &DateTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(value.unix_millis())
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime, D::Error> {
+        deserializer.deserialize_i64(UnixVisitor { millis: true })
+    }
+}
+
+// Ready-made modules for `#[serde(with = ...)]` fields
+pub mod with {
+    // Bytes as a standard base64 string (RFC 4648), for `Vec<u8>` fields:
+    // `#[serde(with = with::base64)] data: Vec<u8>`. Any `&[u8]` can be
+    // written, but reading always decodes into a new buffer. Reading
+    // accepts the string with or without its `=` padding.
+    pub mod base64 {
+        use crate::{CustomError, Deserializer, Serializer, Visitor};
+        use std::fmt;
+        
+        const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        
+        pub fn serialize<T: AsRef<[u8]> + ?Sized, S: Serializer>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&encode(bytes.as_ref()))
+        }
+        
+        // Into a `Vec<u8>`, or anything that can be made from one
+        pub fn deserialize<'de, T: From<Vec<u8>>, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+            deserializer.deserialize_str(Base64Visitor).map(T::from)
+        }
+        
+        pub fn encode(bytes: &[u8]) -> String {
+            let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+            for chunk in bytes.chunks(3) {
+                let group = chunk.iter().enumerate().fold(0u32, |group, (i, &b)| group | u32::from(b) << (16 - 8 * i));
+                for i in 0..4 {
+                    if i <= chunk.len() {
+                        output.push(char::from(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize]));
+                    } else {
+                        output.push('=');
+                    }
+                }
+            }
+            output
+        }
+        
+        // None if the text has a character outside the alphabet, padding
+        // anywhere but the end, or a length no bytes encode to
+        pub fn decode(text: &str) -> Option<Vec<u8>> {
+            let data = text.trim_end_matches('=');
+            let padding = text.len() - data.len();
+            if padding > 2 || (padding > 0 && !text.len().is_multiple_of(4)) || data.len() % 4 == 1 {
+                return None;
+            }
+            let mut output = Vec::with_capacity(data.len() * 3 / 4);
+            let (mut group, mut bits) = (0u32, 0);
+            for b in data.bytes() {
+                let value = ALPHABET.iter().position(|&c| c == b)? as u32;
+                group = group << 6 | value;
+                bits += 6;
+                if bits >= 8 {
+                    bits -= 8;
+                    output.push((group >> bits) as u8);
+                    group &= (1 << bits) - 1;
+                }
+            }
+            Some(output)
+        }
+        
+        struct Base64Visitor;
+        
+        impl<'de> Visitor<'de> for Base64Visitor {
+            type Value = Vec<u8>;
+            
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a base64 string")
+            }
+            
+            fn visit_str<E: CustomError>(self, v: &str) -> Result<Vec<u8>, E> {
+                decode(v).ok_or_else(|| self.invalid_value(&format!("string {:?}", v)))
+            }
+        }
+    }
+}
+
+// How an enum derived with derive_enum! is laid out, following Serde's
+// `#[serde(...)]` attributes:
+//
+// - External (the default): `"Unit"`, `{"Variant": payload}`
+// - Internal (`tag = "type"`): `{"type": "Variant", ...fields}`
+// - Adjacent (`tag = "t", content = "c"`): `{"t": "Variant", "c": payload}`
+// - Untagged: just the payload; deserializing tries each variant in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnumTagging {
+    External,
+    Internal(&'static str),
+    Adjacent(&'static str, &'static str),
+    Untagged,
+}
+
+impl EnumTagging {
+    // The key internally tagged struct variants write before their fields
+    pub fn internal_tag(&self) -> Option<&'static str> {
+        match self {
+            EnumTagging::Internal(tag) => Some(tag),
+            _ => None,
+        }
+    }
+}
+
+// Implemented by derive_enum!, so the helpers below can look up the enum's
+// variants and build one from a variant name and its payload
+pub trait EnumVariants: Sized {
+    const NAME: &'static str;
+    const VARIANTS: &'static [&'static str];
+    const TAGGING: EnumTagging;
+    
+    fn from_variant<'de, C: VariantContent<'de>>(variant: &str, content: C) -> Result<Self, C::Error>;
+}
+
+// The payload of a variant being deserialized, read in place or from a
+// buffered JsonValue depending on the representation
+pub trait VariantContent<'de> {
+    type Error: CustomError;
+    
+    fn unit(self) -> Result<(), Self::Error>;
+    fn value<T: Deserialize<'de>>(self) -> Result<T, Self::Error>;
+}
+
+// The value after an externally tagged variant's key
+struct MapContent<'a, M>(&'a mut M);
+
+impl<'de, M: MapAccess<'de>> VariantContent<'de> for MapContent<'_, M> {
+    type Error = M::Error;
+    
+    fn unit(self) -> Result<(), M::Error> {
+        self.0.next_value()
+    }
+    
+    fn value<T: Deserialize<'de>>(self) -> Result<T, M::Error> {
+        self.0.next_value()
+    }
+}
+
+// A payload that has already been read into a JsonValue. `None` means there
+// was no payload at all, as for a bare unit variant name.
+struct BufferedContent<E> {
+    content: Option<JsonValue>,
+    marker: PhantomData<E>,
+}
+
+impl<E> BufferedContent<E> {
+    fn new(content: Option<JsonValue>) -> Self {
+        BufferedContent { content, marker: PhantomData }
+    }
+}
+
+impl<'de, E: CustomError> VariantContent<'de> for BufferedContent<E> {
+    type Error = E;
+    
+    fn unit(self) -> Result<(), E> {
+        match self.content {
+            None | Some(JsonValue::Null) => Ok(()),
+            // What's left of an internally tagged unit variant once the tag is removed
+            Some(JsonValue::Object(entries)) if entries.is_empty() => Ok(()),
+            Some(other) => Err(E::custom(format!("invalid type: {}, expected unit variant", other.describe()))),
+        }
+    }
+    
+    fn value<T: Deserialize<'de>>(self) -> Result<T, E> {
+        from_value(self.content.unwrap_or(JsonValue::Null)).map_err(E::custom)
+    }
+}
+
+#[doc(hidden)]
+pub fn unknown_variant<E: CustomError>(variant: &str, expected: &[&str]) -> E {
+    let expected: Vec<String> = expected.iter().map(|name| format!("`{}`", name)).collect();
+    E::custom(format!("unknown variant `{}`, expected one of {}", variant, expected.join(", ")))
+}
+
+// A field a struct variant's input left out is an error unless the field's
+// type accepts null, as Option does
+#[doc(hidden)]
+pub fn missing_field<'de, T: Deserialize<'de>, E: CustomError>(field: &str) -> Result<T, E> {
+    from_value(JsonValue::Null).map_err(|_| E::custom(format!("missing field `{}`", field)))
+}
+
+#[doc(hidden)]
+pub fn serialize_unit_variant<T: EnumVariants, S: Serializer>(serializer: S, variant: &'static str) -> Result<S::Ok, S::Error> {
+    match T::TAGGING {
+        EnumTagging::External => serializer.serialize_str(variant),
+        EnumTagging::Internal(tag) | EnumTagging::Adjacent(tag, _) => {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry(&tag, &variant)?;
+            map.end()
+        }
+        EnumTagging::Untagged => serializer.serialize_unit(),
+    }
+}
+
+#[doc(hidden)]
+pub fn serialize_newtype_variant<T: EnumVariants, S: Serializer, V: Serialize>(
+    serializer: S,
+    variant: &'static str,
+    value: &V,
+) -> Result<S::Ok, S::Error> {
+    match T::TAGGING {
+        EnumTagging::External => {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry(&variant, value)?;
+            map.end()
+        }
+        // The tag goes alongside the payload's own fields, so the payload
+        // has to be a map
+        EnumTagging::Internal(tag) => {
+            let entries = match to_value(value).map_err(S::Error::custom)? {
+                JsonValue::Object(entries) => entries,
+                JsonValue::Null => BTreeMap::new(),
+                other => {
+                    return Err(S::Error::custom(format!(
+                        "cannot serialize tagged newtype variant {}::{} containing {}",
+                        T::NAME,
+                        variant,
+                        other.describe()
+                    )))
+                }
+            };
+            let mut map = serializer.serialize_map(Some(entries.len() + 1))?;
+            map.serialize_entry(&tag, &variant)?;
+            for (key, value) in &entries {
+                map.serialize_entry(key, value)?;
+            }
+            map.end()
+        }
+        EnumTagging::Adjacent(tag, content) => {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry(&tag, &variant)?;
+            map.serialize_entry(&content, value)?;
+            map.end()
+        }
+        EnumTagging::Untagged => value.serialize(serializer),
+    }
+}
+
+// Struct variants are serialized like a newtype variant holding the fields,
+// except that internally tagged fields write the tag themselves
+#[doc(hidden)]
+pub fn serialize_struct_variant<T: EnumVariants, S: Serializer, V: Serialize>(
+    serializer: S,
+    variant: &'static str,
+    fields: &V,
+) -> Result<S::Ok, S::Error> {
+    match T::TAGGING {
+        EnumTagging::Internal(_) => fields.serialize(serializer),
+        _ => serialize_newtype_variant::<T, S, V>(serializer, variant, fields),
+    }
+}
+
+struct ExternalVisitor<T>(PhantomData<T>);
+
+impl<'de, T: EnumVariants> Visitor<'de> for ExternalVisitor<T> {
+    type Value = T;
+    
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "enum {}", T::NAME)
+    }
+    
+    fn visit_str<E: CustomError>(self, v: &str) -> Result<T, E> {
+        T::from_variant(v, BufferedContent::new(None))
+    }
+    
+    fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<T, M::Error> {
+        let Some(variant) = map.next_key::<String>()? else {
+            return Err(self.invalid_type("empty map"));
+        };
+        let value = T::from_variant(&variant, MapContent(&mut map))?;
+        if map.next_key::<String>()?.is_some() {
+            return Err(self.invalid_type("map with more than one key"));
+        }
+        Ok(value)
+    }
+}
+
+#[doc(hidden)]
+pub fn deserialize_enum<'de, T: EnumVariants, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    // Only the externally tagged form can be read in place; the others need
+    // the whole value first, to find the tag or to try each variant
+    let (tag, content) = match T::TAGGING {
+        EnumTagging::External => return deserializer.deserialize_any(ExternalVisitor(PhantomData)),
+        EnumTagging::Internal(tag) => (tag, None),
+        EnumTagging::Adjacent(tag, content) => (tag, Some(content)),
+        EnumTagging::Untagged => {
+            let value = JsonValue::deserialize(deserializer)?;
+            for variant in T::VARIANTS {
+                let content = BufferedContent::<Error>::new(Some(value.clone()));
+                if let Ok(value) = T::from_variant(variant, content) {
+                    return Ok(value);
+                }
+            }
+            return Err(D::Error::custom(format!(
+                "data did not match any variant of untagged enum {}",
+                T::NAME
+            )));
+        }
+    };
+    let mut entries = match JsonValue::deserialize(deserializer)? {
+        JsonValue::Object(entries) => entries,
+        other => {
+            return Err(D::Error::custom(format!(
+                "invalid type: {}, expected enum {}",
+                other.describe(),
+                T::NAME
+            )))
+        }
+    };
+    let variant = match entries.remove(tag) {
+        Some(JsonValue::String(variant)) => variant,
+        Some(other) => {
+            return Err(D::Error::custom(format!(
+                "invalid type: {}, expected a variant name at {}",
+                other.describe(),
+                tag
+            )))
+        }
+        None => return Err(D::Error::custom(format!("missing field `{}`", tag))),
+    };
+    let content = match content {
+        Some(content) => entries.remove(content),
+        None => Some(JsonValue::Object(entries)),
+    };
+    T::from_variant(&variant, BufferedContent::new(content))
+}
+
+// Serializes a `#[serde(flatten)]` field by writing its entries into the map
+// of the struct that holds it. Structs and maps flatten; None and unit add
+// nothing; anything else is an error.
+pub struct FlatMapSerializer<'a, M>(pub &'a mut M);
+
+fn flatten_error<E: CustomError>(found: &str) -> E {
+    E::custom(format!("can only flatten structs and maps, not {}", found))
+}
+
+// Scalars have no keys to merge into the parent
+macro_rules! flat_map_scalars {
+    ($($method:ident($ty:ty) => $found:expr;)*) => {
+        $(
+            fn $method(self, _v: $ty) -> Result<(), M::Error> {
+                Err(flatten_error($found))
+            }
+        )*
+    };
+}
+
+impl<'a, M: SerializeMap> Serializer for FlatMapSerializer<'a, M>
+where
+    M::Error: CustomError,
+{
+    type Ok = ();
+    type Error = M::Error;
+    type SerializeSeq = FlatMapCompound<'a, M>;
+    type SerializeTuple = FlatMapCompound<'a, M>;
+    type SerializeMap = FlatMapCompound<'a, M>;
+    type SerializeStruct = FlatMapCompound<'a, M>;
+    
+    flat_map_scalars! {
+        serialize_bool(bool) => "a boolean";
+        serialize_i8(i8) => "an integer";
+        serialize_i16(i16) => "an integer";
+        serialize_i32(i32) => "an integer";
+        serialize_i64(i64) => "an integer";
+        serialize_i128(i128) => "an integer";
+        serialize_u8(u8) => "an integer";
+        serialize_u16(u16) => "an integer";
+        serialize_u32(u32) => "an integer";
+        serialize_u64(u64) => "an integer";
+        serialize_u128(u128) => "an integer";
+        serialize_f32(f32) => "a float";
+        serialize_f64(f64) => "a float";
+        serialize_char(char) => "a char";
+        serialize_str(&str) => "a string";
+        serialize_bytes(&[u8]) => "bytes";
+    }
+    
+    fn serialize_unit(self) -> Result<(), M::Error> {
+        Ok(())
+    }
+    
+    fn serialize_none(self) -> Result<(), M::Error> {
+        Ok(())
+    }
+    
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<(), M::Error> {
+        value.serialize(self)
+    }
+    
+    fn serialize_seq(self, _len: Option<usize>) -> Result<FlatMapCompound<'a, M>, M::Error> {
+        Err(flatten_error("a sequence"))
+    }
+    
+    fn serialize_tuple(self, _len: usize) -> Result<FlatMapCompound<'a, M>, M::Error> {
+        Err(flatten_error("a tuple"))
+    }
+    
+    fn serialize_map(self, _len: Option<usize>) -> Result<FlatMapCompound<'a, M>, M::Error> {
+        Ok(FlatMapCompound(self.0))
+    }
+    
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<FlatMapCompound<'a, M>, M::Error> {
+        Ok(FlatMapCompound(self.0))
+    }
+}
+
+// Passes a flattened map's or struct's entries through to the parent map
+pub struct FlatMapCompound<'a, M>(&'a mut M);
+
+impl<M: SerializeMap> SerializeMap for FlatMapCompound<'_, M> {
+    type Ok = ();
+    type Error = M::Error;
+    
+    fn serialize_key<T: Serialize>(&mut self, key: &T) -> Result<(), M::Error> {
+        self.0.serialize_key(key)
+    }
+    
+    fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), M::Error> {
+        self.0.serialize_value(value)
+    }
+    
+    fn end(self) -> Result<(), M::Error> {
+        Ok(())
+    }
+}
+
+impl<M: SerializeMap> SerializeStruct for FlatMapCompound<'_, M> {
+    type Ok = ();
+    type Error = M::Error;
+    
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), M::Error> {
+        self.0.serialize_entry(&key, value)
+    }
+    
+    fn end(self) -> Result<(), M::Error> {
+        Ok(())
+    }
+}
+
+// Never constructed, since serialize_seq and serialize_tuple fail first
+impl<M: SerializeMap> SerializeSeq for FlatMapCompound<'_, M>
+where
+    M::Error: CustomError,
+{
+    type Ok = ();
+    type Error = M::Error;
+    
+    fn serialize_element<T: Serialize>(&mut self, _value: &T) -> Result<(), M::Error> {
+        Err(flatten_error("a sequence"))
+    }
+    
+    fn end(self) -> Result<(), M::Error> {
+        Ok(())
+    }
+}
+
+impl<M: SerializeMap> SerializeTuple for FlatMapCompound<'_, M>
+where
+    M::Error: CustomError,
+{
+    type Ok = ();
+    type Error = M::Error;
+    
+    fn serialize_element<T: Serialize>(&mut self, _value: &T) -> Result<(), M::Error> {
+        Err(flatten_error("a tuple"))
+    }
+    
+    fn end(self) -> Result<(), M::Error> {
+        Ok(())
+    }
+}
+
+// The state of a struct derived with field attributes: written as a struct,
+// or as a map when a field is flattened, since flattened keys aren't known
+// until the field is serialized
+#[doc(hidden)]
+pub enum StructState<S: Serializer> {
+    Struct(S::SerializeStruct),
+    Map(S::SerializeMap),
+}
+
+impl<S: Serializer> StructState<S> {
+    pub fn new(serializer: S, name: &'static str, len: usize, flatten: bool) -> Result<Self, S::Error> {
+        if flatten {
+            serializer.serialize_map(None).map(StructState::Map)
+        } else {
+            serializer.serialize_struct(name, len).map(StructState::Struct)
+        }
+    }
+    
+    pub fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), S::Error> {
+        match self {
+            StructState::Struct(state) => state.serialize_field(key, value),
+            StructState::Map(map) => map.serialize_entry(&key, value),
+        }
+    }
+    
+    pub fn flatten<T: Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+        match self {
+            StructState::Map(map) => value.serialize(FlatMapSerializer(map)),
+            StructState::Struct(_) => Err(S::Error::custom("flattened fields need a map")),
+        }
+    }
+    
+    pub fn end(self) -> Result<S::Ok, S::Error> {
+        match self {
+            StructState::Struct(state) => state.end(),
+            StructState::Map(map) => map.end(),
+        }
+    }
+}
+
+// A `with` field's module may not handle a missing value, so it's an error
+#[doc(hidden)]
+pub fn required_field<T, E: CustomError>(field: &str) -> Result<T, E> {
+    Err(E::custom(format!("missing field `{}`", field)))
+}
+
+#[doc(hidden)]
+pub fn unknown_field<E: CustomError>(field: &str, expected: &[&str]) -> E {
+    if expected.is_empty() {
+        return E::custom(format!("unknown field `{}`, there are no fields", field));
+    }
+    let expected: Vec<String> = expected.iter().map(|name| format!("`{}`", name)).collect();
+    E::custom(format!("unknown field `{}`, expected one of {}", field, expected.join(", ")))
+}
+
+// A flattened field is deserialized from every key its struct didn't claim
+#[doc(hidden)]
+pub fn flatten_value<'de, T: Deserialize<'de>, E: CustomError>(rest: &BTreeMap<String, JsonValue>) -> Result<T, E> {
+    from_value(JsonValue::Object(rest.clone())).map_err(E::custom)
+}
+
+// Format-independent testing, like the serde_test crate: a value is checked
+// against the sequence of Serializer calls it makes, or built from a
+// sequence of tokens, without going through JSON or any other format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    // Serializing only ever produces Str and Bytes; the borrowed and owned
+    // forms choose which visitor method deserializing calls
+    Str(&'static str),
+    BorrowedStr(&'static str),
+    String(&'static str),
+    Bytes(&'static [u8]),
+    BorrowedBytes(&'static [u8]),
+    ByteBuf(&'static [u8]),
+    None,
+    // Followed by the value
+    Some,
+    Unit,
+    // Followed by the value inside
+    NewtypeStruct { name: &'static str },
+    UnitStruct { name: &'static str },
+    // Followed by the elements, then SeqEnd
+    Seq { len: Option<usize> },
+    SeqEnd,
+    Tuple { len: usize },
+    TupleEnd,
+    // Followed by alternating keys and values, then MapEnd
+    Map { len: Option<usize> },
+    MapEnd,
+    // Followed by a Str for each field name and its value, then StructEnd
+    Struct { name: &'static str, len: usize },
+    StructEnd,
+}
+
+impl Token {
+    // Whether a token the serializer produced stands for this expected one
+    fn matches(&self, actual: &Token) -> bool {
+        match (self, actual) {
+            (Token::Str(a) | Token::BorrowedStr(a) | Token::String(a), Token::Str(b)) => a == b,
+            (Token::Bytes(a) | Token::BorrowedBytes(a) | Token::ByteBuf(a), Token::Bytes(b)) => a == b,
+            _ => self == actual,
+        }
+    }
+}
+
+// Checks each Serializer call against the next expected token
+pub struct TokenSerializer<'a> {
+    tokens: &'a [Token],
+}
+
+impl<'a> TokenSerializer<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        TokenSerializer { tokens }
+    }
+    
+    // The tokens not yet serialized
+    pub fn remaining(&self) -> &'a [Token] {
+        self.tokens
+    }
+    
+    fn emit(&mut self, actual: Token) -> Result<(), Error> {
+        match self.tokens.split_first() {
+            Some((expected, rest)) if expected.matches(&actual) => {
+                self.tokens = rest;
+                Ok(())
+            }
+            Some((expected, _)) => Err(Error::custom(format!(
+                "expected Token::{:?} but serialized as Token::{:?}",
+                expected, actual
+            ))),
+            None => Err(Error::custom(format!(
+                "expected end of tokens, but serialized as Token::{:?}",
+                actual
+            ))),
+        }
+    }
+}
+
+macro_rules! token_serialize_methods {
+    ($($method:ident($ty:ty) => $token:ident;)*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<(), Error> {
+                self.emit(Token::$token(v))
+            }
+        )*
+    };
+}
+
+impl<'a, 'b> Serializer for &'b mut TokenSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    
+    token_serialize_methods! {
+        serialize_bool(bool) => Bool;
+        serialize_i8(i8) => I8;
+        serialize_i16(i16) => I16;
+        serialize_i32(i32) => I32;
+        serialize_i64(i64) => I64;
+        serialize_i128(i128) => I128;
+        serialize_u8(u8) => U8;
+        serialize_u16(u16) => U16;
+        serialize_u32(u32) => U32;
+        serialize_u64(u64) => U64;
+        serialize_u128(u128) => U128;
+        serialize_f32(f32) => F32;
+        serialize_f64(f64) => F64;
+        serialize_char(char) => Char;
+    }
+    
+    // The expected token owns 'static data, so compare before converting
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        match self.tokens.first() {
+            Some(Token::Str(s) | Token::BorrowedStr(s) | Token::String(s)) if *s == v => self.emit(Token::Str(s)),
+            _ => Err(Error::custom(format!(
+                "expected {} but serialized as Token::Str({:?})",
+                describe_next(self.tokens),
+                v
+            ))),
+        }
+    }
+    
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        match self.tokens.first() {
+            Some(Token::Bytes(b) | Token::BorrowedBytes(b) | Token::ByteBuf(b)) if *b == v => self.emit(Token::Bytes(b)),
+            _ => Err(Error::custom(format!(
+                "expected {} but serialized as Token::Bytes({:?})",
+                describe_next(self.tokens),
+                v
+            ))),
+        }
+    }
+    
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.emit(Token::Unit)
+    }
+    
+    fn serialize_none(self) -> Result<(), Error> {
+        self.emit(Token::None)
+    }
+    
+    fn serialize_some<T: Serialize>(self, value: &T) -> Result<(), Error> {
+        self.emit(Token::Some)?;
+        value.serialize(self)
+    }
+    
+    fn serialize_newtype_struct<T: Serialize>(self, name: &'static str, value: &T) -> Result<(), Error> {
+        self.emit(Token::NewtypeStruct { name })?;
+        value.serialize(self)
+    }
+    
+    fn serialize_unit_struct(self, name: &'static str) -> Result<(), Error> {
+        self.emit(Token::UnitStruct { name })
+    }
+    
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, Error> {
+        self.emit(Token::Seq { len })?;
+        Ok(self)
+    }
+    
+    fn serialize_tuple(self, len: usize) -> Result<Self, Error> {
+        self.emit(Token::Tuple { len })?;
+        Ok(self)
+    }
+    
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, Error> {
+        self.emit(Token::Map { len })?;
+        Ok(self)
+    }
+    
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self, Error> {
+        self.emit(Token::Struct { name, len })?;
+        Ok(self)
+    }
+}
+
+fn describe_next(tokens: &[Token]) -> String {
+    match tokens.first() {
+        Some(token) => format!("Token::{:?}", token),
+        None => "end of tokens".to_string(),
+    }
+}
+
+impl SerializeSeq for &mut TokenSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+    
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+    
+    fn end(self) -> Result<(), Error> {
+        self.emit(Token::SeqEnd)
+    }
+}
+
+impl SerializeTuple for &mut TokenSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+    
+    fn serialize_element<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+    
+    fn end(self) -> Result<(), Error> {
+        self.emit(Token::TupleEnd)
+    }
+}
+
+impl SerializeMap for &mut TokenSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+    
+    fn serialize_key<T: Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        key.serialize(&mut **self)
+    }
+    
+    fn serialize_value<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+    
+    fn end(self) -> Result<(), Error> {
+        self.emit(Token::MapEnd)
+    }
+}
+
+impl SerializeStruct for &mut TokenSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+    
+    fn serialize_field<T: Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        key.serialize(&mut **self)?;
+        value.serialize(&mut **self)
+    }
+    
+    fn end(self) -> Result<(), Error> {
+        self.emit(Token::StructEnd)
     }
 }
 
-impl Serialize for i32 {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_i32(*self)
-    }
+// Feeds tokens to a visitor. Tokens describe themselves, so every
+// deserialize_* method reads whatever comes next.
+pub struct TokenDeserializer<'de> {
+    tokens: &'de [Token],
 }
 
-impl Serialize for i64 {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_i64(*self)
+impl<'de> TokenDeserializer<'de> {
+    pub fn new(tokens: &'de [Token]) -> Self {
+        TokenDeserializer { tokens }
+    }
+    
+    // The tokens not yet deserialized
+    pub fn remaining(&self) -> &'de [Token] {
+        self.tokens
+    }
+    
+    fn next(&mut self) -> Result<Token, Error> {
+        match self.tokens.split_first() {
+            Some((token, rest)) => {
+                self.tokens = rest;
+                Ok(*token)
+            }
+            None => Err(Error::custom("unexpected end of tokens")),
+        }
+    }
+    
+    fn expect_end(&mut self, end: Token) -> Result<(), Error> {
+        match self.next()? {
+            token if token == end => Ok(()),
+            token => Err(Error::custom(format!("expected Token::{:?} but found Token::{:?}", end, token))),
+        }
     }
 }
 
-impl Serialize for f64 {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_f64(*self)
+macro_rules! token_deserialize_methods {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.deserialize_any(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for &mut TokenDeserializer<'de> {
+    type Error = Error;
+    
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.next()? {
+            Token::Bool(v) => visitor.visit_bool(v),
+            Token::I8(v) => visitor.visit_i8(v),
+            Token::I16(v) => visitor.visit_i16(v),
+            Token::I32(v) => visitor.visit_i32(v),
+            Token::I64(v) => visitor.visit_i64(v),
+            Token::I128(v) => visitor.visit_i128(v),
+            Token::U8(v) => visitor.visit_u8(v),
+            Token::U16(v) => visitor.visit_u16(v),
+            Token::U32(v) => visitor.visit_u32(v),
+            Token::U64(v) => visitor.visit_u64(v),
+            Token::U128(v) => visitor.visit_u128(v),
+            Token::F32(v) => visitor.visit_f32(v),
+            Token::F64(v) => visitor.visit_f64(v),
+            Token::Char(v) => visitor.visit_char(v),
+            Token::Str(v) => visitor.visit_str(v),
+            Token::BorrowedStr(v) => visitor.visit_borrowed_str(v),
+            Token::String(v) => visitor.visit_string(v.to_string()),
+            Token::Bytes(v) => visitor.visit_bytes(v),
+            Token::BorrowedBytes(v) => visitor.visit_borrowed_bytes(v),
+            Token::ByteBuf(v) => visitor.visit_byte_buf(v.to_vec()),
+            Token::None => visitor.visit_none(),
+            Token::Some => visitor.visit_some(self),
+            Token::Unit | Token::UnitStruct { .. } => visitor.visit_unit(),
+            Token::NewtypeStruct { .. } => visitor.visit_newtype_struct(self),
+            Token::Seq { .. } => {
+                let value = visitor.visit_seq(TokenAccess { de: &mut *self, end: Token::SeqEnd })?;
+                self.expect_end(Token::SeqEnd)?;
+                Ok(value)
+            }
+            Token::Tuple { .. } => {
+                let value = visitor.visit_seq(TokenAccess { de: &mut *self, end: Token::TupleEnd })?;
+                self.expect_end(Token::TupleEnd)?;
+                Ok(value)
+            }
+            Token::Map { .. } => {
+                let value = visitor.visit_map(TokenAccess { de: &mut *self, end: Token::MapEnd })?;
+                self.expect_end(Token::MapEnd)?;
+                Ok(value)
+            }
+            Token::Struct { .. } => {
+                let value = visitor.visit_map(TokenAccess { de: &mut *self, end: Token::StructEnd })?;
+                self.expect_end(Token::StructEnd)?;
+                Ok(value)
+            }
+            end @ (Token::SeqEnd | Token::TupleEnd | Token::MapEnd | Token::StructEnd) => {
+                Err(Error::custom(format!("unexpected Token::{:?}", end)))
+            }
+        }
+    }
+    
+    // Unit reads as None too, as it does in serde_test
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.tokens.first() {
+            Some(Token::None | Token::Unit) => {
+                self.next()?;
+                visitor.visit_none()
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+    
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+    
+    // The NewtypeStruct token is optional, as the value alone is what other
+    // formats give
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        if let Some(Token::NewtypeStruct { .. }) = self.tokens.first() {
+            self.next()?;
+        }
+        visitor.visit_newtype_struct(self)
+    }
+    
+    token_deserialize_methods! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_unit deserialize_seq deserialize_map
     }
 }
 
-impl Serialize for str {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_str(self)
+// The elements of a Seq or Tuple, or the entries of a Map or Struct, up to
+// their end token
+struct TokenAccess<'a, 'de> {
+    de: &'a mut TokenDeserializer<'de>,
+    end: Token,
+}
+
+impl TokenAccess<'_, '_> {
+    fn at_end(&self) -> bool {
+        self.de.tokens.first() == Some(&self.end)
     }
 }
 
-impl Serialize for String {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_str(self)
+impl<'de> SeqAccess<'de> for TokenAccess<'_, 'de> {
+    type Error = Error;
+    
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        if self.at_end() {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
     }
 }
 
-impl<T: Serialize> Serialize for Option<T> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        match self {
-            Some(value) => serializer.serialize_some(value),
-            None => serializer.serialize_none(),
+impl<'de> MapAccess<'de> for TokenAccess<'_, 'de> {
+    type Error = Error;
+    
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        if self.at_end() {
+            return Ok(None);
         }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+    
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de)
     }
 }
 
-impl<T: Serialize> Serialize for Vec<T> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut seq = serializer.serialize_seq(Some(self.len()))?;
-        for element in self {
-            seq.serialize_element(element)?;
+// Assert that a value serializes to exactly these tokens
+pub fn assert_ser_tokens<T: Serialize>(value: &T, tokens: &[Token]) {
+    let mut serializer = TokenSerializer::new(tokens);
+    if let Err(e) = value.serialize(&mut serializer) {
+        panic!("{}", e);
+    }
+    if !serializer.remaining().is_empty() {
+        panic!("{} expected tokens were not serialized: {:?}", serializer.remaining().len(), serializer.remaining());
+    }
+}
+
+// Assert that serializing fails with this error, after producing the tokens
+pub fn assert_ser_tokens_error<T: Serialize>(value: &T, tokens: &[Token], error: &str) {
+    let mut serializer = TokenSerializer::new(tokens);
+    match value.serialize(&mut serializer) {
+        Ok(()) => panic!("value serialized successfully, expected error: {}", error),
+        Err(e) if e.to_string() != error => panic!("expected error {:?}, got {:?}", error, e.to_string()),
+        Err(_) => {}
+    }
+    if !serializer.remaining().is_empty() {
+        panic!("{} expected tokens were not serialized: {:?}", serializer.remaining().len(), serializer.remaining());
+    }
+}
+
+// Assert that these tokens deserialize to the value, using all of them
+pub fn assert_de_tokens<'de, T: Deserialize<'de> + PartialEq + fmt::Debug>(value: &T, tokens: &'de [Token]) {
+    let mut deserializer = TokenDeserializer::new(tokens);
+    match T::deserialize(&mut deserializer) {
+        Ok(result) if result == *value => {}
+        Ok(result) => panic!("tokens deserialized to {:?}, expected {:?}", result, value),
+        Err(e) => panic!("tokens failed to deserialize: {}", e),
+    }
+    if !deserializer.remaining().is_empty() {
+        panic!("{} tokens were left over: {:?}", deserializer.remaining().len(), deserializer.remaining());
+    }
+}
+
+// Assert that deserializing these tokens fails with this error
+pub fn assert_de_tokens_error<'de, T: Deserialize<'de> + fmt::Debug>(tokens: &'de [Token], error: &str) {
+    match T::deserialize(&mut TokenDeserializer::new(tokens)) {
+        Ok(result) => panic!("tokens deserialized to {:?}, expected error: {}", result, error),
+        Err(e) if e.to_string() != error => panic!("expected error {:?}, got {:?}", error, e.to_string()),
+        Err(_) => {}
+    }
+}
+
+// Assert both directions: the value serializes to the tokens, and the tokens
+// deserialize back to the value
+pub fn assert_tokens<'de, T: Serialize + Deserialize<'de> + PartialEq + fmt::Debug>(value: &T, tokens: &'de [Token]) {
+    assert_ser_tokens(value, tokens);
+    assert_de_tokens(value, tokens);
+}
+
+// Property testing for the format backends: random JsonValue trees are
+// written, read back and written again by every format, which must give
+// the same output both times. Strings lean on characters that need
+// escaping or quoting, and numbers on the edges of their types.
+pub struct ValueGenerator {
+    state: u64,
+}
+
+// Text that is easy to escape or quote wrongly
+const TRICKY_STRINGS: &[&str] = &[
+    "", " ", "\"", "\\", "\n", "\t\r", "\u{0}", "\u{1f}", "\u{7f}", "é", "日本", "😀", "\u{2028}",
+    "true", "null", "~", "-", "- x", "a: b", "#", "[1]", "{}", "'", "0", "1e3", "-0", "0x10", "NaN", ".5",
+];
+
+impl ValueGenerator {
+    pub fn new(seed: u64) -> Self {
+        // xorshift needs a nonzero state
+        ValueGenerator { state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1 }
+    }
+    
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+    
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+    
+    // A random value whose arrays and objects nest at most `depth` levels
+    pub fn value(&mut self, depth: usize) -> JsonValue {
+        let kinds = if depth == 0 { 4 } else { 6 };
+        match self.below(kinds) {
+            0 => [JsonValue::Null, JsonValue::Bool(false), JsonValue::Bool(true)][self.below(3)].clone(),
+            1 | 2 => JsonValue::Number(self.number()),
+            3 => JsonValue::String(self.string()),
+            4 => JsonValue::Array((0..self.below(5)).map(|_| self.value(depth - 1)).collect()),
+            _ => JsonValue::Object((0..self.below(5)).map(|_| (self.string(), self.value(depth - 1))).collect()),
         }
-        seq.end()
+    }
+    
+    fn number(&mut self) -> Number {
+        match self.below(6) {
+            0 => Number::Int([i64::MIN, i64::MAX, -1, 0][self.below(4)]),
+            1 => Number::UInt([u64::MAX, 1 << 53, (1 << 53) + 1][self.below(3)]),
+            2 => Number::Int(self.next() as i64),
+            3 => Number::Float([0.1, 1e300, -1e-300, 5e-324, f64::MAX, 0.5, -2.5][self.below(7)]),
+            // Any finite float, from its bits
+            _ => loop {
+                let v = f64::from_bits(self.next());
+                if v.is_finite() {
+                    break Number::Float(v);
+                }
+            },
+        }
+    }
+    
+    fn string(&mut self) -> String {
+        (0..self.below(4))
+            .map(|_| match self.below(3) {
+                0 => char::from(b' ' + self.below(95) as u8).to_string(),
+                _ => TRICKY_STRINGS[self.below(TRICKY_STRINGS.len())].to_string(),
+            })
+            .collect()
     }
 }
 
-impl<K: Serialize, V: Serialize> Serialize for HashMap<K, V> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut map = serializer.serialize_map(Some(self.len()))?;
-        for (key, value) in self {
-            map.serialize_entry(key, value)?;
+type WriteFn = fn(&JsonValue) -> Result<Vec<u8>, Error>;
+type ReadFn = fn(&[u8]) -> Result<JsonValue, Error>;
+
+fn read_text(bytes: &[u8]) -> Result<&str, Error> {
+    std::str::from_utf8(bytes).map_err(Error::custom)
+}
+
+// Every format that can hold any JsonValue
+const ROUND_TRIP_FORMATS: &[(&str, WriteFn, ReadFn)] = &[
+    ("JSON", |v| to_json(v).map(String::into_bytes), |b| from_json(read_text(b)?)),
+    ("canonical JSON", |v| to_canonical_json(v).map(String::into_bytes), |b| from_json(read_text(b)?)),
+    ("JsonWriter", |v| {
+        let mut output = Vec::new();
+        to_writer(&mut output, v)?;
+        Ok(output)
+    }, |b| from_reader(b)),
+    ("YAML", |v| to_yaml(v).map(String::into_bytes), |b| from_yaml(read_text(b)?)),
+    ("MessagePack", |v| to_msgpack(v), |b| from_msgpack(b)),
+];
+
+// Check that every format writes the value, reads it back and writes it
+// again to the same output. The error names the format and both outputs.
+pub fn check_round_trip(value: &JsonValue) -> Result<(), String> {
+    for (name, write, read) in ROUND_TRIP_FORMATS {
+        let describe = |bytes: &[u8]| match std::str::from_utf8(bytes) {
+            Ok(text) => format!("{:?}", text),
+            Err(_) => format!("{:02x?}", bytes),
+        };
+        let first = write(value).map_err(|e| format!("{}: writing {} failed: {}", name, value, e))?;
+        let back = read(&first).map_err(|e| format!("{}: reading {} failed: {}", name, describe(&first), e))?;
+        let second = write(&back).map_err(|e| format!("{}: rewriting {} failed: {}", name, back, e))?;
+        if first != second {
+            return Err(format!("{}: {} was written as {}, then as {}", name, value, describe(&first), describe(&second)));
         }
-        map.end()
     }
+    Ok(())
+}
+
+// Round-trip `cases` random values from the seed, stopping at the first
+// failure. The same seed always generates the same values.
+pub fn fuzz_round_trips(seed: u64, cases: usize) -> Result<(), String> {
+    let mut generator = ValueGenerator::new(seed);
+    for case in 0..cases {
+        let value = generator.value(4);
+        check_round_trip(&value).map_err(|e| format!("case {} of seed {}: {}", case, seed, e))?;
+    }
+    Ok(())
+}
+
+// Build a JsonValue with JSON syntax. Values can be `null`, nested arrays
+// and objects, or any Serialize expression; object keys are literals or
+// parenthesized expressions.
+#[macro_export]
+macro_rules! json {
+    // Arrays: munch one element at a time into `[$elems,]`
+    (@array [$($elems:expr,)*]) => {
+        vec![$($elems,)*]
+    };
+    (@array [$($elems:expr,)*] , $($rest:tt)*) => {
+        json!(@array [$($elems,)*] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] null $($rest:tt)*) => {
+        json!(@array [$($elems,)* json!(null),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] [$($array:tt)*] $($rest:tt)*) => {
+        json!(@array [$($elems,)* json!([$($array)*]),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] {$($object:tt)*} $($rest:tt)*) => {
+        json!(@array [$($elems,)* json!({$($object)*}),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $next:expr $(, $($rest:tt)*)?) => {
+        json!(@array [$($elems,)* json!($next),] $($($rest)*)?)
+    };
+
+    // Objects: insert one `key: value` at a time
+    (@object $object:ident ()) => {};
+    (@object $object:ident (, $($rest:tt)*)) => {
+        json!(@object $object ($($rest)*));
+    };
+    (@object $object:ident (($key:expr) : $($rest:tt)*)) => {
+        json!(@value $object (($key).to_string()) ($($rest)*));
+    };
+    (@object $object:ident ($key:literal : $($rest:tt)*)) => {
+        json!(@value $object ($key.to_string()) ($($rest)*));
+    };
+    (@value $object:ident ($key:expr) (null $($rest:tt)*)) => {
+        $object.insert($key, json!(null));
+        json!(@object $object ($($rest)*));
+    };
+    (@value $object:ident ($key:expr) ([$($array:tt)*] $($rest:tt)*)) => {
+        $object.insert($key, json!([$($array)*]));
+        json!(@object $object ($($rest)*));
+    };
+    (@value $object:ident ($key:expr) ({$($inner:tt)*} $($rest:tt)*)) => {
+        $object.insert($key, json!({$($inner)*}));
+        json!(@object $object ($($rest)*));
+    };
+    (@value $object:ident ($key:expr) ($value:expr $(, $($rest:tt)*)?)) => {
+        $object.insert($key, json!($value));
+        json!(@object $object ($($($rest)*)?));
+    };
+
+    (null) => {
+        JsonValue::Null
+    };
+    ([$($array:tt)*]) => {
+        JsonValue::Array(json!(@array [] $($array)*))
+    };
+    ({$($object:tt)*}) => {{
+        #[allow(unused_mut)]
+        let mut object = std::collections::BTreeMap::new();
+        json!(@object object ($($object)*));
+        JsonValue::Object(object)
+    }};
+    ($other:expr) => {
+        to_value(&$other).expect("value in json! must serialize to JSON")
+    };
 }
 
-// Macro for deriving Serialize
+// Macro for deriving Serialize. Fields can take attributes:
+//
+// - `#[serde(flatten)]` merges the field's entries into the struct's own;
+//   the struct is then written as a map
+// - `#[serde(with = module)] field: Type` writes the field with
+//   `module::serialize(&value, serializer)`. The type is needed here, since
+//   the macro doesn't otherwise see field types.
+//
+// `derive_serialize!(UserId(u64))` is for a newtype struct, written as the
+// value inside, and `derive_serialize!(Marker)` for a unit struct.
 #[macro_export]
 macro_rules! derive_serialize {
+    (@flattens [flatten] $($rest:tt)*) => {
+        true
+    };
+    (@flattens [$($attr:tt)*] $($rest:tt)*) => {
+        derive_serialize!(@flattens $($rest)*)
+    };
+    (@flattens) => {
+        false
+    };
+    (@field $state:ident $self:ident $field:ident [$($fty:ty)?]) => {
+        $state.serialize_field(stringify!($field), &$self.$field)?;
+    };
+    (@field $state:ident $self:ident $field:ident [$($fty:ty)?] [flatten]) => {
+        $state.flatten(&$self.$field)?;
+    };
+    (@field $state:ident $self:ident $field:ident [$fty:ty] [with = $($module:ident)::+]) => {{
+        struct With<'a>(&'a $fty);
+        
+        impl Serialize for With<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                $($module)::+::serialize(self.0, serializer)
+            }
+        }
+        
+        $state.serialize_field(stringify!($field), &With(&$self.$field))?;
+    }};
+    (@field $state:ident $self:ident $field:ident [] [with = $($module:ident)::+]) => {
+        compile_error!(concat!("`with` needs the field's type: `", stringify!($field), ": Type`"));
+    };
+    ($name:ident($inner:ty)) => {
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_newtype_struct(stringify!($name), &self.0)
+            }
+        }
+    };
+    ($name:ident) => {
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_unit_struct(stringify!($name))
+            }
+        }
+    };
     ($name:ident { $($field:ident),* }) => {
         impl Serialize for $name {
             fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-                let mut map = serializer.serialize_map(None)?;
+                let fields: &[&str] = &[$(stringify!($field)),*];
+                let mut state = serializer.serialize_struct(stringify!($name), fields.len())?;
+                $(
+                    state.serialize_field(stringify!($field), &self.$field)?;
+                )*
+                state.end()
+            }
+        }
+    };
+    ($name:ident { $($(#[serde($($attr:tt)*)])* $field:ident $(: $fty:ty)?),* $(,)? }) => {
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let fields: &[&str] = &[$(stringify!($field)),*];
+                let flatten = [$(derive_serialize!(@flattens $([$($attr)*])*)),*].contains(&true);
+                let mut state = StructState::new(serializer, stringify!($name), fields.len(), flatten)?;
+                $(
+                    derive_serialize!(@field state self $field [$($fty)?] $([$($attr)*])*);
+                )*
+                state.end()
+            }
+        }
+    };
+}
+
+// Macro for deriving Deserialize for a struct. Unlike derive_serialize!, it
+// needs each field's type. Keys that aren't fields are ignored, unless a
+// leading `#[serde(deny_unknown_fields)]` makes them an error, and a
+// missing field is an error unless its type accepts null, as Option does. A
+// `#[serde(flatten)]` field is read from all the keys the others left over,
+// and a `#[serde(with = module)]` field with `module::deserialize(deserializer)`.
+// Newtype structs, `UserId(u64)`, and unit structs, `Marker`, work as with
+// derive_serialize!.
+#[macro_export]
+macro_rules! derive_deserialize {
+    (@declare $field:ident $fty:ty) => {
+        let mut $field: Option<$fty> = None;
+    };
+    (@declare $field:ident $fty:ty [flatten]) => {};
+    (@declare $field:ident $fty:ty [with = $($module:ident)::+]) => {
+        let mut $field: Option<$fty> = None;
+    };
+    (@read $map:ident $key:ident $field:ident $fty:ty) => {
+        if $key == stringify!($field) {
+            $field = Some($map.next_value()?);
+            continue;
+        }
+    };
+    (@read $map:ident $key:ident $field:ident $fty:ty [flatten]) => {};
+    (@read $map:ident $key:ident $field:ident $fty:ty [with = $($module:ident)::+]) => {
+        if $key == stringify!($field) {
+            struct With($fty);
+            
+            impl<'de> Deserialize<'de> for With {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    $($module)::+::deserialize(deserializer).map(With)
+                }
+            }
+            
+            $field = Some($map.next_value::<With>()?.0);
+            continue;
+        }
+    };
+    (@expected $field:ident) => {
+        Some(stringify!($field))
+    };
+    (@expected $field:ident [flatten]) => {
+        None
+    };
+    (@expected $field:ident [with = $($module:ident)::+]) => {
+        Some(stringify!($field))
+    };
+    (@finish $rest:ident $field:ident) => {
+        match $field {
+            Some(value) => value,
+            None => missing_field(stringify!($field))?,
+        }
+    };
+    (@finish $rest:ident $field:ident [flatten]) => {
+        flatten_value(&$rest)?
+    };
+    (@finish $rest:ident $field:ident [with = $($module:ident)::+]) => {
+        match $field {
+            Some(value) => value,
+            None => required_field(stringify!($field))?,
+        }
+    };
+    (@impl $name:ident $expecting:expr, $deny:expr, { $($(#[serde($($attr:tt)*)])* $field:ident: $fty:ty),* $(,)? }) => {
+        const _: () = {
+            struct StructVisitor;
+            
+            impl<'de> Visitor<'de> for StructVisitor {
+                type Value = $name;
+                
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    write!(formatter, "{}", $expecting)
+                }
+                
+                fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<$name, M::Error> {
+                    $(
+                        derive_deserialize!(@declare $field $fty $([$($attr)*])*);
+                    )*
+                    let mut rest = std::collections::BTreeMap::new();
+                    while let Some(key) = map.next_key::<String>()? {
+                        $(
+                            derive_deserialize!(@read map key $field $fty $([$($attr)*])*);
+                        )*
+                        if $deny {
+                            let fields: &[Option<&str>] = &[$(derive_deserialize!(@expected $field $([$($attr)*])*)),*];
+                            let expected: Vec<&str> = fields.iter().flatten().copied().collect();
+                            return Err(unknown_field(&key, &expected));
+                        }
+                        let value = map.next_value::<JsonValue>()?;
+                        rest.insert(key, value);
+                    }
+                    Ok($name {
+                        $(
+                            $field: derive_deserialize!(@finish rest $field $([$($attr)*])*),
+                        )*
+                    })
+                }
+            }
+            
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_map(StructVisitor)
+                }
+            }
+        };
+    };
+    ($name:ident($inner:ty)) => {
+        const _: () = {
+            struct NewtypeVisitor;
+            
+            impl<'de> Visitor<'de> for NewtypeVisitor {
+                type Value = $name;
+                
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    write!(formatter, concat!("tuple struct ", stringify!($name)))
+                }
+                
+                fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<$name, D::Error> {
+                    <$inner as Deserialize>::deserialize(deserializer).map($name)
+                }
+            }
+            
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_newtype_struct(stringify!($name), NewtypeVisitor)
+                }
+            }
+        };
+    };
+    ($name:ident) => {
+        const _: () = {
+            struct UnitVisitor;
+            
+            impl<'de> Visitor<'de> for UnitVisitor {
+                type Value = $name;
+                
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    write!(formatter, concat!("unit struct ", stringify!($name)))
+                }
+                
+                fn visit_unit<E: CustomError>(self) -> Result<$name, E> {
+                    Ok($name)
+                }
+            }
+            
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_unit_struct(stringify!($name), UnitVisitor)
+                }
+            }
+        };
+    };
+    (#[serde(deny_unknown_fields)] $name:ident $fields:tt) => {
+        derive_deserialize!(@impl $name concat!("struct ", stringify!($name)), true, $fields);
+    };
+    ($name:ident $fields:tt) => {
+        derive_deserialize!(@impl $name concat!("struct ", stringify!($name)), false, $fields);
+    };
+}
+
+// Macro for deriving Serialize and Deserialize for an enum. List each
+// variant as it's declared: `Unit`, `Newtype(Type)` or
+// `Struct { field: Type, ... }`. A leading `#[serde(tag = "type")]`,
+// `#[serde(tag = "t", content = "c")]` or `#[serde(untagged)]` picks
+// another representation; see EnumTagging.
+#[macro_export]
+macro_rules! derive_enum {
+    // Patterns and payload handling for each kind of variant
+    (@pattern $name:ident $variant:ident $value:ident [] []) => {
+        $name::$variant
+    };
+    (@pattern $name:ident $variant:ident $value:ident [$inner:ty] []) => {
+        $name::$variant($value)
+    };
+    (@pattern $name:ident $variant:ident $value:ident [] [$($field:ident: $fty:ty),*]) => {
+        $name::$variant { $($field),* }
+    };
+    (@serialize $name:ident $variant:ident $serializer:ident $value:ident [] []) => {
+        serialize_unit_variant::<$name, _>($serializer, stringify!($variant))
+    };
+    (@serialize $name:ident $variant:ident $serializer:ident $value:ident [$inner:ty] []) => {
+        serialize_newtype_variant::<$name, _, _>($serializer, stringify!($variant), $value)
+    };
+    (@serialize $name:ident $variant:ident $serializer:ident $value:ident [] [$($field:ident: $fty:ty),*]) => {{
+        struct Fields<'a> {
+            tag: Option<&'static str>,
+            $($field: &'a $fty,)*
+        }
+        
+        impl Serialize for Fields<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let fields: &[&str] = &[$(stringify!($field)),*];
+                let len = fields.len() + usize::from(self.tag.is_some());
+                let mut state = serializer.serialize_struct(stringify!($variant), len)?;
+                if let Some(tag) = self.tag {
+                    state.serialize_field(tag, &stringify!($variant))?;
+                }
                 $(
-                    map.serialize_entry(&stringify!($field).to_string(), &self.$field)?;
+                    state.serialize_field(stringify!($field), self.$field)?;
                 )*
-                map.end()
+                state.end()
+            }
+        }
+        
+        let tag = <$name as EnumVariants>::TAGGING.internal_tag();
+        serialize_struct_variant::<$name, _, _>($serializer, stringify!($variant), &Fields { tag, $($field),* })
+    }};
+    (@deserialize $name:ident $variant:ident $content:ident [] []) => {
+        $content.unit().map(|()| $name::$variant)
+    };
+    (@deserialize $name:ident $variant:ident $content:ident [$inner:ty] []) => {
+        $content.value::<$inner>().map($name::$variant)
+    };
+    (@deserialize $name:ident $variant:ident $content:ident [] [$($field:ident: $fty:ty),*]) => {{
+        struct Fields {
+            $($field: $fty,)*
+        }
+        
+        derive_deserialize!(@impl Fields concat!("struct variant ", stringify!($name), "::", stringify!($variant)), false, {
+            $($field: $fty),*
+        });
+        
+        $content.value::<Fields>().map(|Fields { $($field),* }| $name::$variant { $($field),* })
+    }};
+    (@impl $tagging:expr, $name:ident {
+        $($variant:ident $(($inner:ty))? $({ $($field:ident: $fty:ty),* $(,)? })?),* $(,)?
+    }) => {
+        impl EnumVariants for $name {
+            const NAME: &'static str = stringify!($name);
+            const VARIANTS: &'static [&'static str] = &[$(stringify!($variant)),*];
+            const TAGGING: EnumTagging = $tagging;
+            
+            fn from_variant<'de, C: VariantContent<'de>>(variant: &str, content: C) -> Result<Self, C::Error> {
+                match variant {
+                    $(
+                        stringify!($variant) => derive_enum!(
+                            @deserialize $name $variant content [$($inner)?] [$($($field: $fty),*)?]
+                        ),
+                    )*
+                    _ => Err(unknown_variant(variant, Self::VARIANTS)),
+                }
+            }
+        }
+        
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    $(
+                        derive_enum!(@pattern $name $variant value [$($inner)?] [$($($field: $fty),*)?]) => derive_enum!(
+                            @serialize $name $variant serializer value [$($inner)?] [$($($field: $fty),*)?]
+                        ),
+                    )*
+                }
+            }
+        }
+        
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_enum(deserializer)
             }
         }
     };
+    // The representation attributes
+    (#[serde(untagged)] $name:ident $variants:tt) => {
+        derive_enum!(@impl EnumTagging::Untagged, $name $variants);
+    };
+    (#[serde(tag = $tag:literal, content = $content:literal)] $name:ident $variants:tt) => {
+        derive_enum!(@impl EnumTagging::Adjacent($tag, $content), $name $variants);
+    };
+    (#[serde(tag = $tag:literal)] $name:ident $variants:tt) => {
+        derive_enum!(@impl EnumTagging::Internal($tag), $name $variants);
+    };
+    ($name:ident $variants:tt) => {
+        derive_enum!(@impl EnumTagging::External, $name $variants);
+    };
 }
 
 // Example struct using the derive macro
@@ -401,8 +9056,21 @@ pub struct Point {
 }
 
 derive_serialize!(Point { x, y });
+derive_deserialize!(Point { x: i32, y: i32 });
 
-fn main() {
+// Example enum using the enum derive macro, tagged by a "type" field
+#[derive(Debug, PartialEq)]
+pub enum Shape {
+    Circle { radius: f64 },
+    Square(f64),
+    Empty,
+}
+
+derive_enum!(#[serde(tag = "type")] Shape { Circle { radius: f64 }, Square(f64), Empty });
+
+/// Print a walkthrough of the emulator's serialization support
+#[allow(clippy::approx_constant)]
+pub fn demo() {
     println!("Serde Emulator - Serialization Framework");
     println!("=========================================\n");
     
@@ -459,5 +9127,68 @@ fn main() {
     println!("Point: {}", to_json(&point).unwrap());
     println!();
     
+    // Test deserialization
+    println!("=== Deserialization ===");
+    let numbers: Vec<i32> = from_json("[1, 2, 3]").unwrap();
+    println!("Vec<i32>: {:?}", numbers);
+    let scores: HashMap<String, Option<f64>> = from_json(r#"{"Alice": 9.5, "Bob": null}"#).unwrap();
+    println!("HashMap: {:?}", scores);
+    let origin: Point = from_json(r#"{"y": 0, "x": 0}"#).unwrap();
+    println!("Point: ({}, {})", origin.x, origin.y);
+    println!();
+    
+    // Test YAML
+    println!("=== YAML ===");
+    print!("{}", to_yaml(&person).unwrap());
+    let matrix: Vec<Vec<i32>> = from_yaml("- [1, 2]\n- - 3\n  - 4\n").unwrap();
+    println!("Vec<Vec<i32>>: {:?}", matrix);
+    println!();
+    
+    // Test CSV
+    println!("=== CSV ===");
+    print!("{}", to_csv(&[person]).unwrap());
+    let rows: Vec<HashMap<String, String>> = from_csv("name,city\nBob,\"Portland, OR\"\n").unwrap();
+    println!("Rows: {:?}", rows);
+    println!();
+    
+    // Test MessagePack
+    println!("=== MessagePack ===");
+    let bytes = to_msgpack(&point).unwrap();
+    println!("Point: {:02x?}", bytes);
+    let back: HashMap<String, i32> = from_msgpack(&bytes).unwrap();
+    println!("Decoded: {:?}", back);
+    println!();
+    
+    // Test URL-encoded forms
+    println!("=== URL-encoded forms ===");
+    println!("Point: {}", to_urlencoded(&point).unwrap());
+    let form: HashMap<String, String> = from_urlencoded("q=rust+serde&lang=en%2Dus").unwrap();
+    println!("Form: {:?}", form);
+    println!();
+    
+    // Test dynamic values
+    println!("=== JsonValue ===");
+    let value = json!({"name": "Alice", "scores": [9.5, null], "admin": false});
+    println!("Value: {}", value);
+    println!("name: {:?}, first score: {:?}", value["name"].as_str(), value["scores"][0].as_f64());
+    println!();
+    
+    // Test enums
+    println!("=== Enums ===");
+    println!("Circle: {}", to_json(&Shape::Circle { radius: 1.5 }).unwrap());
+    println!("Empty: {}", to_json(&Shape::Empty).unwrap());
+    let shape: Shape = from_json(r#"{"radius": 2.5, "type": "Circle"}"#).unwrap();
+    println!("Decoded: {:?}", shape);
+    println!();
+    
+    // Test streaming
+    println!("=== Streaming ===");
+    print!("Point to stdout: ");
+    to_writer(std::io::stdout(), &point).unwrap();
+    println!();
+    let read: Vec<i32> = from_reader(&b"[1, 2, 3]"[..]).unwrap();
+    println!("From reader: {:?}", read);
+    println!();
+    
     println!("✓ Serde emulator demonstration complete");
 }
@@ -1,6 +1,10 @@
 // Developed by PowerShield, as an alternative to Serde
 
+// The f64 tests use literals like 3.14 on purpose
+#![allow(clippy::approx_constant)]
+
 #[path = "serde_emulator.rs"]
+#[allow(dead_code)]
 mod serde_emulator;
 
 use serde_emulator::*;
@@ -271,6 +275,2296 @@ fn main() {
         }
     }));
     
+    // Test 21: Deserialize bool
+    results.push(test_runner("Deserialize bool", || {
+        let t: bool = from_json("true").map_err(|e| e.to_string())?;
+        let f: bool = from_json(" false ").map_err(|e| e.to_string())?;
+        if t && !f {
+            Ok(())
+        } else {
+            Err(format!("Expected true and false, got {} and {}", t, f))
+        }
+    }));
+    
+    // Test 22: Deserialize integers
+    results.push(test_runner("Deserialize integers", || {
+        let small: i32 = from_json("-42").map_err(|e| e.to_string())?;
+        let big: i64 = from_json("9223372036854775807").map_err(|e| e.to_string())?;
+        if small == -42 && big == i64::MAX {
+            Ok(())
+        } else {
+            Err(format!("Expected -42 and i64::MAX, got {} and {}", small, big))
+        }
+    }));
+    
+    // Test 23: Deserialize f64
+    results.push(test_runner("Deserialize f64", || {
+        let pi: f64 = from_json("3.14").map_err(|e| e.to_string())?;
+        let small: f64 = from_json("-2.5e-3").map_err(|e| e.to_string())?;
+        let whole: f64 = from_json("7").map_err(|e| e.to_string())?;
+        if pi == 3.14 && small == -0.0025 && whole == 7.0 {
+            Ok(())
+        } else {
+            Err(format!("Unexpected floats: {}, {}, {}", pi, small, whole))
+        }
+    }));
+    
+    // Test 24: Deserialize string with escapes
+    results.push(test_runner("Deserialize String", || {
+        let result: String = from_json(r#""say \"hi\"\n\tbye\\""#).map_err(|e| e.to_string())?;
+        if result == "say \"hi\"\n\tbye\\" {
+            Ok(())
+        } else {
+            Err(format!("Unexpected string: {:?}", result))
+        }
+    }));
+    
+    // Test 25: Deserialize Option
+    results.push(test_runner("Deserialize Option", || {
+        let some: Option<i32> = from_json("42").map_err(|e| e.to_string())?;
+        let none: Option<i32> = from_json("null").map_err(|e| e.to_string())?;
+        if some == Some(42) && none.is_none() {
+            Ok(())
+        } else {
+            Err(format!("Expected Some(42) and None, got {:?} and {:?}", some, none))
+        }
+    }));
+    
+    // Test 26: Deserialize nested Vec
+    results.push(test_runner("Deserialize nested Vec", || {
+        let result: Vec<Vec<i32>> = from_json("[[1, 2], [], [3]]").map_err(|e| e.to_string())?;
+        if result == vec![vec![1, 2], vec![], vec![3]] {
+            Ok(())
+        } else {
+            Err(format!("Unexpected Vec: {:?}", result))
+        }
+    }));
+    
+    // Test 27: Deserialize HashMap
+    results.push(test_runner("Deserialize HashMap", || {
+        let result: HashMap<String, Vec<bool>> =
+            from_json(r#"{"a": [true], "b": []}"#).map_err(|e| e.to_string())?;
+        if result.len() != 2 || result["a"] != vec![true] || !result["b"].is_empty() {
+            return Err(format!("Unexpected HashMap: {:?}", result));
+        }
+        
+        // Number keys are quoted, as JSON requires, and read back
+        let mut numbers = HashMap::new();
+        numbers.insert(1, 2);
+        let json = to_json(&numbers).map_err(|e| e.to_string())?;
+        if json != r#"{"1": 2}"# {
+            return Err(format!("Unexpected JSON: {}", json));
+        }
+        let back: HashMap<i32, i32> = from_json(&json).map_err(|e| e.to_string())?;
+        if back != numbers {
+            return Err(format!("Unexpected HashMap: {:?}", back));
+        }
+        match from_json::<HashMap<u8, i32>>(r#"{"300": 1}"#) {
+            Err(e) if e.message().starts_with("invalid value: string \"300\"") => {}
+            other => return Err(format!("Expected an invalid key, got {:?}", other)),
+        }
+        match to_json(&HashMap::from([(vec![1], 2)])) {
+            Err(e) if e.message() == "JSON object keys must be strings, found array" => Ok(()),
+            other => Err(format!("Expected a key error, got {:?}", other)),
+        }
+    }));
+    
+    // Test 28: Round-trip through to_json and from_json
+    results.push(test_runner("Round-trip JSON", || {
+        let mut map = HashMap::new();
+        map.insert("scores".to_string(), vec![Some(1.5), None, Some(-3.0)]);
+        map.insert("empty".to_string(), vec![]);
+        let json = to_json(&map).map_err(|e| e.to_string())?;
+        let result: HashMap<String, Vec<Option<f64>>> = from_json(&json).map_err(|e| e.to_string())?;
+        if result == map {
+            Ok(())
+        } else {
+            Err(format!("Round-trip changed {:?} into {:?}", map, result))
+        }
+    }));
+    
+    // Test 29: Reject malformed JSON
+    results.push(test_runner("Reject malformed JSON", || {
+        for input in ["[1, 2,]", "[1 2]", "{\"a\" 1}", "01", "1.", "\"open", "true false", ""] {
+            if from_json::<Vec<i32>>(input).is_ok() || from_json::<HashMap<String, i32>>(input).is_ok() {
+                return Err(format!("Accepted malformed JSON '{}'", input));
+            }
+        }
+        let err = from_json::<Vec<i32>>("[1, 2,]").unwrap_err().to_string();
+        if err == "expected a JSON value, found `]` at [2], line 1 column 7" {
+            Ok(())
+        } else {
+            Err(format!("Unexpected error: {}", err))
+        }
+    }));
+    
+    // Test 30: Reject values of the wrong type
+    results.push(test_runner("Reject wrong types", || {
+        let err = from_json::<i32>("\"42\"").unwrap_err().to_string();
+        if err != "invalid type: string, expected an i32 at line 1 column 1" {
+            return Err(format!("Unexpected error: {}", err));
+        }
+        let err = from_json::<i32>("3000000000").unwrap_err().to_string();
+        if err != "invalid value: 3000000000, expected an i32 at line 1 column 11" {
+            return Err(format!("Unexpected error: {}", err));
+        }
+        if from_json::<Vec<String>>("[1]").is_ok() {
+            return Err("Accepted a number as a string".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 31: Escape special characters when serializing strings
+    results.push(test_runner("Serialize escaped string", || {
+        let result = to_json(&"say \"hi\"\\\n\t\u{1}").map_err(|e| e.to_string())?;
+        if result == r#""say \"hi\"\\\n\t\u0001""# {
+            Ok(())
+        } else {
+            Err(format!("Unexpected escaping: {}", result))
+        }
+    }));
+    
+    // Test 32: Decode \u escapes, including surrogate pairs
+    results.push(test_runner("Deserialize unicode escapes", || {
+        let result: String = from_json(r#""caf\u00e9 \ud83d\ude00 \u0001""#).map_err(|e| e.to_string())?;
+        if result != "café 😀 \u{1}" {
+            return Err(format!("Unexpected string: {:?}", result));
+        }
+        for input in [r#""\ud83d""#, r#""\u12g4""#, "\"tab\there\"", r#""\x""#] {
+            if from_json::<String>(input).is_ok() {
+                return Err(format!("Accepted invalid string {}", input));
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 33: Round-trip strings with special characters
+    results.push(test_runner("Round-trip escaped strings", || {
+        let strings = vec![
+            "quote \" and backslash \\".to_string(),
+            "line\nbreak\r\n".to_string(),
+            "control \u{0} \u{1f} \u{8} \u{c}".to_string(),
+            "unicode é 日本 😀 /".to_string(),
+        ];
+        let json = to_json(&strings).map_err(|e| e.to_string())?;
+        let result: Vec<String> = from_json(&json).map_err(|e| e.to_string())?;
+        if result == strings {
+            Ok(())
+        } else {
+            Err(format!("Round-trip changed {:?} into {:?} via {}", strings, result, json))
+        }
+    }));
+    
+    // Test 34: Structs keep their field order
+    results.push(test_runner("Serialize struct field order", || {
+        let person = Person {
+            name: "Alice".to_string(),
+            age: 30,
+            email: "alice@example.com".to_string(),
+        };
+        let result = to_json(&person).map_err(|e| e.to_string())?;
+        if result == r#"{"name": "Alice", "age": 30, "email": "alice@example.com"}"# {
+            Ok(())
+        } else {
+            Err(format!("Unexpected struct serialization: '{}'", result))
+        }
+    }));
+    
+    // Test 35: Implement Serialize with serialize_struct by hand
+    results.push(test_runner("Manual serialize_struct", || {
+        struct Version {
+            major: i32,
+            pre: Option<String>,
+        }
+        
+        impl Serialize for Version {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut state = serializer.serialize_struct("Version", 2)?;
+                state.serialize_field("major", &self.major)?;
+                state.serialize_field("pre", &self.pre)?;
+                state.end()
+            }
+        }
+        
+        let version = Version { major: 2, pre: None };
+        let result = to_json(&version).map_err(|e| e.to_string())?;
+        if result == r#"{"major": 2, "pre": null}"# {
+            Ok(())
+        } else {
+            Err(format!("Unexpected struct serialization: '{}'", result))
+        }
+    }));
+    
+    // Test 36: Serialize every integer width
+    results.push(test_runner("Serialize integer widths", || {
+        let result = vec![
+            to_json(&-8i8),
+            to_json(&-16i16),
+            to_json(&i128::MIN),
+            to_json(&255u8),
+            to_json(&65535u16),
+            to_json(&4294967295u32),
+            to_json(&u64::MAX),
+            to_json(&u128::MAX),
+        ]
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?
+        .join(" ");
+        let expected = "-8 -16 -170141183460469231731687303715884105728 255 65535 4294967295 \
+                        18446744073709551615 340282366920938463463374607431768211455";
+        if result == expected {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}', got '{}'", expected, result))
+        }
+    }));
+    
+    // Test 37: Deserialize every integer width, checking ranges
+    results.push(test_runner("Deserialize integer widths", || {
+        let small: (i8, i16, u8, u16, u32) = (
+            from_json("-128").map_err(|e| e.to_string())?,
+            from_json("32767").map_err(|e| e.to_string())?,
+            from_json("255").map_err(|e| e.to_string())?,
+            from_json("0").map_err(|e| e.to_string())?,
+            from_json("4294967295").map_err(|e| e.to_string())?,
+        );
+        if small != (-128, 32767, 255, 0, 4294967295) {
+            return Err(format!("Unexpected values: {:?}", small));
+        }
+        let big: Vec<u64> = from_json("[18446744073709551615]").map_err(|e| e.to_string())?;
+        let huge: u128 = from_json(&u128::MAX.to_string()).map_err(|e| e.to_string())?;
+        let negative: i128 = from_json(&i128::MIN.to_string()).map_err(|e| e.to_string())?;
+        if big != vec![u64::MAX] || huge != u128::MAX || negative != i128::MIN {
+            return Err(format!("Unexpected values: {:?}, {}, {}", big, huge, negative));
+        }
+        for (input, err) in [
+            (from_json::<u8>("256").err(), "invalid value: 256, expected a u8"),
+            (from_json::<u32>("-1").err(), "invalid value: -1, expected a u32"),
+            (from_json::<i8>("1.5").err(), "invalid value: 1.5, expected an i8"),
+        ] {
+            match input {
+                Some(e) if e.message() == err => {}
+                other => return Err(format!("Expected '{}', got {:?}", err, other.map(|e| e.to_string()))),
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 38: f32, char and unit
+    results.push(test_runner("Serialize f32, char and unit", || {
+        let json = to_json(&vec![0.1f32, -2.5]).map_err(|e| e.to_string())?;
+        let floats: Vec<f32> = from_json(&json).map_err(|e| e.to_string())?;
+        if json != "[0.1, -2.5]" || floats != vec![0.1f32, -2.5] {
+            return Err(format!("Unexpected f32 round-trip: {} -> {:?}", json, floats));
+        }
+        let json = to_json(&vec!['a', '"', 'é']).map_err(|e| e.to_string())?;
+        let chars: Vec<char> = from_json(&json).map_err(|e| e.to_string())?;
+        if json != r#"["a", "\"", "é"]"# || chars != vec!['a', '"', 'é'] {
+            return Err(format!("Unexpected char round-trip: {} -> {:?}", json, chars));
+        }
+        if from_json::<char>("\"ab\"").is_ok() {
+            return Err("Accepted two characters as a char".to_string());
+        }
+        let json = to_json(&()).map_err(|e| e.to_string())?;
+        let () = from_json(&json).map_err(|e| e.to_string())?;
+        if json == "null" {
+            Ok(())
+        } else {
+            Err(format!("Expected 'null', got '{}'", json))
+        }
+    }));
+    
+    // Test 39: Bytes
+    results.push(test_runner("Serialize bytes", || {
+        let bytes = ByteBuf(vec![0, 127, 255]);
+        let json = to_json(&bytes).map_err(|e| e.to_string())?;
+        let back: ByteBuf = from_json(&json).map_err(|e| e.to_string())?;
+        let text: ByteBuf = from_json("\"hi\"").map_err(|e| e.to_string())?;
+        if json == "[0, 127, 255]" && back == bytes && text.0 == b"hi" {
+            Ok(())
+        } else {
+            Err(format!("Unexpected bytes: {} -> {:?}, {:?}", json, back, text))
+        }
+    }));
+    
+    // Test 40: Serialize tuples as arrays
+    results.push(test_runner("Serialize tuples", || {
+        let pair = to_json(&(1, "one".to_string())).map_err(|e| e.to_string())?;
+        let single = to_json(&(true,)).map_err(|e| e.to_string())?;
+        let wide = to_json(&(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12)).map_err(|e| e.to_string())?;
+        if pair == r#"[1, "one"]"# && single == "[true]" && wide == "[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]" {
+            Ok(())
+        } else {
+            Err(format!("Unexpected tuples: {}, {}, {}", pair, single, wide))
+        }
+    }));
+    
+    // Test 41: Deserialize tuples, checking their length
+    results.push(test_runner("Deserialize tuples", || {
+        let points: Vec<(i32, i32)> = from_json("[[1, 2], [3, 4]]").map_err(|e| e.to_string())?;
+        let mixed: (String, Option<f64>, bool) = from_json(r#"["x", null, true]"#).map_err(|e| e.to_string())?;
+        if points != vec![(1, 2), (3, 4)] || mixed != ("x".to_string(), None, true) {
+            return Err(format!("Unexpected tuples: {:?}, {:?}", points, mixed));
+        }
+        let short = from_json::<(i32, i32, i32)>("[1, 2]").unwrap_err().to_string();
+        let long = from_json::<(i32, i32)>("[1, 2, 3]").unwrap_err().to_string();
+        if short == "invalid length 2, expected an array of 3 elements at line 1 column 6"
+            && long == "invalid length, expected an array of 2 elements at line 1 column 6"
+        {
+            Ok(())
+        } else {
+            Err(format!("Unexpected errors: '{}', '{}'", short, long))
+        }
+    }));
+    
+    // Test 42: Fixed-size arrays
+    results.push(test_runner("Fixed-size arrays", || {
+        let grid = [[1u8, 2, 3], [4, 5, 6]];
+        let json = to_json(&grid).map_err(|e| e.to_string())?;
+        let back: [[u8; 3]; 2] = from_json(&json).map_err(|e| e.to_string())?;
+        let empty: [i32; 0] = from_json("[]").map_err(|e| e.to_string())?;
+        if json != "[[1, 2, 3], [4, 5, 6]]" || back != grid || !empty.is_empty() {
+            return Err(format!("Unexpected arrays: {} -> {:?}", json, back));
+        }
+        if from_json::<[i32; 2]>("[1]").is_ok() || from_json::<[i32; 2]>("[1, 2, 3]").is_ok() {
+            return Err("Accepted an array of the wrong length".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 43: Serialize to block-style YAML
+    results.push(test_runner("Serialize to YAML", || {
+        let person = Person {
+            name: "Alice".to_string(),
+            age: 30,
+            email: "alice@example.com".to_string(),
+        };
+        let result = to_yaml(&person).map_err(|e| e.to_string())?;
+        if result != "name: Alice\nage: 30\nemail: alice@example.com\n" {
+            return Err(format!("Unexpected struct YAML: '{}'", result));
+        }
+        let scalars = to_yaml(&vec![Some("true".to_string()), Some("".to_string()), None])
+            .map_err(|e| e.to_string())?;
+        if scalars != "- \"true\"\n- \"\"\n- null\n" {
+            return Err(format!("Unexpected quoting: '{}'", scalars));
+        }
+        Ok(())
+    }));
+    
+    // Test 44: Nested collections are indented
+    results.push(test_runner("Serialize nested YAML", || {
+        let mut map = HashMap::new();
+        map.insert("points".to_string(), vec![(1, 2), (3, 4)]);
+        map.insert("empty".to_string(), vec![]);
+        let mut result = to_yaml(&map).map_err(|e| e.to_string())?;
+        // HashMap order is unspecified, so compare the two entries separately
+        let points = "points:\n  - - 1\n    - 2\n  - - 3\n    - 4\n";
+        let found = result.contains(points) && result.contains("empty: []\n");
+        result = result.replace(points, "").replace("empty: []\n", "");
+        if found && result.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Unexpected nested YAML: '{}'", to_yaml(&map).unwrap()))
+        }
+    }));
+    
+    // Test 45: Read handwritten documents
+    results.push(test_runner("Deserialize YAML documents", || {
+        let strings: HashMap<String, String> =
+            from_yaml("# quoting\n---\nname: 'it''s'\nversion: 1.0\ntier: \"web #1\"  # comment\n")
+                .map_err(|e| e.to_string())?;
+        if strings["name"] != "it's" || strings["version"] != "1.0" || strings["tier"] != "web #1" {
+            return Err(format!("Unexpected strings: {:?}", strings));
+        }
+        let ports: HashMap<String, Vec<u16>> = from_yaml("http:\n- 80\n- 8080\nhttps: [443]\nnone: []\n")
+            .map_err(|e| e.to_string())?;
+        if ports["http"] != vec![80, 8080] || ports["https"] != vec![443] || !ports["none"].is_empty() {
+            return Err(format!("Unexpected sequences: {:?}", ports));
+        }
+        let nested: Vec<HashMap<String, Vec<String>>> =
+            from_yaml("- tags: [a, b]\n  owners:\n    - ops\n-\n  tags: []\n").map_err(|e| e.to_string())?;
+        if nested[0]["tags"] != ["a", "b"] || nested[0]["owners"] != ["ops"] || !nested[1]["tags"].is_empty() {
+            return Err(format!("Unexpected nesting: {:?}", nested));
+        }
+        let values: Vec<Option<f64>> = from_yaml("[0.5, .inf, ~, null]").map_err(|e| e.to_string())?;
+        if values != vec![Some(0.5), Some(f64::INFINITY), None, None] {
+            return Err(format!("Unexpected scalars: {:?}", values));
+        }
+        Ok(())
+    }));
+    
+    // Test 46: Values round-trip through YAML
+    results.push(test_runner("YAML round-trip", || {
+        let mut map: HashMap<String, Vec<(i32, Option<String>)>> = HashMap::new();
+        map.insert("plain".to_string(), vec![(1, Some("one".to_string())), (-2, None)]);
+        map.insert("key: with colon".to_string(), vec![(3, Some("- dash\n\"quoted\" #tag".to_string()))]);
+        map.insert("empty".to_string(), Vec::new());
+        let yaml = to_yaml(&map).map_err(|e| e.to_string())?;
+        let back: HashMap<String, Vec<(i32, Option<String>)>> = from_yaml(&yaml).map_err(|e| e.to_string())?;
+        if back != map {
+            return Err(format!("Round-trip changed {:?} into {:?} via {}", map, back, yaml));
+        }
+        let grid = [[1.5f32, -0.25], [f32::NEG_INFINITY, 0.0]];
+        let back: [[f32; 2]; 2] = from_yaml(&to_yaml(&grid).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if back != grid {
+            return Err(format!("Round-trip changed {:?} into {:?}", grid, back));
+        }
+        Ok(())
+    }));
+    
+    // Test 47: Malformed YAML is reported with its line
+    results.push(test_runner("YAML errors", || {
+        let cases = [
+            ("a: 1\n  b: 2\n", "line 2: unexpected indentation"),
+            ("a: [1, 2\n", "line 1: expected `,` or `]`"),
+            ("a: \"open\n", "line 1: unterminated double-quoted string"),
+            ("a: 1\n\tb: 2\n", "line 2: tabs are not allowed in indentation"),
+        ];
+        for (input, message) in cases {
+            match from_yaml::<HashMap<String, i32>>(input) {
+                Err(e) if e.to_string() == message => {}
+                other => return Err(format!("Expected '{}' for {:?}, got {:?}", message, input, other.map_err(|e| e.to_string()))),
+            }
+        }
+        match from_yaml::<Vec<i32>>("- 1\n- two\n") {
+            Err(e) if e.to_string() == "invalid type: string, expected an i32 at [1]" => {}
+            other => return Err(format!("Accepted a string as an integer: {:?}", other.map_err(|e| e.to_string()))),
+        }
+        let mut map = HashMap::new();
+        map.insert(vec![1], 1);
+        if to_yaml(&map).is_ok() {
+            return Err("Serialized a sequence as a map key".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 48: Serialize records to CSV
+    results.push(test_runner("Serialize to CSV", || {
+        let people = vec![
+            Person {
+                name: "Alice".to_string(),
+                age: 30,
+                email: "alice@example.com".to_string(),
+            },
+            Person {
+                name: "Smith, \"Bob\"".to_string(),
+                age: 41,
+                email: "bob\n@example.com".to_string(),
+            },
+        ];
+        let result = to_csv(&people).map_err(|e| e.to_string())?;
+        let expected = "name,age,email\nAlice,30,alice@example.com\n\"Smith, \"\"Bob\"\"\",41,\"bob\n@example.com\"\n";
+        if result != expected {
+            return Err(format!("Unexpected CSV: '{}'", result));
+        }
+        if !to_csv::<Person>(&[]).map_err(|e| e.to_string())?.is_empty() {
+            return Err("Wrote a header without records".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 49: Deserialize CSV rows by header name
+    results.push(test_runner("Deserialize CSV", || {
+        let input = "city,population,capital\r\nParis,2100000,true\r\n\"Portland, OR\",650000,\r\n\"say \"\"hi\"\"\nthere\",1,false\r\n";
+        let rows: Vec<HashMap<String, Option<String>>> = from_csv(input).map_err(|e| e.to_string())?;
+        if rows.len() != 3
+            || rows[1]["city"].as_deref() != Some("Portland, OR")
+            || rows[1]["capital"].is_some()
+            || rows[2]["city"].as_deref() != Some("say \"hi\"\nthere")
+        {
+            return Err(format!("Unexpected rows: {:?}", rows));
+        }
+        let counts: Vec<HashMap<String, u64>> = from_csv("a,b\n1,2\n\n3,4\n").map_err(|e| e.to_string())?;
+        if counts.len() != 2 || counts[1]["b"] != 4 {
+            return Err(format!("Unexpected numbers: {:?}", counts));
+        }
+        Ok(())
+    }));
+    
+    // Test 50: CSV round-trips and errors
+    results.push(test_runner("CSV round-trip and errors", || {
+        let mut first = HashMap::new();
+        first.insert("id".to_string(), 1.5);
+        first.insert("score".to_string(), -2.0);
+        let mut second = HashMap::new();
+        second.insert("score".to_string(), 0.25);
+        second.insert("id".to_string(), 2.0);
+        let records = vec![first, second];
+        let csv = to_csv(&records).map_err(|e| e.to_string())?;
+        let back: Vec<HashMap<String, f64>> = from_csv(&csv).map_err(|e| e.to_string())?;
+        if back != records {
+            return Err(format!("Round-trip changed {:?} into {:?} via {}", records, back, csv));
+        }
+        let cases = [
+            ("a,b\n1\n", "line 2: expected 2 fields, found 1"),
+            ("a\n\"open\n", "line 2: unterminated quoted field"),
+            ("a\n\"x\"y\n", "line 2: unexpected `y` after quoted field"),
+            ("a,b\n1,2\n3,x\n", "line 3: invalid type: string, expected an i32 at b"),
+        ];
+        for (input, message) in cases {
+            match from_csv::<HashMap<String, i32>>(input) {
+                Err(e) if e.to_string() == message => {}
+                other => return Err(format!("Expected '{}' for {:?}, got {:?}", message, input, other.map_err(|e| e.to_string()))),
+            }
+        }
+        if to_csv(&[vec![1, 2]]).is_ok() || to_csv(&[vec![(1, vec![2])].into_iter().collect::<HashMap<_, _>>()]).is_ok() {
+            return Err("Serialized a record that is not a flat map".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 51: MessagePack uses the spec's type tags
+    results.push(test_runner("Serialize to MessagePack", || {
+        let cases: Vec<(Vec<u8>, Vec<u8>)> = vec![
+            (to_msgpack(&0u8).map_err(|e| e.to_string())?, vec![0x00]),
+            (to_msgpack(&127i32).map_err(|e| e.to_string())?, vec![0x7f]),
+            (to_msgpack(&-32i64).map_err(|e| e.to_string())?, vec![0xe0]),
+            (to_msgpack(&200u64).map_err(|e| e.to_string())?, vec![0xcc, 0xc8]),
+            (to_msgpack(&-129i32).map_err(|e| e.to_string())?, vec![0xd1, 0xff, 0x7f]),
+            (to_msgpack(&70000u32).map_err(|e| e.to_string())?, vec![0xce, 0x00, 0x01, 0x11, 0x70]),
+            (to_msgpack(&u64::MAX).map_err(|e| e.to_string())?, vec![0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            (to_msgpack(&1.5f64).map_err(|e| e.to_string())?, vec![0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]),
+            (to_msgpack(&Some(true)).map_err(|e| e.to_string())?, vec![0xc3]),
+            (to_msgpack(&None::<i32>).map_err(|e| e.to_string())?, vec![0xc0]),
+            (to_msgpack(&"hi").map_err(|e| e.to_string())?, vec![0xa2, b'h', b'i']),
+            (to_msgpack(&ByteBuf(vec![1, 2])).map_err(|e| e.to_string())?, vec![0xc4, 0x02, 0x01, 0x02]),
+            (to_msgpack(&(1, "a")).map_err(|e| e.to_string())?, vec![0x92, 0x01, 0xa1, b'a']),
+        ];
+        for (found, expected) in cases {
+            if found != expected {
+                return Err(format!("Expected {:02x?}, found {:02x?}", expected, found));
+            }
+        }
+        let point = to_msgpack(&Point { x: 10, y: -1 }).map_err(|e| e.to_string())?;
+        if point != vec![0x82, 0xa1, b'x', 0x0a, 0xa1, b'y', 0xff] {
+            return Err(format!("Unexpected struct encoding: {:02x?}", point));
+        }
+        let long = to_msgpack(&"x".repeat(40)).map_err(|e| e.to_string())?;
+        let many = to_msgpack(&vec![0u8; 20]).map_err(|e| e.to_string())?;
+        if long[..2] != [0xd9, 40] || many[..3] != [0xdc, 0, 20] || many.len() != 23 {
+            return Err("Unexpected str8 or array16 header".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 52: Decode MessagePack, including forms the serializer never writes
+    results.push(test_runner("Deserialize MessagePack", || {
+        // uint16 5, int8 -3 and a float32 read into the requested widths
+        let numbers: (u8, i64, f64) = from_msgpack(&[0x93, 0xcd, 0x00, 0x05, 0xd0, 0xfd, 0xca, 0x3f, 0xc0, 0, 0])
+            .map_err(|e| e.to_string())?;
+        if numbers != (5, -3, 1.5) {
+            return Err(format!("Unexpected numbers: {:?}", numbers));
+        }
+        // map16 with a str8 key
+        let map: HashMap<String, Vec<bool>> = from_msgpack(&[0xde, 0x00, 0x01, 0xd9, 0x01, b'k', 0x92, 0xc3, 0xc2])
+            .map_err(|e| e.to_string())?;
+        if map["k"] != vec![true, false] {
+            return Err(format!("Unexpected map: {:?}", map));
+        }
+        let mut scores = HashMap::new();
+        scores.insert("Alice".to_string(), vec![Some(9.5), None]);
+        scores.insert("Bob".to_string(), vec![Some(-1e300)]);
+        let bytes = to_msgpack(&scores).map_err(|e| e.to_string())?;
+        let back: HashMap<String, Vec<Option<f64>>> = from_msgpack(&bytes).map_err(|e| e.to_string())?;
+        let blob: ByteBuf = from_msgpack(&to_msgpack(&ByteBuf(vec![0; 300])).map_err(|e| e.to_string())?)
+            .map_err(|e| e.to_string())?;
+        if back != scores || blob.0.len() != 300 {
+            return Err(format!("Round-trip changed {:?} into {:?}", scores, back));
+        }
+        Ok(())
+    }));
+    
+    // Test 53: Malformed MessagePack is rejected
+    results.push(test_runner("MessagePack errors", || {
+        let cases: [(&[u8], &str); 5] = [
+            (&[0xcd, 0x01], "unexpected end of input"),
+            (&[0xc1], "invalid marker 0xc1 at offset 0"),
+            (&[0x01, 0x02], "trailing bytes at offset 1"),
+            (&[0xa1, b'a'], "invalid type: string, expected an i32"),
+            (&[0x91, 0x01], "invalid length 1, expected an array of 2 elements"),
+        ];
+        for (input, message) in cases {
+            let result = if input == [0x91, 0x01] {
+                from_msgpack::<(i32, i32)>(input).map(|_| ())
+            } else {
+                from_msgpack::<i32>(input).map(|_| ())
+            };
+            match result {
+                Err(e) if e.to_string() == message => {}
+                other => return Err(format!("Expected '{}' for {:02x?}, got {:?}", message, input, other.map_err(|e| e.to_string()))),
+            }
+        }
+        if from_msgpack::<i8>(&[0xcc, 0xc8]).is_ok() || to_msgpack(&u128::MAX).is_ok() {
+            return Err("Accepted an out-of-range integer".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 54: Serialize URL-encoded forms
+    results.push(test_runner("Serialize URL-encoded form", || {
+        let person = Person {
+            name: "Zoë Smith".to_string(),
+            age: 30,
+            email: "zoe+test@example.com".to_string(),
+        };
+        let result = to_urlencoded(&person).map_err(|e| e.to_string())?;
+        if result != "name=Zo%C3%AB+Smith&age=30&email=zoe%2Btest%40example.com" {
+            return Err(format!("Unexpected form: '{}'", result));
+        }
+        let mut filters = HashMap::new();
+        filters.insert("q", None::<String>);
+        let empty = to_urlencoded(&filters).map_err(|e| e.to_string())?;
+        if !empty.is_empty() {
+            return Err(format!("Wrote a None field: '{}'", empty));
+        }
+        if to_urlencoded(&vec![1, 2]).is_ok() || to_urlencoded(&[("tags", vec!["a"])].into_iter().collect::<HashMap<_, _>>()).is_ok() {
+            return Err("Serialized a value that is not a flat map".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 55: Deserialize URL-encoded forms
+    results.push(test_runner("Deserialize URL-encoded form", || {
+        let form: HashMap<String, String> = from_urlencoded("name=Zo%C3%AB+Smith&note=a%26b%3Dc&&flag")
+            .map_err(|e| e.to_string())?;
+        if form["name"] != "Zoë Smith" || form["note"] != "a&b=c" || !form["flag"].is_empty() {
+            return Err(format!("Unexpected form: {:?}", form));
+        }
+        let numbers: HashMap<String, Option<u32>> = from_urlencoded("page=2&limit=").map_err(|e| e.to_string())?;
+        if numbers["page"] != Some(2) || numbers["limit"].is_some() {
+            return Err(format!("Unexpected numbers: {:?}", numbers));
+        }
+        let mut original = HashMap::new();
+        original.insert("a b".to_string(), "100% & more/less".to_string());
+        let back: HashMap<String, String> =
+            from_urlencoded(&to_urlencoded(&original).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if back != original {
+            return Err(format!("Round-trip changed {:?} into {:?}", original, back));
+        }
+        for input in ["a=%G1", "a=%4", "a=%FF"] {
+            if from_urlencoded::<HashMap<String, String>>(input).is_ok() {
+                return Err(format!("Accepted bad encoding {}", input));
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 56: Build and inspect values with json!
+    results.push(test_runner("JsonValue and json!", || {
+        let name = "Alice";
+        let value = json!({
+            "name": name,
+            "age": 30 + 1,
+            "tags": ["admin", null, -2.5, [true]],
+            "address": {"city": "Paris", "zip": null},
+            ("dyn".to_string() + "amic"): {},
+        });
+        if value["name"].as_str() != Some("Alice")
+            || value["age"].as_i64() != Some(31)
+            || value["tags"][2].as_f64() != Some(-2.5)
+            || value["tags"][3][0].as_bool() != Some(true)
+            || !value["tags"][1].is_null()
+            || value["address"]["city"] != json!("Paris")
+            || value.get("dynamic") != Some(&json!({}))
+        {
+            return Err(format!("Unexpected value: {:?}", value));
+        }
+        // Missing keys, out-of-range indexes and wrong kinds give Null
+        if !value["missing"]["deeper"].is_null() || !value["tags"][9].is_null() || value.get(0).is_some() {
+            return Err("Lookup of a missing element found something".to_string());
+        }
+        let text = value.to_string();
+        if !text.starts_with(r#"{"address": {"city": "Paris", "zip": null}, "age": 31, "dynamic": {}"#) {
+            return Err(format!("Unexpected JSON text: {}", text));
+        }
+        let mut config = JsonValue::Null;
+        config["server"]["port"] = json!(8080);
+        config["server"]["hosts"] = json!([]);
+        if config.to_string() != r#"{"server": {"hosts": [], "port": 8080}}"# {
+            return Err(format!("Unexpected built value: {}", config));
+        }
+        Ok(())
+    }));
+    
+    // Test 57: Parse JSON into a JsonValue and back
+    results.push(test_runner("JsonValue round-trip", || {
+        let input = r#"{"id": 18446744073709551615, "delta": -3, "ratio": 0.5, "items": [{"ok": true}], "note": "tab\t"}"#;
+        let value: JsonValue = from_json(input).map_err(|e| e.to_string())?;
+        if value["id"].as_u64() != Some(u64::MAX) || value["id"].as_i64().is_some() || value["delta"].as_i64() != Some(-3) {
+            return Err(format!("Unexpected numbers: {:?}", value));
+        }
+        let again: JsonValue = from_json(&to_json(&value).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        // The same document read through other formats gives an equal value
+        let via_msgpack: JsonValue = from_msgpack(&to_msgpack(&value).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if again != value || via_msgpack != value {
+            return Err(format!("Round-trip changed {:?} into {:?} / {:?}", value, again, via_msgpack));
+        }
+        Ok(())
+    }));
+    
+    // Test 58: Convert between JsonValue and typed values
+    results.push(test_runner("to_value and from_value", || {
+        let point = to_value(&Point { x: 1, y: -2 }).map_err(|e| e.to_string())?;
+        if point != json!({"x": 1, "y": -2}) {
+            return Err(format!("Unexpected value: {:?}", point));
+        }
+        let coords: HashMap<String, i32> = from_value(point).map_err(|e| e.to_string())?;
+        let pair: (u8, Option<String>) = from_value(json!([7, null])).map_err(|e| e.to_string())?;
+        let mut counts = HashMap::new();
+        counts.insert(3, "three");
+        let numbered = to_value(&counts).map_err(|e| e.to_string())?;
+        if coords["y"] != -2 || pair != (7, None) || numbered != json!({"3": "three"}) {
+            return Err(format!("Unexpected conversions: {:?} {:?} {:?}", coords, pair, numbered));
+        }
+        let errors = [
+            from_value::<u8>(json!(300)).map(|_| ()),
+            from_value::<i32>(json!("1")).map(|_| ()),
+            from_value::<(i32, i32)>(json!([1])).map(|_| ()),
+        ];
+        let messages: Vec<String> = errors.into_iter().filter_map(|r| r.err().map(|e| e.to_string())).collect();
+        let expected = [
+            "invalid value: 300, expected a u8",
+            "invalid type: string, expected an i32",
+            "invalid length 1, expected an array of 2 elements",
+        ];
+        if messages != expected {
+            return Err(format!("Unexpected errors: {:?}", messages));
+        }
+        let mut bad_keys = HashMap::new();
+        bad_keys.insert(vec![1], 1);
+        if to_value(&bad_keys).is_ok() {
+            return Err("Converted a map with array keys".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 59: Stream JSON to a writer
+    results.push(test_runner("Serialize with to_writer", || {
+        let mut map = HashMap::new();
+        map.insert("quote \"tab\t\u{1}\"".to_string(), vec![Some(ByteBuf(vec![1, 2])), None]);
+        let value = (json!({"a": [1.5, "é"], "b": null}), map, Person {
+            name: "Alice".to_string(),
+            age: 30,
+            email: "alice@example.com".to_string(),
+        });
+        let mut output = Vec::new();
+        to_writer(&mut output, &value).map_err(|e| e.to_string())?;
+        let expected = to_json(&value).map_err(|e| e.to_string())?;
+        if String::from_utf8(output).map_err(|e| e.to_string())? != expected {
+            return Err(format!("to_writer differs from to_json: {}", expected));
+        }
+        
+        // Small writes are buffered rather than passed on one by one
+        struct CountingWriter {
+            writes: usize,
+            bytes: usize,
+        }
+        
+        impl std::io::Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.writes += 1;
+                self.bytes += buf.len();
+                Ok(buf.len())
+            }
+            
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        
+        let numbers: Vec<u32> = (0..1000).collect();
+        let mut counter = CountingWriter { writes: 0, bytes: 0 };
+        to_writer(&mut counter, &numbers).map_err(|e| e.to_string())?;
+        if counter.bytes != to_json(&numbers).map_err(|e| e.to_string())?.len() || counter.writes > 3 {
+            return Err(format!("{} bytes in {} writes", counter.bytes, counter.writes));
+        }
+        Ok(())
+    }));
+    
+    // Test 60: Read JSON from a reader, and report I/O failures
+    results.push(test_runner("from_reader and I/O errors", || {
+        let input: &[u8] = br#"{"Alice": [9.5, null], "Bob": []}"#;
+        let scores: HashMap<String, Vec<Option<f64>>> = from_reader(input).map_err(|e| e.to_string())?;
+        if scores["Alice"] != vec![Some(9.5), None] || !scores["Bob"].is_empty() {
+            return Err(format!("Unexpected scores: {:?}", scores));
+        }
+        if from_reader::<_, String>(&[b'"', 0xff, b'"'][..]).is_ok() || from_reader::<_, Vec<i32>>(&b"[1,"[..]).is_ok() {
+            return Err("Accepted malformed input".to_string());
+        }
+        
+        struct FullDisk;
+        
+        impl std::io::Write for FullDisk {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk full"))
+            }
+            
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        
+        match to_writer(FullDisk, &vec!["data"; 10]) {
+            Err(e) if e.to_string() == "I/O error: disk full" => Ok(()),
+            other => Err(format!("Expected an I/O error, got {:?}", other.map_err(|e| e.to_string()))),
+        }
+    }));
+    
+    // Test 61: Borrow strings from the input
+    results.push(test_runner("Zero-copy &str", || {
+        let input = String::from(r#"{"name": "Alice", "tags": ["a", "bc"]}"#);
+        let range = input.as_bytes().as_ptr_range();
+        let map: HashMap<&str, JsonValue> = from_json(&input).map_err(|e| e.to_string())?;
+        let tags: Vec<&str> = from_json(r#"["a", "bc"]"#).map_err(|e| e.to_string())?;
+        let name: &str = from_json(&input[9..16]).map_err(|e| e.to_string())?;
+        if name != "Alice" || tags != ["a", "bc"] || !map.contains_key("tags") {
+            return Err(format!("Unexpected values: {} {:?} {:?}", name, tags, map));
+        }
+        // The results point into the input rather than into copies
+        let borrowed = map.keys().all(|key| range.contains(&key.as_ptr())) && range.contains(&name.as_ptr());
+        if !borrowed {
+            return Err("A string was copied instead of borrowed".to_string());
+        }
+        let bytes = to_msgpack(&(ByteBuf(vec![1, 2, 3]), "hi")).map_err(|e| e.to_string())?;
+        let (blob, text): (&[u8], &str) = from_msgpack(&bytes).map_err(|e| e.to_string())?;
+        let range = bytes.as_ptr_range();
+        if blob != [1, 2, 3] || text != "hi" || !range.contains(&blob.as_ptr()) || !range.contains(&text.as_ptr()) {
+            return Err(format!("Unexpected MessagePack values: {:?} {:?}", blob, text));
+        }
+        Ok(())
+    }));
+    
+    // Test 62: Cow<str> borrows when it can and copies escaped strings
+    results.push(test_runner("Cow<str> deserialization", || {
+        use std::borrow::Cow;
+        
+        let values: Vec<Cow<str>> = from_json(r#"["plain", "line\nbreak", "\u00e9"]"#).map_err(|e| e.to_string())?;
+        let kinds: Vec<bool> = values.iter().map(|v| matches!(v, Cow::Borrowed(_))).collect();
+        if values != ["plain", "line\nbreak", "é"] || kinds != [true, false, false] {
+            return Err(format!("Unexpected values: {:?}", values));
+        }
+        // Formats that own their data still work, by copying
+        let owned: Vec<Cow<str>> = from_yaml("- x\n- y\n").map_err(|e| e.to_string())?;
+        if owned != ["x", "y"] || to_json(&owned).map_err(|e| e.to_string())? != r#"["x", "y"]"# {
+            return Err(format!("Unexpected YAML values: {:?}", owned));
+        }
+        Ok(())
+    }));
+    
+    // Test 63: Errors carry a path, line and column
+    results.push(test_runner("Error locations", || {
+        let input = "{\"users\": [\n  {\"age\": 1}, {\"age\": 2}, {\"age\": 3},\n  {\"age\": \"four\"}\n]}";
+        let err = match from_json::<HashMap<String, Vec<HashMap<String, i32>>>>(input) {
+            Ok(v) => return Err(format!("Expected an error, got {:?}", v)),
+            Err(e) => e,
+        };
+        if err.message() != "invalid type: string, expected an i32" || err.path() != "users[3].age" {
+            return Err(format!("Unexpected error: {}", err));
+        }
+        if err.line_column() != Some((3, 11)) || err.offset() != input.find("\"four\"") {
+            return Err(format!("Unexpected location: {:?} {:?}", err.line_column(), err.offset()));
+        }
+        if err.to_string() != "invalid type: string, expected an i32 at users[3].age, line 3 column 11" {
+            return Err(format!("Unexpected message: {}", err));
+        }
+        // Other formats report the path, even without a position
+        let value = json!({"a": [true, "x"]});
+        match from_value::<HashMap<String, Vec<bool>>>(value) {
+            Err(e) if e.to_string() == "invalid type: string, expected a boolean at a[1]" => {}
+            other => return Err(format!("Unexpected from_value result: {:?}", other.map_err(|e| e.to_string()))),
+        }
+        // Visitors that don't handle a type reject it instead of panicking
+        match from_json::<&str>(r#""line\nbreak""#) {
+            Err(e) if e.message() == "invalid type: escaped string, expected a borrowed string" => {}
+            other => return Err(format!("Unexpected &str result: {:?}", other.map_err(|e| e.to_string()))),
+        }
+        Ok(())
+    }));
+    
+    // Test 64: Enums in each tagged representation
+    results.push(test_runner("Tagged enum representations", || {
+        #[derive(Debug, PartialEq)]
+        enum External { Circle { radius: f64, label: Option<String> }, Square(i32), Empty }
+        derive_enum!(External { Circle { radius: f64, label: Option<String> }, Square(i32), Empty });
+        
+        #[derive(Debug, PartialEq)]
+        enum Internal { Circle { radius: f64 }, Named(HashMap<String, i32>), Empty }
+        derive_enum!(#[serde(tag = "type")] Internal { Circle { radius: f64 }, Named(HashMap<String, i32>), Empty });
+        
+        #[derive(Debug, PartialEq)]
+        enum Adjacent { Circle { radius: f64 }, Square(i32), Empty }
+        derive_enum!(#[serde(tag = "t", content = "c")] Adjacent { Circle { radius: f64 }, Square(i32), Empty });
+        
+        let external = [
+            (External::Circle { radius: 1.5, label: None }, r#"{"Circle": {"radius": 1.5, "label": null}}"#),
+            (External::Square(2), r#"{"Square": 2}"#),
+            (External::Empty, r#""Empty""#),
+        ];
+        for (value, json) in external {
+            let result = to_json(&value).map_err(|e| e.to_string())?;
+            let back: External = from_json(&result).map_err(|e| e.to_string())?;
+            let yaml: External = from_yaml(&to_yaml(&value).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            if result != json || back != value || yaml != value {
+                return Err(format!("Unexpected external encoding of {:?}: {}", value, result));
+            }
+        }
+        let internal = [
+            (Internal::Circle { radius: 1.5 }, r#"{"type": "Circle", "radius": 1.5}"#),
+            (Internal::Named(HashMap::from([("a".to_string(), 1)])), r#"{"type": "Named", "a": 1}"#),
+            (Internal::Empty, r#"{"type": "Empty"}"#),
+        ];
+        for (value, json) in internal {
+            let result = to_json(&value).map_err(|e| e.to_string())?;
+            let back: Internal = from_json(&result).map_err(|e| e.to_string())?;
+            if result != json || back != value {
+                return Err(format!("Unexpected internal encoding of {:?}: {}", value, result));
+            }
+        }
+        let adjacent = [
+            (Adjacent::Circle { radius: 1.5 }, r#"{"t": "Circle", "c": {"radius": 1.5}}"#),
+            (Adjacent::Square(2), r#"{"t": "Square", "c": 2}"#),
+            (Adjacent::Empty, r#"{"t": "Empty"}"#),
+        ];
+        for (value, json) in adjacent {
+            let result = to_json(&value).map_err(|e| e.to_string())?;
+            let back: Adjacent = from_json(&result).map_err(|e| e.to_string())?;
+            if result != json || back != value {
+                return Err(format!("Unexpected adjacent encoding of {:?}: {}", value, result));
+            }
+        }
+        // The tag can come anywhere in the object
+        let moved: Internal = from_json(r#"{"radius": 2, "type": "Circle"}"#).map_err(|e| e.to_string())?;
+        if moved != (Internal::Circle { radius: 2.0 }) {
+            return Err(format!("Unexpected value: {:?}", moved));
+        }
+        for (err, expected) in [
+            (from_json::<External>(r#""Oval""#).err(), "unknown variant `Oval`, expected one of `Circle`, `Square`, `Empty`"),
+            (from_json::<External>(r#"{"Circle": {}}"#).err(), "missing field `radius`"),
+            (from_json::<Internal>(r#"{"radius": 1}"#).err(), "missing field `type`"),
+        ] {
+            match err {
+                Some(e) if e.message() == expected => {}
+                other => return Err(format!("Expected '{}', got {:?}", expected, other.map(|e| e.to_string()))),
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 65: Untagged enums try each variant in order
+    results.push(test_runner("Untagged enums", || {
+        #[derive(Debug, PartialEq)]
+        enum Setting { Off, Level(i64), Name(String), Range { low: i32, high: i32 } }
+        derive_enum!(#[serde(untagged)] Setting { Off, Level(i64), Name(String), Range { low: i32, high: i32 } });
+        
+        let values: Vec<Setting> = from_json(r#"[null, 3, "auto", {"low": 1, "high": 5}]"#).map_err(|e| e.to_string())?;
+        let expected = [
+            Setting::Off,
+            Setting::Level(3),
+            Setting::Name("auto".to_string()),
+            Setting::Range { low: 1, high: 5 },
+        ];
+        if values != expected {
+            return Err(format!("Unexpected values: {:?}", values));
+        }
+        let result = to_json(&values).map_err(|e| e.to_string())?;
+        if result != r#"[null, 3, "auto", {"low": 1, "high": 5}]"# {
+            return Err(format!("Unexpected serialization: {}", result));
+        }
+        match from_json::<Setting>("true") {
+            Err(e) if e.message() == "data did not match any variant of untagged enum Setting" => Ok(()),
+            other => Err(format!("Expected an error, got {:?}", other.map_err(|e| e.to_string()))),
+        }
+    }));
+    
+    // Test 66: Flattened fields merge into the parent and collect leftover keys
+    results.push(test_runner("Flatten", || {
+        #[derive(Debug, PartialEq)]
+        struct Pagination { limit: u32, offset: u32 }
+        derive_serialize!(Pagination { limit, offset });
+        derive_deserialize!(Pagination { limit: u32, offset: u32 });
+        
+        #[derive(Debug, PartialEq)]
+        struct Config { name: String, page: Pagination, comment: Option<String> }
+        derive_serialize!(Config { name, #[serde(flatten)] page, comment });
+        derive_deserialize!(Config { name: String, #[serde(flatten)] page: Pagination, comment: Option<String> });
+        
+        #[derive(Debug, PartialEq)]
+        struct Tagged { id: u32, extra: HashMap<String, String> }
+        derive_serialize!(Tagged { id, #[serde(flatten)] extra });
+        derive_deserialize!(Tagged { id: u32, #[serde(flatten)] extra: HashMap<String, String> });
+        
+        let config = Config {
+            name: "list".to_string(),
+            page: Pagination { limit: 10, offset: 20 },
+            comment: None,
+        };
+        let result = to_json(&config).map_err(|e| e.to_string())?;
+        if result != r#"{"name": "list", "limit": 10, "offset": 20, "comment": null}"# {
+            return Err(format!("Unexpected serialization: {}", result));
+        }
+        let back: Config = from_json(&result).map_err(|e| e.to_string())?;
+        let yaml: Config = from_yaml(&to_yaml(&config).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if back != config || yaml != config {
+            return Err(format!("Unexpected round trip: {:?} {:?}", back, yaml));
+        }
+        // Keys the struct doesn't name land in the flattened map, and a
+        // missing Option field reads as None
+        let tagged: Tagged = from_json(r#"{"color": "red", "id": 7, "size": "L"}"#).map_err(|e| e.to_string())?;
+        let expected = HashMap::from([("color".to_string(), "red".to_string()), ("size".to_string(), "L".to_string())]);
+        if tagged.id != 7 || tagged.extra != expected {
+            return Err(format!("Unexpected leftovers: {:?}", tagged));
+        }
+        let form = to_urlencoded(&Tagged { id: 1, extra: HashMap::from([("q".to_string(), "a b".to_string())]) })
+            .map_err(|e| e.to_string())?;
+        if form != "id=1&q=a+b" {
+            return Err(format!("Unexpected form: {}", form));
+        }
+        match from_json::<Config>(r#"{"name": "x", "limit": 1}"#) {
+            Err(e) if e.message() == "missing field `offset`" => {}
+            other => return Err(format!("Expected a missing field error, got {:?}", other.map_err(|e| e.to_string()))),
+        }
+        // Only structs and maps have keys to merge
+        struct Wrapper { inner: i32 }
+        derive_serialize!(Wrapper { #[serde(flatten)] inner });
+        match to_json(&Wrapper { inner: 1 }) {
+            Err(e) if e.to_string() == "can only flatten structs and maps, not an integer" => Ok(()),
+            other => Err(format!("Expected an error, got {:?}", other)),
+        }
+    }));
+    
+    // Test 67: Standard library collections, pointers and Result
+    results.push(test_runner("Std collections", || {
+        use std::collections::{BTreeMap, BTreeSet, HashSet, LinkedList, VecDeque};
+        use std::rc::Rc;
+        use std::sync::Arc;
+        
+        let tree = BTreeMap::from([("b".to_string(), 2), ("a".to_string(), 1)]);
+        let result = to_json(&tree).map_err(|e| e.to_string())?;
+        if result != r#"{"a": 1, "b": 2}"# || from_json::<BTreeMap<String, i32>>(&result).map_err(|e| e.to_string())? != tree {
+            return Err(format!("Unexpected BTreeMap: {}", result));
+        }
+        let set: BTreeSet<i32> = from_json("[3, 1, 2, 1]").map_err(|e| e.to_string())?;
+        let hash_set: HashSet<String> = from_yaml("- a\n- b\n- a\n").map_err(|e| e.to_string())?;
+        if to_json(&set).map_err(|e| e.to_string())? != "[1, 2, 3]" || hash_set.len() != 2 {
+            return Err(format!("Unexpected sets: {:?} {:?}", set, hash_set));
+        }
+        let deque: VecDeque<i32> = from_json("[1, 2]").map_err(|e| e.to_string())?;
+        let list: LinkedList<i32> = from_msgpack(&to_msgpack(&deque).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if list.iter().copied().collect::<Vec<_>>() != [1, 2] {
+            return Err(format!("Unexpected list: {:?}", list));
+        }
+        let boxed: Box<Vec<i32>> = from_json("[1]").map_err(|e| e.to_string())?;
+        let shared: (Rc<String>, Arc<bool>) = from_json(r#"["x", true]"#).map_err(|e| e.to_string())?;
+        let unsized_box: Box<str> = "y".into();
+        let result = to_json(&(boxed, shared, unsized_box)).map_err(|e| e.to_string())?;
+        if result != r#"[[1], ["x", true], "y"]"# {
+            return Err(format!("Unexpected pointers: {}", result));
+        }
+        let results: Vec<Result<i32, String>> = vec![Ok(1), Err("bad".to_string())];
+        let result = to_json(&results).map_err(|e| e.to_string())?;
+        if result != r#"[{"Ok": 1}, {"Err": "bad"}]"# || from_json::<Vec<Result<i32, String>>>(&result).map_err(|e| e.to_string())? != results {
+            return Err(format!("Unexpected results: {}", result));
+        }
+        match from_json::<Result<i32, String>>(r#"{"Maybe": 1}"#) {
+            Err(e) if e.message() == "unknown variant `Maybe`, expected one of `Ok`, `Err`" => Ok(()),
+            other => Err(format!("Expected an error, got {:?}", other.map_err(|e| e.to_string()))),
+        }
+    }));
+    
+    // Test 68: Check derived impls against tokens, independent of any format
+    results.push(test_runner("Token assertions", || {
+        #[derive(Debug, PartialEq)]
+        struct Pair { name: String, value: Option<i64> }
+        derive_serialize!(Pair { name, value });
+        derive_deserialize!(Pair { name: String, value: Option<i64> });
+        
+        #[derive(Debug, PartialEq)]
+        enum Shape { Circle { radius: f64 }, Empty }
+        derive_enum!(Shape { Circle { radius: f64 }, Empty });
+        
+        assert_tokens(&Pair { name: "a".to_string(), value: Some(3) }, &[
+            Token::Struct { name: "Pair", len: 2 },
+            Token::Str("name"),
+            Token::Str("a"),
+            Token::Str("value"),
+            Token::Some,
+            Token::I64(3),
+            Token::StructEnd,
+        ]);
+        assert_tokens(&Shape::Circle { radius: 1.5 }, &[
+            Token::Map { len: Some(1) },
+            Token::Str("Circle"),
+            Token::Struct { name: "Circle", len: 1 },
+            Token::Str("radius"),
+            Token::F64(1.5),
+            Token::StructEnd,
+            Token::MapEnd,
+        ]);
+        assert_tokens(&Shape::Empty, &[Token::Str("Empty")]);
+        assert_tokens(&(1u8, vec!['x']), &[
+            Token::Tuple { len: 2 },
+            Token::U8(1),
+            Token::Seq { len: Some(1) },
+            Token::Char('x'),
+            Token::SeqEnd,
+            Token::TupleEnd,
+        ]);
+        // Deserializing is more forgiving: fields in any order, missing Options
+        assert_de_tokens(&Pair { name: "b".to_string(), value: None }, &[
+            Token::Map { len: None },
+            Token::Str("name"),
+            Token::String("b"),
+            Token::MapEnd,
+        ]);
+        let borrowed: &str = Deserialize::deserialize(&mut TokenDeserializer::new(&[Token::BorrowedStr("hi")]))
+            .map_err(|e| e.to_string())?;
+        if borrowed != "hi" {
+            return Err(format!("Unexpected borrowed string: {}", borrowed));
+        }
+        assert_de_tokens_error::<Shape>(&[Token::Str("Oval")], "unknown variant `Oval`, expected one of `Circle`, `Empty`");
+        assert_de_tokens_error::<Vec<i32>>(&[Token::Seq { len: None }, Token::I32(1)], "unexpected end of tokens");
+        
+        struct Failing;
+        impl Serialize for Failing {
+            fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+                Err(S::Error::custom("cannot serialize"))
+            }
+        }
+        assert_ser_tokens_error(&(1, Failing), &[Token::Tuple { len: 2 }, Token::I32(1)], "cannot serialize");
+        
+        // A mismatch names the expected and actual tokens
+        let mut serializer = TokenSerializer::new(&[Token::I64(1)]);
+        match 1i32.serialize(&mut serializer) {
+            Err(e) if e.to_string() == "expected Token::I64(1) but serialized as Token::I32(1)" => Ok(()),
+            other => Err(format!("Expected a mismatch, got {:?}", other.map_err(|e| e.to_string()))),
+        }
+    }));
+    
+    // Test 69: Fields with custom serialize/deserialize functions
+    results.push(test_runner("Field with modules", || {
+        // Writes a number as a string of hex digits
+        mod hex {
+            use super::*;
+            
+            pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&format!("{:x}", value))
+            }
+            
+            pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+                let text = String::deserialize(deserializer)?;
+                u32::from_str_radix(&text, 16).map_err(|e| D::Error::custom(format!("invalid hex {:?}: {}", text, e)))
+            }
+        }
+        
+        #[derive(Debug, PartialEq)]
+        struct Color { name: String, rgb: u32 }
+        derive_serialize!(Color { name, #[serde(with = hex)] rgb: u32 });
+        derive_deserialize!(Color { name: String, #[serde(with = hex)] rgb: u32 });
+        
+        let color = Color { name: "teal".to_string(), rgb: 0x008080 };
+        let result = to_json(&color).map_err(|e| e.to_string())?;
+        if result != r#"{"name": "teal", "rgb": "8080"}"# {
+            return Err(format!("Unexpected serialization: {}", result));
+        }
+        let back: Color = from_json(&result).map_err(|e| e.to_string())?;
+        if back != color {
+            return Err(format!("Unexpected round trip: {:?}", back));
+        }
+        // Without flatten, the struct is still written as a struct
+        assert_ser_tokens(&color, &[
+            Token::Struct { name: "Color", len: 2 },
+            Token::Str("name"),
+            Token::Str("teal"),
+            Token::Str("rgb"),
+            Token::Str("8080"),
+            Token::StructEnd,
+        ]);
+        for (input, expected) in [
+            (r#"{"name": "x", "rgb": "zz"}"#, "invalid hex \"zz\": invalid digit found in string"),
+            (r#"{"name": "x"}"#, "missing field `rgb`"),
+        ] {
+            match from_json::<Color>(input) {
+                Err(e) if e.message() == expected => {}
+                other => return Err(format!("Expected '{}', got {:?}", expected, other.map_err(|e| e.to_string()))),
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 70: Timestamps in RFC 3339 and Unix formats
+    results.push(test_runner("DateTime", || {
+        let time: DateTime = "2024-02-29T13:45:30.250+02:00".parse().map_err(|e: Error| e.to_string())?;
+        if time.to_rfc3339() != "2024-02-29T11:45:30.250Z" || time.unix_millis() != 1_709_207_130_250 {
+            return Err(format!("Unexpected time: {} ({} ms)", time, time.unix_millis()));
+        }
+        for (input, expected) in [
+            ("1970-01-01T00:00:00Z", "1970-01-01T00:00:00Z"),
+            ("1969-12-31 23:59:59.000001z", "1969-12-31T23:59:59.000001Z"),
+            ("0000-03-01T00:00:00-00:30", "0000-03-01T00:30:00Z"),
+            ("9999-12-31T23:59:59.123456789Z", "9999-12-31T23:59:59.123456789Z"),
+        ] {
+            let time: DateTime = input.parse().map_err(|e: Error| e.to_string())?;
+            if time.to_string() != expected {
+                return Err(format!("{} formatted as {}, expected {}", input, time, expected));
+            }
+        }
+        for input in ["2023-02-29T00:00:00Z", "2024-01-01T24:00:00Z", "2024-01-01T00:00:00", "2024-01-01T00:00:00.Z", "0000-01-01T00:00:00+01:00"] {
+            if input.parse::<DateTime>().is_ok() {
+                return Err(format!("Expected {} to be rejected", input));
+            }
+        }
+        
+        #[derive(Debug, PartialEq)]
+        struct Event { at: DateTime, seconds: DateTime, millis: DateTime }
+        derive_serialize!(Event { at, #[serde(with = unix_seconds)] seconds: DateTime, #[serde(with = unix_millis)] millis: DateTime });
+        derive_deserialize!(Event { at: DateTime, #[serde(with = unix_seconds)] seconds: DateTime, #[serde(with = unix_millis)] millis: DateTime });
+        
+        let event = Event { at: time, seconds: DateTime::from_unix(1_700_000_000, 0).unwrap(), millis: time };
+        let json = to_json(&event).map_err(|e| e.to_string())?;
+        if json != r#"{"at": "2024-02-29T11:45:30.250Z", "seconds": 1700000000, "millis": 1709207130250}"# {
+            return Err(format!("Unexpected serialization: {}", json));
+        }
+        let back: Event = from_json(&json).map_err(|e| e.to_string())?;
+        if back != event {
+            return Err(format!("Unexpected JSON round trip: {:?}", back));
+        }
+        let bytes = to_msgpack(&event).map_err(|e| e.to_string())?;
+        let back: Event = from_msgpack(&bytes).map_err(|e| e.to_string())?;
+        if back != event {
+            return Err(format!("Unexpected MessagePack round trip: {:?}", back));
+        }
+        // Negative milliseconds round down to the earlier second
+        let before_epoch = DateTime::from_unix_millis(-1).unwrap();
+        if before_epoch.unix_seconds() != -1 || before_epoch.subsec_nanos() != 999_000_000 {
+            return Err(format!("Unexpected time: {:?}", before_epoch));
+        }
+        
+        for (input, expected) in [
+            (r#"{"at": "yesterday", "seconds": 0, "millis": 0}"#, "invalid value: string \"yesterday\", expected an RFC 3339 timestamp"),
+            (r#"{"at": "2024-01-01T00:00:00Z", "seconds": 300000000000, "millis": 0}"#, "invalid value: integer 300000000000, expected a Unix timestamp in seconds"),
+        ] {
+            match from_json::<Event>(input) {
+                Err(e) if e.message() == expected => {}
+                other => return Err(format!("Expected '{}', got {:?}", expected, other.map_err(|e| e.to_string()))),
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 71: Arbitrary-precision numbers
+    results.push(test_runner("Arbitrary precision", || {
+        let input = r#"{"id": 123456789012345678901234567890, "price": 0.10, "rate": 1e-7, "count": 42, "ratio": 1.5}"#;
+        let value: JsonValue = JsonDeserializer::new(input).arbitrary_precision(true).parse().map_err(|e| e.to_string())?;
+        let json = to_json(&value).map_err(|e| e.to_string())?;
+        if json != r#"{"count": 42, "id": 123456789012345678901234567890, "price": 0.10, "rate": 1e-7, "ratio": 1.5}"# {
+            return Err(format!("Unexpected serialization: {}", json));
+        }
+        if value["id"] != JsonValue::Number(Number::Arbitrary("123456789012345678901234567890".to_string()))
+            || value["count"].as_u64() != Some(42)
+            || value["ratio"] != JsonValue::Number(Number::Float(1.5))
+        {
+            return Err(format!("Unexpected value: {:?}", value));
+        }
+        let id: u128 = from_value(value["id"].clone()).map_err(|e| e.to_string())?;
+        if id != 123456789012345678901234567890 {
+            return Err(format!("Unexpected id: {}", id));
+        }
+        // Without the option, numbers are rounded as before
+        let rounded: JsonValue = from_json(input).map_err(|e| e.to_string())?;
+        if rounded["price"] != JsonValue::Number(Number::Float(0.1)) {
+            return Err(format!("Unexpected value: {:?}", rounded));
+        }
+        
+        #[derive(Debug, PartialEq)]
+        struct Payment { amount: Number }
+        derive_serialize!(Payment { amount });
+        derive_deserialize!(Payment { amount: Number });
+        let payment: Payment = JsonDeserializer::new(r#"{"amount": 19.990}"#).arbitrary_precision(true).parse().map_err(|e| e.to_string())?;
+        if payment.amount != Number::Arbitrary("19.990".to_string()) {
+            return Err(format!("Unexpected payment: {:?}", payment));
+        }
+        let json = to_json(&payment).map_err(|e| e.to_string())?;
+        if json != r#"{"amount": 19.990}"# {
+            return Err(format!("Unexpected serialization: {}", json));
+        }
+        // Formats without number text get the nearest native value
+        let amount: f64 = from_msgpack(&to_msgpack(&payment.amount).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if amount != 19.99 {
+            return Err(format!("Unexpected amount: {}", amount));
+        }
+        
+        for (text, expected) in [
+            ("-5", Number::Int(-5)),
+            ("0.25", Number::Float(0.25)),
+            ("1.0", Number::Arbitrary("1.0".to_string())),
+            ("18446744073709551616", Number::Arbitrary("18446744073709551616".to_string())),
+        ] {
+            if text.parse::<Number>().map_err(|e| e.to_string())? != expected {
+                return Err(format!("Unexpected parse of {}", text));
+            }
+        }
+        if "1.".parse::<Number>().is_ok() || "01".parse::<Number>().is_ok() || " 1".parse::<Number>().is_ok() {
+            return Err("Expected invalid numbers to be rejected".to_string());
+        }
+        if to_value(&u128::MAX).map_err(|e| e.to_string())? != JsonValue::Number(Number::Arbitrary(u128::MAX.to_string())) {
+            return Err("Expected u128::MAX to be kept exactly".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 72: Canonical output
+    results.push(test_runner("Canonical JSON", || {
+        let mut scores = HashMap::new();
+        for (name, score) in [("carol", 7), ("alice", 9), ("bob", 8), ("dave", 6)] {
+            scores.insert(name.to_string(), score);
+        }
+        let result = to_canonical_json(&scores).map_err(|e| e.to_string())?;
+        if result != r#"{"alice": 9, "bob": 8, "carol": 7, "dave": 6}"# {
+            return Err(format!("Unexpected map: {}", result));
+        }
+        
+        // Keys are sorted unquoted, so a prefix comes first, as in JsonValue
+        let mut prefixed = HashMap::new();
+        for (key, n) in [("a b", 2), ("a", 1), ("a\"", 3), ("ab", 4)] {
+            prefixed.insert(key.to_string(), n);
+        }
+        let result = to_canonical_json(&prefixed).map_err(|e| e.to_string())?;
+        if result != r#"{"a": 1, "a b": 2, "a\"": 3, "ab": 4}"# {
+            return Err(format!("Unexpected prefixed keys: {}", result));
+        }
+        if to_canonical_json(&to_value(&prefixed).map_err(|e| e.to_string())?).map_err(|e| e.to_string())? != result {
+            return Err("Expected JsonValue's key order".to_string());
+        }
+        
+        // Struct fields and nested maps are sorted too
+        #[derive(Debug)]
+        struct Doc { title: String, meta: HashMap<String, f64>, author: String }
+        derive_serialize!(Doc { title, meta, author });
+        let mut meta = HashMap::new();
+        meta.insert("z".to_string(), 1.0);
+        meta.insert("a".to_string(), -0.0);
+        let doc = Doc { title: "T".to_string(), meta, author: "A".to_string() };
+        let result = to_canonical_json(&doc).map_err(|e| e.to_string())?;
+        if result != r#"{"author": "A", "meta": {"a": 0, "z": 1}, "title": "T"}"# {
+            return Err(format!("Unexpected struct: {}", result));
+        }
+        
+        // Every way of writing a number gives the same text
+        for (value, expected) in [
+            (json!(0.5), "0.5"),
+            (json!(1e-7), "1e-7"),
+            (json!(0.000001), "0.000001"),
+            (json!(1e21), "1e21"),
+            (json!(123456.0), "123456"),
+            (JsonValue::Number(Number::Arbitrary("1.50E+2".to_string())), "150"),
+            (JsonValue::Number(Number::Arbitrary("0.10".to_string())), "0.1"),
+            (JsonValue::Number(Number::Arbitrary("-12345e-10".to_string())), "-0.0000012345"),
+            (JsonValue::Number(Number::Arbitrary("1000000000000000000000000".to_string())), "1e24"),
+        ] {
+            let result = to_canonical_json(&value).map_err(|e| e.to_string())?;
+            if result != expected {
+                return Err(format!("{:?} written as {}, expected {}", value, result, expected));
+            }
+        }
+        // Plain to_json is unchanged
+        if to_json(&doc.meta["a"]).map_err(|e| e.to_string())? != "-0" {
+            return Err("Expected to_json to keep -0".to_string());
+        }
+        Ok(())
+    }));
+    
+    // Test 73: NaN and infinity policies
+    results.push(test_runner("Non-finite floats", || {
+        let values = vec![1.5, f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+        match to_json(&values) {
+            Err(e) if e.message() == "NaN is not a valid JSON number" => {}
+            other => return Err(format!("Expected an error, got {:?}", other)),
+        }
+        for (policy, expected) in [
+            (NonFinite::Null, "[1.5, null, null, null]"),
+            (NonFinite::String, r#"[1.5, "NaN", "Infinity", "-Infinity"]"#),
+            (NonFinite::Literal, "[1.5, NaN, Infinity, -Infinity]"),
+        ] {
+            let json = values.serialize(JsonSerializer::new().non_finite(policy)).map_err(|e| e.to_string())?;
+            let mut writer = JsonWriter::new(Vec::new()).non_finite(policy);
+            values.serialize(&mut writer).map_err(|e| e.to_string())?;
+            if json != expected || writer.into_inner() != expected.as_bytes() {
+                return Err(format!("{:?} wrote {}, expected {}", policy, json, expected));
+            }
+            // Each policy reads back what it writes
+            let back: Vec<f64> = JsonDeserializer::new(&json).non_finite(policy).parse().map_err(|e| e.to_string())?;
+            let matches = back[0] == 1.5 && back[1].is_nan() && match policy {
+                NonFinite::Null => back[2].is_nan() && back[3].is_nan(),
+                _ => back[2] == f64::INFINITY && back[3] == f64::NEG_INFINITY,
+            };
+            if !matches {
+                return Err(format!("{:?} read back {:?}", policy, back));
+            }
+            // And is strict by default
+            if from_json::<Vec<f64>>(&json).is_ok() {
+                return Err(format!("Expected {} to be rejected by default", json));
+            }
+        }
+        
+        // Other strings and options are unaffected
+        let names: Vec<String> = JsonDeserializer::new(r#"["NaN"]"#).non_finite(NonFinite::String).parse().map_err(|e| e.to_string())?;
+        let maybe: Option<f32> = JsonDeserializer::new("null").non_finite(NonFinite::Null).parse().map_err(|e| e.to_string())?;
+        if names != ["NaN"] || maybe.is_some() {
+            return Err(format!("Unexpected values: {:?} {:?}", names, maybe));
+        }
+        match JsonDeserializer::new(r#""Inf""#).non_finite(NonFinite::String).parse::<f64>() {
+            Err(e) if e.message() == "invalid type: string, expected an f64" => {}
+            other => return Err(format!("Expected a type error, got {:?}", other)),
+        }
+        let value: JsonValue = JsonDeserializer::new("[-Infinity, -2]").non_finite(NonFinite::Literal).parse().map_err(|e| e.to_string())?;
+        if value[0].as_f64() != Some(f64::NEG_INFINITY) || value[1].as_i64() != Some(-2) {
+            return Err(format!("Unexpected value: {:?}", value));
+        }
+        if value.to_string() != "[null, -2]" {
+            return Err(format!("Unexpected display: {}", value));
+        }
+        
+        // A number too large for its type doesn't become an infinity by default
+        for result in [
+            from_json::<JsonValue>("1e400").map(|_| ()),
+            from_json::<f64>("-1e400").map(|_| ()),
+            from_json::<f32>("1e39").map(|_| ()),
+        ] {
+            match result {
+                Err(e) if e.message().starts_with("number out of range: ") => {}
+                other => return Err(format!("Expected an out of range error, got {:?}", other)),
+            }
+        }
+        let large: f64 = JsonDeserializer::new("1e400").non_finite(NonFinite::Literal).parse().map_err(|e| e.to_string())?;
+        if large != f64::INFINITY || from_json::<f32>("1e38").is_err() {
+            return Err(format!("Unexpected float: {}", large));
+        }
+        Ok(())
+    }));
+    
+    // Test 74: Nesting depth limits
+    results.push(test_runner("Depth limit", || {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        from_json::<JsonValue>(&nested(128)).map_err(|e| e.to_string())?;
+        // Deep enough to overflow the stack without the limit
+        for input in [nested(129), nested(100_000), format!("{}1{}", r#"{"a": "#.repeat(200), "}".repeat(200))] {
+            match from_json::<JsonValue>(&input) {
+                Err(e) if e.message() == "recursion limit exceeded: nested more than 128 levels deep" => {}
+                other => return Err(format!("Expected a depth error, got {:?}", other.map_err(|e| e.to_string()))),
+            }
+        }
+        match JsonDeserializer::new("[[1], [[2]]]").max_depth(2).parse::<JsonValue>() {
+            Err(e) if e.offset() == Some(7) && e.path() == "[1][0]" => {}
+            other => return Err(format!("Expected a depth error, got {:?}", other)),
+        }
+        
+        let mut value = JsonValue::Null;
+        for _ in 0..200 {
+            value = JsonValue::Array(vec![value]);
+        }
+        if to_json(&value).is_ok() || to_writer(Vec::new(), &value).is_ok() {
+            return Err("Expected serializing 200 levels to fail".to_string());
+        }
+        let json = value.serialize(JsonSerializer::new().max_depth(200)).map_err(|e| e.to_string())?;
+        let mut writer = JsonWriter::new(Vec::new()).max_depth(200);
+        value.serialize(&mut writer).map_err(|e| e.to_string())?;
+        if json.len() != 404 || writer.into_inner() != json.as_bytes() {
+            return Err(format!("Unexpected output: {}", json));
+        }
+        // Display writes any value it's given
+        if value.to_string() != json || format!("{}", value).len() != 404 {
+            return Err("Expected Display to print 200 levels".to_string());
+        }
+        let back: JsonValue = JsonDeserializer::new(&json).max_depth(200).parse().map_err(|e| e.to_string())?;
+        if back != value {
+            return Err("Round trip changed the value".to_string());
+        }
+        
+        // One-byte MessagePack arrays nest just as cheaply
+        let mut bytes = vec![0x91; 100_000];
+        bytes.push(0x01);
+        if from_msgpack::<JsonValue>(&bytes).is_ok() {
+            return Err("Expected deep MessagePack to fail".to_string());
+        }
+        let mut deserializer = MsgPackDeserializer::new(&bytes[100_000 - 150..]).max_depth(150);
+        JsonValue::deserialize(&mut deserializer).map_err(|e| e.to_string())?;
+        
+        // YAML block sequences and flow collections share the limit
+        let block = |depth: usize| format!("{}1", "- ".repeat(depth));
+        from_yaml::<JsonValue>(&block(128)).map_err(|e| e.to_string())?;
+        from_yaml::<JsonValue>(&nested(128)).map_err(|e| e.to_string())?;
+        from_yaml::<JsonValue>(&format!("{}{}", "- ".repeat(100), nested(28))).map_err(|e| e.to_string())?;
+        let deep_mapping: String = (0..200).map(|i| format!("{}k:\n", " ".repeat(i))).collect();
+        for input in [block(129), block(200_000), nested(129), "[".repeat(200_000), format!("{}{}", "- ".repeat(100), nested(29)), deep_mapping] {
+            match from_yaml::<JsonValue>(&input) {
+                Err(e) if e.message() == "recursion limit exceeded: nested more than 128 levels deep" => {}
+                other => return Err(format!("Expected a YAML depth error, got {:?}", other.map_err(|e| e.to_string()))),
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 75: Duplicate key policies
+    results.push(test_runner("Duplicate keys", || {
+        #[derive(Debug, PartialEq)]
+        struct Limits { cpu: u32, memory: u32 }
+        derive_deserialize!(Limits { cpu: u32, memory: u32 });
+        
+        let input = r#"{"cpu": 1, "memory": 512, "cpu": [2, {"x": 3}]}"#;
+        // Last wins by default, so the second `cpu` must be a number
+        match from_json::<Limits>(input) {
+            Err(e) if e.path() == "cpu" => {}
+            other => return Err(format!("Expected an error at cpu, got {:?}", other)),
+        }
+        let first: Limits = JsonDeserializer::new(input).duplicate_keys(DuplicateKeys::FirstWins).parse().map_err(|e| e.to_string())?;
+        if first != (Limits { cpu: 1, memory: 512 }) {
+            return Err(format!("Unexpected first-wins result: {:?}", first));
+        }
+        match JsonDeserializer::new(input).duplicate_keys(DuplicateKeys::Error).parse::<Limits>() {
+            Err(e) if e.message() == "duplicate key `cpu`" && e.offset() == Some(26) => {}
+            other => return Err(format!("Expected a duplicate key error, got {:?}", other)),
+        }
+        
+        // Maps, nested objects and escaped keys
+        let input = r#"{"a": {"k": 1, "k": 2}, "\u0061": {}}"#;
+        let last: HashMap<String, HashMap<String, i32>> = from_json(input).map_err(|e| e.to_string())?;
+        let first: HashMap<String, HashMap<String, i32>> =
+            JsonDeserializer::new(input).duplicate_keys(DuplicateKeys::FirstWins).parse().map_err(|e| e.to_string())?;
+        if !last["a"].is_empty() || first["a"]["k"] != 1 {
+            return Err(format!("Unexpected maps: {:?} {:?}", last, first));
+        }
+        match JsonDeserializer::new(input).duplicate_keys(DuplicateKeys::Error).parse::<JsonValue>() {
+            Err(e) if e.message() == "duplicate key `k`" && e.path() == "a" => {}
+            other => return Err(format!("Expected a duplicate key error, got {:?}", other)),
+        }
+        
+        // MessagePack maps: {"x": 1, "x": 2}
+        let bytes = [0x82, 0xa1, b'x', 0x01, 0xa1, b'x', 0x02];
+        let mut deserializer = MsgPackDeserializer::new(&bytes).duplicate_keys(DuplicateKeys::FirstWins);
+        let map = HashMap::<String, u8>::deserialize(&mut deserializer).map_err(|e| e.to_string())?;
+        let mut deserializer = MsgPackDeserializer::new(&bytes).duplicate_keys(DuplicateKeys::Error);
+        if map["x"] != 1 || HashMap::<String, u8>::deserialize(&mut deserializer).is_ok() {
+            return Err(format!("Unexpected MessagePack map: {:?}", map));
+        }
+        Ok(())
+    }));
+    
+    // Test 76: Transcoding between formats
+    results.push(test_runner("Transcode", || {
+        let input = r#"{"id": 7, "name": "disk", "ratio": -0.5, "tags": ["a", null], "extra": {"on": true}}"#;
+        let mut deserializer = JsonDeserializer::new(input);
+        let bytes = transcode(&mut deserializer, MsgPackSerializer::new()).map_err(|e| e.to_string())?;
+        deserializer.end().map_err(|e| e.to_string())?;
+        let value: JsonValue = from_json(input).map_err(|e| e.to_string())?;
+        if from_msgpack::<JsonValue>(&bytes).map_err(|e| e.to_string())? != value {
+            return Err("MessagePack output does not decode to the input".to_string());
+        }
+        let mut deserializer = MsgPackDeserializer::new(&bytes);
+        let json = transcode(&mut deserializer, JsonSerializer::new()).map_err(|e| e.to_string())?;
+        if json != input {
+            return Err(format!("Unexpected JSON: {}", json));
+        }
+        
+        // Values reach the serializer one call at a time
+        let tokens = [
+            Token::Map { len: None },
+            Token::Str("id"), Token::I64(7),
+            Token::Str("tags"), Token::Seq { len: None }, Token::Str("a"), Token::None, Token::SeqEnd,
+            Token::MapEnd,
+        ];
+        let mut serializer = TokenSerializer::new(&tokens);
+        transcode(&mut JsonDeserializer::new(r#"{"id": 7, "tags": ["a", null]}"#), &mut serializer).map_err(|e| e.to_string())?;
+        if !serializer.remaining().is_empty() {
+            return Err(format!("Tokens left over: {:?}", serializer.remaining()));
+        }
+        
+        // Arbitrary-precision numbers keep their text
+        let mut deserializer = JsonDeserializer::new("[0.10, 123456789012345678901234567890]").arbitrary_precision(true);
+        let json = transcode(&mut deserializer, JsonSerializer::new()).map_err(|e| e.to_string())?;
+        if json != "[0.10, 123456789012345678901234567890]" {
+            return Err(format!("Unexpected numbers: {}", json));
+        }
+        
+        // Errors from either side stop the transcode
+        match transcode(&mut JsonDeserializer::new("[1, 2"), JsonSerializer::new()) {
+            Err(e) if e.message() == "expected `,` or `]`, found end of input" => {}
+            other => return Err(format!("Expected a syntax error, got {:?}", other)),
+        }
+        let mut nan = vec![0x91, 0xcb];
+        nan.extend(f64::NAN.to_be_bytes());
+        match transcode(&mut MsgPackDeserializer::new(&nan), JsonSerializer::new()) {
+            Err(e) if e.to_string().contains("NaN is not a valid JSON number") => {}
+            other => return Err(format!("Expected a NaN error, got {:?}", other)),
+        }
+        Ok(())
+    }));
+    
+    // Test 77: Rejecting unknown fields
+    results.push(test_runner("Deny unknown fields", || {
+        use std::collections::BTreeMap;
+        
+        #[derive(Debug, PartialEq)]
+        struct Server { host: String, port: u16, tls: Option<bool> }
+        derive_deserialize!(#[serde(deny_unknown_fields)] Server { host: String, port: u16, tls: Option<bool> });
+        #[derive(Debug, PartialEq)]
+        struct Loose { host: String }
+        derive_deserialize!(Loose { host: String });
+        
+        let server: Server = from_json(r#"{"host": "db", "port": 5432}"#).map_err(|e| e.to_string())?;
+        if server != (Server { host: "db".to_string(), port: 5432, tls: None }) {
+            return Err(format!("Unexpected server: {:?}", server));
+        }
+        let input = r#"{"host": "db", "prot": 5432}"#;
+        match from_json::<Server>(input) {
+            Err(e) if e.message() == "unknown field `prot`, expected one of `host`, `port`, `tls`" && e.offset() == Some(21) => {}
+            other => return Err(format!("Expected an unknown field error, got {:?}", other)),
+        }
+        // Still lenient by default
+        let loose: Loose = from_json(input).map_err(|e| e.to_string())?;
+        if loose.host != "db" {
+            return Err(format!("Unexpected host: {}", loose.host));
+        }
+        
+        // Nested structs report where the key was
+        match from_json::<Vec<Server>>(r#"[{"host": "a", "port": 1}, {"host": "b", "port": 2, "debug": true}]"#) {
+            Err(e) if e.message().starts_with("unknown field `debug`") && e.path() == "[1]" => {}
+            other => return Err(format!("Expected an error at [1], got {:?}", other)),
+        }
+        let bytes = to_msgpack(&BTreeMap::from([("host", "db"), ("user", "root")])).map_err(|e| e.to_string())?;
+        match from_msgpack::<Server>(&bytes) {
+            Err(e) if e.message().starts_with("unknown field `user`") => {}
+            other => return Err(format!("Expected an unknown field error, got {:?}", other)),
+        }
+        
+        #[derive(Debug, PartialEq)]
+        struct Empty {}
+        derive_deserialize!(#[serde(deny_unknown_fields)] Empty {});
+        from_json::<Empty>("{}").map_err(|e| e.to_string())?;
+        match from_json::<Empty>(r#"{"x": 1}"#) {
+            Err(e) if e.message() == "unknown field `x`, there are no fields" => Ok(()),
+            other => Err(format!("Expected an unknown field error, got {:?}", other)),
+        }
+    }));
+    
+    // Test 78: JSON Pointer lookups
+    results.push(test_runner("JSON Pointer", || {
+        let mut value = json!({
+            "users": [{"name": "Alice"}, {"name": "Bob", "roles": ["admin"]}],
+            "a/b": 1,
+            "m~n": 2,
+            "": {"": 3},
+        });
+        let found = [
+            ("/users/0/name", json!("Alice")),
+            ("/users/1/roles/0", json!("admin")),
+            ("/a~1b", json!(1)),
+            ("/m~0n", json!(2)),
+            ("//", json!(3)),
+        ];
+        for (pointer, expected) in found {
+            if value.pointer(pointer) != Some(&expected) {
+                return Err(format!("{} found {:?}", pointer, value.pointer(pointer)));
+            }
+        }
+        if value.pointer("") != Some(&value) {
+            return Err("The empty pointer should be the whole value".to_string());
+        }
+        // Missing keys, bad indexes, scalars and malformed pointers
+        let missing = ["/nobody", "/users/2", "/users/-", "/users/01", "/users/+1", "/a~1b/0", "users/0", "/m~2n", "/m~"];
+        if let Some(pointer) = missing.iter().find(|pointer| value.pointer(pointer).is_some()) {
+            return Err(format!("{} should find nothing", pointer));
+        }
+        
+        *value.pointer_mut("/users/1/name").ok_or("Bob is missing")? = json!("Robert");
+        if let Some(JsonValue::Array(roles)) = value.pointer_mut("/users/1/roles") {
+            roles.push(json!("ops"));
+        }
+        if value["users"][1] != json!({"name": "Robert", "roles": ["admin", "ops"]}) || value.pointer_mut("/users/5").is_some() {
+            return Err(format!("Unexpected value after editing: {}", value));
+        }
+        Ok(())
+    }));
+    
+    // Test 79: JSON Merge Patch and JSON Patch
+    results.push(test_runner("JSON patches", || {
+        // The example from RFC 7396
+        let mut doc = json!({
+            "title": "Goodbye!",
+            "author": {"givenName": "John", "familyName": "Doe"},
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged",
+        });
+        merge_patch(&mut doc, &json!({
+            "title": "Hello!",
+            "phoneNumber": "+01-555-1234",
+            "author": {"familyName": null},
+            "tags": ["example"],
+        }));
+        let expected = json!({
+            "title": "Hello!",
+            "author": {"givenName": "John"},
+            "tags": ["example"],
+            "content": "This will be unchanged",
+            "phoneNumber": "+01-555-1234",
+        });
+        if doc != expected {
+            return Err(format!("Unexpected merge result: {}", doc));
+        }
+        let mut scalar = json!("text");
+        merge_patch(&mut scalar, &json!({"a": {"b": null, "c": 1}}));
+        merge_patch(&mut doc, &json!(null));
+        if scalar != json!({"a": {"c": 1}}) || !doc.is_null() {
+            return Err(format!("Unexpected merge results: {} {}", scalar, doc));
+        }
+        
+        let mut doc = json!({"users": [{"name": "Alice"}], "a/b": 1, "count": 2});
+        apply_patch(&mut doc, &json!([
+            {"op": "test", "path": "/count", "value": 2},
+            {"op": "add", "path": "/users/-", "value": {"name": "Bob"}},
+            {"op": "add", "path": "/users/0", "value": {"name": "Zoe"}},
+            {"op": "replace", "path": "/count", "value": 3},
+            {"op": "copy", "from": "/users/2", "path": "/owner"},
+            {"op": "move", "from": "/a~1b", "path": "/users/0/id"},
+            {"op": "remove", "path": "/users/1"},
+        ])).map_err(|e| e.to_string())?;
+        let expected = json!({"users": [{"name": "Zoe", "id": 1}, {"name": "Bob"}], "count": 3, "owner": {"name": "Bob"}});
+        if doc != expected {
+            return Err(format!("Unexpected patch result: {}", doc));
+        }
+        
+        // A failing operation leaves the document untouched
+        let failures = [
+            (json!([{"op": "add", "path": "/x", "value": 1}, {"op": "test", "path": "/count", "value": 4}]),
+                "test failed: the value at `/count` is different at [1]"),
+            (json!([{"op": "remove", "path": "/users/5"}]), "nothing at `/users/5` at [0]"),
+            (json!([{"op": "replace", "path": "/missing", "value": 1}]), "nothing at `/missing` at [0]"),
+            (json!([{"op": "add", "path": "/users/3", "value": 1}]), "index out of bounds at `/users/3` at [0]"),
+            (json!([{"op": "add", "path": "/count/x", "value": 1}]), "cannot add to a number at `/count` at [0]"),
+            (json!([{"op": "move", "from": "/users", "path": "/users/0/all"}]), "cannot move a value into one of its children at [0]"),
+            (json!([{"op": "add", "path": "/x"}]), "missing field `value` at [0]"),
+            (json!([{"op": "add", "path": "x", "value": 1}]), "invalid JSON Pointer `x` at [0]"),
+            (json!([{"op": "rename", "path": "/x"}]),
+                "unknown variant `rename`, expected one of `add`, `remove`, `replace`, `move`, `copy`, `test` at [0]"),
+            (json!({"op": "add"}), "invalid type: object, expected a JSON Patch array"),
+        ];
+        for (patch, message) in failures {
+            match apply_patch(&mut doc, &patch) {
+                Err(e) if e.to_string() == message => {}
+                other => return Err(format!("Expected {:?}, got {:?}", message, other)),
+            }
+        }
+        if doc != expected {
+            return Err(format!("A failed patch changed the document: {}", doc));
+        }
+        Ok(())
+    }));
+    
+    // Test 80: JSON Schema generation
+    results.push(test_runner("JSON Schema", || {
+        struct Address { city: String, zip: Option<String> }
+        derive_serialize!(Address { city, zip });
+        struct User { id: u64, name: String, score: f64, nickname: Option<String>, tags: Vec<String>, home: Address, point: (i32, i32), extra: HashMap<String, bool> }
+        derive_serialize!(User { id, name, score, nickname, tags, home, point, extra });
+        
+        let user = User {
+            id: 1,
+            name: "Alice".to_string(),
+            score: 9.5,
+            nickname: Some("Al".to_string()),
+            tags: vec!["admin".to_string()],
+            home: Address { city: "Paris".to_string(), zip: None },
+            point: (1, -2),
+            extra: HashMap::from([("beta".to_string(), true)]),
+        };
+        let schema = schema_for(&user).map_err(|e| e.to_string())?;
+        let expected = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "User",
+            "type": "object",
+            "properties": {
+                "id": {"type": "integer", "minimum": 0},
+                "name": {"type": "string"},
+                "score": {"type": "number"},
+                "nickname": {"type": ["string", "null"]},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "home": {
+                    "title": "Address",
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}, "zip": {"type": "null"}},
+                    "required": ["city"],
+                },
+                "point": {"type": "array", "prefixItems": [{"type": "integer"}, {"type": "integer"}], "minItems": 2, "maxItems": 2},
+                "extra": {"type": "object", "additionalProperties": {"type": "boolean"}},
+            },
+            "required": ["extra", "home", "id", "name", "point", "score", "tags"],
+        });
+        if schema != expected {
+            return Err(format!("Unexpected schema: {}", schema));
+        }
+        
+        // Elements of different shapes, empty collections and nested options
+        let schema = schema_for(&json!([1, "a", 2, null])).map_err(|e| e.to_string())?;
+        let items = json!({"anyOf": [{"type": "integer", "minimum": 0}, {"type": "string"}, {"type": "null"}]});
+        if schema["items"] != items || schema["type"] != json!("array") {
+            return Err(format!("Unexpected mixed schema: {}", schema));
+        }
+        let empty: Vec<u8> = Vec::new();
+        if schema_for(&empty).map_err(|e| e.to_string())?["items"] != json!({}) {
+            return Err("An empty Vec should allow any items".to_string());
+        }
+        let nested = schema_for(&Some(Some(vec![1u8]))).map_err(|e| e.to_string())?;
+        if nested["type"] != json!(["array", "null"]) {
+            return Err(format!("Unexpected option schema: {}", nested));
+        }
+        Ok(())
+    }));
+    
+    // Test 81: Serializing into a reused buffer
+    results.push(test_runner("JSON buffer reuse", || {
+        let mut buffer = String::from("[");
+        let rows = [json!({"id": 1, "tags": ["a"]}), json!({"id": 2, "tags": []})];
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
+                buffer.push(',');
+            }
+            row.serialize(JsonSerializer::with_buffer(&mut buffer)).map_err(|e| e.to_string())?;
+        }
+        buffer.push(']');
+        if buffer != r#"[{"id": 1, "tags": ["a"]},{"id": 2, "tags": []}]"# {
+            return Err(format!("Unexpected buffer: {}", buffer));
+        }
+        
+        // Clearing keeps the allocation, and the output matches to_json
+        let capacity = buffer.capacity();
+        for value in [json!("tab\t"), json!(-1.5), json!(null), json!({"b": [true], "a": {}})] {
+            buffer.clear();
+            value.serialize(JsonSerializer::with_buffer(&mut buffer)).map_err(|e| e.to_string())?;
+            if buffer != to_json(&value).map_err(|e| e.to_string())? || buffer.capacity() != capacity {
+                return Err(format!("Unexpected output for {:?}: {}", value, buffer));
+            }
+        }
+        
+        // Options work the same way
+        buffer.clear();
+        let value = json!({"z": 1.50, "a": [1e22]});
+        value.serialize(JsonSerializer::with_buffer(&mut buffer).canonical(true)).map_err(|e| e.to_string())?;
+        if buffer != r#"{"a": [1e22], "z": 1.5}"# {
+            return Err(format!("Unexpected canonical output: {}", buffer));
+        }
+        buffer.clear();
+        match json!([[1]]).serialize(JsonSerializer::with_buffer(&mut buffer).max_depth(1)) {
+            Err(e) if e.message().starts_with("recursion limit exceeded") && buffer == "[" => Ok(()),
+            other => Err(format!("Expected a depth error, got {:?} with {:?}", other, buffer)),
+        }
+    }));
+    
+    // Test 82: Round-trip property checks across formats
+    results.push(test_runner("Round-trip fuzzing", || {
+        for seed in 0..5 {
+            fuzz_round_trips(seed, 300)?;
+        }
+        // The same seed always gives the same values
+        let (mut a, mut b) = (ValueGenerator::new(7), ValueGenerator::new(7));
+        if (0..20).any(|_| a.value(3) != b.value(3)) {
+            return Err("Generators with the same seed differ".to_string());
+        }
+        
+        // Cases it has caught: `#` after a line separator or an escaped
+        // backslash isn't a YAML comment
+        for text in ["z\u{2028}#", "\\\\#", "U\u{2028}#"] {
+            check_round_trip(&json!({ (text): text }))?;
+            let back: String = from_yaml(&to_yaml(&text).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            if back != text {
+                return Err(format!("{:?} read back as {:?}", text, back));
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 83: Newtype and unit structs
+    results.push(test_runner("Newtype and unit structs", || {
+        #[derive(Debug, PartialEq)]
+        struct UserId(u64);
+        derive_serialize!(UserId(u64));
+        derive_deserialize!(UserId(u64));
+        #[derive(Debug, PartialEq)]
+        struct Tags(Vec<String>);
+        derive_serialize!(Tags(Vec<String>));
+        derive_deserialize!(Tags(Vec<String>));
+        #[derive(Debug, PartialEq)]
+        struct Anonymous;
+        derive_serialize!(Anonymous);
+        derive_deserialize!(Anonymous);
+        #[derive(Debug, PartialEq)]
+        struct Account { id: UserId, tags: Tags, owner: Anonymous }
+        derive_serialize!(Account { id, tags, owner });
+        derive_deserialize!(Account { id: UserId, tags: Tags, owner: Anonymous });
+        
+        // Written as the value inside, in every format
+        let account = Account { id: UserId(7), tags: Tags(vec!["new".to_string()]), owner: Anonymous };
+        let json = to_json(&account).map_err(|e| e.to_string())?;
+        if json != r#"{"id": 7, "tags": ["new"], "owner": null}"# {
+            return Err(format!("Unexpected JSON: {}", json));
+        }
+        let back: Account = from_json(&json).map_err(|e| e.to_string())?;
+        let via_yaml: Account = from_yaml(&to_yaml(&account).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        let via_msgpack: Account = from_msgpack(&to_msgpack(&account).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        let via_value: Account = from_value(to_value(&account).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if [&back, &via_yaml, &via_msgpack, &via_value].iter().any(|a| **a != account) {
+            return Err(format!("Round trips changed the account: {:?}", back));
+        }
+        let ids: HashMap<String, UserId> = from_json(r#"{"a": 1}"#).map_err(|e| e.to_string())?;
+        if ids["a"] != UserId(1) {
+            return Err(format!("Unexpected ids: {:?}", ids));
+        }
+        match from_json::<UserId>(r#""7""#) {
+            Err(e) if e.message() == "invalid type: string, expected a u64" => {}
+            other => return Err(format!("Expected a type error, got {:?}", other)),
+        }
+        match from_json::<Anonymous>("{}") {
+            Err(e) if e.message() == "invalid type: map, expected unit struct Anonymous" => {}
+            other => return Err(format!("Expected a type error, got {:?}", other)),
+        }
+        
+        // Tokens name the struct, and deserializing accepts the value alone
+        assert_tokens(&UserId(7), &[Token::NewtypeStruct { name: "UserId" }, Token::U64(7)]);
+        assert_tokens(&Anonymous, &[Token::UnitStruct { name: "Anonymous" }]);
+        assert_de_tokens(&UserId(7), &[Token::U64(7)]);
+        assert_de_tokens(&Anonymous, &[Token::Unit]);
+        Ok(())
+    }));
+    
+    // Test 84: Base64 bytes through a `with` module
+    results.push(test_runner("Base64 with module", || {
+        // The test vectors from RFC 4648
+        let vectors = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+        for (plain, encoded) in vectors {
+            let unpadded = encoded.trim_end_matches('=');
+            if with::base64::encode(plain.as_bytes()) != encoded
+                || with::base64::decode(encoded).as_deref() != Some(plain.as_bytes())
+                || with::base64::decode(unpadded).as_deref() != Some(plain.as_bytes())
+            {
+                return Err(format!("{:?} and {:?} don't match", plain, encoded));
+            }
+        }
+        let all: Vec<u8> = (0..=255).collect();
+        if with::base64::decode(&with::base64::encode(&all)) != Some(all) {
+            return Err("256 bytes didn't round trip".to_string());
+        }
+        
+        #[derive(Debug, PartialEq)]
+        struct Attachment { name: String, data: Vec<u8>, thumbnail: Box<[u8]> }
+        derive_serialize!(Attachment { name, #[serde(with = with::base64)] data: Vec<u8>, #[serde(with = with::base64)] thumbnail: Box<[u8]> });
+        derive_deserialize!(Attachment { name: String, #[serde(with = with::base64)] data: Vec<u8>, #[serde(with = with::base64)] thumbnail: Box<[u8]> });
+        
+        let attachment = Attachment { name: "a.bin".to_string(), data: vec![0, 1, 254, 255], thumbnail: Box::new([b'h', b'i']) };
+        let json = to_json(&attachment).map_err(|e| e.to_string())?;
+        if json != r#"{"name": "a.bin", "data": "AAH+/w==", "thumbnail": "aGk="}"# {
+            return Err(format!("Unexpected JSON: {}", json));
+        }
+        let back: Attachment = from_json(&json).map_err(|e| e.to_string())?;
+        let via_msgpack: Attachment = from_msgpack(&to_msgpack(&attachment).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if back != attachment || via_msgpack != attachment {
+            return Err(format!("Unexpected round trip: {:?}", back));
+        }
+        let borrowed: &[u8] = b"foo";
+        if with::base64::serialize(borrowed, JsonSerializer::new()).map_err(|e| e.to_string())? != r#""Zm9v""# {
+            return Err("Unexpected borrowed bytes".to_string());
+        }
+        
+        for bad in ["Zm9v!", "Z", "Zg=", "Zg===", "Z=g="] {
+            if with::base64::decode(bad).is_some() {
+                return Err(format!("{:?} should not decode", bad));
+            }
+        }
+        match from_json::<Attachment>(r#"{"name": "x", "data": "not base64", "thumbnail": ""}"#) {
+            Err(e) if e.message() == r#"invalid value: string "not base64", expected a base64 string"# && e.path() == "data" => Ok(()),
+            other => Err(format!("Expected a base64 error, got {:?}", other)),
+        }
+    }));
+    
+    // Test 85: RawJson passes fragments through untouched
+    results.push(test_runner("RawJson passthrough", || {
+        #[derive(Debug, PartialEq)]
+        struct Envelope { id: u32, payload: RawJson }
+        derive_serialize!(Envelope { id, payload });
+        derive_deserialize!(Envelope { id: u32, payload: RawJson });
+        
+        let input = r#"{"id": 7, "payload": {"b":1,  "a":[1.50, 2e3, 123456789012345678901234567890]} }"#;
+        let envelope: Envelope = from_json(input).map_err(|e| e.to_string())?;
+        if envelope.payload.get() != r#"{"b":1,  "a":[1.50, 2e3, 123456789012345678901234567890]}"# {
+            return Err(format!("Unexpected fragment: {}", envelope.payload));
+        }
+        let json = to_json(&envelope).map_err(|e| e.to_string())?;
+        if json != r#"{"id": 7, "payload": {"b":1,  "a":[1.50, 2e3, 123456789012345678901234567890]}}"# {
+            return Err(format!("Unexpected JSON: {}", json));
+        }
+        let mut written = Vec::new();
+        to_writer(&mut written, &envelope).map_err(|e| e.to_string())?;
+        if !String::from_utf8_lossy(&written).contains(r#"{"b":1,  "a":[1.50"#) {
+            return Err("JsonWriter changed the fragment".to_string());
+        }
+        
+        // Other formats carry the value the fragment holds
+        let via_yaml: Envelope = from_yaml(&to_yaml(&envelope).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        let payload: JsonValue = via_yaml.payload.parse().map_err(|e| e.to_string())?;
+        if payload["b"] != json!(1) || payload["a"][0] != json!(1.5) {
+            return Err(format!("Unexpected YAML round trip: {}", via_yaml.payload));
+        }
+        let small = Envelope { id: 8, payload: RawJson::from_string(r#"{"b":1, "a":[1.50]}"#.to_string()).map_err(|e| e.to_string())? };
+        let via_msgpack: Envelope = from_msgpack(&to_msgpack(&small).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if via_msgpack.payload.get() != r#"{"a": [1.5], "b": 1}"# {
+            return Err(format!("Unexpected MessagePack round trip: {}", via_msgpack.payload));
+        }
+        let value = to_value(&envelope).map_err(|e| e.to_string())?;
+        if value["payload"]["b"] != json!(1) {
+            return Err(format!("Unexpected value: {:?}", value));
+        }
+        
+        let raw = RawJson::from_string("[true, null]".to_string()).map_err(|e| e.to_string())?;
+        if to_json(&vec![raw.clone(), raw]).map_err(|e| e.to_string())? != "[[true, null], [true, null]]" {
+            return Err("Unexpected list of fragments".to_string());
+        }
+        if RawJson::from_string("[1,".to_string()).is_ok() || RawJson::from_string("1 2".to_string()).is_ok() {
+            return Err("Invalid JSON should be rejected".to_string());
+        }
+        match from_json::<Envelope>(r#"{"id": 1, "payload": [1, }"#) {
+            Err(e) if e.path() == "payload[1]" => Ok(()),
+            other => Err(format!("Expected an error in the payload, got {:?}", other)),
+        }
+    }));
+    
+    // Test 86: Newline-delimited JSON streams
+    results.push(test_runner("NDJSON reader and writer", || {
+        #[derive(Debug, PartialEq)]
+        struct Event { level: String, message: String }
+        derive_serialize!(Event { level, message });
+        derive_deserialize!(Event { level: String, message: String });
+        
+        let events = vec![
+            Event { level: "info".to_string(), message: "started".to_string() },
+            Event { level: "warn".to_string(), message: "two\nlines".to_string() },
+        ];
+        let mut writer = NdjsonWriter::new(Vec::new());
+        for event in &events {
+            writer.write(event).map_err(|e| e.to_string())?;
+        }
+        writer.flush().map_err(|e| e.to_string())?;
+        let output = String::from_utf8(writer.into_inner()).map_err(|e| e.to_string())?;
+        if output != "{\"level\": \"info\", \"message\": \"started\"}\n{\"level\": \"warn\", \"message\": \"two\\nlines\"}\n" {
+            return Err(format!("Unexpected output: {:?}", output));
+        }
+        let mut reader = NdjsonReader::new(output.as_bytes());
+        let back: Vec<Event> = reader.values().collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+        if back != events {
+            return Err(format!("Unexpected round trip: {:?}", back));
+        }
+        
+        // A fragment with line breaks still takes one line
+        let mut writer = NdjsonWriter::new(Vec::new());
+        let raw = RawJson::from_string("{\n  \"a\": 1\r\n}".to_string()).map_err(|e| e.to_string())?;
+        writer.write(&raw).map_err(|e| e.to_string())?;
+        if writer.into_inner() != b"{   \"a\": 1  }\n" {
+            return Err("Unexpected fragment line".to_string());
+        }
+        
+        // Blank lines are skipped, and a bad line doesn't stop the rest
+        let input = "{\"n\": 1}\r\n\n   \n{\"n\": oops}\n{\"n\": 3}";
+        let mut reader = NdjsonReader::new(input.as_bytes());
+        let read: Vec<Result<JsonValue, Error>> = reader.values().collect();
+        match &read[..] {
+            [Ok(first), Err(e), Ok(last)] if *first == json!({"n": 1}) && *last == json!({"n": 3}) => {
+                if e.line_column() != Some((4, 7)) || e.offset() != Some(21) || e.path() != "n" {
+                    return Err(format!("Unexpected error position: {}", e));
+                }
+            }
+            other => return Err(format!("Unexpected values: {:?}", other)),
+        }
+        match reader.read::<JsonValue>() {
+            Ok(None) => Ok(()),
+            other => Err(format!("Expected the end, got {:?}", other)),
+        }
+    }));
+    
+    // Test 87: Lenient JSON5-style parsing
+    results.push(test_runner("Lenient JSON5-style parsing", || {
+        use std::collections::HashMap;
+        
+        #[derive(Debug, PartialEq)]
+        struct Server { host: String, ports: Vec<u16>, origin: (i32, i32), labels: HashMap<String, String> }
+        derive_deserialize!(Server { host: String, ports: Vec<u16>, origin: (i32, i32), labels: HashMap<String, String> });
+        
+        let input = r#"
+            // Hand-written config
+            {
+                host: 'it\'s "local"', /* single quotes */
+                ports: [8080, 8443,],
+                origin: [1, -2,],
+                labels: {$env: 'dev', "team": 'core', region_1: "eu",},
+            }
+        "#;
+        let server: Server = JsonDeserializer::new(input).lenient(true).parse().map_err(|e| e.to_string())?;
+        let labels: HashMap<String, String> = [("$env", "dev"), ("team", "core"), ("region_1", "eu")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        if server != (Server { host: "it's \"local\"".to_string(), ports: vec![8080, 8443], origin: (1, -2), labels }) {
+            return Err(format!("Unexpected config: {:?}", server));
+        }
+        if from_json::<Server>(input).is_ok() {
+            return Err("Strict parsing should reject the config".to_string());
+        }
+        
+        // Bare keys and single-quoted strings without escapes are borrowed
+        let names: HashMap<&str, &str> = JsonDeserializer::new("{first: 'Ada', last: 'Lovelace'}").lenient(true).parse().map_err(|e| e.to_string())?;
+        if names.get("first") != Some(&"Ada") {
+            return Err(format!("Unexpected names: {:?}", names));
+        }
+        let value: JsonValue = JsonDeserializer::new("[null, true, 'x', {null: 1}] // done").lenient(true).parse().map_err(|e| e.to_string())?;
+        if value != json!([null, true, "x", {"null": 1}]) {
+            return Err(format!("Unexpected value: {}", value));
+        }
+        
+        let errors = [
+            ("[1, 2] /* unterminated", "expected end of input, found `/`"),
+            ("{1st: 1}", "expected a string key, found `1`"),
+            ("[1,,]", "expected a JSON value, found `,`"),
+            ("{a: 1,,}", "expected a string key, found `,`"),
+        ];
+        for (input, message) in errors {
+            match JsonDeserializer::new(input).lenient(true).parse::<JsonValue>() {
+                Err(e) if e.message() == message => {}
+                other => return Err(format!("Expected {:?} for {}, got {:?}", message, input, other)),
+            }
+        }
+        Ok(())
+    }));
+    
+    // Test 88: serde_json-style names
+    results.push(test_runner("DeserializeOwned and from_str/to_string", || {
+        #[derive(Debug, PartialEq)]
+        struct Job { id: u32, name: String }
+        derive_serialize!(Job { id, name });
+        derive_deserialize!(Job { id: u32, name: String });
+        
+        // The input is dropped before the value is used
+        fn load<T: DeserializeOwned>(make_input: impl Fn() -> String) -> Result<T, Error> {
+            let input = make_input();
+            from_str(&input)
+        }
+        let job: Job = load(|| r#"{"id": 3, "name": "backup"}"#.to_string()).map_err(|e| e.to_string())?;
+        if job != (Job { id: 3, name: "backup".to_string() }) {
+            return Err(format!("Unexpected job: {:?}", job));
+        }
+        let jobs: Vec<Job> = load(|| "[]".to_string()).map_err(|e| e.to_string())?;
+        if !jobs.is_empty() {
+            return Err("Expected no jobs".to_string());
+        }
+        
+        let json = to_string(&job).map_err(|e| e.to_string())?;
+        if json != to_json(&job).map_err(|e| e.to_string())? || to_string("a\"b").map_err(|e| e.to_string())? != r#""a\"b""# {
+            return Err(format!("Unexpected JSON: {}", json));
+        }
+        // from_str still borrows when the caller allows it
+        let name: &str = from_str(r#""backup""#).map_err(|e| e.to_string())?;
+        if name != "backup" {
+            return Err(format!("Unexpected name: {}", name));
+        }
+        match load::<Job>(|| r#"{"id": 3}"#.to_string()) {
+            Err(e) if e.message() == "missing field `name`" => Ok(()),
+            other => Err(format!("Expected a missing field, got {:?}", other)),
+        }
+    }));
+    
+    // Test 89: Reading TOML
+    results.push(test_runner("TOML documents", || {
+        #[derive(Debug, PartialEq)]
+        struct Server { host: String, port: u16 }
+        derive_deserialize!(Server { host: String, port: u16 });
+        
+        let input = r#"
+# Deployment settings
+title = "web"
+ports = [ 8000,
+  8001, # the spare
+]
+retries = 1_000
+mask = 0xff
+ratio = 6.5e-1
+enabled = true
+started = 1979-05-27 07:32:00Z
+"quoted key" = 'C:\temp'
+owner.name = "Ada"
+motd = """
+Hello \
+    there\t"""
+
+[server]
+host = "example.com"
+port = 8080
+
+[[replica]]
+host = "a"
+[[replica]]
+host = "b"
+limits = { cpu = 2, memory = "1G" }
+"#;
+        let value: JsonValue = from_toml(input).map_err(|e| e.to_string())?;
+        let expected: JsonValue = from_json(r#"{
+            "title": "web", "ports": [8000, 8001], "retries": 1000, "mask": 255, "ratio": 0.65,
+            "enabled": true, "started": "1979-05-27 07:32:00Z", "quoted key": "C:\\temp",
+            "owner": {"name": "Ada"}, "motd": "Hello there\t",
+            "server": {"host": "example.com", "port": 8080},
+            "replica": [{"host": "a"}, {"host": "b", "limits": {"cpu": 2, "memory": "1G"}}]
+        }"#).map_err(|e| e.to_string())?;
+        if value != expected {
+            return Err(format!("Unexpected document: {}", value));
+        }
+        
+        // Tables deserialize into structs like any other map
+        let server: Server = from_toml("host = \"localhost\"\nport = 443\n").map_err(|e| e.to_string())?;
+        if server != (Server { host: "localhost".to_string(), port: 443 }) {
+            return Err(format!("Unexpected server: {:?}", server));
+        }
+        
+        for (input, message) in [
+            ("a = 1\na = 2", "key `a` is defined twice at line 2"),
+            ("[a]\n[a]", "table `a` is defined twice at line 2"),
+            ("a = 1\n[a]", "`a` is not a table at line 2"),
+            ("a = 1 b = 2", "expected a newline at line 1"),
+            ("a = \"open", "unterminated string at line 1"),
+            ("a = 01", "invalid value `01` at line 1"),
+            ("a = [1 2]", "expected `,` or `]` at line 1"),
+            ("a =", "expected a value at line 1"),
+            ("a = 99999999999999999999", "number out of range: 99999999999999999999 at line 1"),
+            ("a = -9_223_372_036_854_775_809", "number out of range: -9_223_372_036_854_775_809 at line 1"),
+            ("a = 0xffff_ffff_ffff_ffff", "number out of range: 0xffff_ffff_ffff_ffff at line 1"),
+        ] {
+            match from_toml::<JsonValue>(input) {
+                Err(e) if e.message() == message => {}
+                other => return Err(format!("Expected {:?} for {:?}, got {:?}", message, input, other)),
+            }
+        }
+        let limits: JsonValue = from_toml("max = 9_223_372_036_854_775_807\nmin = -9223372036854775808\n").map_err(|e| e.to_string())?;
+        if limits["max"] != json!(i64::MAX) || limits["min"] != json!(i64::MIN) {
+            return Err(format!("Unexpected limits: {}", limits));
+        }
+        
+        // Arrays, inline tables and dotted keys count toward the nesting limit
+        let nested = |depth: usize| format!("a = {}{}", "[".repeat(depth), "]".repeat(depth));
+        from_toml::<JsonValue>(&nested(128)).map_err(|e| e.to_string())?;
+        let dotted = |depth: usize| format!("{} = 1", vec!["k"; depth].join("."));
+        from_toml::<JsonValue>(&dotted(128)).map_err(|e| e.to_string())?;
+        for input in [nested(129), format!("a = {}", "[".repeat(200_000)), format!("a = {}", "{ b = ".repeat(200_000)), dotted(129), format!("[{}]", vec!["k"; 200_000].join("."))] {
+            match from_toml::<JsonValue>(&input) {
+                Err(e) if e.message() == "recursion limit exceeded: nested more than 128 levels deep" => {}
+                other => return Err(format!("Expected a TOML depth error, got {:?}", other.map_err(|e| e.to_string()))),
+            }
+        }
+        Ok(())
+    }));
+    
     // Print results
     println!("\n=== Test Results ===");
     let mut passed = 0;